@@ -0,0 +1,164 @@
+//! Caching for proc macros, so expansions that do expensive work (parsing a large schema,
+//! generating bindings) are reused across compilations instead of redone by every `cargo build`
+//! and every rust-analyzer pass.
+//!
+//! `#[cached]` is meant for ordinary programs: it relies on a process-wide configuration and
+//! panics where a program would rather fail loudly. A proc macro runs inside the compiler, where
+//! a panic is a compile error in someone else's crate, so this crate goes through the store
+//! without either:
+//!
+//! - Nothing here panics. A store that can't be opened or read, or fails in any other way,
+//!   disables the cache and the expansion is computed as if it weren't there.
+//! - The store lives in the user's cache directory, or in the temporary directory where that
+//!   isn't writable (sandboxed builds, read-only home directories), or in
+//!   `SMART_CACHE_BUILDTIME_DIR` if set.
+//! - Compilations never wait for each other: while one holds the store, others expand without
+//!   it.
+//!
+//! ```no_run
+//! use proc_macro2::TokenStream;
+//!
+//! // In a `proc-macro = true` crate, `#[proc_macro] pub fn schema(input: TokenStream)`.
+//! fn schema(input: TokenStream) -> TokenStream {
+//!     let path = input.to_string();
+//!     let source = std::fs::read_to_string(path.trim_matches('"')).unwrap_or_default();
+//!     smart_cache_buildtime::cache!("schema").get_or_insert_with(&source, || {
+//!         // Parse and generate; only runs when the schema changed.
+//!         format!("pub const FIELDS: usize = {};", source.lines().count())
+//!     })
+//!     .parse()
+//!     .unwrap_or_default()
+//! }
+//! ```
+//!
+//! Entries are keyed by the input and the macro crate's name and version, not by the macro's
+//! code: while developing a macro, change its name or version (or clear it with
+//! `smart-cache clear`) when its output changes.
+
+use std::{
+    env,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Once,
+};
+
+use proc_macro2::TokenStream;
+use sha2::{Digest, Sha256};
+use smart_cache::{Config, Function};
+use tracing::{debug, warn};
+
+/// Name of the store holding expansions.
+const STORE: &str = "buildtime";
+
+/// A cache for one proc macro's expansions, keyed by its crate's name and version; see
+/// [`Cache::new`].
+#[macro_export]
+macro_rules! cache {
+    ($name:expr) => {
+        $crate::Cache::new(
+            $name,
+            concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION")),
+        )
+    };
+}
+
+/// The expansions of one proc macro.
+#[derive(Clone, Copy)]
+pub struct Cache {
+    function: &'static Function,
+}
+
+impl Cache {
+    /// A cache for the expansions of the macro `name`, shared by every macro crate of the same
+    /// `version`. [`cache!`] passes the calling crate's name and version.
+    #[must_use]
+    pub fn new(name: &str, version: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(version.as_bytes());
+        Self {
+            function: smart_cache::intern(name, hasher.finalize().into()),
+        }
+    }
+
+    /// Returns the expansion cached for `input`, or computes and caches it.
+    pub fn get_or_insert_with(&self, input: &str, compute: impl FnOnce() -> String) -> String {
+        install();
+        if let Some(cached) = self.get(input) {
+            return cached;
+        }
+        let expansion = compute();
+        let written = panic::catch_unwind(AssertUnwindSafe(|| {
+            smart_cache::insert_archived(self.function, input.as_bytes(), &expansion)
+        }));
+        if let Ok(Err(e)) = written {
+            debug!(
+                "Failed to cache expansion of {}: {e:#}",
+                self.function.name()
+            );
+        }
+        expansion
+    }
+
+    /// [`Self::get_or_insert_with`] for token streams, keyed by `input`'s text. Tokens read back
+    /// from the cache have call-site spans, so errors in the expansion point at the macro
+    /// invocation rather than inside it.
+    pub fn expand(&self, input: &TokenStream, expand: impl FnOnce() -> TokenStream) -> TokenStream {
+        let mut computed = None;
+        let expansion =
+            self.get_or_insert_with(&input.to_string(), || computed.insert(expand()).to_string());
+        computed.unwrap_or_else(|| expansion.parse().unwrap_or_default())
+    }
+
+    /// Reads the expansion cached for `input`, treating failures, panics included, as misses.
+    fn get(&self, input: &str) -> Option<String> {
+        let read = panic::catch_unwind(AssertUnwindSafe(|| {
+            smart_cache::get_archived::<String>(self.function, input.as_bytes())
+        }));
+        match read {
+            Ok(Ok(value)) => value.map(|value| value.as_str().to_owned()),
+            Ok(Err(e)) => {
+                debug!(
+                    "Ignoring cached expansion of {}: {e:#}",
+                    self.function.name()
+                );
+                None
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+/// Where the store lives: `SMART_CACHE_BUILDTIME_DIR`, the user's cache directory, or the
+/// temporary directory, whichever can be created first.
+fn store_dir() -> Option<PathBuf> {
+    let candidates = [
+        env::var_os("SMART_CACHE_BUILDTIME_DIR").map(PathBuf::from),
+        dirs::cache_dir().map(|dir| dir.join("smart-cache")),
+        Some(env::temp_dir().join("smart-cache")),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|dir| std::fs::create_dir_all(dir).is_ok())
+}
+
+/// Points smart-cache at the expansion store, once per compiler process. If the macro crate
+/// configured smart-cache itself, expansions go to its store instead.
+fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let Some(dir) = store_dir() else {
+            warn!("No writable directory for cached expansions; computing them every time");
+            return;
+        };
+        let config = Config::default()
+            .store_name(STORE)
+            .database(STORE, dir.join(format!("{STORE}.redb")))
+            .lock_retries(0);
+        if let Err(e) = config.install() {
+            debug!("Using the existing smart-cache configuration for expansions: {e:#}");
+        }
+    });
+}
@@ -0,0 +1,41 @@
+use std::{
+    env, process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use proc_macro2::TokenStream;
+use smart_cache_buildtime::{cache, Cache};
+
+static EXPANSIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn expand_schema(cache: Cache, input: &TokenStream) -> TokenStream {
+    cache.expand(input, || {
+        EXPANSIONS.fetch_add(1, Ordering::SeqCst);
+        format!("pub const FIELDS: usize = {};", input.to_string().len())
+            .parse()
+            .unwrap()
+    })
+}
+
+#[test]
+fn expansions_are_reused() {
+    let dir = env::temp_dir().join(format!("smart-cache-buildtime-{}", process::id()));
+    env::set_var("SMART_CACHE_BUILDTIME_DIR", &dir);
+
+    let input: TokenStream = "struct User { name: String }".parse().unwrap();
+    let expansion = expand_schema(cache!("schema"), &input);
+    assert_eq!(expansion.to_string(), "pub const FIELDS : usize = 29 ;");
+    assert_eq!(
+        expand_schema(cache!("schema"), &input).to_string(),
+        expansion.to_string()
+    );
+    assert_eq!(EXPANSIONS.load(Ordering::SeqCst), 1);
+
+    // Another version of the macro crate expands again.
+    expand_schema(Cache::new("schema", "schema-macros-0.2.0"), &input);
+    assert_eq!(EXPANSIONS.load(Ordering::SeqCst), 2);
+
+    smart_cache::flush();
+    assert!(dir.join("buildtime.redb").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,293 @@
+//! `smart-cache`: inspect and manage a smart-cache store without writing Rust.
+//!
+//! ```text
+//! smart-cache ls ~/.cache/smart-cache/my-app-0.1.0.redb
+//! smart-cache clear ~/.cache/smart-cache/my-app-0.1.0.redb --function embed_text
+//! smart-cache export ~/.cache/smart-cache/my-app-0.1.0.redb warm.archive --newer-than 7
+//! ```
+//!
+//! Every command takes the path of a redb store file; `--shards <n>` opens a sharded store.
+//! The store is named after the file, as the library names the stores it creates, so archives
+//! move freely between the CLI and programs.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use smart_cache::{backend::RedbBackend, Config, ExportFilter, FunctionHash};
+
+const USAGE: &str = "\
+usage: smart-cache <command> <store> [options]
+
+commands:
+  ls <store>                            list cached functions and their entry counts
+  stats <store>                         show call, hit and time-saved statistics
+  top <store>                           show the space each function occupies, largest first
+  keys <store> --function <name>...     list a function's entries and the arguments they
+                                        were computed from, if recorded
+  clear <store> [--function <name>]     remove every entry, or those of one function
+  gc <store>                            remove corrupt and expired entries
+  doctor <store> [--repair]             check every entry, optionally removing broken ones
+  compact <store>                       reclaim the space of removed entries
+  export <store> <archive> [--function <name>]... [--newer-than <days>]
+  import <store> <archive>
+  diff                                  list functions whose body changed in the last run
+
+options:
+  --shards <n>                          the store is split across <n> files";
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Options {
+    command: String,
+    store: PathBuf,
+    archive: Option<PathBuf>,
+    functions: Vec<String>,
+    newer_than: Option<Duration>,
+    repair: bool,
+    shards: usize,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let command = args.next().ok_or_else(|| eyre!("{USAGE}"))?;
+        if matches!(command.as_str(), "-h" | "--help") {
+            println!("{USAGE}");
+            std::process::exit(0);
+        }
+
+        let mut positional = Vec::new();
+        let mut options = Self {
+            command,
+            store: PathBuf::new(),
+            archive: None,
+            functions: Vec::new(),
+            newer_than: None,
+            repair: false,
+            shards: 1,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| eyre!("{arg} needs a value\n{USAGE}"))
+            };
+            match arg.as_str() {
+                "--function" => options.functions.push(value()?),
+                "--newer-than" => {
+                    let days: u32 = value()?.parse().wrap_err("invalid --newer-than")?;
+                    options.newer_than = Some(DAY * days);
+                }
+                "--repair" => options.repair = true,
+                "--shards" => options.shards = value()?.parse().wrap_err("invalid --shards")?,
+                flag if flag.starts_with("--") => bail!("unknown option {flag:?}\n{USAGE}"),
+                _ => positional.push(PathBuf::from(arg)),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        options.store = match positional.next() {
+            Some(store) => store,
+            // The manifest `diff` reads lives in the cache directory, not in a store.
+            None if options.command == "diff" => PathBuf::new(),
+            None => bail!("missing the store path\n{USAGE}"),
+        };
+        options.archive = positional.next();
+        if positional.next().is_some() {
+            bail!("too many arguments\n{USAGE}");
+        }
+        Ok(options)
+    }
+
+    fn archive(&self) -> Result<&Path> {
+        self.archive
+            .as_deref()
+            .ok_or_else(|| eyre!("{} needs an archive path\n{USAGE}", self.command))
+    }
+}
+
+/// The store name the library gives the file at `path`: its name without the extension.
+fn store_name(path: &Path) -> Result<String> {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| eyre!("{} is not a store file", path.display()))
+}
+
+fn hex(hash: &FunctionHash) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn main() -> Result<()> {
+    let options = Options::parse(env::args().skip(1))?;
+    if options.command == "diff" {
+        return diff();
+    }
+    if !options.store.exists() && options.command != "import" {
+        bail!("no store at {}", options.store.display());
+    }
+
+    if options.command == "compact" {
+        let mut backend = RedbBackend::open(&options.store, options.shards)
+            .wrap_err_with(|| format!("failed to open {}", options.store.display()))?;
+        backend.compact()?;
+        println!("compacted {}", options.store.display());
+        return Ok(());
+    }
+
+    let store = store_name(&options.store)?;
+    Config::default()
+        .store_name(&store)
+        .database(&store, &options.store)
+        .shards(options.shards)
+        .install()?;
+    run(&options, &store)?;
+    smart_cache::flush();
+    Ok(())
+}
+
+fn run(options: &Options, store: &str) -> Result<()> {
+    match options.command.as_str() {
+        "ls" => {
+            for function in smart_cache::functions()? {
+                println!(
+                    "{}\t{}\t{}",
+                    function.name,
+                    hex(&function.hash),
+                    function.entries
+                );
+            }
+        }
+        "stats" => {
+            println!("function\tcalls\thits\ttime saved\tavg hit\tavg compute");
+            for stats in smart_cache::stats()? {
+                let average = |average: Option<Duration>| {
+                    average.map_or_else(|| "-".to_string(), |average| format!("{average:?}"))
+                };
+                println!(
+                    "{}\t{}\t{}\t{:?}\t{}\t{}{}",
+                    stats.name,
+                    stats.calls,
+                    stats.hits,
+                    stats.time_saved,
+                    average(stats.average_hit_time()),
+                    average(stats.average_compute_time()),
+                    if stats.unprofitable() {
+                        "\t(hits cost more than computing)"
+                    } else {
+                        ""
+                    },
+                );
+            }
+        }
+        "top" => {
+            println!("function\tentries\tbytes\tlast written");
+            for usage in smart_cache::usage()? {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    usage.name,
+                    usage.entries,
+                    usage.bytes,
+                    age(usage.last_written)
+                );
+            }
+        }
+        "keys" => keys(options)?,
+        "clear" => clear(options)?,
+        "gc" => {
+            let report = smart_cache::doctor(true)?;
+            println!("removed {} of {} entries", report.removed, report.checked);
+        }
+        "doctor" => {
+            let report = smart_cache::doctor(options.repair)?;
+            println!("{report:#?}");
+        }
+        "export" => {
+            let mut filter = ExportFilter::new();
+            for name in &options.functions {
+                filter = filter.or_function(name);
+            }
+            if let Some(age) = options.newer_than {
+                filter = filter.newer_than(age);
+            }
+            let exported = smart_cache::export_filtered(options.archive()?, &filter)?;
+            println!("exported {exported} entries");
+        }
+        "import" => {
+            let imported = smart_cache::import_into(options.archive()?, store)?;
+            println!("imported {imported} entries");
+        }
+        command => bail!("unknown command {command:?}\n{USAGE}"),
+    }
+    Ok(())
+}
+
+/// How long ago `time` was, in the coarsest whole unit.
+fn age(time: Option<SystemTime>) -> String {
+    let Some(elapsed) = time.and_then(|time| time.elapsed().ok()) else {
+        return "-".to_string();
+    };
+    let secs = elapsed.as_secs();
+    match secs {
+        0..60 => format!("{secs}s ago"),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn diff() -> Result<()> {
+    for change in smart_cache::hash_changes()? {
+        match change.previous {
+            Some(previous) => println!(
+                "{}\t{} -> {}",
+                change.name,
+                hex(&previous),
+                hex(&change.current)
+            ),
+            None => println!("{}\tnew {}", change.name, hex(&change.current)),
+        }
+    }
+    Ok(())
+}
+
+fn keys(options: &Options) -> Result<()> {
+    if options.functions.is_empty() {
+        bail!("keys needs --function\n{USAGE}");
+    }
+    println!("function\tversion\tbytes\twritten\targuments");
+    for name in &options.functions {
+        for entry in smart_cache::entries(name)? {
+            println!(
+                "{name}\t{}\t{}\t{}\t{}",
+                &hex(&entry.hash)[..8],
+                entry.bytes,
+                age(entry.created_at),
+                entry.debug_key.as_deref().unwrap_or("-")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn clear(options: &Options) -> Result<()> {
+    let names = if options.functions.is_empty() {
+        let mut names: Vec<_> = smart_cache::functions()?
+            .into_iter()
+            .map(|function| function.name)
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    } else {
+        options.functions.clone()
+    };
+
+    for name in names {
+        let cleared = smart_cache::clear_function(&name)?;
+        println!("cleared {cleared} version(s) of {name}");
+    }
+    Ok(())
+}
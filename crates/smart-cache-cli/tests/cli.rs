@@ -0,0 +1,103 @@
+use std::{
+    fs,
+    path::Path,
+    process::{self, Command},
+    sync::Arc,
+};
+
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, RedbBackend, WriteEntry},
+    cached, Function,
+};
+
+#[cached]
+fn square(x: u64) -> u64 {
+    x * x
+}
+
+#[cached]
+fn cube(x: u64) -> u64 {
+    x * x * x
+}
+
+/// Runs the CLI with `args`, returning what it printed.
+fn smart_cache(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-cache"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "smart-cache {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The functions `ls` lists, as `<name> <entries>`, sorted.
+fn ls(store: &Path) -> Vec<String> {
+    let mut functions: Vec<_> = smart_cache(&["ls", store.to_str().unwrap()])
+        .lines()
+        .map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            format!("{} {}", fields[0], fields[2])
+        })
+        .collect();
+    functions.sort();
+    functions
+}
+
+#[test]
+fn manages_a_store_from_the_command_line() {
+    // Compute some entries in memory, then store them where the CLI can open them.
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("memory")
+        .backend("memory", Arc::clone(&memory) as Arc<dyn CacheBackend>)
+        .install()
+        .unwrap();
+    assert_eq!(square(2) + square(3) + cube(2), 21);
+    smart_cache::flush();
+
+    let dir = std::env::temp_dir().join(format!("smart-cache-cli-{}", process::id()));
+    let store = dir.join("store.redb");
+    let backend = RedbBackend::open(&store, 1).unwrap();
+    for info in memory.functions().unwrap() {
+        let function = Function::new(info.name.leak(), info.hash);
+        memory
+            .for_each_function_entry(&info.hash, &mut |key, entry| {
+                backend
+                    .insert_batch(&[WriteEntry {
+                        function: &function,
+                        key,
+                        entry,
+                    }])
+                    .unwrap();
+            })
+            .unwrap();
+    }
+    drop(backend);
+
+    let path = store.to_str().unwrap();
+    assert_eq!(ls(&store), ["cube 1", "square 2"]);
+    smart_cache(&["clear", path, "--function", "cube"]);
+    assert_eq!(ls(&store), ["square 2"]);
+
+    let archive = dir.join("store.archive");
+    let archive = archive.to_str().unwrap();
+    let copy = dir.join("copy.redb");
+    let output = smart_cache(&["export", path, archive]);
+    assert_eq!(output.trim(), "exported 2 entries");
+    let output = smart_cache(&["import", copy.to_str().unwrap(), archive]);
+    assert_eq!(output.trim(), "imported 2 entries");
+    assert_eq!(ls(&copy), ["square 2"]);
+
+    let output = smart_cache(&["gc", path]);
+    assert_eq!(output.trim(), "removed 0 of 2 entries");
+    assert_eq!(ls(&store), ["square 2"]);
+    smart_cache(&["clear", path]);
+    assert!(ls(&store).is_empty());
+    smart_cache(&["compact", path]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
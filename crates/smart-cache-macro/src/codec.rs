@@ -0,0 +1,142 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// How a function's keys and values are encoded.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Archived with rkyv, and read in place on hits.
+    #[default]
+    Rkyv,
+    /// `with = "serde"`: serialized through serde, in the format set with
+    /// `Config::serde_format`.
+    Serde,
+    /// `with = "postcard"`: serialized through serde, in postcard's format.
+    Postcard,
+    /// `codec = "borsh"`: serialized with the calling crate's borsh.
+    Borsh,
+    /// `with = "json"`: serialized through serde, as JSON.
+    Json,
+}
+
+impl Codec {
+    /// The `use` items and derives of the key struct, and the attribute of each of its
+    /// borrowed fields.
+    pub fn key_struct(self) -> (TokenStream, TokenStream) {
+        match self {
+            Self::Rkyv => (
+                quote! {
+                    use rkyv::{with::InlineAsBox, Archive, Deserialize, Serialize};
+
+                    #[derive(Archive, Serialize, Deserialize, Debug)]
+                },
+                quote!(#[rkyv(with = InlineAsBox)]),
+            ),
+            Self::Serde | Self::Postcard | Self::Json => (
+                quote! {
+                    #[derive(smart_cache::codec::serde::Serialize, Debug)]
+                    #[serde(crate = "smart_cache::codec::serde")]
+                },
+                TokenStream::new(),
+            ),
+            Self::Borsh => (
+                quote!(#[derive(borsh::BorshSerialize, Debug)]),
+                TokenStream::new(),
+            ),
+        }
+    }
+
+    /// Encodes `value` into bytes, as something that derefs to `[u8]`.
+    pub fn encode(self, value: &TokenStream) -> TokenStream {
+        match self {
+            Self::Rkyv => quote!(rkyv::to_bytes::<rkyv::rancor::Error>(&#value).unwrap()),
+            Self::Serde => quote!(smart_cache::codec::to_bytes(&#value)),
+            Self::Postcard | Self::Json => {
+                let format = self.format();
+                quote!(smart_cache::codec::to_bytes_in(#format, &#value))
+            }
+            Self::Borsh => quote!(borsh::to_vec(&#value).unwrap()),
+        }
+    }
+
+    /// Encodes `value` into a `Vec<u8>`.
+    pub fn encode_vec(self, value: &TokenStream) -> TokenStream {
+        let encoded = self.encode(value);
+        match self {
+            Self::Rkyv => quote!(#encoded.to_vec()),
+            Self::Serde | Self::Postcard | Self::Borsh | Self::Json => encoded,
+        }
+    }
+
+    /// Decodes the `smart_cache::CachedValue` `value` into an `Option<#output>`, `None` if it
+    /// doesn't decode as one.
+    pub fn decode(self, value: &TokenStream, output: &TokenStream) -> TokenStream {
+        match self {
+            Self::Rkyv => quote! {{
+                let value = #value.aligned();
+                rkyv::access::<rkyv::Archived<#output>, rkyv::rancor::Error>(&*value)
+                    .ok()
+                    .map(|value| rkyv::deserialize::<#output, rkyv::rancor::Error>(value).unwrap())
+            }},
+            Self::Serde | Self::Postcard | Self::Json => {
+                self.decode_serde(&quote!(&#value), output)
+            }
+            Self::Borsh => quote!(borsh::from_slice::<#output>(&#value).ok()),
+        }
+    }
+
+    /// Whether the `smart_cache::CachedValue` `value` decodes as an `#output`, without
+    /// decoding it where that's already known.
+    pub fn decodes(self, value: &TokenStream, output: &TokenStream) -> TokenStream {
+        match self {
+            Self::Rkyv => quote!(true),
+            Self::Serde | Self::Postcard | Self::Json => {
+                let decoded = self.decode_serde(value, output);
+                quote!(#decoded.is_some())
+            }
+            Self::Borsh => quote!(borsh::from_slice::<#output>(#value).is_ok()),
+        }
+    }
+
+    /// `smart_cache::type_layout` for `#output`, as stored by this codec.
+    pub fn type_layout(self, output: &TokenStream) -> TokenStream {
+        match self {
+            Self::Rkyv => quote!(smart_cache::type_layout::<#output, rkyv::Archived<#output>>),
+            Self::Serde | Self::Postcard | Self::Borsh | Self::Json => {
+                quote!(smart_cache::type_layout::<#output, #output>)
+            }
+        }
+    }
+
+    /// Whether the bytes `value` hold a valid `#output`.
+    pub fn validate(self, value: &TokenStream, output: &TokenStream) -> TokenStream {
+        match self {
+            Self::Rkyv => quote! {
+                rkyv::access::<rkyv::Archived<#output>, rkyv::rancor::Error>(#value).is_ok()
+            },
+            Self::Serde | Self::Postcard | Self::Json => {
+                let decoded = self.decode_serde(value, output);
+                quote!(#decoded.is_some())
+            }
+            Self::Borsh => quote!(borsh::from_slice::<#output>(#value).is_ok()),
+        }
+    }
+
+    /// Decodes the bytes `bytes` into an `Option<#output>` through serde.
+    fn decode_serde(self, bytes: &TokenStream, output: &TokenStream) -> TokenStream {
+        if self == Self::Serde {
+            quote!(smart_cache::codec::from_bytes::<#output>(#bytes))
+        } else {
+            let format = self.format();
+            quote!(smart_cache::codec::from_bytes_in::<#output>(#format, #bytes))
+        }
+    }
+
+    /// The `smart_cache::codec::Format` of a serde codec with a format of its own.
+    fn format(self) -> TokenStream {
+        if self == Self::Json {
+            quote!(smart_cache::codec::Format::Json)
+        } else {
+            quote!(smart_cache::codec::Format::Postcard)
+        }
+    }
+}
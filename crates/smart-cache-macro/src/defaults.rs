@@ -0,0 +1,103 @@
+//! Crate-wide defaults for `#[cached]` options, declared in the defining crate's manifest:
+//!
+//! ```toml
+//! [package.metadata.smart-cache.defaults]
+//! ttl = "1h"
+//! codec = "rkyv"
+//! per_target = true
+//! ```
+//!
+//! An attribute only sees the item it is on, so defaults can't come from a macro elsewhere in
+//! the crate; the manifest is read at every expansion instead.
+
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{punctuated::Punctuated, Ident, LitBool, LitInt, LitStr, Token};
+
+/// Manifests an expansion already made the crate depend on, in this compiler process.
+static TRACKED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+fn manifest() -> Option<PathBuf> {
+    Some(PathBuf::from(env::var_os("CARGO_MANIFEST_DIR")?).join("Cargo.toml"))
+}
+
+pub fn error(message: impl std::fmt::Display) -> syn::Error {
+    syn::Error::new(
+        Span::call_site(),
+        format!("in [package.metadata.smart-cache.defaults] of Cargo.toml: {message}"),
+    )
+}
+
+/// One default as the `#[cached(...)]` option it stands for.
+fn option(name: &str, value: &toml::Value) -> syn::Result<TokenStream> {
+    let name: Ident =
+        syn::parse_str(name).map_err(|_| error(format!("`{name}` isn't an option")))?;
+    Ok(match value {
+        toml::Value::String(value) => {
+            let value = LitStr::new(value, Span::call_site());
+            quote!(#name = #value)
+        }
+        toml::Value::Integer(value) => {
+            let value = LitInt::new(&value.to_string(), Span::call_site());
+            quote!(#name = #value)
+        }
+        toml::Value::Boolean(value) => {
+            let value = LitBool::new(*value, Span::call_site());
+            quote!(#name = #value)
+        }
+        _ => {
+            return Err(error(format!(
+                "`{name}` must be a string, integer or boolean"
+            )))
+        }
+    })
+}
+
+/// The defaults of the crate being compiled, as `#[cached(...)]` options to parse before the
+/// attribute's own.
+pub fn load() -> syn::Result<TokenStream> {
+    let Some(manifest) = manifest() else {
+        return Ok(TokenStream::new());
+    };
+    let Ok(source) = fs::read_to_string(&manifest) else {
+        return Ok(TokenStream::new());
+    };
+    let manifest: toml::Table = source.parse().map_err(error)?;
+    let defaults = ["package", "metadata", "smart-cache", "defaults"]
+        .into_iter()
+        .try_fold(&manifest, |table, key| table.get(key)?.as_table());
+    let Some(defaults) = defaults else {
+        return Ok(TokenStream::new());
+    };
+
+    let mut options = Punctuated::<TokenStream, Token![,]>::new();
+    for (name, value) in defaults {
+        options.push(option(name, value)?);
+    }
+    Ok(quote!(#options))
+}
+
+/// Makes the crate recompile when its manifest, and so its defaults, change. Only the crate's
+/// first expansion includes the manifest, which is all Cargo needs to watch it.
+pub fn track() -> TokenStream {
+    let manifest = manifest().filter(|manifest| {
+        manifest.is_file()
+            && TRACKED
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(manifest.clone())
+    });
+    match manifest.and_then(|manifest| manifest.to_str().map(str::to_owned)) {
+        Some(manifest) => quote! {
+            const _: &[u8] = ::core::include_bytes!(#manifest);
+        },
+        None => TokenStream::new(),
+    }
+}
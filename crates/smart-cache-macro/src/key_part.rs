@@ -0,0 +1,130 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, Data, DeriveInput, Fields, Ident, Index, Member};
+
+/// `impl CacheKeyPart` writing each field's name and value, in the order of their names, and
+/// an enum's variant name ahead of its fields.
+pub fn derive(mut input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = input.ident.clone();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let members: Vec<_> = keyed_fields(&data.fields)?
+                .into_iter()
+                .map(|(label, member)| (label, quote!(&self.#member)))
+                .collect();
+            write_fields(&members)
+        }
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_name = &variant.ident;
+                    let label = variant_name.to_string();
+                    let fields = keyed_fields(&variant.fields)?;
+                    let bindings: Vec<_> = fields
+                        .iter()
+                        .map(|(label, _)| format_ident!("field_{}", label))
+                        .collect();
+                    let members = fields.iter().map(|(_, member)| member);
+                    let write = write_fields(
+                        &fields
+                            .iter()
+                            .zip(&bindings)
+                            .map(|((label, _), binding)| (label.clone(), quote!(#binding)))
+                            .collect::<Vec<_>>(),
+                    );
+                    Ok(quote! {
+                        Self::#variant_name { #(#members: #bindings,)* .. } => {
+                            smart_cache::CacheKeyPart::write_key(#label, key);
+                            #write
+                        }
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "`CacheKeyPart` can't be derived for unions",
+            ))
+        }
+    };
+
+    let type_params: Vec<Ident> = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    let where_clause = input.generics.make_where_clause();
+    for param in type_params {
+        where_clause
+            .predicates
+            .push(parse_quote!(#param: smart_cache::CacheKeyPart));
+    }
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics smart_cache::CacheKeyPart for #name #type_generics #where_clause {
+            fn write_key(&self, key: &mut ::std::vec::Vec<u8>) {
+                #body
+            }
+        }
+    })
+}
+
+/// The fields that are part of the key, sorted by name (or position, for tuple fields), with
+/// the labels written ahead of their values.
+fn keyed_fields(fields: &Fields) -> syn::Result<Vec<(String, Member)>> {
+    let mut keyed = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        if skipped(field)? {
+            continue;
+        }
+        let member = field
+            .ident
+            .clone()
+            .map_or_else(|| Member::Unnamed(Index::from(index)), Member::Named);
+        let label = field
+            .ident
+            .as_ref()
+            .map_or_else(|| index.to_string(), ToString::to_string);
+        keyed.push((label, member));
+    }
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(keyed)
+}
+
+/// Whether `field` is marked `#[cache_key(skip)]`.
+fn skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cache_key"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                return Ok(());
+            }
+            Err(meta.error("expected `skip`"))
+        })?;
+    }
+    Ok(skip)
+}
+
+fn write_fields(fields: &[(String, TokenStream)]) -> TokenStream {
+    let writes = fields.iter().map(|(label, value)| {
+        quote! {
+            smart_cache::CacheKeyPart::write_key(#label, key);
+            smart_cache::CacheKeyPart::write_key(#value, key);
+        }
+    });
+    quote!(#(#writes)*)
+}
@@ -1,8 +1,18 @@
+mod codec;
+mod defaults;
+mod key_part;
+mod module;
+mod options;
+
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::quote;
 use sha2::{Digest, Sha256};
-use syn::{parse_macro_input, FnArg, Ident, ItemFn, Pat, ReturnType, Type};
+use syn::{
+    parse_macro_input,
+    visit_mut::{self, VisitMut},
+    FnArg, Ident, ItemFn, Pat, ReturnType, Type,
+};
 
 fn hash_token_stream(tokens: &proc_macro2::TokenStream) -> [u8; 32] {
     // Convert TokenStream to a string representation
@@ -42,6 +52,70 @@ fn check_for_mutable_refs(
     Ok(())
 }
 
+/// Removes `#[cache_key]` from the arguments, returning the names of those it marked, which are
+/// keyed by their `CacheKeyPart` encoding instead of their archived value.
+fn take_cache_key_arguments(
+    fn_inputs: &mut syn::punctuated::Punctuated<FnArg, syn::token::Comma>,
+) -> syn::Result<Vec<Ident>> {
+    let mut marked = Vec::new();
+    for arg in fn_inputs {
+        let FnArg::Typed(pat_type) = arg else {
+            continue;
+        };
+        let Some(position) = pat_type
+            .attrs
+            .iter()
+            .position(|attr| attr.path().is_ident("cache_key"))
+        else {
+            continue;
+        };
+        let attr = pat_type.attrs.remove(position);
+        attr.meta.require_path_only()?;
+        let Pat::Ident(pat_ident) = &*pat_type.pat else {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "`#[cache_key]` only applies to arguments bound to a name",
+            ));
+        };
+        marked.push(pat_ident.ident.clone());
+    }
+    Ok(marked)
+}
+
+/// Statements giving each argument an owned copy of its own, for moving into a computation on
+/// another thread, and the expressions passing those copies back to the function. Arguments
+/// passed by value are cloned if `clone` is set, and moved otherwise.
+fn owned_arguments(
+    fn_inputs: &syn::punctuated::Punctuated<FnArg, syn::token::Comma>,
+    clone: bool,
+) -> (Vec<proc_macro2::TokenStream>, Vec<proc_macro2::TokenStream>) {
+    fn_inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(pat_type) => match &*pat_type.pat {
+                Pat::Ident(pat_ident) => Some((&pat_ident.ident, &*pat_type.ty)),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .map(|(name, ty)| {
+            if matches!(ty, Type::Reference(_)) {
+                (
+                    quote!(let #name = ::std::borrow::ToOwned::to_owned(#name);),
+                    quote!(&#name),
+                )
+            } else if clone {
+                (
+                    quote!(let #name = ::std::clone::Clone::clone(&#name);),
+                    quote!(#name),
+                )
+            } else {
+                (quote!(), quote!(#name))
+            }
+        })
+        .unzip()
+}
+
 fn get_param_type(ty: &Type) -> &Type {
     if let Type::Reference(type_ref) = ty {
         &type_ref.elem
@@ -50,9 +124,190 @@ fn get_param_type(ty: &Type) -> &Type {
     }
 }
 
+/// The last segment of `ty`'s path, if it is a path.
+fn last_segment(ty: &Type) -> Option<&syn::PathSegment> {
+    match ty {
+        Type::Path(path) => path.path.segments.last(),
+        _ => None,
+    }
+}
+
+/// The type arguments of `segment`, e.g. `T` of `Box<T>`.
+fn type_arguments(segment: &syn::PathSegment) -> impl Iterator<Item = &syn::GenericArgument> {
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => Some(arguments.args.iter()),
+        _ => None,
+    }
+    .into_iter()
+    .flatten()
+}
+
+/// The `Output` of `Pin<Box<dyn Future<Output = T> + ..>>`.
+fn boxed_future_output(ty: &Type) -> Option<&Type> {
+    let pin = last_segment(ty).filter(|segment| segment.ident == "Pin")?;
+    let boxed = type_arguments(pin).find_map(|argument| match argument {
+        syn::GenericArgument::Type(ty) => last_segment(ty).filter(|segment| segment.ident == "Box"),
+        _ => None,
+    })?;
+    let Some(syn::GenericArgument::Type(Type::TraitObject(future))) = type_arguments(boxed).next()
+    else {
+        return None;
+    };
+    future.bounds.iter().find_map(|bound| {
+        let syn::TypeParamBound::Trait(bound) = bound else {
+            return None;
+        };
+        let segment = bound
+            .path
+            .segments
+            .last()
+            .filter(|segment| segment.ident == "Future")?;
+        type_arguments(segment).find_map(|argument| match argument {
+            syn::GenericArgument::AssocType(output) if output.ident == "Output" => Some(&output.ty),
+            _ => None,
+        })
+    })
+}
+
+/// The body and output of a method desugared by `#[async_trait]`, which expands before the
+/// `#[cached]` on the method it rewrites into
+///
+/// ```text
+/// fn name<'life0, 'async_trait>(&'life0 self, ..) -> Pin<Box<dyn Future<Output = T> + Send + 'async_trait>> {
+///     Box::pin(async move { .. })
+/// }
+/// ```
+fn async_trait_body(input_fn: &ItemFn) -> Option<(syn::ExprAsync, Type)> {
+    if input_fn.sig.asyncness.is_some() {
+        return None;
+    }
+    let ReturnType::Type(_, ty) = &input_fn.sig.output else {
+        return None;
+    };
+    let output = boxed_future_output(ty)?;
+    let [syn::Stmt::Expr(syn::Expr::Call(call), None)] = input_fn.block.stmts.as_slice() else {
+        return None;
+    };
+    let syn::Expr::Path(function) = &*call.func else {
+        return None;
+    };
+    let is_box_pin = function.path.segments.len() >= 2
+        && function
+            .path
+            .segments
+            .iter()
+            .rev()
+            .take(2)
+            .map(|segment| &segment.ident)
+            .eq(["pin", "Box"]);
+    match call.args.iter().collect::<Vec<_>>().as_slice() {
+        [syn::Expr::Async(body)] if is_box_pin && body.capture.is_some() => {
+            Some((body.clone(), output.clone()))
+        }
+        _ => None,
+    }
+}
+
 #[proc_macro_attribute]
-pub fn cached(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn cached(attr: TokenStream, item: TokenStream) -> TokenStream {
     let input_fn = parse_macro_input!(item as ItemFn);
+    expand(options::Options::parse(attr), input_fn)
+}
+
+/// `#[cached]` on every eligible function of a module, re-exported as
+/// `smart_cache::cached_mod`.
+#[proc_macro_attribute]
+pub fn cached_mod(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let module = parse_macro_input!(item as syn::ItemMod);
+    // Options are checked once here rather than at every function they end up on.
+    let expanded = options::Options::parse(attr.clone())
+        .and_then(|_| module::expand(&attr.into(), module.clone()));
+    match expanded {
+        Ok(expanded) => expanded.into(),
+        Err(err) => {
+            let compiler_err = err.to_compile_error();
+            quote! {
+                #module
+
+                #compiler_err
+            }
+            .into()
+        }
+    }
+}
+
+/// `impl CacheKeyPart`, re-exported as `smart_cache::CacheKeyPart`.
+#[proc_macro_derive(CacheKeyPart, attributes(cache_key))]
+pub fn derive_cache_key_part(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    key_part::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// `#[cached]` accepting the attribute names of the `cached` crate, re-exported as
+/// `smart_cache::compat::cached`.
+#[proc_macro_attribute]
+pub fn cached_compat(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input_fn = parse_macro_input!(item as ItemFn);
+    expand(options::Options::parse_compat(attr), input_fn)
+}
+
+/// `#[test]` running the test against a fresh store of its own, re-exported as
+/// `smart_cache::test`.
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+    if !attr.is_empty() {
+        let attr = proc_macro2::TokenStream::from(attr);
+        return syn::Error::new_spanned(attr, "`#[smart_cache::test]` takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    if let Some(asyncness) = &input_fn.sig.asyncness {
+        return syn::Error::new_spanned(
+            asyncness,
+            "`#[smart_cache::test]` can't run async tests; call \
+             `smart_cache::testing::isolated()` inside your runtime's test attribute instead",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let block = &input_fn.block;
+    input_fn.block = syn::parse_quote!({
+        let _isolated = smart_cache::testing::isolated();
+        #block
+    });
+    TokenStream::from(quote! {
+        #[test]
+        #input_fn
+    })
+}
+
+fn expand(options: syn::Result<options::Options>, mut input_fn: ItemFn) -> TokenStream {
+    let cache_key_arguments = take_cache_key_arguments(&mut input_fn.sig.inputs);
+    // `#[async_trait]` methods are cached like `async fn`s, awaiting their boxed body.
+    let async_trait = async_trait_body(&input_fn);
+    let is_async = input_fn.sig.asyncness.is_some() || async_trait.is_some();
+    let (options, cache_key_arguments) = match options.and_then(|options| {
+        options.validate(&input_fn.sig, is_async)?;
+        if async_trait.is_some() {
+            options.validate_async_trait()?;
+        }
+        Ok((options, cache_key_arguments?))
+    }) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            let compiler_err = err.to_compile_error();
+            return quote! {
+                #input_fn
+
+                #compiler_err
+            }
+            .into();
+        }
+    };
 
     // Check for mutable references and return the original function with error if found
     if let Err(err) = check_for_mutable_refs(&input_fn.sig.inputs) {
@@ -66,14 +321,33 @@ pub fn cached(_attr: TokenStream, item: TokenStream) -> TokenStream {
         .into();
     }
 
-    let mut input_fn = input_fn;
-
     let mut fn_with_name_inner = input_fn.clone();
     fn_with_name_inner.sig.ident = Ident::new("inner", Span::call_site());
 
     let fn_with_name_inner_tokens = quote! {
         #fn_with_name_inner
     };
+    // A method's body is a closure instead, so it keeps access to `self`. An `#[async_trait]`
+    // method's is its `async move` block, awaited where the value is computed.
+    let inner_definition = if async_trait.is_some() {
+        quote!()
+    } else if options.self_is_context() {
+        let params = input_fn.sig.inputs.iter().filter_map(|arg| match arg {
+            FnArg::Typed(pat_type) => Some(pat_type),
+            FnArg::Receiver(_) => None,
+        });
+        let output = match &input_fn.sig.output {
+            ReturnType::Default => quote!(()),
+            ReturnType::Type(_, ty) => quote!(#ty),
+        };
+        let block = &input_fn.block;
+        quote! {
+            #[allow(unused_mut)]
+            let mut inner = |#(#params),*| -> #output #block;
+        }
+    } else {
+        fn_with_name_inner_tokens.clone()
+    };
 
     let inner_fn_hash = hash_token_stream(&fn_with_name_inner_tokens);
 
@@ -84,11 +358,21 @@ pub fn cached(_attr: TokenStream, item: TokenStream) -> TokenStream {
         ]
     };
 
-    let fn_inputs = &input_fn.sig.inputs;
-    let fn_output = match &input_fn.sig.output {
-        ReturnType::Default => quote!(()),
-        ReturnType::Type(_, ty) => quote!(#ty),
+    let fn_name = input_fn.sig.ident.to_string();
+    let fn_output = match (&async_trait, &input_fn.sig.output) {
+        (Some((_, output)), _) => quote!(#output),
+        (None, ReturnType::Default) => quote!(()),
+        (None, ReturnType::Type(_, ty)) => quote!(#ty),
     };
+    let function_builder = options.function_builder();
+    let should_store = options.should_store();
+    let negative = options.negative();
+    let (ttl_of, expire) = options.ttl_of(&fn_output);
+    let codec = options.codec();
+    let encode_result = codec.encode(&quote!(result));
+    let encode_result_vec = codec.encode_vec(&quote!(result));
+    let (extra_key_fields, extra_key_values) = options.key_fields();
+    let fn_inputs = &input_fn.sig.inputs;
 
     let param_names: Vec<_> = fn_inputs
         .iter()
@@ -104,53 +388,410 @@ pub fn cached(_attr: TokenStream, item: TokenStream) -> TokenStream {
         })
         .collect();
 
+    // `#[cache_key]` arguments are keyed by their `CacheKeyPart` encoding.
     let param_types: Vec<_> = fn_inputs
         .iter()
         .filter_map(|arg| match arg {
-            FnArg::Typed(pat_type) => Some(get_param_type(&pat_type.ty)),
+            FnArg::Typed(pat_type) => Some(match &*pat_type.pat {
+                Pat::Ident(pat_ident) if cache_key_arguments.contains(&pat_ident.ident) => {
+                    quote!(::std::vec::Vec<u8>)
+                }
+                _ => {
+                    let ty = get_param_type(&pat_type.ty);
+                    quote!(#ty)
+                }
+            }),
             _ => None,
         })
         .collect();
+    let param_values: Vec<_> = param_names
+        .iter()
+        .map(|name| {
+            if cache_key_arguments.contains(name) {
+                quote!(&smart_cache::key_bytes(&#name))
+            } else {
+                quote!(&#name)
+            }
+        })
+        .collect();
 
-    let new_block = quote! {{
-        #fn_with_name_inner
+    // Values due to be recomputed are recomputed on another thread, which needs its own copy of
+    // the arguments.
+    let revalidate = if options.revalidates() && !is_async {
+        let (owned, arguments) = owned_arguments(fn_inputs, true);
+        quote! {
+            if cached_result.should_revalidate() {
+                #(#owned)*
+                smart_cache::revalidate(&FUNCTION, &key_bytes, debug_key, move || {
+                    let result = inner(#(#arguments,)*);
+                    (#should_store).then(|| {
+                        #expire
+                        (#encode_result_vec, #negative)
+                    })
+                });
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    // Misses that found an expired value compute the new one on another thread, which needs its
+    // own copy of the arguments, so they can stop waiting for it. If the value that comes back
+    // doesn't decode, the miss goes on to compute one here.
+    let expired_fallback = if options.times_out() {
+        let decodes = codec.decodes(&quote!(expired), &fn_output);
+        let decode_value = codec.decode(&quote!(value), &fn_output);
+        let (owned, arguments) = owned_arguments(fn_inputs, true);
+        quote! {
+            if let Some(expired) = smart_cache::expired_value(&FUNCTION).filter(|expired| #decodes) {
+                let value = {
+                    #(#owned)*
+                    smart_cache::compute_or_expired(&FUNCTION, &key_bytes, debug_key.clone(), expired, move || {
+                        let result = inner(#(#arguments,)*);
+                        let value = #encode_result_vec;
+                        (value, (#should_store).then(|| {
+                            #expire
+                            #negative
+                        }))
+                    })
+                };
+                if let Some(value) = #decode_value {
+                    return value;
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    // Retrying calls the function again, with its own copy of the arguments each time.
+    let retry = options.retries().then(|| {
+        let arguments: Vec<_> = fn_inputs
+            .iter()
+            .filter_map(|arg| match arg {
+                FnArg::Typed(pat_type) => match &*pat_type.pat {
+                    Pat::Ident(pat_ident) => Some((&pat_ident.ident, &*pat_type.ty)),
+                    _ => None,
+                },
+                FnArg::Receiver(_) => None,
+            })
+            .map(|(name, ty)| {
+                if matches!(ty, Type::Reference(_)) {
+                    quote!(#name)
+                } else {
+                    quote!(::std::clone::Clone::clone(&#name))
+                }
+            })
+            .collect();
+        quote!(|| inner(#(#arguments,)*))
+    });
+
+    // `async fn`s await their body and go through the async store API instead, resolving the
+    // key in the context the call starts in.
+    // Only sync functions collect the dependencies of their values, since an `async fn` may
+    // resume on another thread.
+    let (resolve, lookup, track, call, store) = if is_async {
+        let call = match &async_trait {
+            Some((body, _)) => quote!(#body.await),
+            None => retry.map_or_else(
+                || quote!(inner(#(#param_names,)*).await),
+                |retry| quote!(smart_cache::retry_async(&FUNCTION, #retry).await),
+            ),
+        };
+        (
+            quote!(let async_key = smart_cache::async_key(&FUNCTION, &key_bytes);),
+            quote!(smart_cache::get_cached_async(&FUNCTION, &async_key).await),
+            quote!(let permit = smart_cache::compute_permit_async().await;),
+            call,
+            quote!(
+                smart_cache::set_cached_async(
+                    &FUNCTION,
+                    &async_key,
+                    &value_bytes,
+                    compute_time,
+                    negative,
+                    debug_key.as_deref()
+                )
+                .await
+            ),
+        )
+    } else {
+        (
+            quote!(),
+            quote!(smart_cache::get_cached(&FUNCTION, &*key_bytes)),
+            quote! {
+                let _permit = smart_cache::compute_permit();
+                let _dependencies = smart_cache::track_dependencies(&FUNCTION);
+                let _events = smart_cache::capture_events();
+            },
+            retry.map_or_else(
+                || quote!(inner(#(#param_names,)*)),
+                |retry| quote!(smart_cache::retry(&FUNCTION, #retry)),
+            ),
+            quote!(smart_cache::set_cached(
+                &FUNCTION,
+                &key_bytes,
+                &value_bytes,
+                compute_time,
+                negative,
+                debug_key.as_deref()
+            )),
+        )
+    };
+
+    // The cached calls an `async fn` makes while computing share its permit, on whichever
+    // thread it runs.
+    let compute = if is_async {
+        quote!(smart_cache::with_permit(permit, async { #call }).await)
+    } else {
+        call.clone()
+    };
+
+    // Audits compute the value of a hit anyway, with the arguments the hit left unused, once the
+    // hit is timed so the computation doesn't count towards it. An `#[async_trait]` method's
+    // body can only be awaited once, so those aren't audited.
+    let audit = if async_trait.is_some() {
+        quote!()
+    } else {
+        quote! {
+            if smart_cache::auditing() {
+                let result = #call;
+                let computed = #encode_result;
+                smart_cache::audit(&FUNCTION, &key_bytes, &cached_result, &computed);
+            }
+        }
+    };
 
-        use rkyv::{with::InlineAsBox, Archive, Deserialize, Serialize};
+    let (key_struct, key_field) = codec.key_struct();
+    let encode_key = codec.encode(&quote!(key));
+    let validate_value = codec.validate(&quote!(value), &fn_output);
+    let type_layout = codec.type_layout(&fn_output);
+    let decode_cached_result = codec.decode(&quote!(cached_result), &fn_output);
+    let track_defaults = defaults::track();
 
-        #[derive(Archive, Serialize, Deserialize, Debug)]
+    let new_block = quote! {{
+        #inner_definition
+        #track_defaults
+
+        #key_struct
         struct CacheKey<'a> {
             #(
-                #[rkyv(with = InlineAsBox)]
+                #key_field
                 #param_names: &'a #param_types,
             )*
-            _function_hash: [u8; 32],
+            #extra_key_fields
         }
 
         let key = CacheKey {
-            #(#param_names: &#param_names,)*
-            _function_hash: #inner_fn_hash_literal,
+            #(#param_names: #param_values,)*
+            #extra_key_values
         };
         println!("{key:?}");
-        let key_bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&key).unwrap();
+        let key_bytes = #encode_key;
+        let debug_key = smart_cache::inspectable_keys().then(|| format!("{key:?}"));
+
+        fn validate_cached(value: &[u8]) -> bool {
+            #validate_value
+        }
+
+        fn type_layout() -> u128 {
+            static LAYOUT: ::std::sync::OnceLock<u128> = ::std::sync::OnceLock::new();
+            *LAYOUT.get_or_init(#type_layout)
+        }
+
+        #ttl_of
+
+        static FUNCTION: smart_cache::Function = smart_cache::Function::new(#fn_name, #inner_fn_hash_literal)#function_builder.validator(validate_cached).type_layout(type_layout);
 
-        if let Some(cached_result) = smart_cache::get_cached(&*key_bytes) {
-            let cached_result = &*cached_result;
-            let cached_result: &rkyv::Archived<#fn_output> = rkyv::access::<_, rkyv::rancor::Error>(cached_result).unwrap();
-            let cached_result: #fn_output = rkyv::deserialize::<#fn_output, rkyv::rancor::Error>(cached_result).unwrap();
-            return cached_result;
+        #resolve
+        let lookup_started = smart_cache::clock::Instant::now();
+        if let Some(cached_result) = #lookup {
+            if let Some(decoded_result) = #decode_cached_result {
+                #revalidate
+                smart_cache::record_hit_time(&FUNCTION, lookup_started.elapsed());
+                smart_cache::trace_call(&FUNCTION, &key_bytes, lookup_started, None);
+                #audit
+                return decoded_result;
+            }
         }
 
-        let result = inner(#(#param_names,)*);
+        #expired_fallback
+        #track
+        let started = smart_cache::clock::Instant::now();
+        let result = #compute;
+        let compute_time = started.elapsed();
 
-        let value_bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&result).unwrap();
-        let _ = smart_cache::set_cached(&key_bytes, &value_bytes);
+        if #should_store {
+            #expire
+            let negative = #negative;
+            let value_bytes = #encode_result;
+            let _ = #store;
+        }
+        smart_cache::trace_call(&FUNCTION, &key_bytes, lookup_started, Some(compute_time));
 
         result
     }};
 
-    input_fn.block = syn::parse2(new_block).unwrap();
+    let warm = if options.warms() {
+        warm(&input_fn)
+    } else {
+        quote!()
+    };
+    let prefetch = if options.prefetches() {
+        prefetch(&input_fn)
+    } else {
+        quote!()
+    };
+    input_fn.block = if async_trait.is_some() {
+        syn::parse_quote!({ ::std::boxed::Box::pin(async move #new_block) })
+    } else {
+        syn::parse2(new_block).unwrap()
+    };
 
     TokenStream::from(quote! {
         #input_fn
+        #warm
+        #prefetch
     })
 }
+
+/// `<name>_prefetch`, which takes the function's arguments and returns right away, computing
+/// and storing their value on the prefetch thread if it isn't cached yet.
+fn prefetch(input_fn: &ItemFn) -> proc_macro2::TokenStream {
+    let sig = &input_fn.sig;
+    let name = &sig.ident;
+    let prefetch_name = Ident::new(&format!("{name}_prefetch"), name.span());
+    let vis = &input_fn.vis;
+    let inputs = &sig.inputs;
+    let (impl_generics, _, where_clause) = sig.generics.split_for_impl();
+    let (owned, arguments) = owned_arguments(inputs, false);
+    let call = match sig.output {
+        ReturnType::Default => quote!(#name(#(#arguments,)*);),
+        ReturnType::Type(..) => quote!(let _ = #name(#(#arguments,)*);),
+    };
+    let doc = format!(
+        "Starts computing and caching [`{name}`] for these arguments in the background, so a \
+         later call with them is a hit. Returns right away."
+    );
+
+    quote! {
+        #[doc = #doc]
+        #[allow(dead_code)]
+        #vis fn #prefetch_name #impl_generics(#inputs) #where_clause {
+            #(#owned)*
+            smart_cache::prefetch(move || {
+                #call
+            });
+        }
+    }
+}
+
+/// Names the elided lifetimes of references, and `'_`, after `lifetime`, noting whether there
+/// were any.
+struct NameElided<'a> {
+    lifetime: &'a syn::Lifetime,
+    named: bool,
+}
+
+impl VisitMut for NameElided<'_> {
+    fn visit_type_reference_mut(&mut self, reference: &mut syn::TypeReference) {
+        if reference
+            .lifetime
+            .as_ref()
+            .is_none_or(|lifetime| lifetime.ident == "_")
+        {
+            reference.lifetime = Some(self.lifetime.clone());
+            self.named = true;
+        }
+        visit_mut::visit_type_reference_mut(self, reference);
+    }
+
+    fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+        if lifetime.ident == "_" {
+            *lifetime = self.lifetime.clone();
+            self.named = true;
+        }
+    }
+}
+
+/// `<name>_warm`, which calls the cached function once for each item of an iterator of
+/// arguments, so their values are stored before they are first needed. Items are tuples of the
+/// arguments, or the argument itself for functions taking one. Methods get a method, taking the
+/// same receiver; associated functions without one aren't supported, since nothing tells them
+/// apart from free functions.
+fn warm(input_fn: &ItemFn) -> proc_macro2::TokenStream {
+    let sig = &input_fn.sig;
+    let receiver = sig.receiver().map(|receiver| quote!(#receiver,));
+    let name = &sig.ident;
+    let callee = if receiver.is_some() {
+        quote!(self.#name)
+    } else {
+        quote!(#name)
+    };
+    let warm_name = Ident::new(&format!("{name}_warm"), name.span());
+    let vis = &input_fn.vis;
+    // Argument types borrow for as long as the iterator's items, which need a lifetime of
+    // their own to be named in `impl IntoIterator`.
+    let lifetime = syn::Lifetime::new("'warm", Span::call_site());
+    let mut elided = NameElided {
+        lifetime: &lifetime,
+        named: false,
+    };
+    let types: Vec<_> = sig
+        .inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(pat_type) => {
+                let mut ty = (*pat_type.ty).clone();
+                elided.visit_type_mut(&mut ty);
+                Some(ty)
+            }
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+    let mut generics = sig.generics.clone();
+    if elided.named {
+        generics.params.insert(
+            0,
+            syn::GenericParam::Lifetime(syn::LifetimeParam::new(lifetime.clone())),
+        );
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let asyncness = &sig.asyncness;
+    let await_call = sig.asyncness.map(|_| quote!(.await));
+    let (item, pattern, arguments) = match types.as_slice() {
+        [] => (quote!(()), quote!(()), quote!()),
+        [ty] => (quote!(#ty), quote!(args), quote!(args)),
+        _ => {
+            let indices = (0..types.len()).map(syn::Index::from);
+            (
+                quote!((#(#types,)*)),
+                quote!(args),
+                quote!(#(args.#indices,)*),
+            )
+        }
+    };
+    // Values are only computed for their side effect on the store, and may be `#[must_use]`.
+    let call = match sig.output {
+        ReturnType::Default => quote!(#callee(#arguments)#await_call;),
+        ReturnType::Type(..) => quote!(let _ = #callee(#arguments)#await_call;),
+    };
+    let path = if receiver.is_some() { "Self::" } else { "" };
+    let doc = format!(
+        "Computes and caches [`{path}{name}`] for each of `args`, so later calls with them are \
+         hits."
+    );
+
+    quote! {
+        #[doc = #doc]
+        #[allow(dead_code)]
+        #vis #asyncness fn #warm_name #impl_generics(
+            #receiver
+            args: impl ::std::iter::IntoIterator<Item = #item>,
+        ) #where_clause {
+            for #pattern in args {
+                #call
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{punctuated::Punctuated, Attribute, FnArg, Item, ItemFn, Meta, ReturnType, Token, Type};
+
+/// Whether `attr` is `#[cached]` or `#[smart_cache::cached]`, with or without arguments.
+fn is_cached(attr: &Attribute) -> bool {
+    let path = attr.path();
+    path.is_ident("cached")
+        || path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .eq(["smart_cache", "cached"])
+}
+
+fn is_test(attr: &Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "test")
+}
+
+/// Whether `#[cached]` would take `function` as is: it returns something, runs at runtime, and
+/// doesn't mutate its arguments.
+fn eligible(function: &ItemFn) -> bool {
+    let sig = &function.sig;
+    let mutates = sig.inputs.iter().any(|arg| match arg {
+        FnArg::Receiver(_) => true,
+        FnArg::Typed(arg) => {
+            matches!(&*arg.ty, Type::Reference(reference) if reference.mutability.is_some())
+        }
+    });
+    !matches!(sig.output, ReturnType::Default)
+        && sig.constness.is_none()
+        && sig.abi.is_none()
+        && !mutates
+        && !function.attrs.iter().any(is_test)
+}
+
+/// Adds `#[cached(defaults)]` to `function`, or merges `defaults` into the `#[cached(...)]` it
+/// already has, its own options taking precedence. `#[cached(skip)]` leaves it uncached.
+fn annotate(defaults: &TokenStream, function: &mut ItemFn) -> syn::Result<()> {
+    let Some(index) = function.attrs.iter().position(is_cached) else {
+        if eligible(function) {
+            function
+                .attrs
+                .push(syn::parse_quote!(#[smart_cache::cached(#defaults)]));
+        }
+        return Ok(());
+    };
+
+    let own = match &function.attrs[index].meta {
+        Meta::Path(_) => TokenStream::new(),
+        Meta::List(list) => list.tokens.clone(),
+        Meta::NameValue(meta) => {
+            return Err(syn::Error::new_spanned(meta, "expected `#[cached(...)]`"));
+        }
+    };
+    if own.to_string() == "skip" {
+        function.attrs.remove(index);
+        return Ok(());
+    }
+    let options: Punctuated<TokenStream, Token![,]> = [defaults, &own]
+        .into_iter()
+        .filter(|options| !options.is_empty())
+        .cloned()
+        .collect();
+    let path = function.attrs[index].path().clone();
+    function.attrs[index] = syn::parse_quote!(#[#path(#options)]);
+    Ok(())
+}
+
+/// `#[cached_mod(defaults)]`: `#[cached(defaults)]` on every eligible function directly inside
+/// the module.
+pub fn expand(defaults: &TokenStream, mut module: syn::ItemMod) -> syn::Result<TokenStream> {
+    let Some((_, items)) = &mut module.content else {
+        return Err(syn::Error::new_spanned(
+            &module,
+            "`#[cached_mod]` needs the module's contents inline (`mod name { ... }`)",
+        ));
+    };
+    for item in items {
+        if let Item::Fn(function) = item {
+            annotate(defaults, function)?;
+        }
+    }
+    Ok(quote!(#module))
+}
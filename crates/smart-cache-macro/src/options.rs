@@ -0,0 +1,623 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{meta::ParseNestedMeta, Ident, LitBool, LitInt, LitStr};
+
+use crate::codec::Codec;
+
+/// Which results are worth storing.
+#[derive(Default, Clone, Copy)]
+enum Store {
+    #[default]
+    All,
+    /// `result = true`: only `Ok` values.
+    Ok,
+    /// `option = true`: only `Some` values.
+    Some,
+}
+
+/// Arguments accepted by `#[cached(...)]`.
+#[derive(Default)]
+pub struct Options {
+    /// `db = "name"`: store entries in a separate, named database.
+    db: Option<LitStr>,
+    /// `ttl = "10m"`, or `time = seconds` in compat mode: let entries expire.
+    ttl: Option<TokenStream>,
+    /// `negative_ttl = "30s"`: let negative results (`None`, `Err`) expire sooner.
+    negative_ttl: Option<TokenStream>,
+    /// `ttl_fn = "|result| result.valid_for"`: let each entry expire after the `Duration`
+    /// computed from its value, instead of `ttl` or `negative_ttl`.
+    ttl_fn: Option<syn::Expr>,
+    /// `stale_for = "1h"`: serve expired entries for this long while recomputing them.
+    stale_for: Option<TokenStream>,
+    /// `refresh_ahead`: recompute values in the background once they are close to expiring, as
+    /// set by `Config::refresh_ahead`.
+    refresh_ahead: Option<Ident>,
+    store: Store,
+    /// `per_target`: mix the target triple into the key.
+    per_target: bool,
+    /// `share_across_crates`: leave the defining crate's name and version out of the key, so
+    /// the same function body in another crate or version finds the same entries.
+    share_across_crates: bool,
+    /// `max_size = bytes`: treat larger values as oversize.
+    max_size: Option<LitInt>,
+    /// `oversize = "skip" | "spill" | "compress" | "error"`: what to do with oversize values.
+    oversize: Option<Ident>,
+    /// `retry = times`: compute negative results (`None`, `Err`) again before returning them.
+    retry: Option<LitInt>,
+    /// `backoff = "constant" | "linear" | "exponential"`: how the delay between retries grows.
+    backoff: Option<Ident>,
+    /// `retry_delay = "100ms"`: the delay before the first retry.
+    retry_delay: Option<TokenStream>,
+    /// `timeout = "30s"`: serve the expired value if recomputing it takes longer than this.
+    timeout: Option<TokenStream>,
+    /// `on_timeout = "stale"`: what to do once `timeout` passes; only serving the expired value
+    /// is supported, and is the default.
+    on_timeout: Option<LitStr>,
+    /// `prefetch`: generate `<name>_prefetch`, which computes values in the background.
+    prefetch: Option<Ident>,
+    /// `warm`: generate `<name>_warm`, which computes values for a list of arguments.
+    warm: Option<Ident>,
+    /// `self_is_context`: cache a method, leaving its receiver out of the key and allowing it to
+    /// be `&mut self`.
+    self_is_context: Option<Ident>,
+    /// `codec = "rkyv" | "serde" | "postcard" | "json" | "borsh"`, or `with = ...`: how keys
+    /// and values are encoded.
+    codec: Codec,
+}
+
+impl Options {
+    /// Parses `#[cached(...)]`'s attributes over the crate's defaults (see `defaults`).
+    pub fn parse(attr: proc_macro::TokenStream) -> syn::Result<Self> {
+        let mut options = Self::default();
+        let defaults = syn::meta::parser(|meta| options.parse_meta(&meta));
+        syn::parse::Parser::parse2(defaults, crate::defaults::load()?)
+            .map_err(crate::defaults::error)?;
+        let parser = syn::meta::parser(|meta| options.parse_meta(&meta));
+        syn::parse::Parser::parse(parser, attr)?;
+        Ok(options)
+    }
+
+    /// Parses the attributes of the `cached` crate's `#[cached]`.
+    pub fn parse_compat(attr: proc_macro::TokenStream) -> syn::Result<Self> {
+        let mut options = Self::default();
+        let parser = syn::meta::parser(|meta| options.parse_compat_meta(&meta));
+        syn::parse::Parser::parse(parser, attr)?;
+        Ok(options)
+    }
+
+    fn parse_meta(&mut self, meta: &ParseNestedMeta<'_>) -> syn::Result<()> {
+        if meta.path.is_ident("skip") {
+            return Err(meta.error("`skip` only applies to functions in a `#[cached_mod]` module"));
+        }
+        if meta.path.is_ident("db") {
+            self.db = Some(meta.value()?.parse()?);
+            return Ok(());
+        }
+        if meta.path.is_ident("per_target") {
+            self.per_target = flag(meta)?;
+            return Ok(());
+        }
+        if meta.path.is_ident("share_across_crates") {
+            self.share_across_crates = flag(meta)?;
+            return Ok(());
+        }
+        if meta.path.is_ident("ttl") {
+            self.ttl = Some(duration(&meta.value()?.parse()?)?);
+            return Ok(());
+        }
+        if meta.path.is_ident("negative_ttl") {
+            self.negative_ttl = Some(duration(&meta.value()?.parse()?)?);
+            return Ok(());
+        }
+        if meta.path.is_ident("ttl_fn") {
+            let ttl_fn: LitStr = meta.value()?.parse()?;
+            self.ttl_fn = Some(ttl_fn.parse()?);
+            return Ok(());
+        }
+        if meta.path.is_ident("stale_for") {
+            self.stale_for = Some(duration(&meta.value()?.parse()?)?);
+            return Ok(());
+        }
+        if meta.path.is_ident("max_size") {
+            self.max_size = Some(meta.value()?.parse()?);
+            return Ok(());
+        }
+        if meta.path.is_ident("oversize") {
+            let policy: LitStr = meta.value()?.parse()?;
+            let variant = match policy.value().as_str() {
+                "skip" => "Skip",
+                "spill" => "Spill",
+                "compress" => "Compress",
+                "error" => "Error",
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        policy,
+                        "expected one of \"skip\", \"spill\", \"compress\" or \"error\"",
+                    ))
+                }
+            };
+            self.oversize = Some(Ident::new(variant, policy.span()));
+            return Ok(());
+        }
+        if meta.path.is_ident("retry") {
+            self.retry = Some(meta.value()?.parse()?);
+            return Ok(());
+        }
+        if meta.path.is_ident("backoff") {
+            let backoff: LitStr = meta.value()?.parse()?;
+            let variant = match backoff.value().as_str() {
+                "constant" => "Constant",
+                "linear" => "Linear",
+                "exponential" => "Exponential",
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        backoff,
+                        "expected one of \"constant\", \"linear\" or \"exponential\"",
+                    ))
+                }
+            };
+            self.backoff = Some(Ident::new(variant, backoff.span()));
+            return Ok(());
+        }
+        if meta.path.is_ident("timeout") {
+            self.timeout = Some(duration(&meta.value()?.parse()?)?);
+            return Ok(());
+        }
+        if meta.path.is_ident("on_timeout") {
+            let policy: LitStr = meta.value()?.parse()?;
+            if policy.value() != "stale" {
+                return Err(syn::Error::new_spanned(policy, "expected \"stale\""));
+            }
+            self.on_timeout = Some(policy);
+            return Ok(());
+        }
+        if meta.path.is_ident("refresh_ahead") {
+            self.refresh_ahead = flag_ident(meta)?;
+            return Ok(());
+        }
+        if meta.path.is_ident("prefetch") {
+            self.prefetch = flag_ident(meta)?;
+            return Ok(());
+        }
+        if meta.path.is_ident("warm") {
+            self.warm = flag_ident(meta)?;
+            return Ok(());
+        }
+        if meta.path.is_ident("self_is_context") {
+            self.self_is_context = flag_ident(meta)?;
+            return Ok(());
+        }
+        if meta.path.is_ident("codec") || meta.path.is_ident("with") {
+            let codec: LitStr = meta.value()?.parse()?;
+            self.codec =
+                match codec.value().as_str() {
+                    "rkyv" => Codec::Rkyv,
+                    "serde" => Codec::Serde,
+                    "postcard" => Codec::Postcard,
+                    "json" if cfg!(feature = "json") => Codec::Json,
+                    "json" => {
+                        return Err(syn::Error::new_spanned(
+                            codec,
+                            "`codec = \"json\"` needs smart-cache's `json` feature",
+                        ))
+                    }
+                    "borsh" if cfg!(feature = "borsh") => Codec::Borsh,
+                    "borsh" => {
+                        return Err(syn::Error::new_spanned(
+                            codec,
+                            "`codec = \"borsh\"` needs smart-cache's `borsh` feature",
+                        ))
+                    }
+                    _ => return Err(syn::Error::new_spanned(
+                        codec,
+                        "expected one of \"rkyv\", \"serde\", \"postcard\", \"json\" or \"borsh\"",
+                    )),
+                };
+            return Ok(());
+        }
+        if meta.path.is_ident("retry_delay") {
+            self.retry_delay = Some(duration(&meta.value()?.parse()?)?);
+            return Ok(());
+        }
+
+        Err(meta.error("unsupported cached option"))
+    }
+
+    fn parse_compat_meta(&mut self, meta: &ParseNestedMeta<'_>) -> syn::Result<()> {
+        let Some(name) = meta.path.get_ident().map(ToString::to_string) else {
+            return Err(meta.error("unsupported cached option"));
+        };
+
+        match name.as_str() {
+            "db" => self.db = Some(meta.value()?.parse()?),
+            "time" => {
+                let time: LitInt = meta.value()?.parse()?;
+                self.ttl = Some(quote!(::std::time::Duration::from_secs(#time)));
+            }
+            "result" | "option" => {
+                let only = if name == "result" {
+                    Store::Ok
+                } else {
+                    Store::Some
+                };
+                if meta.value()?.parse::<LitBool>()?.value {
+                    self.store = only;
+                }
+            }
+            // The persistent store is never bounded, so this asks for what happens anyway.
+            "unbound" => {}
+            "sync_writes" => unless_set(meta, "concurrent misses each compute the value")?,
+            "time_refresh" => unless_set(meta, "hits don't extend how long a value lives")?,
+            "size" | "name" => {
+                return Err(meta.error(format!(
+                    "`{name}` is not supported; entries live in the persistent store, which is \
+                     neither bounded in memory nor reachable through a named static"
+                )));
+            }
+            "key" | "convert" | "ty" | "create" => {
+                return Err(meta.error(format!(
+                    "`{name}` is not supported; smart-cache keys entries by all of the \
+                     function's arguments"
+                )));
+            }
+            _ => return Err(meta.error("unsupported cached option")),
+        }
+        Ok(())
+    }
+
+    /// Whether values may be recomputed in the background, when stale (`stale_for`) or close
+    /// to expiring (`refresh_ahead`), which copies the arguments to another thread. Methods
+    /// can't be, having no `self` there.
+    pub const fn revalidates(&self) -> bool {
+        (self.stale_for.is_some() || self.refresh_ahead.is_some()) && self.self_is_context.is_none()
+    }
+
+    /// Whether the function is a method whose receiver is left out of the key.
+    pub const fn self_is_context(&self) -> bool {
+        self.self_is_context.is_some()
+    }
+
+    /// Definition of `ttl_of`, which computes a value's TTL with `ttl_fn`, and the call giving
+    /// the computed `result` that TTL when it is stored; both empty without `ttl_fn`.
+    pub fn ttl_of(&self, output: &TokenStream) -> (TokenStream, TokenStream) {
+        let Some(ttl_fn) = &self.ttl_fn else {
+            return (TokenStream::new(), TokenStream::new());
+        };
+        (
+            quote!(let ttl_of: fn(&#output) -> ::std::time::Duration = #ttl_fn;),
+            quote!(smart_cache::expire_after(ttl_of(&result));),
+        )
+    }
+
+    /// Whether negative results are computed again before being returned, which calls the
+    /// function more than once with the same arguments.
+    pub const fn retries(&self) -> bool {
+        self.retry.is_some()
+    }
+
+    /// Whether misses that find an expired value compute the new one with a timeout.
+    pub const fn times_out(&self) -> bool {
+        self.timeout.is_some()
+    }
+
+    /// How keys and values are encoded.
+    pub const fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Whether to generate `<name>_prefetch`.
+    pub const fn prefetches(&self) -> bool {
+        self.prefetch.is_some()
+    }
+
+    /// Whether to generate `<name>_warm`.
+    pub const fn warms(&self) -> bool {
+        self.warm.is_some()
+    }
+
+    /// Checks the options make sense together, and for the function with signature `sig`, which
+    /// is an `async fn` or desugared from one if `is_async`.
+    pub fn validate(&self, sig: &syn::Signature, is_async: bool) -> syn::Result<()> {
+        self.validate_receiver(sig)?;
+        if self.retry.is_none() {
+            let backoff = self.backoff.as_ref().map(ToTokens::to_token_stream);
+            if let Some(option) = backoff.as_ref().or(self.retry_delay.as_ref()) {
+                return Err(syn::Error::new_spanned(
+                    option,
+                    "`backoff` and `retry_delay` require `retry`",
+                ));
+            }
+        }
+        if let (Some(on_timeout), None) = (&self.on_timeout, &self.timeout) {
+            return Err(syn::Error::new_spanned(
+                on_timeout,
+                "`on_timeout` requires `timeout`",
+            ));
+        }
+        if let Some(timeout) = &self.timeout {
+            if self.ttl.is_none() {
+                return Err(syn::Error::new_spanned(
+                    timeout,
+                    "`timeout` requires a `ttl`, since only expired values are served on timeout",
+                ));
+            }
+            if is_async {
+                return Err(syn::Error::new_spanned(
+                    timeout,
+                    "`timeout` is not supported on `async fn`s, which can't be computed in the \
+                     background without an executor",
+                ));
+            }
+        }
+        if let Some(prefetch) = self.prefetch.as_ref().filter(|_| is_async) {
+            return Err(syn::Error::new_spanned(
+                prefetch,
+                "`prefetch` is not supported on `async fn`s, which can't be computed in the \
+                 background without an executor",
+            ));
+        }
+        if let Some(refresh_ahead) = &self.refresh_ahead {
+            if self.ttl.is_none() {
+                return Err(syn::Error::new_spanned(
+                    refresh_ahead,
+                    "`refresh_ahead` requires a `ttl`",
+                ));
+            }
+            if is_async {
+                return Err(syn::Error::new_spanned(
+                    refresh_ahead,
+                    "`refresh_ahead` is not supported on `async fn`s, which can't be recomputed \
+                     in the background without an executor",
+                ));
+            }
+        }
+        let Some(stale_for) = &self.stale_for else {
+            return Ok(());
+        };
+        if self.ttl.is_none() {
+            return Err(syn::Error::new_spanned(
+                stale_for,
+                "`stale_for` requires a `ttl`",
+            ));
+        }
+        if is_async {
+            return Err(syn::Error::new_spanned(
+                stale_for,
+                "`stale_for` is not supported on `async fn`s, which can't be recomputed in the \
+                 background without an executor",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the options can be applied to a method desugared by `#[async_trait]`, whose body
+    /// is a future that can only be awaited once.
+    pub fn validate_async_trait(&self) -> syn::Result<()> {
+        if let Some(warm) = &self.warm {
+            return Err(syn::Error::new_spanned(
+                warm,
+                "`warm` is not supported on `#[async_trait]` methods, since trait impls can't \
+                 have methods of their own",
+            ));
+        }
+        match &self.retry {
+            Some(retry) => Err(syn::Error::new_spanned(
+                retry,
+                "`retry` is not supported on `#[async_trait]` methods, whose body can only run \
+                 once per call",
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks methods opt into `self_is_context`, and that it is only used where the receiver
+    /// can stay on the calling thread.
+    fn validate_receiver(&self, sig: &syn::Signature) -> syn::Result<()> {
+        let Some(self_is_context) = &self.self_is_context else {
+            return match sig.receiver() {
+                Some(receiver) => Err(syn::Error::new_spanned(
+                    receiver,
+                    "cached methods need `#[cached(self_is_context)]`, which leaves `self` out \
+                     of the key",
+                )),
+                None => Ok(()),
+            };
+        };
+        if sig.receiver().is_none() {
+            return Err(syn::Error::new_spanned(
+                self_is_context,
+                "`self_is_context` is for methods taking `self`",
+            ));
+        }
+        if let Some(asyncness) = &sig.asyncness {
+            return Err(syn::Error::new_spanned(
+                asyncness,
+                "`self_is_context` is not supported on `async fn`s",
+            ));
+        }
+        let by_value = sig
+            .receiver()
+            .filter(|receiver| !matches!(*receiver.ty, syn::Type::Reference(_)));
+        if let (Some(warm), Some(_)) = (&self.warm, by_value) {
+            return Err(syn::Error::new_spanned(
+                warm,
+                "`warm` calls the method once per item, so it needs a `&self` or `&mut self` \
+                 receiver",
+            ));
+        }
+        let background = self
+            .timeout
+            .as_ref()
+            .or(self.stale_for.as_ref())
+            .cloned()
+            .or_else(|| self.refresh_ahead.as_ref().map(ToTokens::to_token_stream))
+            .or_else(|| self.prefetch.as_ref().map(ToTokens::to_token_stream));
+        if let Some(option) = background {
+            return Err(syn::Error::new_spanned(
+                option,
+                "`timeout`, `stale_for`, `refresh_ahead` and `prefetch` compute values on another \
+                 thread, where `self` isn't available, so they can't be combined with \
+                 `self_is_context`",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builder calls applied to the `smart_cache::Function` descriptor.
+    pub fn function_builder(&self) -> TokenStream {
+        let db = self.db.as_ref().map(|db| quote!(.db(#db)));
+        let ttl = self.ttl.as_ref().map(|ttl| quote!(.ttl(#ttl)));
+        let negative_ttl = self
+            .negative_ttl
+            .as_ref()
+            .map(|ttl| quote!(.negative_ttl(#ttl)));
+        let stale_for = self
+            .stale_for
+            .as_ref()
+            .map(|stale_for| quote!(.stale_for(#stale_for)));
+        let refresh_ahead = self
+            .refresh_ahead
+            .as_ref()
+            .map(|_| quote!(.refresh_ahead()));
+        let timeout = self
+            .timeout
+            .as_ref()
+            .map(|timeout| quote!(.timeout(#timeout)));
+        let max_size = self
+            .max_size
+            .as_ref()
+            .map(|bytes| quote!(.max_size(#bytes)));
+        let oversize = self
+            .oversize
+            .as_ref()
+            .map(|policy| quote!(.oversize(smart_cache::Oversize::#policy)));
+        let retry = self.retry.as_ref().map(|retries| {
+            let backoff = self
+                .backoff
+                .clone()
+                .unwrap_or_else(|| Ident::new("Constant", retries.span()));
+            let delay = self
+                .retry_delay
+                .clone()
+                .unwrap_or_else(|| quote!(::std::time::Duration::from_millis(100)));
+            quote!(.retry(smart_cache::Retry::new(#retries, smart_cache::Backoff::#backoff, #delay)))
+        });
+        quote! {
+            .package(concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION")))
+            .module(module_path!())
+            .for_tests(cfg!(test))
+            .debug_assertions(cfg!(debug_assertions))
+            #db
+            #ttl
+            #negative_ttl
+            #stale_for
+            #refresh_ahead
+            #timeout
+            #max_size
+            #oversize
+            #retry
+        }
+    }
+
+    /// Extra fields of the key struct, with their values: the name and version of the crate
+    /// defining the function, so programs sharing a store never collide on identical function
+    /// bodies, and the target triple with `per_target`.
+    pub fn key_fields(&self) -> (TokenStream, TokenStream) {
+        let (_, key_field) = self.codec.key_struct();
+        let mut fields = TokenStream::new();
+        let mut values = TokenStream::new();
+        if !self.share_across_crates {
+            fields.extend(quote!(#key_field _package: &'a str,));
+            values.extend(quote!(
+                _package: concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION")),
+            ));
+        }
+        if self.per_target {
+            fields.extend(quote!(#key_field _target: &'a str,));
+            values.extend(quote!(_target: smart_cache::TARGET,));
+        }
+        (fields, values)
+    }
+
+    /// Condition on the computed `result` for storing it. Negative results are stored when
+    /// they have a TTL of their own, and otherwise not when they were retried.
+    pub fn should_store(&self) -> TokenStream {
+        let negative = self.negative();
+        let store = match self.store {
+            Store::All => quote!(true),
+            Store::Ok => quote!(result.is_ok()),
+            Store::Some => quote!(result.is_some()),
+        };
+        if self.negative_ttl.is_some() {
+            quote!(#store || #negative)
+        } else if self.retry.is_some() {
+            quote!(#store && !smart_cache::Negative::is_negative(&result))
+        } else {
+            store
+        }
+    }
+
+    /// Whether the computed `result` is stored with the negative TTL.
+    pub fn negative(&self) -> TokenStream {
+        if self.negative_ttl.is_some() {
+            quote!(smart_cache::Negative::is_negative(&result))
+        } else {
+            quote!(false)
+        }
+    }
+}
+
+/// Whether a flag is set: by its name alone or as `name = true`, or unset with `name = false`,
+/// say over a crate-wide default.
+fn flag(meta: &ParseNestedMeta<'_>) -> syn::Result<bool> {
+    if meta.input.peek(syn::Token![=]) {
+        Ok(meta.value()?.parse::<LitBool>()?.value)
+    } else {
+        Ok(true)
+    }
+}
+
+/// The name of a flag, to point errors at, if it is set (see [`flag`]).
+fn flag_ident(meta: &ParseNestedMeta<'_>) -> syn::Result<Option<Ident>> {
+    let set = flag(meta)?;
+    Ok(meta.path.get_ident().filter(|_| set).cloned())
+}
+
+/// Parses a duration written as a number followed by `ms`, `s`, `m`, `h` or `d`, such as
+/// `"10m"`, into a `Duration` expression.
+fn duration(literal: &LitStr) -> syn::Result<TokenStream> {
+    let text = literal.value();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let millis_per_unit: u64 = match unit.trim() {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                literal,
+                "expected a duration such as \"500ms\", \"30s\", \"10m\", \"1h\" or \"7d\"",
+            ))
+        }
+    };
+    let millis = amount
+        .parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(millis_per_unit))
+        .ok_or_else(|| syn::Error::new_spanned(literal, "invalid duration"))?;
+    Ok(quote!(::std::time::Duration::from_millis(#millis)))
+}
+
+/// Accepts a compat flag set to `false`, which asks for what smart-cache does anyway, and
+/// rejects it set to `true`, saying why with `reason`.
+fn unless_set(meta: &ParseNestedMeta<'_>, reason: &str) -> syn::Result<()> {
+    if meta.value()?.parse::<LitBool>()?.value {
+        let name = meta.path.to_token_stream();
+        return Err(meta.error(format!("`{name}` is not supported; {reason}")));
+    }
+    Ok(())
+}
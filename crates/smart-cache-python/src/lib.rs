@@ -0,0 +1,169 @@
+//! The `smart_cache` Python module: reads and writes a smart-cache store, so orchestration
+//! scripts can warm, inspect and purge the cache Rust workers use.
+//!
+//! ```python
+//! import smart_cache
+//!
+//! smart_cache.open("/var/cache/workers/cache.redb")
+//! smart_cache.set("render", b"page-1", b"<html>...</html>")
+//! assert smart_cache.get("render", b"page-1") is not None
+//! for stats in smart_cache.stats():
+//!     print(stats.name, stats.hits, stats.misses)
+//! smart_cache.clear("render")
+//! ```
+//!
+//! As with the C ABI, functions are identified by name and, optionally, the 32-byte hash their
+//! entries were stored under; without a hash, the SHA-256 of the name is used. Keys and values
+//! are opaque bytes. Build the module with `maturin develop` or `maturin build` from this
+//! directory.
+
+use std::{path::PathBuf, time::Duration};
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use sha2::{Digest, Sha256};
+use smart_cache::{Config, Function, FunctionHash};
+
+/// Name of the store [`open`] points at a file.
+const STORE: &str = "python";
+
+fn function(name: &str, hash: Option<&[u8]>) -> PyResult<&'static Function> {
+    let hash: FunctionHash = match hash {
+        Some(hash) => hash
+            .try_into()
+            .map_err(|_| PyValueError::new_err("function hashes are 32 bytes"))?,
+        None => Sha256::digest(name.as_bytes()).into(),
+    };
+    Ok(smart_cache::intern(name, hash))
+}
+
+/// Statistics of one function, as `smart_cache.stats()` returns them.
+#[pyclass(frozen, get_all, module = "smart_cache")]
+struct FunctionStats {
+    name: String,
+    /// The function's hash, in hex.
+    hash: String,
+    calls: u64,
+    hits: u64,
+    compute_ns: u64,
+    saved_ns: u64,
+    hit_ns: u64,
+    bytes_written: u64,
+}
+
+#[pymethods]
+impl FunctionStats {
+    #[getter]
+    fn misses(&self) -> u64 {
+        self.calls.saturating_sub(self.hits)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "FunctionStats(name={:?}, calls={}, hits={})",
+            self.name, self.calls, self.hits
+        )
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Uses the redb file at `path` (such as a Rust crate's store) for every call. Must be called
+/// before any other function here.
+#[pyfunction]
+fn open(path: PathBuf) -> PyResult<()> {
+    Config::default()
+        .store_name(STORE)
+        .database(STORE, &path)
+        .install()
+        .map_err(|e| PyRuntimeError::new_err(format!("failed to open {}: {e:#}", path.display())))
+}
+
+/// Returns the value stored under `key` for `function`, or `None`.
+#[pyfunction]
+#[pyo3(signature = (function, key, hash = None))]
+fn get<'py>(
+    py: Python<'py>,
+    function: &str,
+    key: &[u8],
+    hash: Option<&[u8]>,
+) -> PyResult<Option<Bound<'py, PyBytes>>> {
+    let function = self::function(function, hash)?;
+    let cached =
+        py.allow_threads(|| smart_cache::get_cached(function, key).map(|value| value.to_vec()));
+    Ok(cached.map(|value| PyBytes::new(py, &value)))
+}
+
+/// Stores `value` under `key` for `function`.
+#[pyfunction]
+#[pyo3(signature = (function, key, value, hash = None))]
+fn set(
+    py: Python<'_>,
+    function: &str,
+    key: &[u8],
+    value: &[u8],
+    hash: Option<&[u8]>,
+) -> PyResult<()> {
+    let function = self::function(function, hash)?;
+    py.allow_threads(|| smart_cache::set_cached(function, key, value, Duration::ZERO, false, None))
+        .map_err(|e| {
+            let name = function.name();
+            PyRuntimeError::new_err(format!("failed to write the cache for {name}: {e:#}"))
+        })
+}
+
+/// Removes every entry of `function`, returning the number of function versions cleared.
+#[pyfunction]
+fn clear(py: Python<'_>, function: &str) -> PyResult<usize> {
+    py.allow_threads(|| smart_cache::clear_function(function))
+        .map_err(|e| PyRuntimeError::new_err(format!("failed to clear {function}: {e:#}")))
+}
+
+/// Returns the statistics of every function in the store.
+#[pyfunction]
+fn stats(py: Python<'_>) -> PyResult<Vec<FunctionStats>> {
+    let stats = py
+        .allow_threads(smart_cache::stats)
+        .map_err(|e| PyRuntimeError::new_err(format!("failed to read cache statistics: {e:#}")))?;
+    Ok(stats
+        .into_iter()
+        .map(|stats| FunctionStats {
+            hash: stats
+                .hash
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+            name: stats.name,
+            calls: stats.calls,
+            hits: stats.hits,
+            compute_ns: nanos(stats.compute_time),
+            saved_ns: nanos(stats.time_saved),
+            hit_ns: nanos(stats.hit_time),
+            bytes_written: stats.bytes_written,
+        })
+        .collect())
+}
+
+/// Waits for pending writes to reach the store.
+#[pyfunction]
+fn flush(py: Python<'_>) {
+    py.allow_threads(smart_cache::flush);
+}
+
+#[pymodule]
+#[pyo3(name = "smart_cache")]
+fn smart_cache_python(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<FunctionStats>()?;
+    module.add_function(wrap_pyfunction!(open, module)?)?;
+    module.add_function(wrap_pyfunction!(get, module)?)?;
+    module.add_function(wrap_pyfunction!(set, module)?)?;
+    module.add_function(wrap_pyfunction!(clear, module)?)?;
+    module.add_function(wrap_pyfunction!(stats, module)?)?;
+    module.add_function(wrap_pyfunction!(flush, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+"""Exercises the built module against a fresh store: `maturin develop`, then run this file."""
+
+import os
+import tempfile
+
+import smart_cache
+
+path = os.path.join(tempfile.mkdtemp(), "cache.redb")
+smart_cache.open(path)
+
+smart_cache.set("render", b"page-1", b"<html></html>")
+smart_cache.flush()
+assert smart_cache.get("render", b"page-1") == b"<html></html>"
+assert smart_cache.get("render", b"page-2") is None
+
+# Entries stored under an explicit hash are separate from those under the name's.
+smart_cache.set("render", b"page-1", b"other", hash=bytes(32))
+smart_cache.flush()
+assert smart_cache.get("render", b"page-1", hash=bytes(32)) == b"other"
+assert smart_cache.get("render", b"page-1") == b"<html></html>"
+
+try:
+    smart_cache.get("render", b"page-1", hash=b"short")
+except ValueError:
+    pass
+else:
+    raise AssertionError("a short hash was accepted")
+
+assert all(stats.misses >= 0 for stats in smart_cache.stats())
+
+assert smart_cache.clear("render") == 2
+assert smart_cache.get("render", b"page-1") is None
+print("ok")
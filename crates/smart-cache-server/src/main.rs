@@ -0,0 +1,97 @@
+//! A shared cache service for `smart-cache`.
+//!
+//! Serves the HTTP remote cache protocol (see [`smart_cache::backend::HttpServer`]) from a redb
+//! store, so a team can point their `HttpBackend`s at one machine:
+//!
+//! ```text
+//! smart-cache-server --listen 0.0.0.0:7878 --dir /var/cache/smart-cache --token <secret>
+//! ```
+//!
+//! Get, set, stats and purge all go through the same endpoints. The token may also be given in
+//! `SMART_CACHE_TOKEN`, which keeps it out of the process list.
+//!
+//! With `--grpc <addr>`, the same store is also served as the gRPC service
+//! `smart_cache.v1.Cache` (see [`smart_cache::backend::GrpcServer`]), for `GrpcBackend`s and
+//! clients generated from `proto/smart_cache.proto`, under the same token.
+
+use std::{env, net::TcpListener, path::PathBuf, sync::Arc, thread};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use smart_cache::backend::{GrpcServer, HttpServer, RedbBackend};
+
+const USAGE: &str = "usage: smart-cache-server [--listen <addr>] [--dir <path>] \
+                     [--token <token>] [--shards <n>] [--grpc <addr>]";
+
+struct Options {
+    listen: String,
+    dir: PathBuf,
+    token: Option<String>,
+    shards: usize,
+    grpc: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut options = Self {
+            listen: "127.0.0.1:7878".to_string(),
+            dir: PathBuf::from("smart-cache-server"),
+            token: env::var("SMART_CACHE_TOKEN").ok(),
+            shards: 1,
+            grpc: None,
+        };
+
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| eyre!("{flag} needs a value\n{USAGE}"))
+            };
+            match flag.as_str() {
+                "--listen" => options.listen = value()?,
+                "--dir" => options.dir = value()?.into(),
+                "--token" => options.token = Some(value()?),
+                "--shards" => options.shards = value()?.parse().wrap_err("invalid --shards")?,
+                "--grpc" => options.grpc = Some(value()?),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => bail!("unknown argument {flag:?}\n{USAGE}"),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn main() -> Result<()> {
+    let options = Options::parse(env::args().skip(1))?;
+
+    let backend = Arc::new(RedbBackend::open(
+        &options.dir.join("cache.redb"),
+        options.shards,
+    )?);
+    let mut server = HttpServer::new(Arc::clone(&backend));
+    let mut grpc = GrpcServer::new(backend);
+    match options.token {
+        Some(token) => {
+            server = server.token(token.clone());
+            grpc = grpc.token(token);
+        }
+        None => eprintln!("warning: serving without a token; anyone who can connect may write"),
+    }
+
+    if let Some(addr) = options.grpc {
+        let listener =
+            TcpListener::bind(&addr).wrap_err_with(|| format!("failed to listen on {addr}"))?;
+        eprintln!("serving gRPC on {addr}");
+        thread::spawn(move || {
+            if let Err(e) = grpc.serve(&listener) {
+                eprintln!("error: gRPC server stopped: {e:#}");
+            }
+        });
+    }
+
+    let listener = TcpListener::bind(&options.listen)
+        .wrap_err_with(|| format!("failed to listen on {}", options.listen))?;
+    eprintln!("serving {} on {}", options.dir.display(), options.listen);
+    server.serve(&listener)
+}
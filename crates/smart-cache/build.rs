@@ -0,0 +1,21 @@
+fn main() {
+    // Exposes the target triple to `#[cached(per_target)]` keys.
+    let target = std::env::var("TARGET").expect("cargo sets TARGET for build scripts");
+    println!("cargo:rustc-env=SMART_CACHE_TARGET={target}");
+
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+/// Generates the gRPC service's messages, client and server from its definition. protox parses
+/// the proto, so building doesn't need protoc installed.
+#[cfg(feature = "grpc")]
+fn grpc() {
+    const PROTO: &str = "proto/smart_cache.proto";
+    println!("cargo:rerun-if-changed={PROTO}");
+    let descriptors =
+        protox::compile([PROTO], ["proto"]).expect("proto/smart_cache.proto is valid");
+    tonic_prost_build::configure()
+        .compile_fds(descriptors)
+        .expect("failed to generate the gRPC service");
+}
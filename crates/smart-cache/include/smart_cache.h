@@ -0,0 +1,51 @@
+/* C interface to the smart-cache store, available with the `ffi` feature. */
+
+#ifndef SMART_CACHE_H
+#define SMART_CACHE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Functions are identified by `name` and an optional 32-byte `hash`; pass NULL to use the
+ * SHA-256 of the name. Keys and values are opaque bytes. Functions returning a status report
+ * -1 where Rust code would panic, such as a miss while replaying.
+ */
+
+/* Uses the redb file at `path` for every function. Call before anything else; returns 0 on
+ * success and -1 if the cache is already in use. */
+int smart_cache_open(const char *path);
+
+/* Returns 0 and a copy of the value on a hit (release it with smart_cache_free), 1 on a
+ * miss, and -1 on invalid arguments or failure. */
+int smart_cache_get(const char *name, const uint8_t *hash, const uint8_t *key, size_t key_len,
+                    uint8_t **value, size_t *value_len);
+
+/* Returns 0 on success and -1 on failure. */
+int smart_cache_set(const char *name, const uint8_t *hash, const uint8_t *key, size_t key_len,
+                    const uint8_t *value, size_t value_len);
+
+/* Removes every entry of the functions called `name`. Returns the number of function
+ * versions cleared, or -1 on failure. */
+int64_t smart_cache_clear(const char *name);
+
+/* Returns 0 and one tab-separated line per function (name, hex hash, calls, hits, compute
+ * ns, saved ns, bytes written); release the text with smart_cache_free. Returns -1 on
+ * failure. */
+int smart_cache_stats(uint8_t **text, size_t *text_len);
+
+/* Waits for pending writes to reach the store. */
+void smart_cache_flush(void);
+
+/* Releases a value returned by smart_cache_get or smart_cache_stats. */
+void smart_cache_free(uint8_t *value, size_t value_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SMART_CACHE_H */
@@ -0,0 +1,351 @@
+//! Packing the store into a single file and back, so CI jobs can hand their cache to later
+//! runs as a build artifact.
+//!
+//! An archive starts with [`MAGIC`] and holds one record per entry, with every integer
+//! little-endian:
+//!
+//! ```text
+//! [store name: u32 length, bytes][function name: u32 length, bytes][function hash: 32 bytes]
+//! [key: u32 length, bytes][entry: u64 length, bytes]
+//! ```
+//!
+//! Entries are copied with their envelope, so imported values keep their checksum, compute time
+//! and expiry. Archives are not compressed; artifact stores usually compress uploads already,
+//! and otherwise the file can be piped through any compressor.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use tracing::debug;
+
+use crate::{
+    backend::{self, CacheBackend, WriteEntry},
+    clock,
+    entry::{self, Header},
+    filter, function, journal, FunctionHash, FunctionInfo,
+};
+
+/// Identifies an archive and the version of its layout.
+const MAGIC: &[u8] = b"smart-cache archive v1\n";
+
+/// Entries inserted per write transaction while importing.
+const IMPORT_BATCH: usize = 1024;
+
+/// Selects the entries [`export_filtered`] writes.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use smart_cache::ExportFilter;
+///
+/// # fn main() -> eyre::Result<()> {
+/// let week = Duration::from_secs(7 * 24 * 60 * 60);
+/// let filter = ExportFilter::function("embed_text").newer_than(week);
+/// smart_cache::export_filtered("cache.archive", &filter)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ExportFilter {
+    functions: Vec<String>,
+    newer_than: Option<Duration>,
+    key: Option<KeyPredicate>,
+}
+
+type KeyPredicate = Box<dyn Fn(&[u8]) -> bool>;
+
+impl ExportFilter {
+    /// A filter that selects every live entry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects only the entries of functions named `name`, across all versions of their bodies.
+    #[must_use]
+    pub fn function(name: impl Into<String>) -> Self {
+        Self::new().or_function(name)
+    }
+
+    /// Also selects the entries of functions named `name`.
+    #[must_use]
+    pub fn or_function(mut self, name: impl Into<String>) -> Self {
+        self.functions.push(name.into());
+        self
+    }
+
+    /// Selects only entries computed within `age` of now.
+    #[must_use]
+    pub const fn newer_than(mut self, age: Duration) -> Self {
+        self.newer_than = Some(age);
+        self
+    }
+
+    /// Selects only entries whose stored key satisfies `predicate`. Keys are the serialized
+    /// arguments, prefixed with the namespace of the [`crate::context`] they were cached in and
+    /// followed by the [`crate::Config::key_epoch`] and build profile, if configured. Arguments
+    /// starting with `0x00` or `0xff` are preceded by an extra `0xff`.
+    #[must_use]
+    pub fn key(mut self, predicate: impl Fn(&[u8]) -> bool + 'static) -> Self {
+        self.key = Some(Box::new(predicate));
+        self
+    }
+
+    fn selects_function(&self, function: &FunctionInfo) -> bool {
+        self.functions.is_empty() || self.functions.contains(&function.name)
+    }
+
+    fn selects_entry(&self, key: &[u8], header: &Header) -> bool {
+        let recent = self.newer_than.is_none_or(|age| {
+            header
+                .created_at
+                .and_then(|at| clock::now().duration_since(at).ok())
+                .is_some_and(|elapsed| elapsed <= age)
+        });
+        !header.is_expired()
+            && recent
+            && self.key.as_ref().is_none_or(|key_matches| key_matches(key))
+    }
+}
+
+/// Writes every live entry of every store to an archive at `path`, replacing any file there.
+/// Returns the number of entries written.
+///
+/// Pending writes are flushed first. Only stores this process knows about are exported, as
+/// with [`crate::functions`]; expired and corrupt entries are skipped.
+///
+/// # Errors
+///
+/// Fails if a store cannot be read or the archive cannot be written.
+pub fn export(path: impl AsRef<Path>) -> Result<u64> {
+    export_filtered(path, &ExportFilter::new())
+}
+
+/// Like [`export`], writing only the entries `filter` selects.
+///
+/// # Errors
+///
+/// Fails if a store cannot be read or the archive cannot be written.
+pub fn export_filtered(path: impl AsRef<Path>, filter: &ExportFilter) -> Result<u64> {
+    let path = path.as_ref();
+    crate::flush();
+
+    let file = File::create(path).wrap_err_with(|| format!("creating {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(MAGIC)?;
+
+    let mut exported = 0;
+    for (store, backend) in backend::all_named() {
+        for function in backend.functions()? {
+            if filter.selects_function(&function) {
+                exported += export_function(&mut out, &store, backend, &function, filter)?;
+            }
+        }
+    }
+
+    out.flush()
+        .wrap_err_with(|| format!("writing {}", path.display()))?;
+    Ok(exported)
+}
+
+fn export_function(
+    out: &mut impl Write,
+    store: &str,
+    backend: &dyn CacheBackend,
+    function: &FunctionInfo,
+    filter: &ExportFilter,
+) -> Result<u64> {
+    let mut exported = 0;
+    let mut written = Ok(());
+    backend.for_each_function_entry(&function.hash, &mut |key, entry| {
+        let selected =
+            entry::decode(entry).is_ok_and(|(header, _)| filter.selects_entry(key, &header));
+        if written.is_ok() && selected {
+            written = write_record(out, store, function, key, entry);
+            exported += 1;
+        }
+    })?;
+    written?;
+    Ok(exported)
+}
+
+fn write_record(
+    out: &mut impl Write,
+    store: &str,
+    function: &FunctionInfo,
+    key: &[u8],
+    entry: &[u8],
+) -> io::Result<()> {
+    write_bytes(out, store.as_bytes())?;
+    write_bytes(out, function.name.as_bytes())?;
+    out.write_all(&function.hash)?;
+    write_bytes(out, key)?;
+    out.write_all(&(entry.len() as u64).to_le_bytes())?;
+    out.write_all(entry)
+}
+
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "field too long to archive"))?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(bytes)
+}
+
+/// Inserts every entry of the archive at `path` into the store it was exported from, replacing
+/// entries with the same key. Returns the number of entries imported.
+///
+/// Entries that expired since the export are skipped.
+///
+/// # Errors
+///
+/// Fails if the archive cannot be read or is not one written by [`export`], or if a store
+/// cannot be written. Entries imported before the failure stay in their stores.
+pub fn import(path: impl AsRef<Path>) -> Result<u64> {
+    import_archive(path.as_ref(), None)
+}
+
+/// Like [`import`], but inserts every entry into the store called `store`, whichever store
+/// it was exported from.
+///
+/// # Errors
+///
+/// Fails if the archive cannot be read or is not one written by [`export`], or if the store
+/// cannot be written.
+pub fn import_into(path: impl AsRef<Path>, store: &str) -> Result<u64> {
+    import_archive(path.as_ref(), Some(store))
+}
+
+fn import_archive(path: &Path, store: Option<&str>) -> Result<u64> {
+    let file = File::open(path).wrap_err_with(|| format!("opening {}", path.display()))?;
+    let mut input = BufReader::new(file);
+
+    let mut magic = [0; MAGIC.len()];
+    input.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        bail!("{} is not a smart-cache archive", path.display());
+    }
+
+    let mut imported = 0;
+    let mut batch = Vec::new();
+    while let Some(record) = read_record(&mut input)? {
+        let live = entry::decode(&record.entry).is_ok_and(|(header, _)| !header.is_expired());
+        if live {
+            batch.push(record);
+        }
+        if batch.len() == IMPORT_BATCH {
+            imported += import_batch(&batch, store)?;
+            batch.clear();
+        }
+    }
+    imported += import_batch(&batch, store)?;
+    Ok(imported)
+}
+
+/// Imports every `*.archive` file in `dir`, in name order. Returns the number of entries
+/// imported, which is 0 if `dir` does not exist.
+pub(crate) fn import_dir(dir: &Path) -> Result<u64> {
+    let mut archives = match fs::read_dir(dir) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        entries => entries
+            .wrap_err_with(|| format!("reading {}", dir.display()))?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()?,
+    };
+    archives.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "archive")
+    });
+    archives.sort();
+
+    let mut imported = 0;
+    for path in archives {
+        imported += import(&path)?;
+    }
+    debug!("Loaded {imported} cache fixtures from {}", dir.display());
+    Ok(imported)
+}
+
+struct Record {
+    store: String,
+    name: String,
+    hash: FunctionHash,
+    key: Vec<u8>,
+    entry: Vec<u8>,
+}
+
+/// Reads the next record, or `None` at the end of the archive.
+fn read_record(input: &mut impl BufRead) -> Result<Option<Record>> {
+    if input.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let store = read_string(input)?;
+    let name = read_string(input)?;
+    let mut hash = FunctionHash::default();
+    input.read_exact(&mut hash)?;
+    let key = read_field(input)?;
+
+    let mut entry_len = [0; 8];
+    input.read_exact(&mut entry_len)?;
+    let entry = read_exact(input, u64::from_le_bytes(entry_len))?;
+
+    Ok(Some(Record {
+        store,
+        name,
+        hash,
+        key,
+        entry,
+    }))
+}
+
+/// Reads a field written by [`write_bytes`].
+fn read_field(input: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    read_exact(input, u32::from_le_bytes(len).into())
+}
+
+fn read_string(input: &mut impl Read) -> Result<String> {
+    String::from_utf8(read_field(input)?).map_err(|_| eyre!("archive is corrupt"))
+}
+
+fn read_exact(input: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    // Reading through `take` keeps a corrupt length from allocating more than the file holds.
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        bail!("archive is truncated");
+    }
+    Ok(bytes)
+}
+
+fn import_batch(records: &[Record], store: Option<&str>) -> Result<u64> {
+    let mut by_store: HashMap<&str, Vec<WriteEntry<'_>>> = HashMap::new();
+    for record in records {
+        let store = store.unwrap_or(&record.store);
+        by_store.entry(store).or_default().push(WriteEntry {
+            function: function::intern(&record.name, record.hash),
+            key: &record.key,
+            entry: &record.entry,
+        });
+        if let Some(filter) = filter::global() {
+            filter.insert(&record.key);
+        }
+    }
+
+    for (store, entries) in by_store {
+        let Some(backend) = backend::named(store) else {
+            bail!("cache store {store} is unavailable");
+        };
+        backend.insert_batch(&entries)?;
+        for entry in &entries {
+            journal::set(entry.function, entry.key, entry.entry.len());
+        }
+    }
+    Ok(records.len() as u64)
+}
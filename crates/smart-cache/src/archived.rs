@@ -0,0 +1,107 @@
+//! Reading cached values in their archived form, without deserializing them.
+//!
+//! For values spilled to files (see [`crate::Config::spill_values`]), a hit maps the file into
+//! memory on Unix, so an [`ArchivedValue`] of a multi-gigabyte value reads it straight from the
+//! page cache rather than copying it into the heap.
+
+use std::{marker::PhantomData, ops::Deref, time::Duration};
+
+use eyre::{bail, Result};
+use rkyv::{
+    api::high::{HighSerializer, HighValidator},
+    bytecheck::CheckBytes,
+    rancor,
+    ser::allocator::ArenaHandle,
+    util::AlignedVec,
+    Archive, Portable,
+};
+
+use crate::{get_cached, set_cached, CachedValue, Function};
+
+/// A cached value of type `T`, dereferencing to its archived form; see [`get_archived`].
+pub struct ArchivedValue<T> {
+    /// Validated as a `T::Archived` and aligned for it.
+    value: CachedValue,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: Archive> Deref for ArchivedValue<T>
+where
+    T::Archived: Portable,
+{
+    type Target = T::Archived;
+
+    fn deref(&self) -> &T::Archived {
+        // SAFETY: `get_archived` checked that the bytes hold a valid `T::Archived`, and they
+        // don't change for as long as `value` is alive.
+        unsafe { rkyv::access_unchecked::<T::Archived>(&self.value) }
+    }
+}
+
+/// Serializes `value` with rkyv and caches it for `function` under `key`, to be read back with
+/// [`get_archived`]. Hits don't count towards [`crate::FunctionStats::time_saved`], since the
+/// time it took to compute the value is unknown.
+///
+/// # Errors
+///
+/// Fails if `value` cannot be serialized or the store cannot be written.
+pub fn insert_archived<T>(function: &'static Function, key: &[u8], value: &T) -> Result<()>
+where
+    T: for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    let bytes = rkyv::to_bytes::<rancor::Error>(value)?;
+    set_cached(function, key, &bytes, Duration::ZERO, false, None)
+}
+
+impl<T> ArchivedValue<T>
+where
+    T: Archive,
+    T::Archived: Portable + for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+{
+    /// Checks that `value`, cached for `function`, holds a valid `T::Archived`.
+    pub(crate) fn validated(function: &Function, value: CachedValue) -> Result<Self> {
+        let value = value.into_aligned();
+        if let Err(e) = rkyv::access::<T::Archived, rancor::Error>(&value) {
+            bail!(
+                "cached value of {} is not of the requested type: {e}",
+                function.name()
+            );
+        }
+        Ok(Self {
+            value,
+            _type: PhantomData,
+        })
+    }
+}
+
+/// Looks up a value of `function` cached under `key` and returns a handle to its archived form,
+/// or `None` on a miss. Unlike a `#[cached]` function, this never deserializes the value, so
+/// reading part of a large value only touches the pages holding that part.
+///
+/// ```no_run
+/// use smart_cache::Function;
+///
+/// static EMBEDDINGS: Function = Function::new("embeddings", [0; 32]);
+///
+/// # fn main() -> eyre::Result<()> {
+/// smart_cache::insert_archived(&EMBEDDINGS, b"corpus", &vec![0.5_f32; 1 << 28])?;
+/// if let Some(embeddings) = smart_cache::get_archived::<Vec<f32>>(&EMBEDDINGS, b"corpus")? {
+///     println!("first: {}", embeddings[0]);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the stored bytes are not a valid archived `T`.
+pub fn get_archived<T>(function: &'static Function, key: &[u8]) -> Result<Option<ArchivedValue<T>>>
+where
+    T: Archive,
+    T::Archived: Portable + for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+{
+    let Some(value) = get_cached(function, key) else {
+        return Ok(None);
+    };
+    ArchivedValue::validated(function, value).map(Some)
+}
@@ -0,0 +1,77 @@
+//! Checking that cached functions are deterministic, in [`Mode::Audit`].
+//!
+//! Every hit also computes its value and compares it with the stored one. A function that
+//! returns something else for the same arguments reads the clock, the environment or some
+//! other state its arguments don't capture, so its cached values can't be trusted.
+//!
+//! The value is computed by the call itself, with the arguments the hit leaves unused, since
+//! they can't be assumed to be `Send` or `'static`. Hits still return the stored value, but
+//! take as long as misses.
+
+use std::sync::{Mutex, PoisonError};
+
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::{context, mode, Function, FunctionHash, Mode};
+
+/// Divergences kept for [`divergences`]; later ones are only logged.
+const MAX_DIVERGENCES: usize = 1024;
+
+static DIVERGENCES: Lazy<Mutex<Vec<Divergence>>> = Lazy::new(Mutex::default);
+
+/// A hit whose stored value differs from the one its function computed for the same arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The function's name as written in the source.
+    pub name: String,
+    /// Hash of the function body.
+    pub hash: FunctionHash,
+    /// The key the value is stored under.
+    pub key: Vec<u8>,
+    /// The serialized value in the store.
+    pub stored: Vec<u8>,
+    /// The serialized value computed in its place.
+    pub computed: Vec<u8>,
+}
+
+/// Returns the divergences found in [`Mode::Audit`] so far, oldest first.
+#[must_use]
+pub fn divergences() -> Vec<Divergence> {
+    DIVERGENCES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Internal function used by the macro to tell whether hits should be recomputed and audited.
+#[doc(hidden)]
+#[must_use]
+pub fn auditing() -> bool {
+    mode::current() == Mode::Audit
+}
+
+/// Internal function used by the macro to compare the `stored` value of a hit with the one
+/// `computed` for the same arguments.
+#[doc(hidden)]
+pub fn audit(function: &'static Function, key_bytes: &[u8], stored: &[u8], computed: &[u8]) {
+    if stored == computed {
+        return;
+    }
+    warn!(
+        "{} computed a different value than the one cached for the same arguments; it may \
+         depend on state its arguments don't capture",
+        function.name()
+    );
+
+    let mut divergences = DIVERGENCES.lock().unwrap_or_else(PoisonError::into_inner);
+    if divergences.len() < MAX_DIVERGENCES {
+        divergences.push(Divergence {
+            name: function.name().to_string(),
+            hash: *function.hash(),
+            key: context::key(function, key_bytes).into_owned(),
+            stored: stored.to_vec(),
+            computed: computed.to_vec(),
+        });
+    }
+}
@@ -0,0 +1,236 @@
+//! A content-addressed directory tree, one file per entry.
+//!
+//! ```text
+//! <root>/<function hash>/name        the function's name
+//! <root>/<function hash>/ab/cdef...  an entry, named by the SHA-256 of its key
+//! ```
+//!
+//! Each entry file holds the key (so keys can be enumerated) followed by the entry itself.
+//! Files are written to a temporary name and renamed into place, so readers never see a
+//! partial entry and there is no lock shared between writers. The tree can be inspected by
+//! hand and synced between machines with ordinary file tools.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use eyre::{Result, WrapErr};
+use sha2::{Digest, Sha256};
+
+use super::{from_hex, to_hex, CacheBackend, Description, WriteEntry};
+use crate::{value::StoredEntry, Function, FunctionHash, FunctionInfo};
+
+const NAME_FILE: &str = "name";
+
+/// Distinguishes temporary files written concurrently by threads of the same process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Stores entries as individual files under a root directory.
+pub struct FsBackend {
+    root: PathBuf,
+}
+
+fn not_found_as_none<T>(result: io::Result<T>) -> io::Result<Option<T>> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+/// Writes `contents` to `path` atomically.
+pub(super) fn write_atomic(path: &Path, contents: &[&[u8]]) -> Result<()> {
+    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp = path.with_extension(format!("tmp-{}-{counter}", process::id()));
+    fs::write(&temp, contents.concat())?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Splits an entry file into its key and entry.
+fn split_file(contents: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = contents.split_first_chunk::<8>()?;
+    let len = usize::try_from(u64::from_le_bytes(*len)).ok()?;
+    rest.split_at_checked(len)
+}
+
+/// Calls `f` with the path and contents of every entry file of one function.
+fn for_each_file(dir: &Path, f: &mut dyn FnMut(&Path, &[u8]) -> Result<()>) -> Result<()> {
+    let Some(buckets) = not_found_as_none(fs::read_dir(dir))? else {
+        return Ok(());
+    };
+    for bucket in buckets {
+        let bucket = bucket?.path();
+        if bucket.is_dir() {
+            for_each_file_in_bucket(&bucket, f)?;
+        }
+    }
+    Ok(())
+}
+
+fn for_each_file_in_bucket(
+    bucket: &Path,
+    f: &mut dyn FnMut(&Path, &[u8]) -> Result<()>,
+) -> Result<()> {
+    for file in fs::read_dir(bucket)? {
+        let path = file?.path();
+        if path.extension().is_some() {
+            // A temporary file of an in-flight write.
+            continue;
+        }
+        if let Some(contents) = not_found_as_none(fs::read(&path))? {
+            f(&path, &contents)?;
+        }
+    }
+    Ok(())
+}
+
+impl FsBackend {
+    /// Uses `root` as the store, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be created.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root).wrap_err("failed to create cache directory")?;
+        Ok(Self { root })
+    }
+
+    fn function_dir(&self, function: &FunctionHash) -> PathBuf {
+        self.root.join(to_hex(function))
+    }
+
+    fn entry_path(&self, function: &FunctionHash, key: &[u8]) -> PathBuf {
+        let digest = to_hex(&Sha256::digest(key));
+        let (bucket, file) = digest.split_at(2);
+        self.function_dir(function).join(bucket).join(file)
+    }
+
+    fn function_dirs(&self) -> Result<Vec<(FunctionHash, PathBuf)>> {
+        let mut dirs = Vec::new();
+        for dir in fs::read_dir(&self.root)? {
+            let dir = dir?;
+            let hash = dir
+                .file_name()
+                .to_str()
+                .and_then(|name| from_hex(name)?.try_into().ok());
+            if let Some(hash) = hash {
+                dirs.push((hash, dir.path()));
+            }
+        }
+        Ok(dirs)
+    }
+
+    /// Calls `f` with the path and key of every entry file.
+    fn for_each_entry(&self, f: &mut dyn FnMut(&Path, &[u8]) -> Result<()>) -> Result<()> {
+        for (_, dir) in self.function_dirs()? {
+            for_each_file(&dir, &mut |path, contents| match split_file(contents) {
+                Some((key, _)) => f(path, key),
+                None => Ok(()),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, entry: &WriteEntry<'_>) -> Result<()> {
+        let dir = self.function_dir(entry.function.hash());
+        let path = self.entry_path(entry.function.hash(), entry.key);
+        if let Some(bucket) = path.parent() {
+            fs::create_dir_all(bucket)?;
+        }
+        if !dir.join(NAME_FILE).exists() {
+            write_atomic(&dir.join(NAME_FILE), &[entry.function.name().as_bytes()])?;
+        }
+
+        let key_len = entry.key.len() as u64;
+        write_atomic(&path, &[&key_len.to_le_bytes(), entry.key, entry.entry])
+    }
+}
+
+impl CacheBackend for FsBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let path = self.entry_path(function.hash(), key);
+        let Some(contents) = not_found_as_none(fs::read(path))? else {
+            return Ok(None);
+        };
+
+        // A different key with the same digest is as good as a miss.
+        Ok(split_file(&contents)
+            .filter(|(stored_key, _)| *stored_key == key)
+            .map(|(_, entry)| entry.to_vec().into()))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        for entry in entries {
+            self.write(entry)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        not_found_as_none(fs::remove_file(self.entry_path(function.hash(), key)))?;
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let mut removed = 0;
+        self.for_each_entry(&mut |path, key| {
+            if key.starts_with(prefix) {
+                not_found_as_none(fs::remove_file(path))?;
+                removed += 1;
+            }
+            Ok(())
+        })?;
+        Ok(removed)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let mut functions = Vec::new();
+        for (hash, dir) in self.function_dirs()? {
+            let name = not_found_as_none(fs::read_to_string(dir.join(NAME_FILE)))?;
+            let mut entries = 0;
+            for_each_file(&dir, &mut |_, _| {
+                entries += 1;
+                Ok(())
+            })?;
+            functions.push(FunctionInfo {
+                name: name.unwrap_or_default(),
+                hash,
+                entries,
+            });
+        }
+        Ok(functions)
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        not_found_as_none(fs::remove_dir_all(self.function_dir(function)))?;
+        Ok(())
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.for_each_entry(&mut |_, key| {
+            f(key);
+            Ok(())
+        })
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        for_each_file(&self.function_dir(function), &mut |_, contents| {
+            if let Some((key, entry)) = split_file(contents) {
+                f(key, entry);
+            }
+            Ok(())
+        })
+    }
+
+    fn describe(&self) -> Description {
+        Description::new("fs").location(self.root.display().to_string())
+    }
+}
@@ -0,0 +1,680 @@
+//! A gRPC cache service, with a client backend and a reference server, for deployments that
+//! standardize on gRPC infrastructure (load balancers, proxies, service meshes).
+//!
+//! The service is `smart_cache.v1.Cache`, defined in `proto/smart_cache.proto` at the root of
+//! the crate, so clients in other languages can be generated from it. Calls are unary and
+//! mirror [`CacheBackend`]: `Get`, `Set`, `Remove`, `Purge`, `Functions`, `ClearFunction`,
+//! `Keys`, `Stats` and `MergeStats`.
+//!
+//! Both sides are tonic's, with the messages, client and server generated from the proto at
+//! build time, and run on a runtime shared by every client and server in the process, so
+//! callers block as they do on the other backends. Backend calls on the server run on tokio's
+//! blocking threads.
+//!
+//! When a token is configured, every call must carry `authorization: Bearer <token>`
+//! metadata, and calls without it fail with `UNAUTHENTICATED` before their message is read.
+//! The server speaks HTTP/2 over plain TCP (h2c); terminate TLS in front of it. The client
+//! also reaches `https://` endpoints with the `tls` feature.
+
+use std::{
+    future::Future,
+    io,
+    net::TcpListener,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use once_cell::sync::Lazy;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    runtime::Runtime,
+};
+use tokio_io_timeout::TimeoutStream;
+use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+#[cfg(feature = "tls")]
+use tonic::transport::ClientTlsConfig;
+use tonic::{
+    metadata::MetadataValue,
+    service::interceptor::InterceptedService,
+    transport::{
+        server::{Connected, TcpConnectInfo},
+        Channel, Endpoint, Server,
+    },
+    Code, Request, Response, Status,
+};
+use tracing::debug;
+
+use self::proto::{cache_client::CacheClient, cache_server::CacheServer};
+use super::{http::Slot, CacheBackend, Description, WriteEntry};
+use crate::{http::Url, value::StoredEntry, Function, FunctionHash, FunctionInfo, FunctionStats};
+
+/// The service's messages, client and server, generated from `proto/smart_cache.proto`.
+#[allow(clippy::pedantic, clippy::nursery, clippy::excessive_nesting)]
+mod proto {
+    tonic::include_proto!("smart_cache.v1");
+}
+
+/// How long a client waits to connect, or for a call to be answered.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The runtime every client and server in the process makes and serves calls on.
+static RUNTIME: Lazy<Result<Runtime, String>> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .thread_name("smart-cache-grpc")
+        .enable_all()
+        .build()
+        .map_err(|error| error.to_string())
+});
+
+/// Runs `future` on the shared runtime, blocking until it finishes. Unlike
+/// `Runtime::block_on`, this also works on threads already running async code, as when a
+/// cached function is called from an async function.
+fn run<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> Result<T> {
+    let runtime = RUNTIME
+        .as_ref()
+        .map_err(|error| eyre!("failed to start the gRPC runtime: {error}"))?;
+    let (sender, receiver) = mpsc::sync_channel(1);
+    runtime.spawn(async move {
+        // The receiver only goes away if the caller did.
+        let _ = sender.send(future.await);
+    });
+    receiver.recv().map_err(|_| eyre!("the gRPC task panicked"))
+}
+
+fn function_hash(bytes: &[u8]) -> Result<FunctionHash> {
+    bytes
+        .try_into()
+        .map_err(|_| eyre!("function hashes are 32 bytes"))
+}
+
+fn decode_function_info(info: proto::FunctionInfo) -> Result<FunctionInfo> {
+    Ok(FunctionInfo {
+        hash: function_hash(&info.hash)?,
+        name: info.name,
+        entries: info.entries,
+    })
+}
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+fn encode_stats(stats: &FunctionStats) -> proto::FunctionStats {
+    proto::FunctionStats {
+        hash: stats.hash.to_vec(),
+        name: stats.name.clone(),
+        calls: stats.calls,
+        hits: stats.hits,
+        compute_time_ns: nanos(stats.compute_time),
+        time_saved_ns: nanos(stats.time_saved),
+        hit_time_ns: nanos(stats.hit_time),
+        bytes_written: stats.bytes_written,
+    }
+}
+
+fn decode_stats(stats: proto::FunctionStats) -> Result<FunctionStats> {
+    Ok(FunctionStats {
+        hash: function_hash(&stats.hash)?,
+        name: stats.name,
+        calls: stats.calls,
+        hits: stats.hits,
+        compute_time: Duration::from_nanos(stats.compute_time_ns),
+        time_saved: Duration::from_nanos(stats.time_saved_ns),
+        hit_time: Duration::from_nanos(stats.hit_time_ns),
+        bytes_written: stats.bytes_written,
+    })
+}
+
+/// Talks to a remote cache service over gRPC, keeping one connection open across calls.
+pub struct GrpcBackend {
+    url: Url,
+    token: Option<String>,
+    client: CacheClient<Channel>,
+}
+
+impl GrpcBackend {
+    /// Uses the service at `url` (e.g. `http://cache.internal:50051`).
+    ///
+    /// # Errors
+    ///
+    /// Fails if `url` is not an `http://` URL, or an `https://` one with the `tls` feature.
+    pub fn new(url: &str) -> Result<Self> {
+        let parsed = Url::parse(url)?;
+        let endpoint = Endpoint::from_shared(url.to_string())
+            .wrap_err_with(|| format!("invalid URL {url:?}"))?
+            .connect_timeout(TIMEOUT)
+            .timeout(TIMEOUT);
+        #[cfg(feature = "tls")]
+        let endpoint = if parsed.tls {
+            endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?
+        } else {
+            endpoint
+        };
+        // Connects on the first call, and again whenever the connection is lost.
+        let channel = run(async move { endpoint.connect_lazy() })?;
+        Ok(Self {
+            url: parsed,
+            token: None,
+            client: CacheClient::new(channel),
+        })
+    }
+
+    /// Sends `authorization: Bearer <token>` with every call.
+    #[must_use]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Makes a unary call to `method` through `send`, returning the response message.
+    ///
+    /// A call that finds the connection gone, e.g. because the server closed it while idle, is
+    /// made once more on a new one. `MergeStats` is not, as the server may have applied it
+    /// before the connection failed.
+    fn call<M, R, F>(
+        &self,
+        method: &str,
+        message: &M,
+        send: impl Fn(CacheClient<Channel>, Request<M>) -> F,
+    ) -> Result<R>
+    where
+        M: Clone,
+        R: Send + 'static,
+        F: Future<Output = Result<Response<R>, Status>> + Send + 'static,
+    {
+        let mut retry = method != "MergeStats";
+        loop {
+            let mut request = Request::new(message.clone());
+            if let Some(token) = &self.token {
+                let authorization = MetadataValue::try_from(format!("Bearer {token}"))
+                    .wrap_err("the token is not valid in a header")?;
+                request
+                    .metadata_mut()
+                    .insert("authorization", authorization);
+            }
+            match run(send(self.client.clone(), request))? {
+                Ok(response) => return Ok(response.into_inner()),
+                Err(status) if status.code() == Code::Unavailable && retry => {
+                    debug!(
+                        "Retrying {method} on a new connection: {}",
+                        status.message()
+                    );
+                    retry = false;
+                }
+                Err(status) => bail!(
+                    "{method} failed with gRPC status {}: {}",
+                    i32::from(status.code()),
+                    status.message()
+                ),
+            }
+        }
+    }
+}
+
+impl CacheBackend for GrpcBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let request = proto::GetRequest {
+            function: function.hash().to_vec(),
+            key: key.to_vec(),
+        };
+        let response = self.call("Get", &request, |mut client, request| async move {
+            client.get(request).await
+        })?;
+        Ok(response.found.then(|| response.entry.into()))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        let entries = entries.iter().map(|entry| proto::Entry {
+            function: entry.function.hash().to_vec(),
+            function_name: entry.function.name().to_string(),
+            key: entry.key.to_vec(),
+            entry: entry.entry.to_vec(),
+        });
+        let request = proto::SetRequest {
+            entries: entries.collect(),
+        };
+        self.call("Set", &request, |mut client, request| async move {
+            client.set(request).await
+        })?;
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        let request = proto::RemoveRequest {
+            function: function.hash().to_vec(),
+            key: key.to_vec(),
+        };
+        self.call("Remove", &request, |mut client, request| async move {
+            client.remove(request).await
+        })?;
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let request = proto::PurgeRequest {
+            prefix: prefix.to_vec(),
+        };
+        let response = self.call("Purge", &request, |mut client, request| async move {
+            client.purge(request).await
+        })?;
+        Ok(response.removed)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let response = self.call(
+            "Functions",
+            &proto::Empty {},
+            |mut client, request| async move { client.functions(request).await },
+        )?;
+        response
+            .functions
+            .into_iter()
+            .map(decode_function_info)
+            .collect()
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        let request = proto::ClearFunctionRequest {
+            function: function.to_vec(),
+        };
+        self.call(
+            "ClearFunction",
+            &request,
+            |mut client, request| async move { client.clear_function(request).await },
+        )?;
+        Ok(())
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        let request = proto::MergeStatsRequest {
+            deltas: deltas.iter().map(encode_stats).collect(),
+        };
+        self.call("MergeStats", &request, |mut client, request| async move {
+            client.merge_stats(request).await
+        })?;
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        let response = self.call(
+            "Stats",
+            &proto::Empty {},
+            |mut client, request| async move { client.stats(request).await },
+        )?;
+        response.functions.into_iter().map(decode_stats).collect()
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        let response = self.call("Keys", &proto::Empty {}, |mut client, request| async move {
+            client.keys(request).await
+        })?;
+        for key in &response.keys {
+            f(key);
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        let scheme = if self.url.tls { "https" } else { "http" };
+        Description::new("grpc").location(format!("{scheme}://{}{}", self.url.host, self.url.path))
+    }
+}
+
+/// A reference server for the service, storing entries in any [`CacheBackend`].
+pub struct GrpcServer {
+    backend: Arc<dyn CacheBackend>,
+    token: Option<String>,
+    max_message: usize,
+    max_connections: usize,
+    timeout: Duration,
+}
+
+impl GrpcServer {
+    pub fn new(backend: impl CacheBackend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            token: None,
+            max_message: 64 << 20,
+            max_connections: 64,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Rejects calls that don't carry `authorization: Bearer <token>` metadata.
+    #[must_use]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Fails calls whose request is larger than `bytes` with `OUT_OF_RANGE`, 64 MiB by default.
+    #[must_use]
+    pub const fn max_message(mut self, bytes: usize) -> Self {
+        self.max_message = bytes;
+        self
+    }
+
+    /// Serves at most `connections` at once (64 by default), closing any beyond that.
+    #[must_use]
+    pub const fn max_connections(mut self, connections: usize) -> Self {
+        self.max_connections = connections;
+        self
+    }
+
+    /// Closes connections that stay idle, or stall mid-call, for longer than `timeout`, 30
+    /// seconds by default. Clients reconnect on their next call.
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Serves connections from `listener` until it fails, blocking the calling thread.
+    ///
+    /// # Errors
+    ///
+    /// Fails if accepting a connection fails.
+    pub fn serve(self, listener: &TcpListener) -> Result<()> {
+        let listener = listener.try_clone()?;
+        listener.set_nonblocking(true)?;
+        let expected = self.token.map(|token| format!("Bearer {token}"));
+        let authorize = move |request: Request<()>| {
+            let given = request.metadata().get("authorization");
+            match &expected {
+                Some(expected) if given.and_then(|given| given.to_str().ok()) != Some(expected) => {
+                    Err(Status::unauthenticated("missing or invalid token"))
+                }
+                _ => Ok(request),
+            }
+        };
+        let service = CacheServer::new(Service {
+            backend: self.backend,
+        })
+        .max_decoding_message_size(self.max_message);
+        let service = InterceptedService::new(service, authorize);
+
+        let limits = Limits {
+            active: Arc::new(AtomicUsize::new(0)),
+            max_connections: self.max_connections,
+            timeout: self.timeout,
+        };
+        run(async move {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            let incoming =
+                TcpListenerStream::new(listener).filter_map(move |stream| limits.admit(stream));
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await?;
+            Ok(())
+        })?
+    }
+}
+
+/// The limits `GrpcServer` puts on connections.
+struct Limits {
+    active: Arc<AtomicUsize>,
+    max_connections: usize,
+    timeout: Duration,
+}
+
+impl Limits {
+    /// Serves a newly accepted connection, unless `max_connections` are already open.
+    fn admit(&self, stream: io::Result<TcpStream>) -> Option<io::Result<Connection>> {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => return Some(Err(e)),
+        };
+        let connection = Connection::new(stream, Slot::take(&self.active), self.timeout);
+        if self.active.load(Ordering::SeqCst) > self.max_connections {
+            debug!(
+                "Turning away a gRPC connection; {} are open",
+                self.max_connections
+            );
+            return None;
+        }
+        Some(Ok(connection))
+    }
+}
+
+/// Serves calls from a [`CacheBackend`].
+struct Service {
+    backend: Arc<dyn CacheBackend>,
+}
+
+impl Service {
+    /// Runs `f` on a blocking thread, as backends block on disk and network I/O.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&dyn CacheBackend) -> Result<T> + Send + 'static,
+    ) -> Result<Response<T>, Status> {
+        let backend = Arc::clone(&self.backend);
+        let response = tokio::task::spawn_blocking(move || f(&*backend)).await;
+        match response {
+            Ok(Ok(response)) => Ok(Response::new(response)),
+            Ok(Err(e)) => Err(Status::internal(format!("{e:#}"))),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
+
+fn invalid(error: eyre::Report) -> Status {
+    Status::invalid_argument(format!("{error:#}"))
+}
+
+#[tonic::async_trait]
+impl proto::cache_server::Cache for Service {
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> Result<Response<proto::GetResponse>, Status> {
+        let proto::GetRequest { function, key } = request.into_inner();
+        let hash = function_hash(&function).map_err(invalid)?;
+        self.blocking(move |backend| {
+            let entry = backend.get(&Function::new("", hash), &key)?;
+            Ok(proto::GetResponse {
+                found: entry.is_some(),
+                entry: entry.map(|entry| entry.to_vec()).unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    async fn set(
+        &self,
+        request: Request<proto::SetRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let entries = request.into_inner().entries;
+        let functions = entries
+            .iter()
+            .map(|entry| {
+                Ok(Function::owned(
+                    &entry.function_name,
+                    function_hash(&entry.function)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map_err(invalid)?;
+        self.blocking(move |backend| {
+            let entries: Vec<_> = entries
+                .iter()
+                .zip(&functions)
+                .map(|(entry, function)| WriteEntry {
+                    function,
+                    key: &entry.key,
+                    entry: &entry.entry,
+                })
+                .collect();
+            backend.insert_batch(&entries)?;
+            Ok(proto::Empty {})
+        })
+        .await
+    }
+
+    async fn remove(
+        &self,
+        request: Request<proto::RemoveRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let proto::RemoveRequest { function, key } = request.into_inner();
+        let hash = function_hash(&function).map_err(invalid)?;
+        self.blocking(move |backend| {
+            backend.remove(&Function::new("", hash), &key)?;
+            Ok(proto::Empty {})
+        })
+        .await
+    }
+
+    async fn purge(
+        &self,
+        request: Request<proto::PurgeRequest>,
+    ) -> Result<Response<proto::PurgeResponse>, Status> {
+        let prefix = request.into_inner().prefix;
+        self.blocking(move |backend| {
+            let removed = backend.remove_prefix(&prefix)?;
+            Ok(proto::PurgeResponse { removed })
+        })
+        .await
+    }
+
+    async fn functions(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::FunctionsResponse>, Status> {
+        self.blocking(|backend| {
+            let functions = backend
+                .functions()?
+                .into_iter()
+                .map(|info| proto::FunctionInfo {
+                    hash: info.hash.to_vec(),
+                    name: info.name,
+                    entries: info.entries,
+                });
+            Ok(proto::FunctionsResponse {
+                functions: functions.collect(),
+            })
+        })
+        .await
+    }
+
+    async fn clear_function(
+        &self,
+        request: Request<proto::ClearFunctionRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let hash = function_hash(&request.into_inner().function).map_err(invalid)?;
+        self.blocking(move |backend| {
+            backend.clear_function(&hash)?;
+            Ok(proto::Empty {})
+        })
+        .await
+    }
+
+    async fn keys(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::KeysResponse>, Status> {
+        self.blocking(|backend| {
+            let mut keys = Vec::new();
+            backend.for_each_key(&mut |key| keys.push(key.to_vec()))?;
+            Ok(proto::KeysResponse { keys })
+        })
+        .await
+    }
+
+    async fn stats(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::StatsResponse>, Status> {
+        self.blocking(|backend| {
+            let functions = backend.stats()?.iter().map(encode_stats).collect();
+            Ok(proto::StatsResponse { functions })
+        })
+        .await
+    }
+
+    async fn merge_stats(
+        &self,
+        request: Request<proto::MergeStatsRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let deltas = request.into_inner().deltas.into_iter().map(decode_stats);
+        let deltas = deltas.collect::<Result<Vec<_>>>().map_err(invalid)?;
+        self.blocking(move |backend| {
+            backend.merge_stats(&deltas)?;
+            Ok(proto::Empty {})
+        })
+        .await
+    }
+}
+
+/// An accepted connection, holding its place under `max_connections` until it closes. Reads
+/// and writes that stall for longer than the server's timeout fail, closing it.
+struct Connection {
+    stream: Pin<Box<TimeoutStream<TcpStream>>>,
+    _slot: Slot,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, slot: Slot, timeout: Duration) -> Self {
+        let mut stream = TimeoutStream::new(stream);
+        stream.set_read_timeout(Some(timeout));
+        stream.set_write_timeout(Some(timeout));
+        Self {
+            stream: Box::pin(stream),
+            _slot: slot,
+        }
+    }
+}
+
+impl Connected for Connection {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> TcpConnectInfo {
+        self.stream.get_ref().connect_info()
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.stream.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.stream.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.stream.as_mut().poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream.as_mut().poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,471 @@
+//! A simple HTTP remote cache protocol, with a client backend and a reference server.
+//!
+//! Function hashes and keys travel hex-encoded in the path:
+//!
+//! ```text
+//! GET    /v1/entries/<function>/<key>   200 with the entry, or 404
+//! PUT    /v1/entries/<function>/<key>   stores the body; X-Smart-Cache-Function names the function
+//! DELETE /v1/entries/<function>/<key>
+//! GET    /v1/functions                  one "<function> <entries> <name>" line per function
+//! DELETE /v1/functions/<function>       removes all of a function's entries
+//! GET    /v1/keys                       one hex-encoded key per line
+//! GET    /v1/stats                      one stats line per function
+//! POST   /v1/stats                      adds the stats lines in the body to the stored ones
+//! POST   /v1/purge                      removes keys starting with the hex-encoded body; replies
+//!                                       with the number removed
+//! ```
+//!
+//! A stats line is `<function> <calls> <hits> <compute ns> <saved ns> <bytes written> <name>`.
+//!
+//! When a token is configured, every request must carry `Authorization: Bearer <token>`; the
+//! server checks it before reading the body. Bodies and concurrent connections are capped, and
+//! stalled connections time out (see [`HttpServer::max_body`] and its neighbours).
+
+use std::{
+    io::{BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use eyre::{bail, eyre, Result};
+use tracing::{debug, warn};
+
+use super::{from_hex, to_hex, CacheBackend, Description, WriteEntry};
+use crate::{
+    http::{self, Url},
+    value::StoredEntry,
+    Function, FunctionHash, FunctionInfo, FunctionStats,
+};
+
+const FUNCTION_HEADER: &str = "X-Smart-Cache-Function";
+
+/// Talks to a remote cache server over HTTP.
+pub struct HttpBackend {
+    url: Url,
+    token: Option<String>,
+}
+
+impl HttpBackend {
+    /// Uses the server at `url` (e.g. `http://cache.internal:8080`).
+    ///
+    /// # Errors
+    ///
+    /// Fails if `url` is not an `http://` URL, or an `https://` one with the `tls` feature.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(url)?,
+            token: None,
+        })
+    }
+
+    /// Authenticates every request with `token`.
+    #[must_use]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        mut headers: Vec<(&str, String)>,
+        body: &[u8],
+    ) -> Result<http::Response> {
+        if let Some(token) = &self.token {
+            headers.push(("Authorization", format!("Bearer {token}")));
+        }
+        let path = format!("{}/v1/{path}", self.url.path);
+        http::request(method, &self.url, &path, &headers, body)
+    }
+
+    fn entry_path(function: &FunctionHash, key: &[u8]) -> String {
+        format!("entries/{}/{}", to_hex(function), to_hex(key))
+    }
+
+    fn text(&self, method: &str, path: &str, body: &[u8]) -> Result<String> {
+        let response = self
+            .send(method, path, Vec::new(), body)?
+            .error_for_status()?;
+        Ok(String::from_utf8_lossy(&response.body).into_owned())
+    }
+}
+
+impl CacheBackend for HttpBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let path = Self::entry_path(function.hash(), key);
+        let response = self.send("GET", &path, Vec::new(), &[])?;
+        if response.status == 404 {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.body.into()))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        for entry in entries {
+            let path = Self::entry_path(entry.function.hash(), entry.key);
+            let headers = vec![(FUNCTION_HEADER, entry.function.name().to_string())];
+            self.send("PUT", &path, headers, entry.entry)?
+                .error_for_status()?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        let path = Self::entry_path(function.hash(), key);
+        self.send("DELETE", &path, Vec::new(), &[])?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let removed = self.text("POST", "purge", to_hex(prefix).as_bytes())?;
+        removed
+            .trim()
+            .parse()
+            .map_err(|_| eyre!("malformed purge reply {removed:?}"))
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let listing = self.text("GET", "functions", &[])?;
+        Ok(listing.lines().filter_map(parse_function).collect())
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.text("DELETE", &format!("functions/{}", to_hex(function)), &[])
+            .map(drop)
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        if deltas.is_empty() {
+            return Ok(());
+        }
+        let body: String = deltas.iter().map(format_stats).collect();
+        self.text("POST", "stats", body.as_bytes()).map(drop)
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        let listing = self.text("GET", "stats", &[])?;
+        Ok(listing.lines().filter_map(parse_stats).collect())
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        for key in self.text("GET", "keys", &[])?.lines() {
+            if let Some(key) = from_hex(key) {
+                f(&key);
+            }
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        let scheme = if self.url.tls { "https" } else { "http" };
+        Description::new("http").location(format!("{scheme}://{}{}", self.url.host, self.url.path))
+    }
+}
+
+fn parse_function(line: &str) -> Option<FunctionInfo> {
+    let mut fields = line.splitn(3, ' ');
+    let hash = FunctionHash::try_from(from_hex(fields.next()?)?).ok()?;
+    let entries = fields.next()?.parse().ok()?;
+    Some(FunctionInfo {
+        name: fields.next().unwrap_or_default().to_string(),
+        hash,
+        entries,
+    })
+}
+
+fn format_stats(stats: &FunctionStats) -> String {
+    format!(
+        "{} {} {} {} {} {} {} {}\n",
+        to_hex(&stats.hash),
+        stats.calls,
+        stats.hits,
+        stats.compute_time.as_nanos(),
+        stats.time_saved.as_nanos(),
+        stats.bytes_written,
+        stats.hit_time.as_nanos(),
+        stats.name,
+    )
+}
+
+fn parse_stats(line: &str) -> Option<FunctionStats> {
+    let mut fields = line.splitn(8, ' ');
+    let hash = FunctionHash::try_from(from_hex(fields.next()?)?).ok()?;
+    let mut counter = || fields.next()?.parse::<u64>().ok();
+    Some(FunctionStats {
+        hash,
+        calls: counter()?,
+        hits: counter()?,
+        compute_time: Duration::from_nanos(counter()?),
+        time_saved: Duration::from_nanos(counter()?),
+        bytes_written: counter()?,
+        hit_time: Duration::from_nanos(counter()?),
+        name: fields.next().unwrap_or_default().to_string(),
+    })
+}
+
+/// A parsed request to the server.
+struct Request {
+    method: String,
+    path: String,
+    function_name: Option<String>,
+    body: Vec<u8>,
+}
+
+fn respond(mut stream: &TcpStream, status: u16, body: &[u8]) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+/// A reference server for the protocol, storing entries in any [`CacheBackend`].
+pub struct HttpServer {
+    backend: Arc<dyn CacheBackend>,
+    token: Option<String>,
+    max_body: usize,
+    max_connections: usize,
+    timeout: Duration,
+}
+
+impl HttpServer {
+    pub fn new(backend: impl CacheBackend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            token: None,
+            max_body: 64 << 20,
+            max_connections: 64,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Rejects requests that don't carry `Authorization: Bearer <token>`.
+    #[must_use]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Rejects request bodies (stored entries, mostly) larger than `bytes`, 64 MiB by default.
+    #[must_use]
+    pub const fn max_body(mut self, bytes: usize) -> Self {
+        self.max_body = bytes;
+        self
+    }
+
+    /// Serves at most `connections` at once (64 by default), answering any beyond that with
+    /// 503 Service Unavailable.
+    #[must_use]
+    pub const fn max_connections(mut self, connections: usize) -> Self {
+        self.max_connections = connections;
+        self
+    }
+
+    /// Drops connections that stall reading or writing for longer than `timeout`, 30 seconds by
+    /// default.
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Serves connections from `listener` until it fails, one thread per connection.
+    ///
+    /// # Errors
+    ///
+    /// Fails if accepting a connection fails.
+    pub fn serve(self, listener: &TcpListener) -> Result<()> {
+        let server = Arc::new(self);
+        let active = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let stream = stream?;
+            stream.set_read_timeout(Some(server.timeout))?;
+            stream.set_write_timeout(Some(server.timeout))?;
+            let slot = Slot::take(&active);
+            if active.load(Ordering::SeqCst) > server.max_connections {
+                let refused = respond(&stream, 503, b"too many connections");
+                refused.unwrap_or_else(|e| debug!("Failed to turn away a connection: {e:#}"));
+                continue;
+            }
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                let _slot = slot;
+                server.serve_connection(&stream);
+            });
+        }
+        Ok(())
+    }
+
+    fn serve_connection(&self, stream: &TcpStream) {
+        if let Err(e) = self.handle_connection(stream) {
+            warn!("Failed to serve cache request: {e:#}");
+        }
+    }
+
+    fn handle_connection(&self, stream: &TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+        let head = http::read_head(&mut reader)?;
+        let mut parts = head.start.split(' ');
+        let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+            bail!("malformed request line {:?}", head.start);
+        };
+        debug!("{method} {path}");
+
+        // Checked before the body is read, so unauthenticated clients can't make it buffer one.
+        let expected = self.token.as_ref().map(|token| format!("Bearer {token}"));
+        if expected.is_some() && head.header("authorization") != expected.as_deref() {
+            return respond(stream, 401, b"missing or invalid token");
+        }
+        if head
+            .content_length()?
+            .is_some_and(|length| length > self.max_body)
+        {
+            return respond(stream, 413, b"request body too large");
+        }
+
+        let request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            function_name: head.header(FUNCTION_HEADER).map(str::to_string),
+            body: http::read_body(&mut reader, &head, true, self.max_body)?,
+        };
+        match self.handle(&request) {
+            Ok((status, body)) => respond(stream, status, &body),
+            Err(e) => respond(stream, 500, format!("{e:#}").as_bytes()),
+        }
+    }
+
+    fn handle(&self, request: &Request) -> Result<(u16, Vec<u8>)> {
+        let path = request.path.trim_start_matches('/');
+        let segments: Vec<_> = path.split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            (method, ["v1", "entries", function, key]) => {
+                let (Some(function), Some(key)) = (parse_hash(function), from_hex(key)) else {
+                    return Ok((400, b"malformed function or key".to_vec()));
+                };
+                self.handle_entry(method, request, function, &key)
+            }
+            ("GET", ["v1", "functions"]) => {
+                let listing: String = self
+                    .backend
+                    .functions()?
+                    .iter()
+                    .map(|f| format!("{} {} {}\n", to_hex(&f.hash), f.entries, f.name))
+                    .collect();
+                Ok((200, listing.into_bytes()))
+            }
+            ("DELETE", ["v1", "functions", function]) => {
+                let Some(function) = parse_hash(function) else {
+                    return Ok((400, b"malformed function".to_vec()));
+                };
+                self.backend.clear_function(&function)?;
+                Ok((204, Vec::new()))
+            }
+            ("GET", ["v1", "keys"]) => {
+                let mut keys = String::new();
+                self.backend.for_each_key(&mut |key| {
+                    keys.push_str(&to_hex(key));
+                    keys.push('\n');
+                })?;
+                Ok((200, keys.into_bytes()))
+            }
+            ("GET", ["v1", "stats"]) => {
+                let listing: String = self.backend.stats()?.iter().map(format_stats).collect();
+                Ok((200, listing.into_bytes()))
+            }
+            ("POST", ["v1", "stats"]) => {
+                let deltas: Option<Vec<_>> = String::from_utf8_lossy(&request.body)
+                    .lines()
+                    .map(parse_stats)
+                    .collect();
+                let Some(deltas) = deltas else {
+                    return Ok((400, b"malformed stats".to_vec()));
+                };
+                self.backend.merge_stats(&deltas)?;
+                Ok((204, Vec::new()))
+            }
+            ("POST", ["v1", "purge"]) => {
+                let prefix = std::str::from_utf8(&request.body).ok().and_then(from_hex);
+                let Some(prefix) = prefix else {
+                    return Ok((400, b"malformed prefix".to_vec()));
+                };
+                let removed = self.backend.remove_prefix(&prefix)?;
+                Ok((200, removed.to_string().into_bytes()))
+            }
+            _ => Ok((404, b"no such endpoint".to_vec())),
+        }
+    }
+
+    fn handle_entry(
+        &self,
+        method: &str,
+        request: &Request,
+        hash: FunctionHash,
+        key: &[u8],
+    ) -> Result<(u16, Vec<u8>)> {
+        // Only the hash identifies the function; the name is kept for listings.
+        let function = match (method, &request.function_name) {
+            ("PUT", Some(name)) => &Function::owned(name, hash),
+            _ => &Function::new("", hash),
+        };
+
+        match method {
+            "GET" => Ok(match self.backend.get(function, key)? {
+                Some(entry) => (200, entry.to_vec()),
+                None => (404, Vec::new()),
+            }),
+            "PUT" => {
+                self.backend.insert_batch(&[WriteEntry {
+                    function,
+                    key,
+                    entry: &request.body,
+                }])?;
+                Ok((204, Vec::new()))
+            }
+            "DELETE" => {
+                self.backend.remove(function, key)?;
+                Ok((204, Vec::new()))
+            }
+            _ => Ok((404, b"no such endpoint".to_vec())),
+        }
+    }
+}
+
+/// One connection counted against [`HttpServer::max_connections`], released when dropped.
+pub(super) struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    pub(super) fn take(active: &Arc<AtomicUsize>) -> Self {
+        active.fetch_add(1, Ordering::SeqCst);
+        Self(Arc::clone(active))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn parse_hash(hex: &str) -> Option<FunctionHash> {
+    FunctionHash::try_from(from_hex(hex)?).ok()
+}
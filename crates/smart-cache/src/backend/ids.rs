@@ -0,0 +1,75 @@
+//! Small IDs standing in for function hashes at the start of keys, for backends keeping every
+//! function's entries in one ordered keyspace.
+//!
+//! Each backend persists a `functions` table mapping every hash it has stored entries for to
+//! [`record`]: the ID as a big-endian `u32`, followed by the function's name. Keys are then the
+//! 4-byte ID followed by the arguments rather than the 32-byte hash, so each function's entries
+//! are still a contiguous range. IDs are assigned in the order functions are first stored and
+//! never reused, so a function keeps its ID after being cleared.
+
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{PoisonError, RwLock},
+};
+
+use eyre::Result;
+
+use crate::FunctionHash;
+
+/// Length of the ID at the start of every key.
+pub(super) const ID_LEN: usize = mem::size_of::<u32>();
+
+/// The IDs a backend has assigned, loaded from its `functions` table when it is opened.
+pub(super) struct FunctionIds(RwLock<HashMap<FunctionHash, u32>>);
+
+impl FunctionIds {
+    /// The IDs in the `(hash, record)` pairs of a `functions` table.
+    pub(super) fn load<'a>(records: impl IntoIterator<Item = (&'a [u8], &'a [u8])>) -> Self {
+        let ids = records
+            .into_iter()
+            .filter_map(|(hash, record)| Some((hash.try_into().ok()?, decode(record)?.0)))
+            .collect();
+        Self(RwLock::new(ids))
+    }
+
+    /// The key prefix of the function with this hash, if it has been assigned one.
+    pub(super) fn get(&self, hash: &FunctionHash) -> Option<[u8; ID_LEN]> {
+        let ids = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        ids.get(hash).map(|id| id.to_be_bytes())
+    }
+
+    /// The key prefix of the function with this hash, assigning it the next ID if it has none.
+    /// A new ID is only used once `persist` has written its record.
+    pub(super) fn assign(
+        &self,
+        hash: &FunctionHash,
+        persist: impl FnOnce(u32) -> Result<()>,
+    ) -> Result<[u8; ID_LEN]> {
+        if let Some(id) = self.get(hash) {
+            return Ok(id);
+        }
+        let mut ids = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(id) = ids.get(hash) {
+            return Ok(id.to_be_bytes());
+        }
+        let id = ids.values().max().map_or(0, |last| last + 1);
+        persist(id)?;
+        ids.insert(*hash, id);
+        Ok(id.to_be_bytes())
+    }
+}
+
+/// The `functions` table record of the function with this ID and name.
+pub(super) fn record(id: u32, name: &str) -> Vec<u8> {
+    [id.to_be_bytes().as_slice(), name.as_bytes()].concat()
+}
+
+/// The ID and name in a `functions` table record.
+pub(super) fn decode(record: &[u8]) -> Option<(u32, String)> {
+    let (id, name) = record.split_first_chunk::<ID_LEN>()?;
+    Some((
+        u32::from_be_bytes(*id),
+        String::from_utf8_lossy(name).into_owned(),
+    ))
+}
@@ -0,0 +1,330 @@
+//! A directory of pretty-printed JSON files, one per entry, for reading cached values while
+//! debugging.
+//!
+//! ```text
+//! <root>/<function name>-<function hash>/<key SHA-256>.json
+//! ```
+//!
+//! Each file spells out the entry: its key, when it was computed and expires, what it depends
+//! on, and its value. Keys and values written as JSON, by functions cached `with = "json"` (or
+//! `with = "serde"` under [`crate::codec::Format::Json`]), appear as JSON; others as hex.
+//! Editing a value changes what is served, which makes it easy to try out a fix downstream of a
+//! cached step. Files are rewritten whole, and parsed whole on every read, so this is for
+//! development, not production.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{eyre, Result, WrapErr};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use super::{from_hex, fs::write_atomic, to_hex, CacheBackend, Description, WriteEntry};
+use crate::{
+    db, dependency::Dependency, entry, value::StoredEntry, Function, FunctionHash, FunctionInfo,
+};
+
+/// Stores entries as pretty-printed JSON files under a root directory.
+pub struct JsonBackend {
+    root: PathBuf,
+}
+
+fn not_found_as_none<T>(result: io::Result<T>) -> io::Result<Option<T>> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+/// `bytes` as the JSON they hold, if they are JSON exactly as `serde_json` writes it, so
+/// writing the value back gives the same bytes.
+fn as_json(bytes: &[u8]) -> Option<Value> {
+    let value: Value = serde_json::from_slice(bytes).ok()?;
+    (serde_json::to_vec(&value).ok()? == bytes).then_some(value)
+}
+
+/// Sets `name` to `bytes` as JSON if they are, and `<name>_hex` to their hex otherwise.
+fn insert_bytes(document: &mut Value, name: &str, bytes: &[u8]) {
+    match as_json(bytes) {
+        Some(value) => document[name] = value,
+        None => document[format!("{name}_hex")] = Value::String(to_hex(bytes)),
+    }
+}
+
+/// The bytes [`insert_bytes`] set in `document` under `name`.
+fn bytes(document: &Value, name: &str) -> Option<Vec<u8>> {
+    if let Some(value) = document.get(name) {
+        return serde_json::to_vec(value).ok();
+    }
+    from_hex(document.get(format!("{name}_hex"))?.as_str()?)
+}
+
+fn secs(time: Option<SystemTime>) -> Value {
+    time.and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+        .map_or(Value::Null, |since| since.as_secs().into())
+}
+
+fn time(value: &Value) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_secs(value.as_u64()?))
+}
+
+/// The JSON document describing `entry`, stored under `key` for `function`.
+fn to_document(function: &Function, key: &[u8], stored: &[u8]) -> Result<Value> {
+    let (header, offset) = entry::decode(stored).map_err(|e| eyre!("invalid entry: {e:?}"))?;
+    let value = entry::value(stored, &header, offset).ok_or_else(|| eyre!("invalid entry"))?;
+    let dependencies: Vec<_> = entry::dependencies(stored, offset)
+        .iter()
+        .map(|dependency| json!({"name": &*dependency.name, "hash": to_hex(&dependency.hash)}))
+        .collect();
+
+    let mut document = json!({
+        "function": function.name(),
+        "function_hash": to_hex(function.hash()),
+    });
+    insert_bytes(&mut document, "key", key);
+    if let Some(debug_key) = entry::debug_key(stored, offset) {
+        document["debug_key"] = debug_key.into();
+    }
+    document["created_at"] = secs(header.created_at);
+    document["expires_at"] = secs(header.expires_at);
+    document["compute_time_us"] = u64::try_from(header.compute_time.as_micros())
+        .unwrap_or(u64::MAX)
+        .into();
+    document["spill"] = header.spill.into();
+    document["type_layout"] = header
+        .type_layout
+        .map_or(Value::Null, |layout| to_hex(&layout.to_le_bytes()).into());
+    document["dependencies"] = dependencies.into();
+    if let Some(events) = entry::events(stored, offset) {
+        document["events_hex"] = to_hex(events).into();
+    }
+    insert_bytes(&mut document, "value", &value);
+    Ok(document)
+}
+
+/// The key and entry [`to_document`] described.
+fn from_document(document: &Value) -> Option<(Vec<u8>, Vec<u8>)> {
+    let key = bytes(document, "key")?;
+    let value = bytes(document, "value")?;
+    let type_layout = match document.get("type_layout") {
+        Some(Value::String(hex)) => Some(u128::from_le_bytes(from_hex(hex)?.try_into().ok()?)),
+        _ => None,
+    };
+    let header = entry::Header {
+        compute_time: Duration::from_micros(document.get("compute_time_us")?.as_u64()?),
+        expires_at: document.get("expires_at").and_then(time),
+        created_at: document.get("created_at").and_then(time),
+        compressed: false,
+        spill: document.get("spill").and_then(Value::as_bool) == Some(true),
+        type_layout,
+    };
+    let dependencies = document
+        .get("dependencies")?
+        .as_array()?
+        .iter()
+        .map(|dependency| {
+            Some(Dependency {
+                name: dependency.get("name")?.as_str()?.into(),
+                hash: from_hex(dependency.get("hash")?.as_str()?)?
+                    .try_into()
+                    .ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let debug_key = document.get("debug_key").and_then(Value::as_str);
+    let events = match document.get("events_hex") {
+        Some(hex) => Some(from_hex(hex.as_str()?)?),
+        None => None,
+    };
+    let entry = entry::encode(header, &dependencies, debug_key, events.as_deref(), &value);
+    Some((key, entry))
+}
+
+/// Reads the document at `path`, or `None` if there is none or it isn't valid, say after a
+/// botched edit.
+fn read_document(path: &Path) -> Result<Option<Value>> {
+    let Some(contents) = not_found_as_none(fs::read(path))? else {
+        return Ok(None);
+    };
+    match serde_json::from_slice(&contents) {
+        Ok(document) => Ok(Some(document)),
+        Err(e) => {
+            warn!("Ignoring {}, which isn't valid JSON: {e}", path.display());
+            Ok(None)
+        }
+    }
+}
+
+impl JsonBackend {
+    /// Uses `root` as the store, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be created.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root).wrap_err("failed to create cache directory")?;
+        Ok(Self { root })
+    }
+
+    /// Uses the `json` directory under the cache directory as the store.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be created.
+    pub fn in_cache_dir() -> Result<Self> {
+        Self::open(db::cache_dir()?.join("json"))
+    }
+
+    fn function_dir(&self, function: &Function) -> PathBuf {
+        self.root
+            .join(format!("{}-{}", function.name(), to_hex(function.hash())))
+    }
+
+    fn entry_path(&self, function: &Function, key: &[u8]) -> PathBuf {
+        self.function_dir(function)
+            .join(format!("{}.json", to_hex(&Sha256::digest(key))))
+    }
+
+    fn function_dirs(&self) -> Result<Vec<(String, FunctionHash, PathBuf)>> {
+        let mut dirs = Vec::new();
+        for dir in fs::read_dir(&self.root)? {
+            let dir = dir?;
+            let parsed = dir.file_name().to_str().and_then(|name| {
+                let (function, hash) = name.rsplit_once('-')?;
+                Some((function.to_string(), from_hex(hash)?.try_into().ok()?))
+            });
+            if let Some((name, hash)) = parsed {
+                dirs.push((name, hash, dir.path()));
+            }
+        }
+        Ok(dirs)
+    }
+
+    fn dir_of(&self, function: &FunctionHash) -> Result<Option<PathBuf>> {
+        Ok(self
+            .function_dirs()?
+            .into_iter()
+            .find(|(_, hash, _)| hash == function)
+            .map(|(_, _, dir)| dir))
+    }
+
+    /// Calls `f` with the path and document of every entry file in `dir`.
+    fn for_each_document(dir: &Path, f: &mut dyn FnMut(&Path, &Value)) -> Result<()> {
+        let Some(files) = not_found_as_none(fs::read_dir(dir))? else {
+            return Ok(());
+        };
+        for file in files {
+            let path = file?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            if let Some(document) = read_document(&path)? {
+                f(&path, &document);
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with the path and document of every entry file, of every function.
+    fn for_each_stored_document(&self, f: &mut dyn FnMut(&Path, &Value)) -> Result<()> {
+        for (_, _, dir) in self.function_dirs()? {
+            Self::for_each_document(&dir, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl CacheBackend for JsonBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let Some(document) = read_document(&self.entry_path(function, key))? else {
+            return Ok(None);
+        };
+        // A different key with the same digest is as good as a miss.
+        Ok(from_document(&document)
+            .filter(|(stored_key, _)| stored_key == key)
+            .map(|(_, entry)| entry.into()))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        for entry in entries {
+            let document = to_document(entry.function, entry.key, entry.entry)?;
+            let path = self.entry_path(entry.function, entry.key);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            write_atomic(&path, &[&serde_json::to_vec_pretty(&document)?])?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        not_found_as_none(fs::remove_file(self.entry_path(function, key)))?;
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let mut removed = Vec::new();
+        self.for_each_stored_document(&mut |path, document| {
+            if bytes(document, "key").is_some_and(|key| key.starts_with(prefix)) {
+                removed.push(path.to_path_buf());
+            }
+        })?;
+        for path in &removed {
+            not_found_as_none(fs::remove_file(path))?;
+        }
+        Ok(removed.len() as u64)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let mut functions = Vec::new();
+        for (name, hash, dir) in self.function_dirs()? {
+            let mut entries = 0;
+            Self::for_each_document(&dir, &mut |_, _| entries += 1)?;
+            functions.push(FunctionInfo {
+                name,
+                hash,
+                entries,
+            });
+        }
+        Ok(functions)
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        if let Some(dir) = self.dir_of(function)? {
+            not_found_as_none(fs::remove_dir_all(dir))?;
+        }
+        Ok(())
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.for_each_stored_document(&mut |_, document| {
+            if let Some(key) = bytes(document, "key") {
+                f(&key);
+            }
+        })
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let Some(dir) = self.dir_of(function)? else {
+            return Ok(());
+        };
+        Self::for_each_document(&dir, &mut |_, document| {
+            if let Some((key, entry)) = from_document(document) {
+                f(&key, &entry);
+            }
+        })
+    }
+
+    fn describe(&self) -> Description {
+        Description::new("json").location(self.root.display().to_string())
+    }
+}
@@ -0,0 +1,318 @@
+//! A memcached backend (text protocol), for ephemeral shared caching in clusters that already
+//! run memcached.
+//!
+//! Memcached keys are limited to 250 printable bytes, so entries are stored under the SHA-256 of
+//! their cache key:
+//!
+//! ```text
+//! <namespace>:gen:<function hash>                   generation of the function's entries
+//! <namespace>:<function hash>:<generation>:<digest>  an entry, or a chunk manifest
+//! <namespace>:<function hash>:<generation>:<digest>:<n>  the n-th chunk of a large entry
+//! ```
+//!
+//! Entries larger than the server's item size limit are split into chunks. Memcached cannot
+//! enumerate its keys, so a function is cleared by bumping its generation (orphaning the old
+//! entries until they are evicted), and listing functions or keys reports nothing.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use sha2::{Digest, Sha256};
+
+use super::{to_hex, CacheBackend, Description, WriteEntry};
+use crate::{http, value::StoredEntry, Function, FunctionHash, FunctionInfo};
+
+const DEFAULT_NAMESPACE: &str = "smart-cache";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Memcached's default item size limit is 1 MiB, which includes the key and item overhead.
+const DEFAULT_CHUNK_SIZE: usize = 1000 * 1024;
+
+/// Item flags marking how a stored value is laid out.
+const FLAG_PLAIN: u32 = 0;
+const FLAG_CHUNKED: u32 = 1;
+
+/// A value read back from the server, with its flags.
+struct Item {
+    flags: u32,
+    data: Vec<u8>,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn open(address: &str) -> Result<Self> {
+        let writer = http::connect(address, TIMEOUT)?;
+        writer.set_nodelay(true)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self { reader, writer })
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        if !line.ends_with("\r\n") {
+            bail!("connection closed by the memcached server");
+        }
+        line.truncate(line.len() - 2);
+        Ok(line)
+    }
+
+    fn expect(&mut self, expected: &[&str]) -> Result<String> {
+        let line = self.read_line()?;
+        if !expected.contains(&line.as_str()) {
+            bail!("unexpected memcached reply {line:?}");
+        }
+        Ok(line)
+    }
+
+    fn get(&mut self, key: &str) -> Result<Option<Item>> {
+        self.writer.write_all(format!("get {key}\r\n").as_bytes())?;
+
+        let mut item = None;
+        loop {
+            let line = self.read_line()?;
+            if line == "END" {
+                return Ok(item);
+            }
+            // VALUE <key> <flags> <bytes>
+            let mut fields = line.split(' ').skip(2);
+            let mut next = || -> Result<usize> {
+                let field = fields
+                    .next()
+                    .ok_or_else(|| eyre!("malformed reply {line:?}"))?;
+                field.parse().wrap_err("malformed memcached reply")
+            };
+            let flags = u32::try_from(next()?)?;
+            let mut data = vec![0; next()? + 2];
+            self.reader.read_exact(&mut data)?;
+            data.truncate(data.len() - 2);
+            item = Some(Item { flags, data });
+        }
+    }
+
+    fn set(&mut self, key: &str, flags: u32, data: &[u8]) -> Result<()> {
+        let mut command = format!("set {key} {flags} 0 {}\r\n", data.len()).into_bytes();
+        command.extend_from_slice(data);
+        command.extend_from_slice(b"\r\n");
+        self.writer.write_all(&command)?;
+        self.expect(&["STORED"]).map(drop)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<()> {
+        self.writer
+            .write_all(format!("delete {key}\r\n").as_bytes())?;
+        self.expect(&["DELETED", "NOT_FOUND"]).map(drop)
+    }
+
+    /// Increments the counter at `key`, creating it if missing.
+    fn bump(&mut self, key: &str) -> Result<()> {
+        self.writer
+            .write_all(format!("incr {key} 1\r\n").as_bytes())?;
+        if self.read_line()? == "NOT_FOUND" {
+            self.set(key, FLAG_PLAIN, b"1")?;
+        }
+        Ok(())
+    }
+}
+
+/// Stores entries in one or more memcached servers.
+pub struct MemcachedBackend {
+    servers: Vec<(String, Mutex<Option<Connection>>)>,
+    namespace: String,
+    chunk_size: usize,
+}
+
+impl MemcachedBackend {
+    /// Uses the servers at `addresses` (`host:port`), routing each item to one of them by the
+    /// hash of its key. Connections are opened lazily.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no address is given.
+    pub fn new<S: Into<String>>(addresses: impl IntoIterator<Item = S>) -> Result<Self> {
+        let servers: Vec<_> = addresses
+            .into_iter()
+            .map(|address| (address.into(), Mutex::new(None)))
+            .collect();
+        if servers.is_empty() {
+            bail!("at least one memcached server is required");
+        }
+
+        Ok(Self {
+            servers,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Prefixes every memcached key with `namespace` instead of `smart-cache`.
+    #[must_use]
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Splits entries into items of at most `bytes`, for servers configured with an item size
+    /// limit (`-I`) other than the default 1 MiB.
+    #[must_use]
+    pub const fn chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes;
+        self
+    }
+
+    /// Runs `f` on a connection to the server responsible for `key`, reconnecting after errors.
+    fn with_connection<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut Connection) -> Result<T>,
+    ) -> Result<T> {
+        let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+        let [a, b, c, d, e, f_, g, h, ..] = digest;
+        let route = u64::from_le_bytes([a, b, c, d, e, f_, g, h]);
+        // The remainder is below `servers.len()`, so it always fits in a usize.
+        #[allow(clippy::cast_possible_truncation)]
+        let (address, connection) = &self.servers[(route % self.servers.len() as u64) as usize];
+
+        let mut guard = connection.lock().unwrap_or_else(PoisonError::into_inner);
+        let connection = match guard.take() {
+            Some(connection) => connection,
+            None => Connection::open(address)
+                .wrap_err_with(|| format!("failed to connect to memcached at {address}"))?,
+        };
+        let connection = guard.insert(connection);
+
+        let result = f(connection);
+        if result.is_err() {
+            guard.take();
+        }
+        result
+    }
+
+    fn generation_key(&self, function: &FunctionHash) -> String {
+        format!("{}:gen:{}", self.namespace, to_hex(function))
+    }
+
+    fn generation(&self, function: &FunctionHash) -> Result<String> {
+        let key = self.generation_key(function);
+        let item = self.with_connection(&key, |connection| connection.get(&key))?;
+        Ok(item.map_or_else(
+            || "0".to_string(),
+            |item| String::from_utf8_lossy(&item.data).into_owned(),
+        ))
+    }
+
+    fn item_key(&self, function: &FunctionHash, key: &[u8]) -> Result<String> {
+        Ok(format!(
+            "{}:{}:{}:{}",
+            self.namespace,
+            to_hex(function),
+            self.generation(function)?,
+            to_hex(&Sha256::digest(key)),
+        ))
+    }
+
+    fn get_item(&self, key: &str) -> Result<Option<Item>> {
+        self.with_connection(key, |connection| connection.get(key))
+    }
+
+    fn read_chunks(&self, key: &str, manifest: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Ok(count) = <[u8; 4]>::try_from(manifest).map(u32::from_le_bytes) else {
+            bail!("malformed chunk manifest for {key}");
+        };
+
+        let mut entry = Vec::new();
+        for index in 0..count {
+            match self.get_item(&format!("{key}:{index}"))? {
+                Some(chunk) => entry.extend_from_slice(&chunk.data),
+                // A chunk was evicted, so the entry is gone.
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(entry))
+    }
+
+    fn write(&self, entry: &WriteEntry<'_>) -> Result<()> {
+        let key = self.item_key(entry.function.hash(), entry.key)?;
+        if entry.entry.len() <= self.chunk_size {
+            return self.with_connection(&key, |connection| {
+                connection.set(&key, FLAG_PLAIN, entry.entry)
+            });
+        }
+
+        let chunks = entry.entry.chunks(self.chunk_size.max(1));
+        let count = u32::try_from(chunks.len()).wrap_err("entry too large for memcached")?;
+        for (index, chunk) in chunks.enumerate() {
+            let chunk_key = format!("{key}:{index}");
+            self.with_connection(&chunk_key, |connection| {
+                connection.set(&chunk_key, FLAG_PLAIN, chunk)
+            })?;
+        }
+        // The manifest goes last, so readers never see it before all of its chunks.
+        self.with_connection(&key, |connection| {
+            connection.set(&key, FLAG_CHUNKED, &count.to_le_bytes())
+        })
+    }
+}
+
+impl CacheBackend for MemcachedBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let key = self.item_key(function.hash(), key)?;
+        let entry = match self.get_item(&key)? {
+            None => None,
+            Some(Item {
+                flags: FLAG_CHUNKED,
+                data,
+            }) => self.read_chunks(&key, &data)?,
+            Some(item) => Some(item.data),
+        };
+        Ok(entry.map(StoredEntry::from))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        for entry in entries {
+            self.write(entry)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        // Chunks of a large entry are left for the server to evict.
+        let key = self.item_key(function.hash(), key)?;
+        self.with_connection(&key, |connection| connection.delete(&key))
+    }
+
+    fn remove_prefix(&self, _prefix: &[u8]) -> Result<u64> {
+        bail!("memcached cannot enumerate keys, so scopes stored in it cannot be purged")
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        Ok(Vec::new())
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        let key = self.generation_key(function);
+        self.with_connection(&key, |connection| connection.bump(&key))
+    }
+
+    fn for_each_key(&self, _f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        bail!("memcached cannot enumerate keys")
+    }
+
+    fn describe(&self) -> Description {
+        let addresses: Vec<&str> = self
+            .servers
+            .iter()
+            .map(|(address, _)| address.as_str())
+            .collect();
+        Description::new("memcached").location(addresses.join(","))
+    }
+}
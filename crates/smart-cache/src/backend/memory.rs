@@ -0,0 +1,166 @@
+//! A process-local backend that keeps entries in memory.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use eyre::Result;
+
+use super::{CacheBackend, Description, WriteEntry};
+use crate::{value::StoredEntry, Function, FunctionHash, FunctionInfo, FunctionStats};
+
+/// One function's entries.
+struct Entries {
+    name: String,
+    entries: HashMap<Vec<u8>, Arc<[u8]>>,
+}
+
+/// Keeps entries in memory for the lifetime of the process, as the fastest tier of a
+/// [`super::TieredBackend`] or where nothing can be persisted.
+#[derive(Default)]
+pub struct MemoryBackend {
+    functions: RwLock<HashMap<FunctionHash, Entries>>,
+    stats: RwLock<HashMap<FunctionHash, FunctionStats>>,
+}
+
+impl MemoryBackend {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CacheBackend for MemoryBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let functions = self
+            .functions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let entry = functions
+            .get(function.hash())
+            .and_then(|entries| entries.entries.get(key));
+        Ok(entry.map(|entry| Arc::clone(entry).into()))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        let mut functions = self
+            .functions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        for entry in entries {
+            functions
+                .entry(*entry.function.hash())
+                .or_insert_with(|| Entries {
+                    name: entry.function.name().to_string(),
+                    entries: HashMap::new(),
+                })
+                .entries
+                .insert(entry.key.to_vec(), entry.entry.into());
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        let mut functions = self
+            .functions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(entries) = functions.get_mut(function.hash()) {
+            entries.entries.remove(key);
+        }
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let mut functions = self
+            .functions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut removed = 0;
+        for entries in functions.values_mut() {
+            let before = entries.entries.len();
+            entries.entries.retain(|key, _| !key.starts_with(prefix));
+            removed += (before - entries.entries.len()) as u64;
+        }
+        Ok(removed)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let functions = self
+            .functions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(functions
+            .iter()
+            .filter(|(_, entries)| !entries.entries.is_empty())
+            .map(|(hash, entries)| FunctionInfo {
+                name: entries.name.clone(),
+                hash: *hash,
+                entries: entries.entries.len() as u64,
+            })
+            .collect())
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        let mut functions = self
+            .functions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        functions.remove(function);
+        Ok(())
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        let mut stats = self.stats.write().unwrap_or_else(PoisonError::into_inner);
+        for delta in deltas {
+            stats
+                .entry(delta.hash)
+                .or_insert_with(|| FunctionStats {
+                    name: delta.name.clone(),
+                    hash: delta.hash,
+                    ..FunctionStats::default()
+                })
+                .merge(delta);
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        let stats = self.stats.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(stats.values().cloned().collect())
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let functions = self
+            .functions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (key, entry) in functions.get(function).into_iter().flat_map(|e| &e.entries) {
+            f(key, entry);
+        }
+        Ok(())
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        let functions = self
+            .functions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        for key in functions
+            .values()
+            .flat_map(|entries| entries.entries.keys())
+        {
+            f(key);
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        Description::new("memory")
+    }
+}
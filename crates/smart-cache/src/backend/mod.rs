@@ -0,0 +1,436 @@
+//! Storage engines the cache can persist entries to.
+//!
+//! Entries live in redb files by default. [`FsBackend`] instead keeps one file per entry, which
+//! can be inspected and synced with ordinary file tools, and [`RedisBackend`] shares entries
+//! between machines through a Redis server ([`MemcachedBackend`] does the same for memcached
+//! clusters, minus enumeration). [`HttpBackend`] talks to a team cache service such as
+//! [`HttpServer`], and with the `s3` feature, `S3Backend` pushes and pulls results through an
+//! S3-compatible bucket. With the `grpc` feature, `GrpcBackend` does the same as
+//! [`HttpBackend`] over gRPC, against a service such as `GrpcServer`. [`TieredBackend`] stacks
+//! several of these, e.g. a [`MemoryBackend`] in front of a local redb store in front of a
+//! remote one. With the `json` feature, `JsonBackend` keeps each entry in a pretty-printed JSON
+//! file, to read while debugging, and with the `sqlite` feature, `SqliteBackend` keeps entries
+//! in a SQLite database, to query and edit with standard SQL tooling. With the `sled` feature,
+//! `SledBackend` keeps entries in a sled database, for write rates redb's transactions can't
+//! keep up with, and with the `rocksdb` feature, `RocksDbBackend` keeps them in a RocksDB
+//! database, for caches of hundreds of gigabytes whose compaction and block cache need tuning.
+//! Other engines plug in by implementing [`CacheBackend`] and registering an instance with
+//! [`crate::Config::backend`].
+
+mod fs;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod http;
+#[cfg(any(feature = "sled", feature = "rocksdb"))]
+mod ids;
+#[cfg(feature = "json")]
+mod json;
+mod memcached;
+mod memory;
+mod redb;
+mod redis;
+#[cfg(feature = "rocksdb")]
+mod rocksdb;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "sled")]
+mod sled;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod tiered;
+
+#[cfg(feature = "grpc")]
+pub use self::grpc::{GrpcBackend, GrpcServer};
+#[cfg(feature = "json")]
+pub use self::json::JsonBackend;
+#[cfg(feature = "rocksdb")]
+pub use self::rocksdb::{RocksDbBackend, RocksDbCompaction, RocksDbOptions};
+#[cfg(feature = "s3")]
+pub use self::s3::{S3Backend, S3Mode};
+#[cfg(feature = "sled")]
+pub use self::sled::SledBackend;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteBackend;
+pub use self::{
+    fs::FsBackend,
+    http::{HttpBackend, HttpServer},
+    memcached::MemcachedBackend,
+    memory::MemoryBackend,
+    redb::RedbBackend,
+    redis::RedisBackend,
+    tiered::{TieredBackend, WritePolicy},
+};
+pub use crate::value::StoredEntry;
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::Path,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use eyre::{bail, Result};
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::{config, context, db, filter, Function, FunctionHash, FunctionInfo, FunctionStats};
+
+/// One entry to be written as part of a batch.
+pub struct WriteEntry<'a> {
+    /// The function that computed the value.
+    pub function: &'a Function,
+    /// The serialized call arguments.
+    pub key: &'a [u8],
+    /// The enveloped value, to be returned verbatim by [`CacheBackend::get`].
+    pub entry: &'a [u8],
+}
+
+/// A key/value store holding cache entries.
+///
+/// Entries are opaque envelopes (see the `entry` module); the backend only stores and returns
+/// them. Every operation also receives the function the key belongs to, so backends can
+/// partition their data by function and enumerate or drop one function's entries cheaply.
+pub trait CacheBackend: Send + Sync {
+    /// Returns the entry stored under `key`, if any.
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>>;
+
+    /// Writes all `entries`, atomically where the backend supports it.
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()>;
+
+    /// Removes the entry stored under `key`, if any.
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()>;
+
+    /// Removes every entry whose key starts with `prefix`, across all functions. Returns the
+    /// number of entries removed.
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64>;
+
+    /// Lists every function that has entries stored.
+    fn functions(&self) -> Result<Vec<FunctionInfo>>;
+
+    /// Removes every entry stored for the function with this hash.
+    fn clear_function(&self, function: &FunctionHash) -> Result<()>;
+
+    /// Adds `deltas` to the persisted per-function statistics in one transaction. Backends
+    /// that cannot persist statistics ignore them.
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        let _ = deltas;
+        Ok(())
+    }
+
+    /// Returns the persisted per-function statistics.
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        Ok(Vec::new())
+    }
+
+    /// Calls `f` with every stored key.
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()>;
+
+    /// Calls `f` with the key and entry of everything stored for the function with this hash.
+    /// Backends that cannot enumerate their entries fail, which rules out exporting them.
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let _ = (function, f);
+        bail!("this cache backend cannot enumerate its entries")
+    }
+
+    /// Blocks until writes the backend has accepted but deferred are persisted.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// What kind of backend this is and where it keeps its entries, for [`crate::status`].
+    fn describe(&self) -> Description {
+        Description::new("custom")
+    }
+}
+
+/// What a [`CacheBackend`] reports about itself, for [`crate::status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Description {
+    /// The kind of backend, e.g. `redb` or `redis`.
+    pub kind: &'static str,
+    /// Where the backend keeps its entries: a path, an address or a URL, without credentials.
+    pub location: Option<String>,
+    /// Bytes the backend occupies on disk, if it keeps its entries in files it can measure
+    /// cheaply.
+    pub size: Option<u64>,
+    /// Version of the backend's storage layout, if it records one.
+    pub layout_version: Option<u64>,
+}
+
+impl Description {
+    #[must_use]
+    pub const fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            location: None,
+            size: None,
+            layout_version: None,
+        }
+    }
+
+    #[must_use]
+    pub fn location(mut self, location: impl Into<String>) -> Self {
+        self.location = Some(location.into());
+        self
+    }
+
+    #[must_use]
+    pub const fn size(mut self, bytes: u64) -> Self {
+        self.size = Some(bytes);
+        self
+    }
+
+    #[must_use]
+    pub const fn layout_version(mut self, version: u64) -> Self {
+        self.layout_version = Some(version);
+        self
+    }
+}
+
+impl<B: CacheBackend + ?Sized> CacheBackend for Arc<B> {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        (**self).get(function, key)
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        (**self).insert_batch(entries)
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        (**self).remove(function, key)
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        (**self).remove_prefix(prefix)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        (**self).functions()
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        (**self).clear_function(function)
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        (**self).merge_stats(deltas)
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        (**self).stats()
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        (**self).for_each_key(f)
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        (**self).for_each_function_entry(function, f)
+    }
+
+    fn flush(&self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn describe(&self) -> Description {
+        (**self).describe()
+    }
+}
+
+/// Lowercase hex encoding of `bytes`.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Name of the store shared by every program when [`crate::Config::shared_store`] is enabled.
+const SHARED_STORE: &str = "cache";
+
+/// Stores opened so far, by name. A `None` value records a store that could not be opened
+/// (read-only filesystem, sandbox, ...), for which caching degrades to a no-op and cached
+/// functions simply compute. Stores live for the rest of the program, so they are leaked to
+/// hand out `'static` references.
+static STORES: Lazy<RwLock<HashMap<String, Option<&'static dyn CacheBackend>>>> =
+    Lazy::new(RwLock::default);
+
+/// Name of the store holding `function`'s entries.
+///
+/// Functions with an explicit `#[cached(db = "...")]` use that database. Everything else goes to
+/// the configured store name, the shared store if opted into, or otherwise a store named after
+/// the crate (and version) that defines the function, so unrelated programs don't contend for
+/// the same file.
+pub(crate) fn store_name(function: &Function) -> &'static str {
+    let config = config::get();
+    function
+        .database()
+        .or(config.store_name.as_deref())
+        .or_else(|| {
+            (!config.shared_store)
+                .then(|| function.package_name())
+                .flatten()
+        })
+        .unwrap_or(SHARED_STORE)
+}
+
+fn open(name: &str) -> Option<&'static dyn CacheBackend> {
+    let config = config::get();
+    if let Some(backend) = config.backends.get(name) {
+        return Some(&*backend.0);
+    }
+
+    match open_default(name, config) {
+        Ok(backend) => {
+            let backend: &'static dyn CacheBackend = Box::leak(backend);
+            filter::populate(backend);
+            Some(backend)
+        }
+        Err(e) => {
+            warn!("Failed to open cache database {name:?}, caching is disabled for it: {e:#}");
+            None
+        }
+    }
+}
+
+/// Opens a store that wasn't registered with [`crate::Config::backend`] as a redb file.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn open_default(name: &str, config: &config::Config) -> Result<Box<dyn CacheBackend>> {
+    let path = match config.databases.get(name) {
+        Some(path) => path.clone(),
+        None => db::cache_dir()?.join(format!("{name}.redb")),
+    };
+    open_file(&path, config)
+}
+
+/// Opens the redb file at `path` as a store, set up as the config has it.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) fn open_file(path: &Path, config: &config::Config) -> Result<Box<dyn CacheBackend>> {
+    let mut backend = RedbBackend::open(path, config.shards)?;
+    if let Some(min_bytes) = config.deduplicate {
+        backend = backend.deduplicate(min_bytes);
+    }
+    if let Some(min_bytes) = config.spill {
+        backend = backend.spill(min_bytes);
+    }
+    Ok(Box::new(backend))
+}
+
+/// Browsers have no filesystem for redb to live in, so unregistered stores keep their entries
+/// in memory for the lifetime of the page.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn open_default(_name: &str, _config: &config::Config) -> Result<Box<dyn CacheBackend>> {
+    Ok(Box::new(MemoryBackend::new()))
+}
+
+/// Browsers have no filesystem, so stores meant to live in a file are kept in memory.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) fn open_file(_path: &Path, _config: &config::Config) -> Result<Box<dyn CacheBackend>> {
+    Ok(Box::new(MemoryBackend::new()))
+}
+
+/// The store called `name`, opened on first use, or the thread's [isolated] store if it has
+/// one.
+///
+/// [isolated]: crate::testing::isolated
+pub(crate) fn named(name: &str) -> Option<&'static dyn CacheBackend> {
+    if let Some(store) = context::store() {
+        return Some(store);
+    }
+    if let Some(backend) = STORES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(name)
+    {
+        return *backend;
+    }
+
+    *STORES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(name.to_string())
+        .or_insert_with(|| open(name))
+}
+
+/// Whether misses of the miss filter are definite for `function`'s store: it is a file this
+/// process holds the lock of, so no one else adds entries to it. Registered backends are shared
+/// with other processes (or could be), so they are always asked.
+pub(crate) fn filterable(function: &Function) -> bool {
+    context::store().is_none() && !config::get().backends.contains_key(store_name(function))
+}
+
+/// The backend holding `function`'s entries.
+pub(crate) fn for_function(function: &Function) -> Option<&'static dyn CacheBackend> {
+    named(store_name(function))
+}
+
+/// The backend holding `function`'s entries, if its store has been opened (or failed to open)
+/// already.
+pub(crate) fn opened_for(function: &Function) -> Option<Option<&'static dyn CacheBackend>> {
+    if let Some(store) = context::store() {
+        return Some(Some(store));
+    }
+    let stores = STORES.read().unwrap_or_else(PoisonError::into_inner);
+    stores.get(store_name(function)).copied()
+}
+
+/// Every store this process has opened so far.
+pub(crate) fn opened() -> Vec<&'static dyn CacheBackend> {
+    if let Some(store) = context::store() {
+        return vec![store];
+    }
+    let stores = STORES.read().unwrap_or_else(PoisonError::into_inner);
+    stores.values().flatten().copied().collect()
+}
+
+/// Like [`all`], along with each store's name.
+pub(crate) fn all_named() -> Vec<(String, &'static dyn CacheBackend)> {
+    if let Some(store) = context::store() {
+        return vec![(SHARED_STORE.to_string(), store)];
+    }
+    all();
+    let stores = STORES.read().unwrap_or_else(PoisonError::into_inner);
+    stores
+        .iter()
+        .filter_map(|(name, backend)| Some((name.clone(), (*backend)?)))
+        .collect()
+}
+
+/// Every store this process has used, plus the configured and registered ones.
+///
+/// Stores named after a crate are only known once one of that crate's cached functions has
+/// been called, so maintenance calls made before then don't see them.
+pub(crate) fn all() -> Vec<&'static dyn CacheBackend> {
+    if let Some(store) = context::store() {
+        return vec![store];
+    }
+    let config = config::get();
+    if let Some(name) = &config.store_name {
+        named(name);
+    } else if config.shared_store {
+        named(SHARED_STORE);
+    }
+    for name in config.databases.keys().chain(config.backends.keys()) {
+        named(name);
+    }
+    opened()
+}
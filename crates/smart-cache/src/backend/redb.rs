@@ -0,0 +1,782 @@
+//! The default backend: one or more redb database files in the cache directory.
+//!
+//! Each cached function gets its own table, named after its hash, so everything belonging to
+//! one function can be enumerated or dropped without scanning the entries of all the others.
+//! A `functions` index table maps each hash to the function's name. Keys hold only the
+//! arguments, since the table already says which function an entry belongs to.
+//!
+//! With [`RedbBackend::deduplicate`], large values are stored once per file in a `blobs` table
+//! keyed by their SHA-256, and entries hold a reference to the blob in place of the value. The
+//! `blob_refs` table counts the entries referencing each blob, which is deleted along with the
+//! last of them.
+//!
+//! With [`RedbBackend::spill`], values too large to keep in the database well go into files
+//! instead, in a `<file stem>.values` directory next to the database and named after the
+//! value's SHA-256. Entries reference them the same way, sharing the reference counts. On Unix,
+//! reads map spilled values into memory instead of copying them.
+//!
+//! Each file records the version of this layout in its `meta` table. Opening a file written
+//! with an older layout migrates it in place, and opening one written by a newer smart-cache
+//! fails rather than misreading it. Entries carry their own format version in their envelope.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use eyre::{bail, Result, WrapErr};
+use redb::{
+    Database, ReadTransaction, ReadableTable, ReadableTableMetadata, Table, TableDefinition,
+    TableError, WriteTransaction,
+};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::{to_hex, CacheBackend, Description, WriteEntry};
+#[cfg(unix)]
+use crate::mapped::Mapping;
+use crate::{
+    config, db, entry, value::StoredEntry, Durability, Function, FunctionHash, FunctionInfo,
+    FunctionStats,
+};
+
+/// Table used before entries were split per function. Its entries are unreachable now.
+const LEGACY_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("cache");
+
+/// Database-wide records, such as [`SCHEMA_VERSION_KEY`].
+const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Upgrades a database from the layout version at its index to the next one. Files from
+/// before the version was recorded are version 0.
+const MIGRATIONS: [fn(&WriteTransaction) -> Result<()>; 2] =
+    [drop_legacy_table, create_blob_tables];
+
+/// The layout version this build writes.
+const SCHEMA_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// Index of the functions stored in a database: function hash to function name.
+const FUNCTIONS: TableDefinition<&[u8], &str> = TableDefinition::new("functions");
+
+/// Persisted per-function statistics, kept in the first shard: function hash to the counters
+/// encoded by [`encode_stats`].
+const STATS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("stats");
+
+const STATS_COUNTERS: usize = 5;
+
+/// Deduplicated values: SHA-256 of the value to the value.
+const BLOBS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("blobs");
+
+/// Number of entries referencing each value in [`BLOBS`] or in a file.
+const BLOB_REFS: TableDefinition<&[u8], u64> = TableDefinition::new("blob_refs");
+
+/// Starts an entry whose value is in [`BLOBS`]: `[BLOB_REFERENCE][value hash][envelope]`, where
+/// the envelope is the entry up to its value. Entries start with a checksum, which is
+/// vanishingly unlikely to match.
+const BLOB_REFERENCE: &[u8] = b"sc-blob\0";
+
+/// Starts an entry whose value was spilled to a file, laid out like a [`BLOB_REFERENCE`].
+const FILE_REFERENCE: &[u8] = b"sc-file\0";
+
+const BLOB_HASH_LEN: usize = 32;
+
+/// Where the value of an entry stored by reference lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Location {
+    Table,
+    File,
+}
+
+/// Where the value of an entry stored as `stored` lives and its hash, if it is stored by
+/// reference.
+fn reference(stored: &[u8]) -> Option<(Location, &[u8])> {
+    let (location, rest) = match stored.strip_prefix(BLOB_REFERENCE) {
+        Some(rest) => (Location::Table, rest),
+        None => (Location::File, stored.strip_prefix(FILE_REFERENCE)?),
+    };
+    Some((location, rest.get(..BLOB_HASH_LEN)?))
+}
+
+/// The hash of the value an entry stored as `stored` references, if it references one.
+fn referenced_blob(stored: &[u8]) -> Option<&[u8]> {
+    reference(stored).map(|(_, hash)| hash)
+}
+
+/// Which values are stored by reference rather than inline.
+#[derive(Debug, Clone, Copy, Default)]
+struct Thresholds {
+    /// Values at least this large go into [`BLOBS`].
+    deduplicate: Option<usize>,
+    /// Values at least this large go into files.
+    spill: Option<usize>,
+}
+
+impl Thresholds {
+    fn location(self, len: usize) -> Option<Location> {
+        let reaches =
+            |threshold: Option<usize>| threshold.is_some_and(|threshold| len >= threshold);
+        if reaches(self.spill) {
+            Some(Location::File)
+        } else if reaches(self.deduplicate) {
+            Some(Location::Table)
+        } else {
+            None
+        }
+    }
+}
+
+/// The tables and files holding the values of a write transaction's entries stored by
+/// reference. Values are shared by every entry holding the same bytes, whichever way it
+/// references them, and deleted along with the last reference.
+struct Blobs<'a> {
+    blobs: Table<'a, &'static [u8], &'static [u8]>,
+    refs: Table<'a, &'static [u8], u64>,
+    /// Directory of the spilled values.
+    values: &'a Path,
+    /// Values whose last reference was dropped, whose files are deleted unless the transaction
+    /// references them again.
+    released: Vec<Vec<u8>>,
+}
+
+impl<'a> Blobs<'a> {
+    fn open(write_txn: &'a WriteTransaction, values: &'a Path) -> Result<Self> {
+        Ok(Self {
+            blobs: write_txn.open_table(BLOBS)?,
+            refs: write_txn.open_table(BLOB_REFS)?,
+            values,
+            released: Vec::new(),
+        })
+    }
+
+    /// What to store for `entry`: a reference to its value if [`Thresholds`] or its header
+    /// says so, and the entry itself otherwise.
+    fn store<'e>(&mut self, entry: &'e [u8], thresholds: Thresholds) -> Result<Cow<'e, [u8]>> {
+        let Ok((header, offset)) = entry::decode(entry) else {
+            return Ok(Cow::Borrowed(entry));
+        };
+        let (envelope, value) = entry.split_at(offset);
+        let location = if header.spill {
+            Some(Location::File)
+        } else {
+            thresholds.location(value.len())
+        };
+        let Some(location) = location else {
+            return Ok(Cow::Borrowed(entry));
+        };
+
+        let hash: [u8; BLOB_HASH_LEN] = Sha256::digest(value).into();
+        let marker = match location {
+            Location::Table => {
+                if self.blobs.get(hash.as_slice())?.is_none() {
+                    self.blobs.insert(hash.as_slice(), value)?;
+                }
+                BLOB_REFERENCE
+            }
+            Location::File => {
+                spill(&self.values.join(to_hex(&hash)), value)?;
+                FILE_REFERENCE
+            }
+        };
+        let refs = self
+            .refs
+            .get(hash.as_slice())?
+            .map_or(0, |refs| refs.value());
+        self.refs.insert(hash.as_slice(), refs + 1)?;
+        Ok(Cow::Owned([marker, &hash, envelope].concat()))
+    }
+
+    /// Inserts `entry` into `table`, replacing and releasing the entry stored under its key.
+    fn insert(
+        &mut self,
+        table: &mut Table<'_, &'static [u8], &'static [u8]>,
+        entry: &WriteEntry<'_>,
+        thresholds: Thresholds,
+    ) -> Result<()> {
+        let stored = self.store(entry.entry, thresholds)?;
+        let released = table
+            .insert(entry.key, &*stored)?
+            .and_then(|replaced| referenced_blob(replaced.value()).map(<[u8]>::to_vec));
+        match released {
+            Some(hash) => self.release(&hash),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops a reference to the value `hash`, deleting the value if it was the last.
+    fn release(&mut self, hash: &[u8]) -> Result<()> {
+        let refs = self.refs.get(hash)?.map_or(0, |refs| refs.value());
+        match refs {
+            0 | 1 => {
+                self.refs.remove(hash)?;
+                self.blobs.remove(hash)?;
+                self.released.push(hash.to_vec());
+            }
+            refs => {
+                self.refs.insert(hash, refs - 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the reference held by an entry stored as `stored`, if any.
+    fn release_entry(&mut self, stored: &[u8]) -> Result<()> {
+        match referenced_blob(stored) {
+            Some(hash) => self.release(hash),
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes the files of the spilled values no entry references anymore, and closes the
+    /// tables. This happens before the transaction commits, while it holds the write lock, so
+    /// no other writer can find a file about to be deleted and reference it. Should the commit
+    /// then fail, entries left referencing a deleted file are misses, like any damaged entry.
+    fn finish(self) -> Result<()> {
+        for hash in self.released {
+            if self.refs.get(hash.as_slice())?.is_some() {
+                continue;
+            }
+            let path = self.values.join(to_hex(&hash));
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    warn!("Failed to delete spilled value {}: {e:#}", path.display());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes a spilled value to `path` unless an identical one is there already, which stays until
+/// a write transaction drops its last reference. The value is written under a temporary name
+/// first, so readers never see a partial file.
+fn spill(path: &Path, value: &[u8]) -> Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).wrap_err("failed to create spilled value directory")?;
+    }
+    let temporary = path.with_extension(format!("tmp-{}", process::id()));
+    fs::write(&temporary, value)
+        .and_then(|()| fs::rename(&temporary, path))
+        .wrap_err_with(|| format!("failed to write {}", path.display()))
+}
+
+/// Rebuilds an entry stored by reference, or returns `None` for an entry stored whole. An
+/// entry whose value is missing is returned without it, so it fails verification like any
+/// other damaged entry.
+fn resolve(
+    read_txn: &ReadTransaction,
+    values: &Path,
+    stored: &[u8],
+) -> Result<Option<StoredEntry>> {
+    let Some((location, hash)) = reference(stored) else {
+        return Ok(None);
+    };
+    let envelope = &stored[BLOB_REFERENCE.len() + BLOB_HASH_LEN..];
+    let entry = match location {
+        Location::Table => {
+            let mut entry = envelope.to_vec();
+            if let Some(value) = read_txn.open_table(BLOBS)?.get(hash)? {
+                entry.extend_from_slice(value.value());
+            }
+            entry.into()
+        }
+        Location::File => match File::open(values.join(to_hex(hash))) {
+            Ok(file) => read_spilled(envelope, file)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => envelope.to_vec().into(),
+            Err(e) => return Err(e.into()),
+        },
+    };
+    Ok(Some(entry))
+}
+
+/// Joins `envelope` and the spilled value in `file`, mapping the file rather than reading it
+/// where the platform allows.
+fn read_spilled(envelope: &[u8], mut file: File) -> Result<StoredEntry> {
+    #[cfg(unix)]
+    match Mapping::with_prefix(envelope, &file) {
+        Ok(mapping) => return Ok(mapping.into()),
+        Err(e) => debug!("Reading spilled value instead of mapping it: {e}"),
+    }
+    let mut entry = envelope.to_vec();
+    file.read_to_end(&mut entry)?;
+    Ok(entry.into())
+}
+
+pub(super) fn encode_stats(stats: &FunctionStats) -> Vec<u8> {
+    let nanos = |duration: Duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    let counters: [u64; STATS_COUNTERS] = [
+        stats.calls,
+        stats.hits,
+        nanos(stats.compute_time),
+        nanos(stats.time_saved),
+        stats.bytes_written,
+    ];
+
+    let mut bytes: Vec<u8> = counters.iter().flat_map(|c| c.to_le_bytes()).collect();
+    bytes.extend_from_slice(stats.name.as_bytes());
+    // Counters added since follow the name after a NUL, which names never contain, so rows
+    // written before them still decode.
+    bytes.push(0);
+    bytes.extend_from_slice(&nanos(stats.hit_time).to_le_bytes());
+    bytes
+}
+
+pub(super) fn decode_stats(hash: FunctionHash, bytes: &[u8]) -> Option<FunctionStats> {
+    let (counters, rest) = bytes.split_at_checked(STATS_COUNTERS * 8)?;
+    let (name, added) = match rest.iter().position(|&byte| byte == 0) {
+        Some(nul) => (&rest[..nul], &rest[nul + 1..]),
+        None => (rest, &[][..]),
+    };
+    let hit_time = added
+        .first_chunk::<8>()
+        .map_or(0, |counter| u64::from_le_bytes(*counter));
+    let mut counters = counters
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap_or_default()));
+    let mut next = || counters.next().unwrap_or_default();
+
+    Some(FunctionStats {
+        calls: next(),
+        hits: next(),
+        compute_time: Duration::from_nanos(next()),
+        time_saved: Duration::from_nanos(next()),
+        bytes_written: next(),
+        hit_time: Duration::from_nanos(hit_time),
+        name: String::from_utf8_lossy(name).into_owned(),
+        hash,
+    })
+}
+
+fn table_name(function: &FunctionHash) -> String {
+    format!("fn:{}", to_hex(function))
+}
+
+fn table(name: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(name)
+}
+
+/// Stores entries in redb, optionally sharded across several files by function hash so that
+/// concurrent writers of different functions don't serialize on a single file's write lock.
+pub struct RedbBackend {
+    shards: Vec<Shard>,
+    thresholds: Thresholds,
+}
+
+struct Shard {
+    db: Database,
+    path: PathBuf,
+    /// Directory of the values spilled out of `db`.
+    values: PathBuf,
+}
+
+impl Shard {
+    fn open(path: &Path) -> Result<Self> {
+        let (db, path) = db::open(path)?;
+        migrate(&db, &path)?;
+        Ok(Self {
+            db,
+            values: path.with_extension("values"),
+            path,
+        })
+    }
+}
+
+impl RedbBackend {
+    /// Opens `shards` database files. The first shard lives at `path` itself, so an unsharded
+    /// store keeps its file name; the others get a `-shard<N>` suffix.
+    pub fn open(path: &Path, shards: usize) -> Result<Self> {
+        let shards = (0..shards.max(1))
+            .map(|shard| match shard {
+                0 => Shard::open(path),
+                shard => Shard::open(&db::with_stem_suffix(path, &format!("-shard{shard}"))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            shards,
+            thresholds: Thresholds::default(),
+        })
+    }
+
+    /// Stores values of at least `min_bytes` once per database file, however many entries
+    /// hold them, for workloads where many keys produce identical large results. Reading
+    /// such an entry copies the value out of the database.
+    #[must_use]
+    pub fn deduplicate(mut self, min_bytes: usize) -> Self {
+        self.thresholds.deduplicate = Some(min_bytes);
+        self
+    }
+
+    /// Stores values of at least `min_bytes` in files of their own rather than in the
+    /// database, so results of hundreds of megabytes don't bloat the database file and its page
+    /// cache. Spilled values are shared between entries like deduplicated ones, and read back
+    /// from their file on each hit.
+    #[must_use]
+    pub fn spill(mut self, min_bytes: usize) -> Self {
+        self.thresholds.spill = Some(min_bytes);
+        self
+    }
+
+    /// Rewrites every database file to reclaim the space of removed entries. Needs exclusive
+    /// access, so it is meant for maintenance tools rather than running programs.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a file cannot be compacted, for example while a read is in progress.
+    pub fn compact(&mut self) -> Result<()> {
+        for shard in &mut self.shards {
+            shard.db.compact()?;
+        }
+        Ok(())
+    }
+
+    fn shard(&self, function: &FunctionHash) -> &Shard {
+        let [a, b, c, d, e, f, g, h, ..] = *function;
+        let prefix = u64::from_le_bytes([a, b, c, d, e, f, g, h]);
+        // The remainder is below `shards.len()`, so it always fits in a usize.
+        #[allow(clippy::cast_possible_truncation)]
+        let index = (prefix % self.shards.len() as u64) as usize;
+        &self.shards[index]
+    }
+}
+
+/// Brings `db` to [`SCHEMA_VERSION`], running the migrations from its recorded version.
+fn migrate(db: &Database, path: &Path) -> Result<()> {
+    let write_txn = db::begin_write(db)?;
+    let recorded = write_txn
+        .open_table(META)?
+        .get(SCHEMA_VERSION_KEY)?
+        .map_or(0, |version| version.value());
+    if recorded > SCHEMA_VERSION {
+        bail!(
+            "cache database at {} was written by a newer smart-cache (layout version {recorded}, \
+             this version understands up to {SCHEMA_VERSION})",
+            path.display()
+        );
+    }
+    if recorded == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // Both bounds are at most `MIGRATIONS.len()`, so they fit in a usize.
+    #[allow(clippy::cast_possible_truncation)]
+    for migration in &MIGRATIONS[recorded as usize..] {
+        migration(&write_txn)?;
+    }
+    write_txn
+        .open_table(META)?
+        .insert(SCHEMA_VERSION_KEY, SCHEMA_VERSION)?;
+    write_txn.commit()?;
+    debug!(
+        "Migrated cache database at {} from layout version {recorded} to {SCHEMA_VERSION}",
+        path.display()
+    );
+    Ok(())
+}
+
+fn drop_legacy_table(write_txn: &WriteTransaction) -> Result<()> {
+    if write_txn.delete_table(LEGACY_TABLE)? {
+        debug!("Dropped entries stored in the legacy single-table layout");
+    }
+    Ok(())
+}
+
+/// Older builds would take entries referencing blobs for corrupt ones and delete them without
+/// releasing their blobs, so files that may hold such entries must not be opened by them.
+fn create_blob_tables(write_txn: &WriteTransaction) -> Result<()> {
+    write_txn.open_table(BLOBS)?;
+    write_txn.open_table(BLOB_REFS)?;
+    Ok(())
+}
+
+fn insert_into(shard: &Shard, entries: &[&WriteEntry<'_>], thresholds: Thresholds) -> Result<()> {
+    let mut by_function: HashMap<_, Vec<_>> = HashMap::new();
+    for entry in entries {
+        by_function.entry(entry.function).or_default().push(entry);
+    }
+
+    let write_txn = db::begin_write(&shard.db)?;
+    {
+        let mut blobs = Blobs::open(&write_txn, &shard.values)?;
+        for (function, entries) in by_function {
+            write_txn
+                .open_table(FUNCTIONS)?
+                .insert(function.hash().as_slice(), function.name())?;
+
+            let name = table_name(function.hash());
+            let mut table = write_txn.open_table(table(&name))?;
+            for entry in entries {
+                blobs.insert(&mut table, entry, thresholds)?;
+            }
+        }
+        blobs.finish()?;
+    }
+    write_txn.commit()?;
+    Ok(())
+}
+
+/// Reads a database's function index.
+fn indexed_functions(read_txn: &ReadTransaction) -> Result<Vec<(FunctionHash, String)>> {
+    let index = match read_txn.open_table(FUNCTIONS) {
+        Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+        index => index?,
+    };
+
+    let mut functions = Vec::new();
+    for row in index.iter()? {
+        let (hash, name) = row?;
+        if let Ok(hash) = FunctionHash::try_from(hash.value()) {
+            functions.push((hash, name.value().to_string()));
+        }
+    }
+    Ok(functions)
+}
+
+fn for_each_key_in(
+    read_txn: &ReadTransaction,
+    function: &FunctionHash,
+    f: &mut dyn FnMut(&[u8]),
+) -> Result<()> {
+    for entry in read_txn.open_table(table(&table_name(function)))?.iter()? {
+        f(entry?.0.value());
+    }
+    Ok(())
+}
+
+/// Drops the references held by every entry of `function`, before its table is deleted.
+fn release_blobs_of(
+    write_txn: &WriteTransaction,
+    values: &Path,
+    function: &FunctionHash,
+) -> Result<()> {
+    let name = table_name(function);
+    let entries = match write_txn.open_table(table(&name)) {
+        Err(TableError::TableDoesNotExist(_)) => return Ok(()),
+        entries => entries?,
+    };
+    let mut blobs = Blobs::open(write_txn, values)?;
+    for row in entries.iter()? {
+        blobs.release_entry(row?.1.value())?;
+    }
+    blobs.finish()
+}
+
+fn remove_prefix_in(shard: &Shard, prefix: &[u8]) -> Result<u64> {
+    let functions = indexed_functions(&shard.db.begin_read()?)?;
+    let mut removed = 0;
+    let write_txn = db::begin_write(&shard.db)?;
+    {
+        let mut released = Vec::new();
+        for (hash, _) in functions {
+            let mut table = write_txn.open_table(table(&table_name(&hash)))?;
+            table.retain_in::<&[u8], _>(prefix.., |key, stored| {
+                let remove = key.starts_with(prefix);
+                removed += u64::from(remove);
+                released.extend(
+                    referenced_blob(stored)
+                        .filter(|_| remove)
+                        .map(<[u8]>::to_vec),
+                );
+                !remove
+            })?;
+        }
+        let mut blobs = Blobs::open(&write_txn, &shard.values)?;
+        for hash in released {
+            blobs.release(&hash)?;
+        }
+        blobs.finish()?;
+    }
+    write_txn.commit()?;
+    Ok(removed)
+}
+
+impl CacheBackend for RedbBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let name = table_name(function.hash());
+        let shard = self.shard(function.hash());
+        let read_txn = shard.db.begin_read()?;
+        let table = match read_txn.open_table(table(&name)) {
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            table => table?,
+        };
+        let Some(guard) = table.get(key)? else {
+            return Ok(None);
+        };
+        match resolve(&read_txn, &shard.values, guard.value())? {
+            Some(entry) => Ok(Some(entry)),
+            None => Ok(Some(StoredEntry::redb(guard, table))),
+        }
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        for shard in &self.shards {
+            let entries: Vec<_> = entries
+                .iter()
+                .filter(|entry| std::ptr::eq(self.shard(entry.function.hash()), shard))
+                .collect();
+            if !entries.is_empty() {
+                insert_into(shard, &entries, self.thresholds)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        let name = table_name(function.hash());
+        let shard = self.shard(function.hash());
+        let write_txn = db::begin_write(&shard.db)?;
+        {
+            let mut table = write_txn.open_table(table(&name))?;
+            let released = table
+                .remove(key)?
+                .and_then(|removed| referenced_blob(removed.value()).map(<[u8]>::to_vec));
+            let mut blobs = Blobs::open(&write_txn, &shard.values)?;
+            if let Some(hash) = released {
+                blobs.release(&hash)?;
+            }
+            blobs.finish()?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let mut removed = 0;
+        for shard in &self.shards {
+            removed += remove_prefix_in(shard, prefix)?;
+        }
+        Ok(removed)
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        for shard in &self.shards {
+            let read_txn = shard.db.begin_read()?;
+            for (hash, _) in indexed_functions(&read_txn)? {
+                for_each_key_in(&read_txn, &hash, f)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let shard = self.shard(function);
+        let read_txn = shard.db.begin_read()?;
+        let table = match read_txn.open_table(table(&table_name(function))) {
+            Err(TableError::TableDoesNotExist(_)) => return Ok(()),
+            table => table?,
+        };
+        for row in table.iter()? {
+            let (key, stored) = row?;
+            match resolve(&read_txn, &shard.values, stored.value())? {
+                Some(entry) => f(key.value(), &entry),
+                None => f(key.value(), stored.value()),
+            }
+        }
+        Ok(())
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let mut functions = Vec::new();
+        for shard in &self.shards {
+            let read_txn = shard.db.begin_read()?;
+            for (hash, name) in indexed_functions(&read_txn)? {
+                let entries = read_txn.open_table(table(&table_name(&hash)))?.len()?;
+                functions.push(FunctionInfo {
+                    name,
+                    hash,
+                    entries,
+                });
+            }
+        }
+        Ok(functions)
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        let shard = self.shard(function);
+        let write_txn = db::begin_write(&shard.db)?;
+        release_blobs_of(&write_txn, &shard.values, function)?;
+        write_txn.delete_table(table(&table_name(function)))?;
+        write_txn
+            .open_table(FUNCTIONS)?
+            .remove(function.as_slice())?;
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        let write_txn = db::begin_write(&self.shards[0].db)?;
+        {
+            let mut table = write_txn.open_table(STATS)?;
+            for delta in deltas {
+                let mut stats = table
+                    .get(delta.hash.as_slice())?
+                    .and_then(|stored| decode_stats(delta.hash, stored.value()))
+                    .unwrap_or_else(|| FunctionStats {
+                        name: delta.name.clone(),
+                        hash: delta.hash,
+                        ..FunctionStats::default()
+                    });
+                stats.merge(delta);
+                table.insert(delta.hash.as_slice(), encode_stats(&stats).as_slice())?;
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        let read_txn = self.shards[0].db.begin_read()?;
+        let table = match read_txn.open_table(STATS) {
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            table => table?,
+        };
+
+        let mut stats = Vec::new();
+        for row in table.iter()? {
+            let (hash, stored) = row?;
+            let Ok(hash) = FunctionHash::try_from(hash.value()) else {
+                continue;
+            };
+            stats.extend(decode_stats(hash, stored.value()));
+        }
+        Ok(stats)
+    }
+
+    fn flush(&self) -> Result<()> {
+        if config::get().durability == Durability::Immediate {
+            return Ok(());
+        }
+        // Committing with immediate durability persists every commit made before it too.
+        for shard in &self.shards {
+            let mut write_txn = shard.db.begin_write()?;
+            write_txn.set_durability(redb::Durability::Immediate);
+            write_txn.commit()?;
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        let size = self
+            .shards
+            .iter()
+            .filter_map(|shard| fs::metadata(&shard.path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        Description::new("redb")
+            .location(self.shards[0].path.display().to_string())
+            .size(size)
+            .layout_version(SCHEMA_VERSION)
+    }
+}
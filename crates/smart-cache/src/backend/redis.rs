@@ -0,0 +1,615 @@
+//! A Redis (or any RESP-speaking server) backend, for sharing hot results between services and
+//! developer machines.
+//!
+//! ```text
+//! <namespace>:e:<function hash>:<key>  an entry
+//! <namespace>:functions                hash of function hash -> name
+//! <namespace>:stats:<function hash>    hash of statistics counters
+//! ```
+//!
+//! The client is a minimal blocking RESP implementation over a single connection, reconnecting
+//! after errors. When the server cannot be reached, operations go to the fallback backend, if
+//! one was set with [`RedisBackend::with_fallback`]; errors the server replies with
+//! (`WRONGTYPE`, `NOAUTH`, ...) are returned, since they would not go away by themselves.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use tracing::warn;
+
+use super::{from_hex, to_hex, CacheBackend, Description, WriteEntry};
+use crate::{http, value::StoredEntry, Function, FunctionHash, FunctionInfo, FunctionStats};
+
+/// Environment variable read by [`RedisBackend::from_env`].
+const URL_VAR: &str = "SMART_CACHE_REDIS_URL";
+
+const DEFAULT_NAMESPACE: &str = "smart-cache";
+const TIMEOUT: Duration = Duration::from_secs(5);
+const SCAN_COUNT: &str = "1000";
+
+/// A parsed RESP reply.
+#[derive(Debug)]
+enum Reply {
+    Status,
+    Integer,
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn into_bulk(self) -> Option<Vec<u8>> {
+        match self {
+            Self::Bulk(bytes) => bytes,
+            _ => None,
+        }
+    }
+
+    fn into_array(self) -> Vec<Self> {
+        match self {
+            Self::Array(items) => items,
+            _ => Vec::new(),
+        }
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn open(address: &str) -> Result<Self> {
+        let writer = http::connect(address, TIMEOUT)?;
+        writer.set_nodelay(true)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self { reader, writer })
+    }
+
+    fn send(&mut self, command: &[&[u8]]) -> Result<()> {
+        let mut buffer = format!("*{}\r\n", command.len()).into_bytes();
+        for arg in command {
+            buffer.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            buffer.extend_from_slice(arg);
+            buffer.extend_from_slice(b"\r\n");
+        }
+        self.writer.write_all(&buffer)?;
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>> {
+        let mut line = Vec::new();
+        self.reader.read_until(b'\n', &mut line)?;
+        if !line.ends_with(b"\r\n") {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by the Redis server",
+            )
+            .into());
+        }
+        line.truncate(line.len() - 2);
+        Ok(line)
+    }
+
+    fn read_reply(&mut self) -> Result<Reply> {
+        let line = self.read_line()?;
+        let (kind, rest) = line
+            .split_first()
+            .ok_or_else(|| eyre!("empty Redis reply"))?;
+        let rest = String::from_utf8_lossy(rest);
+        let length = || rest.parse::<i64>().wrap_err("malformed Redis reply");
+
+        match kind {
+            b'+' => Ok(Reply::Status),
+            b'-' => Err(eyre!("Redis error: {rest}")),
+            b':' => length().map(|_| Reply::Integer),
+            b'$' => self.read_bulk(length()?),
+            b'*' => {
+                let items = (0..length()?.max(0))
+                    .map(|_| self.read_reply())
+                    .collect::<Result<_>>()?;
+                Ok(Reply::Array(items))
+            }
+            _ => bail!("unexpected Redis reply type {:?}", char::from(*kind)),
+        }
+    }
+
+    fn read_bulk(&mut self, length: i64) -> Result<Reply> {
+        let Ok(length) = usize::try_from(length) else {
+            return Ok(Reply::Bulk(None));
+        };
+        let mut bytes = vec![0; length + 2];
+        self.reader.read_exact(&mut bytes)?;
+        bytes.truncate(length);
+        Ok(Reply::Bulk(Some(bytes)))
+    }
+}
+
+/// Whether `error` means the server couldn't be reached or the connection broke, as opposed to
+/// the server answering with an error.
+fn unreachable(error: &eyre::Report) -> bool {
+    error.chain().any(|cause| cause.is::<io::Error>())
+}
+
+fn exchange(connection: &mut Connection, commands: &[Vec<&[u8]>]) -> Result<Vec<Reply>> {
+    for command in commands {
+        connection.send(command)?;
+    }
+    commands.iter().map(|_| connection.read_reply()).collect()
+}
+
+/// Connection settings parsed from a `redis://[:password@]host[:port][/db]` URL.
+struct Target {
+    address: String,
+    password: Option<String>,
+    database: Option<String>,
+}
+
+fn parse_url(url: &str) -> Result<Target> {
+    let rest = url
+        .strip_prefix("redis://")
+        .ok_or_else(|| eyre!("Redis URL must start with redis://, got {url:?}"))?;
+    let (authority, database) = rest.split_once('/').unwrap_or((rest, ""));
+    let (password, host) = match authority.rsplit_once('@') {
+        Some((credentials, host)) => {
+            let password = credentials.rsplit(':').next().unwrap_or(credentials);
+            (Some(password.to_string()), host)
+        }
+        None => (None, authority),
+    };
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{host}:6379")
+    };
+
+    Ok(Target {
+        address,
+        password,
+        database: (!database.is_empty()).then(|| database.to_string()),
+    })
+}
+
+/// Stores entries in Redis.
+pub struct RedisBackend {
+    target: Target,
+    namespace: String,
+    connection: Mutex<Option<Connection>>,
+    fallback: Option<Box<dyn CacheBackend>>,
+}
+
+impl RedisBackend {
+    /// Connects lazily to the server at `url` (`redis://[:password@]host[:port][/db]`).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the URL is malformed.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            target: parse_url(url)?,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            connection: Mutex::new(None),
+            fallback: None,
+        })
+    }
+
+    /// Connects to the server named by the `SMART_CACHE_REDIS_URL` environment variable, if it
+    /// is set.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the URL is malformed.
+    pub fn from_env() -> Result<Option<Self>> {
+        std::env::var(URL_VAR)
+            .ok()
+            .map(|url| Self::new(&url))
+            .transpose()
+    }
+
+    /// Prefixes every Redis key with `namespace` instead of `smart-cache`, so several caches
+    /// can share a server.
+    #[must_use]
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Serves requests from `fallback` (typically a local [`super::RedbBackend`]) while the
+    /// server cannot be reached.
+    #[must_use]
+    pub fn with_fallback(mut self, fallback: impl CacheBackend + 'static) -> Self {
+        self.fallback = Some(Box::new(fallback));
+        self
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        let mut connection = Connection::open(&self.target.address)?;
+        if let Some(password) = &self.target.password {
+            connection.send(&[b"AUTH", password.as_bytes()])?;
+            connection.read_reply()?;
+        }
+        if let Some(database) = &self.target.database {
+            connection.send(&[b"SELECT", database.as_bytes()])?;
+            connection.read_reply()?;
+        }
+        Ok(connection)
+    }
+
+    /// Sends `commands` in one pipeline and returns their replies in order.
+    fn pipeline(&self, commands: &[Vec<&[u8]>]) -> Result<Vec<Reply>> {
+        let mut guard = self
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let connection = match guard.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+        let connection = guard.insert(connection);
+
+        let replies = exchange(connection, commands);
+        if replies.is_err() {
+            // The remaining replies of the pipeline are still unread, so start over with a
+            // fresh connection next time.
+            guard.take();
+        }
+        replies
+    }
+
+    fn call(&self, command: &[&[u8]]) -> Result<Reply> {
+        let mut replies = self.pipeline(&[command.to_vec()])?;
+        replies.pop().ok_or_else(|| eyre!("missing Redis reply"))
+    }
+
+    /// The fallback backend to retry with after `error`, or `error` itself if the server was
+    /// reached or there is no fallback.
+    fn fallback_after(&self, error: eyre::Report) -> Result<&dyn CacheBackend> {
+        match &self.fallback {
+            Some(fallback) if unreachable(&error) => {
+                warn!("Redis cache unavailable, using the local fallback: {error:#}");
+                Ok(&**fallback)
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Runs `remote`, or `local` against the fallback backend if the server is unreachable.
+    fn or_fallback<T>(
+        &self,
+        remote: impl FnOnce() -> Result<T>,
+        local: impl FnOnce(&dyn CacheBackend) -> Result<T>,
+    ) -> Result<T> {
+        remote().or_else(|e| local(self.fallback_after(e)?))
+    }
+
+    fn function_prefix(&self, function: &FunctionHash) -> String {
+        format!("{}:e:{}:", self.namespace, to_hex(function))
+    }
+
+    fn entry_key(&self, function: &FunctionHash, key: &[u8]) -> Vec<u8> {
+        [self.function_prefix(function).as_bytes(), key].concat()
+    }
+
+    fn functions_key(&self) -> String {
+        format!("{}:functions", self.namespace)
+    }
+
+    fn stats_key(&self, function: &FunctionHash) -> String {
+        format!("{}:stats:{}", self.namespace, to_hex(function))
+    }
+
+    /// Calls `f` with every Redis key matching `pattern`.
+    fn scan(&self, pattern: &str, f: &mut dyn FnMut(Vec<u8>) -> Result<()>) -> Result<()> {
+        let mut cursor = b"0".to_vec();
+        loop {
+            let command: [&[u8]; 6] = [
+                b"SCAN",
+                &cursor,
+                b"MATCH",
+                pattern.as_bytes(),
+                b"COUNT",
+                SCAN_COUNT.as_bytes(),
+            ];
+            let mut reply = self.call(&command)?.into_array().into_iter();
+            cursor = reply.next().and_then(Reply::into_bulk).unwrap_or_default();
+            let keys = reply.next().map(Reply::into_array).unwrap_or_default();
+            keys.into_iter()
+                .filter_map(Reply::into_bulk)
+                .try_for_each(&mut *f)?;
+            if cursor == b"0" || cursor.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn indexed_functions(&self) -> Result<Vec<(FunctionHash, String)>> {
+        let reply = self.call(&[b"HGETALL", self.functions_key().as_bytes()])?;
+        let mut fields = reply.into_array().into_iter().filter_map(Reply::into_bulk);
+
+        let mut functions = Vec::new();
+        while let (Some(hash), Some(name)) = (fields.next(), fields.next()) {
+            let hash = std::str::from_utf8(&hash)
+                .ok()
+                .and_then(from_hex)
+                .and_then(|hash| FunctionHash::try_from(hash).ok());
+            if let Some(hash) = hash {
+                functions.push((hash, String::from_utf8_lossy(&name).into_owned()));
+            }
+        }
+        Ok(functions)
+    }
+
+    fn remote_insert(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        let functions_key = self.functions_key();
+        let hashes: Vec<_> = entries
+            .iter()
+            .map(|entry| to_hex(entry.function.hash()))
+            .collect();
+        let keys: Vec<_> = entries
+            .iter()
+            .map(|entry| self.entry_key(entry.function.hash(), entry.key))
+            .collect();
+
+        let mut commands = Vec::with_capacity(entries.len() * 2);
+        for ((entry, hash), key) in entries.iter().zip(&hashes).zip(&keys) {
+            commands.push(vec![&b"SET"[..], key, entry.entry]);
+            commands.push(vec![
+                &b"HSET"[..],
+                functions_key.as_bytes(),
+                hash.as_bytes(),
+                entry.function.name().as_bytes(),
+            ]);
+        }
+        self.pipeline(&commands)?;
+        Ok(())
+    }
+
+    /// Calls `f` with the Redis key and cache key of every stored entry.
+    fn for_each_entry(&self, mut f: impl FnMut(&[u8], &[u8]) -> Result<()>) -> Result<()> {
+        for (hash, _) in self.indexed_functions()? {
+            let function_prefix = self.function_prefix(&hash);
+            self.scan(&format!("{function_prefix}*"), &mut |key| {
+                f(&key, &key[function_prefix.len()..])
+            })?;
+        }
+        Ok(())
+    }
+
+    fn remote_for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let function_prefix = self.function_prefix(function);
+        let mut keys = Vec::new();
+        self.scan(&format!("{function_prefix}*"), &mut |key| {
+            keys.push(key);
+            Ok(())
+        })?;
+
+        for redis_key in keys {
+            // Entries may be evicted between the scan and the read.
+            if let Some(entry) = self.call(&[b"GET", &redis_key])?.into_bulk() {
+                f(&redis_key[function_prefix.len()..], &entry);
+            }
+        }
+        Ok(())
+    }
+
+    fn remote_remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let mut doomed = Vec::new();
+        self.for_each_entry(|redis_key, key| {
+            if key.starts_with(prefix) {
+                doomed.push(redis_key.to_vec());
+            }
+            Ok(())
+        })?;
+
+        let commands: Vec<_> = doomed.iter().map(|key| vec![&b"DEL"[..], key]).collect();
+        self.pipeline(&commands)?;
+        Ok(doomed.len() as u64)
+    }
+
+    fn remote_functions(&self) -> Result<Vec<FunctionInfo>> {
+        let mut functions = Vec::new();
+        for (hash, name) in self.indexed_functions()? {
+            let mut entries = 0;
+            self.scan(&format!("{}*", self.function_prefix(&hash)), &mut |_| {
+                entries += 1;
+                Ok(())
+            })?;
+            functions.push(FunctionInfo {
+                name,
+                hash,
+                entries,
+            });
+        }
+        Ok(functions)
+    }
+
+    fn remote_clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.scan(
+            &format!("{}*", self.function_prefix(function)),
+            &mut |key| self.call(&[b"DEL", &key]).map(drop),
+        )?;
+        let hash = to_hex(function);
+        self.call(&[b"HDEL", self.functions_key().as_bytes(), hash.as_bytes()])?;
+        Ok(())
+    }
+
+    fn remote_for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.for_each_entry(|_, key| {
+            f(key);
+            Ok(())
+        })
+    }
+
+    fn remote_merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        let nanos = |duration: Duration| duration.as_nanos().to_string();
+        let rows: Vec<_> = deltas
+            .iter()
+            .map(|delta| {
+                let counters = [
+                    ("calls", delta.calls.to_string()),
+                    ("hits", delta.hits.to_string()),
+                    ("compute_ns", nanos(delta.compute_time)),
+                    ("saved_ns", nanos(delta.time_saved)),
+                    ("bytes_written", delta.bytes_written.to_string()),
+                    ("hit_ns", nanos(delta.hit_time)),
+                ];
+                (self.stats_key(&delta.hash), delta, counters)
+            })
+            .collect();
+
+        let mut commands = Vec::new();
+        for (key, delta, counters) in &rows {
+            commands.push(vec![
+                &b"HSET"[..],
+                key.as_bytes(),
+                b"name",
+                delta.name.as_bytes(),
+            ]);
+            for (field, value) in counters {
+                commands.push(vec![
+                    &b"HINCRBY"[..],
+                    key.as_bytes(),
+                    field.as_bytes(),
+                    value.as_bytes(),
+                ]);
+            }
+        }
+        self.pipeline(&commands)?;
+        Ok(())
+    }
+
+    fn remote_stats(&self) -> Result<Vec<FunctionStats>> {
+        let prefix = format!("{}:stats:", self.namespace);
+        let mut keys = Vec::new();
+        self.scan(&format!("{prefix}*"), &mut |key| {
+            keys.push(key);
+            Ok(())
+        })?;
+
+        let mut stats = Vec::new();
+        for key in keys {
+            let hash = std::str::from_utf8(&key[prefix.len()..])
+                .ok()
+                .and_then(from_hex)
+                .and_then(|hash| FunctionHash::try_from(hash).ok());
+            if let Some(hash) = hash {
+                let fields = self.call(&[b"HGETALL", &key])?.into_array();
+                stats.push(decode_stats(hash, fields));
+            }
+        }
+        Ok(stats)
+    }
+}
+
+fn decode_stats(hash: FunctionHash, fields: Vec<Reply>) -> FunctionStats {
+    let mut stats = FunctionStats {
+        hash,
+        ..FunctionStats::default()
+    };
+    let mut fields = fields.into_iter().filter_map(Reply::into_bulk);
+    while let (Some(field), Some(value)) = (fields.next(), fields.next()) {
+        let value = String::from_utf8_lossy(&value);
+        let counter = value.parse().unwrap_or_default();
+        match field.as_slice() {
+            b"name" => stats.name = value.into_owned(),
+            b"calls" => stats.calls = counter,
+            b"hits" => stats.hits = counter,
+            b"compute_ns" => stats.compute_time = Duration::from_nanos(counter),
+            b"saved_ns" => stats.time_saved = Duration::from_nanos(counter),
+            b"bytes_written" => stats.bytes_written = counter,
+            b"hit_ns" => stats.hit_time = Duration::from_nanos(counter),
+            _ => {}
+        }
+    }
+    stats
+}
+
+impl CacheBackend for RedisBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        self.or_fallback(
+            || {
+                let key = self.entry_key(function.hash(), key);
+                let entry = self.call(&[b"GET", &key])?.into_bulk();
+                Ok(entry.map(StoredEntry::from))
+            },
+            |fallback| fallback.get(function, key),
+        )
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        self.or_fallback(
+            || self.remote_insert(entries),
+            |fallback| fallback.insert_batch(entries),
+        )
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        self.or_fallback(
+            || {
+                let key = self.entry_key(function.hash(), key);
+                self.call(&[b"DEL", &key]).map(drop)
+            },
+            |fallback| fallback.remove(function, key),
+        )
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        self.or_fallback(
+            || self.remote_remove_prefix(prefix),
+            |fallback| fallback.remove_prefix(prefix),
+        )
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        self.or_fallback(|| self.remote_functions(), |fallback| fallback.functions())
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.or_fallback(
+            || self.remote_clear_function(function),
+            |fallback| fallback.clear_function(function),
+        )
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        self.or_fallback(
+            || self.remote_merge_stats(deltas),
+            |fallback| fallback.merge_stats(deltas),
+        )
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        self.or_fallback(|| self.remote_stats(), |fallback| fallback.stats())
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        match self.remote_for_each_function_entry(function, f) {
+            Err(e) => self.fallback_after(e)?.for_each_function_entry(function, f),
+            result => result,
+        }
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        // Keys seen before a failure are reported again by the fallback.
+        match self.remote_for_each_key(f) {
+            Err(e) => self.fallback_after(e)?.for_each_key(f),
+            result => result,
+        }
+    }
+
+    fn describe(&self) -> Description {
+        Description::new("redis").location(self.target.address.clone())
+    }
+}
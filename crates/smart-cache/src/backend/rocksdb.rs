@@ -0,0 +1,383 @@
+//! A RocksDB database, for caches of hundreds of gigabytes where how the store compacts and
+//! caches its files matters more than having a single file.
+//!
+//! Entries live in an `entries` column family keyed by the function's ID followed by the
+//! arguments, so each function's entries are a contiguous range, and clearing a function is
+//! a single range deletion rather than a rewrite. A `functions` family maps each hash to the
+//! function's ID and name (see [`super::ids`]), and a `stats` family holds the per-function
+//! statistics, encoded as the redb backend encodes them. Keeping the small families apart means their lookups and
+//! compactions never touch the entries' files.
+//!
+//! [`RocksDbOptions`] sizes the block cache and memtables of the `entries` family and picks its
+//! compaction style; lookups of absent keys are answered by bloom filters without reading its
+//! files. Writes go to RocksDB's write-ahead log, which is synced before they return with
+//! [`Durability::Immediate`] and in the background with [`Durability::Eventual`]. RocksDB
+//! locks its directory, so only one process can have a database open at a time.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use eyre::{eyre, Result, WrapErr};
+use rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBCompactionStyle, Direction,
+    IteratorMode, Options, WriteBatch, WriteOptions, DB,
+};
+
+use super::{
+    ids::{self, FunctionIds, ID_LEN},
+    redb::{decode_stats, encode_stats},
+    CacheBackend, Description, WriteEntry,
+};
+use crate::{
+    config, value::StoredEntry, Durability, Function, FunctionHash, FunctionInfo, FunctionStats,
+};
+
+/// The column families holding the entries, the functions' IDs and names, and their
+/// statistics. The `default` family RocksDB always opens stays empty.
+const ENTRIES: &str = "entries";
+const FUNCTIONS: &str = "functions";
+const STATS: &str = "stats";
+
+/// Bits per key of the `entries` family's bloom filters, for about a 1% false positive rate.
+const BLOOM_BITS_PER_KEY: f64 = 10.0;
+
+/// How a [`RocksDbBackend`] compacts the files holding its entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RocksDbCompaction {
+    /// Leveled compaction, RocksDB's default: the least disk space and the fewest files read
+    /// per lookup, at the cost of rewriting entries more often.
+    #[default]
+    Level,
+    /// Universal compaction: far fewer rewrites for write-heavy caches, at the cost of needing
+    /// up to twice the store's size in disk space while compacting.
+    Universal,
+}
+
+/// Tuning of a [`RocksDbBackend`], applied when it is opened. Anything not set keeps RocksDB's
+/// default.
+///
+/// ```no_run
+/// use smart_cache::backend::{RocksDbBackend, RocksDbCompaction, RocksDbOptions};
+///
+/// # fn main() -> eyre::Result<()> {
+/// let options = RocksDbOptions::new()
+///     .block_cache(8 << 30)
+///     .write_buffer(256 << 20)
+///     .compaction(RocksDbCompaction::Universal);
+/// let backend = RocksDbBackend::open("/mnt/cache/rocksdb", options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RocksDbOptions {
+    block_cache: Option<usize>,
+    write_buffer: Option<usize>,
+    compaction: RocksDbCompaction,
+    background_jobs: Option<u16>,
+}
+
+impl RocksDbOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep up to `bytes` of recently read blocks of entries in memory.
+    #[must_use]
+    pub const fn block_cache(mut self, bytes: usize) -> Self {
+        self.block_cache = Some(bytes);
+        self
+    }
+
+    /// Buffer up to `bytes` of writes in memory before flushing them to a file. Larger buffers
+    /// mean fewer, larger files and less compaction.
+    #[must_use]
+    pub const fn write_buffer(mut self, bytes: usize) -> Self {
+        self.write_buffer = Some(bytes);
+        self
+    }
+
+    #[must_use]
+    pub const fn compaction(mut self, compaction: RocksDbCompaction) -> Self {
+        self.compaction = compaction;
+        self
+    }
+
+    /// Run up to `jobs` flushes and compactions at once.
+    #[must_use]
+    pub const fn background_jobs(mut self, jobs: u16) -> Self {
+        self.background_jobs = Some(jobs);
+        self
+    }
+}
+
+/// Stores entries in a RocksDB database directory.
+pub struct RocksDbBackend {
+    path: PathBuf,
+    db: DB,
+    ids: FunctionIds,
+    /// Serializes the read-modify-write of statistics merges.
+    stats: Mutex<()>,
+}
+
+impl RocksDbBackend {
+    /// Opens (or creates) the database in the directory at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be opened as a RocksDB database, e.g. because another
+    /// process has it open.
+    pub fn open(path: impl Into<PathBuf>, options: RocksDbOptions) -> Result<Self> {
+        let path = path.into();
+        let mut db_options = Options::default();
+        db_options.create_if_missing(true);
+        db_options.create_missing_column_families(true);
+        if let Some(jobs) = options.background_jobs {
+            db_options.set_max_background_jobs(i32::from(jobs));
+        }
+
+        let mut table = BlockBasedOptions::default();
+        if let Some(bytes) = options.block_cache {
+            table.set_block_cache(&Cache::new_lru_cache(bytes));
+        }
+        table.set_bloom_filter(BLOOM_BITS_PER_KEY, false);
+        let mut entries = Options::default();
+        entries.set_block_based_table_factory(&table);
+        if let Some(bytes) = options.write_buffer {
+            entries.set_write_buffer_size(bytes);
+        }
+        if options.compaction == RocksDbCompaction::Universal {
+            entries.set_compaction_style(DBCompactionStyle::Universal);
+        }
+
+        let families = [
+            ColumnFamilyDescriptor::new(ENTRIES, entries),
+            ColumnFamilyDescriptor::new(FUNCTIONS, Options::default()),
+            ColumnFamilyDescriptor::new(STATS, Options::default()),
+        ];
+        let db = DB::open_cf_descriptors(&db_options, &path, families)
+            .wrap_err_with(|| format!("failed to open {}", path.display()))?;
+        let mut records = Vec::new();
+        let functions = db
+            .cf_handle(FUNCTIONS)
+            .ok_or_else(|| eyre!("missing column family {FUNCTIONS}"))?;
+        for record in db.iterator_cf(functions, IteratorMode::Start) {
+            records.push(record?);
+        }
+        let ids = FunctionIds::load(
+            records
+                .iter()
+                .map(|(hash, record)| (hash.as_ref(), record.as_ref())),
+        );
+        Ok(Self {
+            path,
+            db,
+            ids,
+            stats: Mutex::new(()),
+        })
+    }
+
+    fn family(&self, name: &str) -> Result<&ColumnFamily> {
+        self.db
+            .cf_handle(name)
+            .ok_or_else(|| eyre!("missing column family {name}"))
+    }
+
+    /// Applies `batch` atomically, syncing the write-ahead log first if commits are to be
+    /// durable when they return.
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut options = WriteOptions::default();
+        options.set_sync(config::get().durability == Durability::Immediate);
+        self.db.write_opt(batch, &options)?;
+        Ok(())
+    }
+
+    /// The key prefix of `function`, recording a new ID and its name if it has none.
+    fn assign(&self, function: &Function) -> Result<[u8; ID_LEN]> {
+        self.ids.assign(function.hash(), |id| {
+            let mut batch = WriteBatch::default();
+            let record = ids::record(id, function.name());
+            batch.put_cf(self.family(FUNCTIONS)?, function.hash(), record);
+            self.write(batch)
+        })
+    }
+
+    /// Calls `f` with the key and value of every record of `family` whose key starts with
+    /// `prefix`, in key order.
+    fn scan(&self, family: &str, prefix: &[u8], f: &mut dyn FnMut(&[u8], &[u8])) -> Result<()> {
+        let mode = IteratorMode::From(prefix, Direction::Forward);
+        for record in self.db.iterator_cf(self.family(family)?, mode) {
+            let (key, value) = record?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            f(&key, &value);
+        }
+        Ok(())
+    }
+}
+
+/// The first key after every key starting with `prefix`, or `None` if there is none.
+fn successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&byte| byte != u8::MAX)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
+fn entry_key(id: [u8; ID_LEN], key: &[u8]) -> Vec<u8> {
+    [id.as_slice(), key].concat()
+}
+
+fn hash(bytes: &[u8]) -> Option<FunctionHash> {
+    bytes.try_into().ok()
+}
+
+impl CacheBackend for RocksDbBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let Some(id) = self.ids.get(function.hash()) else {
+            return Ok(None);
+        };
+        let entry = self.db.get_cf(self.family(ENTRIES)?, entry_key(id, key))?;
+        Ok(entry.map(Into::into))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        let stored = self.family(ENTRIES)?;
+        let mut batch = WriteBatch::default();
+        for entry in entries {
+            // IDs are recorded first, so every function with entries has one.
+            let id = self.assign(entry.function)?;
+            batch.put_cf(stored, entry_key(id, entry.key), entry.entry);
+        }
+        self.write(batch)
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        let Some(id) = self.ids.get(function.hash()) else {
+            return Ok(());
+        };
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(self.family(ENTRIES)?, entry_key(id, key));
+        self.write(batch)
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let stored = self.family(ENTRIES)?;
+        let mut batch = WriteBatch::default();
+        let mut removed = 0;
+        self.scan(ENTRIES, &[], &mut |key, _| {
+            if key.get(ID_LEN..).is_some_and(|key| key.starts_with(prefix)) {
+                batch.delete_cf(stored, key);
+                removed += 1;
+            }
+        })?;
+        self.write(batch)?;
+        Ok(removed)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let mut named = Vec::new();
+        self.scan(FUNCTIONS, &[], &mut |hash_bytes, record| {
+            if let (Some(hash), Some((id, name))) = (hash(hash_bytes), ids::decode(record)) {
+                named.push((hash, id, name));
+            }
+        })?;
+        let mut functions = Vec::new();
+        for (hash, id, name) in named {
+            let mut entries = 0;
+            self.scan(ENTRIES, &id.to_be_bytes(), &mut |_, _| entries += 1)?;
+            if entries > 0 {
+                functions.push(FunctionInfo {
+                    name,
+                    hash,
+                    entries,
+                });
+            }
+        }
+        Ok(functions)
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        // The function keeps its ID, since other threads may be about to write under it.
+        let Some(id) = self.ids.get(function) else {
+            return Ok(());
+        };
+        let stored = self.family(ENTRIES)?;
+        let mut batch = WriteBatch::default();
+        match successor(&id) {
+            Some(end) => batch.delete_range_cf(stored, id.as_slice(), &end),
+            // Every key from an ID of all 0xff bytes on starts with it.
+            None => self.scan(ENTRIES, &id, &mut |key, _| batch.delete_cf(stored, key))?,
+        }
+        self.write(batch)
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        let _merging = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        let stats = self.family(STATS)?;
+        let mut batch = WriteBatch::default();
+        for delta in deltas {
+            let mut merged = self
+                .db
+                .get_cf(stats, delta.hash)?
+                .and_then(|stored| decode_stats(delta.hash, &stored))
+                .unwrap_or_else(|| FunctionStats {
+                    name: delta.name.clone(),
+                    hash: delta.hash,
+                    ..FunctionStats::default()
+                });
+            merged.merge(delta);
+            batch.put_cf(stats, delta.hash, encode_stats(&merged));
+        }
+        self.write(batch)
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        let mut stats = Vec::new();
+        self.scan(STATS, &[], &mut |hash_bytes, stored| {
+            if let Some(hash) = hash(hash_bytes) {
+                stats.extend(decode_stats(hash, stored));
+            }
+        })?;
+        Ok(stats)
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.scan(ENTRIES, &[], &mut |key, _| f(&key[ID_LEN..]))
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let Some(id) = self.ids.get(function) else {
+            return Ok(());
+        };
+        self.scan(ENTRIES, &id, &mut |key, entry| f(&key[ID_LEN..], entry))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush_wal(true)?;
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        let description = Description::new("rocksdb").location(self.path.display().to_string());
+        // RocksDB keeps every file of a database directly in its directory.
+        let size = fs::read_dir(&self.path).map(|files| {
+            files
+                .filter_map(|file| file.ok()?.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        });
+        match size {
+            Ok(size) => description.size(size),
+            Err(_) => description,
+        }
+    }
+}
@@ -0,0 +1,506 @@
+//! An S3-compatible object storage backend, for CI jobs and batch workers that pull and push
+//! cached results through a bucket.
+//!
+//! ```text
+//! <prefix>/<function hash>/name              the function's name
+//! <prefix>/<function hash>/<head>.<digest>  an entry: the first 200 bytes of its key in hex,
+//!                                           then the SHA-256 of the whole key
+//! ```
+//!
+//! Like [`super::FsBackend`]'s files, each object holds the key followed by the entry.
+//! Requests are signed with AWS Signature Version 4 and sent over TLS to `https://` endpoints
+//! (AWS itself, e.g. `https://s3.us-east-1.amazonaws.com`), or over plain HTTP to `http://`
+//! ones, such as MinIO on a trusted network.
+//!
+//! Objects are named by their key's leading bytes in hex, so [`CacheBackend::remove_prefix`]
+//! finds a scope's entries by listing them rather than downloading the bucket.
+
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{bail, Result};
+use ring::hmac;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use super::{from_hex, to_hex, CacheBackend, Description, WriteEntry};
+use crate::{
+    http::{self, Url},
+    value::StoredEntry,
+    Function, FunctionHash, FunctionInfo,
+};
+
+const NAME_OBJECT: &str = "name";
+const DEFAULT_PREFIX: &str = "smart-cache";
+const DEFAULT_REGION: &str = "us-east-1";
+/// How many leading key bytes an entry's object name spells out, keeping names well under S3's
+/// 1024-byte limit.
+const HEAD_BYTES: usize = 200;
+
+/// Whether an [`S3Backend`] pushes results to the bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum S3Mode {
+    /// Only read from the bucket; writes, removals, and purges are skipped. Useful for jobs
+    /// that should benefit from a shared cache without being trusted to populate it.
+    ReadOnly,
+    /// Write every computed value to the bucket as it is stored.
+    #[default]
+    WriteThrough,
+}
+
+#[derive(Clone)]
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key: std::env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> hmac::Tag {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message)
+}
+
+/// Formats `time` as an ISO 8601 basic timestamp (`20240131T235959Z`).
+fn amz_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, time_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Howard Hinnant's days-to-civil algorithm, for days since the epoch.
+    let z = days + 719_468;
+    let (era, day_of_era) = (z / 146_097, z % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    )
+}
+
+/// Returns the inner text of every `<tag>` element in `xml`.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Splits an object into its key and entry.
+fn split_object(contents: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = contents.split_first_chunk::<8>()?;
+    let len = usize::try_from(u64::from_le_bytes(*len)).ok()?;
+    rest.split_at_checked(len)
+}
+
+/// One page of a bucket listing.
+#[derive(Default)]
+struct Listing {
+    keys: Vec<String>,
+    common_prefixes: Vec<String>,
+}
+
+/// Stores entries as objects in an S3-compatible bucket.
+pub struct S3Backend {
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    credentials: Option<Credentials>,
+    mode: S3Mode,
+}
+
+impl S3Backend {
+    /// Uses `bucket` at `endpoint` (e.g. `https://s3.us-east-1.amazonaws.com`, or
+    /// `http://localhost:9000` for MinIO), with path-style addressing.
+    ///
+    /// Credentials are read from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, and
+    /// `AWS_SESSION_TOKEN` if set; without them, requests are sent unsigned.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `endpoint` is not an `http://` or `https://` URL.
+    pub fn new(endpoint: &str, bucket: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            endpoint: Url::parse(endpoint)?,
+            bucket: bucket.into(),
+            region: std::env::var("AWS_REGION").unwrap_or_else(|_| DEFAULT_REGION.to_string()),
+            prefix: DEFAULT_PREFIX.to_string(),
+            credentials: Credentials::from_env(),
+            mode: S3Mode::default(),
+        })
+    }
+
+    /// Signs requests for `region` instead of `AWS_REGION` or `us-east-1`.
+    #[must_use]
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.region = region.into();
+        self
+    }
+
+    /// Stores objects under `prefix` instead of `smart-cache`.
+    #[must_use]
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Signs requests with these credentials instead of those from the environment.
+    #[must_use]
+    pub fn credentials(
+        mut self,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some(Credentials {
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            session_token: None,
+        });
+        self
+    }
+
+    #[must_use]
+    pub const fn mode(mut self, mode: S3Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    fn function_prefix(&self, function: &FunctionHash) -> String {
+        format!("{}/{}/", self.prefix, to_hex(function))
+    }
+
+    fn object_key(&self, function: &FunctionHash, key: &[u8]) -> String {
+        format!(
+            "{}{}.{}",
+            self.function_prefix(function),
+            to_hex(&key[..key.len().min(HEAD_BYTES)]),
+            to_hex(&Sha256::digest(key))
+        )
+    }
+
+    /// Headers proving the request's authenticity, per AWS Signature Version 4.
+    fn sign(
+        &self,
+        method: &str,
+        uri: &str,
+        query: &str,
+        body: &[u8],
+    ) -> Vec<(&'static str, String)> {
+        let payload_hash = to_hex(&Sha256::digest(body));
+        let date = amz_date(SystemTime::now());
+        let mut headers = vec![
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", date.clone()),
+        ];
+        let Some(credentials) = &self.credentials else {
+            return headers;
+        };
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+
+        let mut signed: Vec<_> = headers.iter().map(|(n, v)| (*n, v.as_str())).collect();
+        signed.push(("host", &self.endpoint.host));
+        signed.sort_unstable();
+        let canonical_headers: String = signed.iter().map(|(n, v)| format!("{n}:{v}\n")).collect();
+        let signed_headers = signed.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(";");
+
+        let canonical_request = format!(
+            "{method}\n{uri}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{}/{}/s3/aws4_request", &date[..8], self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{date}\n{scope}\n{}",
+            to_hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = hmac(
+            format!("AWS4{}", credentials.secret_key).as_bytes(),
+            &date.as_bytes()[..8],
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(key.as_ref(), part.as_bytes());
+        }
+        let signature = to_hex(hmac(key.as_ref(), string_to_sign.as_bytes()).as_ref());
+
+        headers.push((
+            "Authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+                 Signature={signature}",
+                credentials.access_key
+            ),
+        ));
+        headers
+    }
+
+    /// Sends a request for `object` (or the bucket itself, if empty) with `query` parameters.
+    fn send(
+        &self,
+        method: &str,
+        object: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<http::Response> {
+        let mut uri = format!(
+            "{}/{}",
+            self.endpoint.path,
+            http::encode(&self.bucket, false)
+        );
+        if !object.is_empty() {
+            uri.push('/');
+            uri.push_str(&http::encode(object, true));
+        }
+        let mut query: Vec<_> = query
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}={}",
+                    http::encode(name, false),
+                    http::encode(value, false)
+                )
+            })
+            .collect();
+        query.sort_unstable();
+        let query = query.join("&");
+
+        let headers = self.sign(method, &uri, &query, body);
+        let path_and_query = if query.is_empty() {
+            uri
+        } else {
+            format!("{uri}?{query}")
+        };
+        http::request(method, &self.endpoint, &path_and_query, &headers, body)
+    }
+
+    fn get_object(&self, object: &str) -> Result<Option<Vec<u8>>> {
+        let response = self.send("GET", object, &[], &[])?;
+        if response.status == 404 {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.body))
+    }
+
+    fn put_object(&self, object: &str, body: &[u8]) -> Result<()> {
+        self.send("PUT", object, &[], body)?.error_for_status()?;
+        Ok(())
+    }
+
+    fn delete_object(&self, object: &str) -> Result<()> {
+        let response = self.send("DELETE", object, &[], &[])?;
+        if response.status != 404 {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+
+    /// Lists every object under `prefix`, or with a `/` delimiter, the "directories" under it.
+    fn list(&self, prefix: &str, delimited: bool) -> Result<Listing> {
+        let mut listing = Listing::default();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if delimited {
+                query.push(("delimiter", "/"));
+            }
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let body = self.send("GET", "", &query, &[])?.error_for_status()?.body;
+            let xml = String::from_utf8_lossy(&body);
+
+            for contents in elements(&xml, "Contents") {
+                listing
+                    .keys
+                    .extend(elements(contents, "Key").into_iter().map(unescape_xml));
+            }
+            for common in elements(&xml, "CommonPrefixes") {
+                let prefixes = elements(common, "Prefix").into_iter().map(unescape_xml);
+                listing.common_prefixes.extend(prefixes);
+            }
+
+            token = elements(&xml, "NextContinuationToken")
+                .first()
+                .map(|token| unescape_xml(token));
+            if token.is_none() || elements(&xml, "IsTruncated").first() != Some(&"true") {
+                return Ok(listing);
+            }
+        }
+    }
+
+    /// Lists the entry objects of one function.
+    fn entry_objects(&self, function: &FunctionHash) -> Result<Vec<String>> {
+        let prefix = self.function_prefix(function);
+        let name = format!("{prefix}{NAME_OBJECT}");
+        let mut keys = self.list(&prefix, false)?.keys;
+        keys.retain(|key| *key != name);
+        Ok(keys)
+    }
+
+    fn stored_functions(&self) -> Result<Vec<FunctionHash>> {
+        let prefix = format!("{}/", self.prefix);
+        let listing = self.list(&prefix, true)?;
+        Ok(listing
+            .common_prefixes
+            .iter()
+            .filter_map(|common| {
+                let hex = common.strip_prefix(&prefix)?.trim_end_matches('/');
+                FunctionHash::try_from(from_hex(hex)?).ok()
+            })
+            .collect())
+    }
+
+    /// Whether the key of `object`, listed under the head of `prefix`, starts with `prefix`. Only
+    /// prefixes longer than the head need the object downloaded to tell.
+    fn has_prefix(&self, object: &str, prefix: &[u8]) -> Result<bool> {
+        if prefix.len() <= HEAD_BYTES {
+            return Ok(true);
+        }
+        let contents = self.get_object(object)?.unwrap_or_default();
+        Ok(split_object(&contents).is_some_and(|(key, _)| key.starts_with(prefix)))
+    }
+
+    fn is_read_only(&self, operation: &str) -> bool {
+        let read_only = self.mode == S3Mode::ReadOnly;
+        if read_only {
+            debug!("S3 cache is read-only; skipping {operation}");
+        }
+        read_only
+    }
+}
+
+impl CacheBackend for S3Backend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let Some(object) = self.get_object(&self.object_key(function.hash(), key))? else {
+            return Ok(None);
+        };
+        // A different key with the same digest is as good as a miss.
+        Ok(split_object(&object)
+            .filter(|(stored_key, _)| *stored_key == key)
+            .map(|(_, entry)| entry.to_vec().into()))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        if self.is_read_only("write") {
+            return Ok(());
+        }
+
+        let mut named = HashSet::new();
+        for entry in entries {
+            let function = entry.function.hash();
+            if named.insert(function) {
+                let name = format!("{}{NAME_OBJECT}", self.function_prefix(function));
+                self.put_object(&name, entry.function.name().as_bytes())?;
+            }
+
+            let key_len = entry.key.len() as u64;
+            let object = [&key_len.to_le_bytes(), entry.key, entry.entry].concat();
+            self.put_object(&self.object_key(function, entry.key), &object)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        if self.is_read_only("removal") {
+            return Ok(());
+        }
+        self.delete_object(&self.object_key(function.hash(), key))
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        if self.is_read_only("purge") {
+            return Ok(0);
+        }
+
+        // Object names spell out the head of the key, so listing by it finds the candidates.
+        let head = to_hex(&prefix[..prefix.len().min(HEAD_BYTES)]);
+        let mut candidates = Vec::new();
+        for function in self.stored_functions()? {
+            let function_prefix = self.function_prefix(&function);
+            let name = format!("{function_prefix}{NAME_OBJECT}");
+            let listing = self.list(&format!("{function_prefix}{head}"), false)?;
+            candidates.extend(listing.keys.into_iter().filter(|object| *object != name));
+        }
+
+        let mut removed = 0;
+        for object in candidates {
+            if self.has_prefix(&object, prefix)? {
+                self.delete_object(&object)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let mut functions = Vec::new();
+        for hash in self.stored_functions()? {
+            let name = format!("{}{NAME_OBJECT}", self.function_prefix(&hash));
+            let name = self.get_object(&name)?.unwrap_or_default();
+            functions.push(FunctionInfo {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                hash,
+                entries: self.entry_objects(&hash)?.len() as u64,
+            });
+        }
+        Ok(functions)
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        if self.is_read_only("clear") {
+            return Ok(());
+        }
+        for object in self.list(&self.function_prefix(function), false)?.keys {
+            self.delete_object(&object)?;
+        }
+        Ok(())
+    }
+
+    fn for_each_key(&self, _f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        bail!("listing the keys of an S3 cache would download every object")
+    }
+
+    fn describe(&self) -> Description {
+        Description::new("s3").location(format!("s3://{}/{}", self.bucket, self.prefix))
+    }
+}
@@ -0,0 +1,248 @@
+//! A sled database, for workloads writing faster than redb's transactions commit.
+//!
+//! Entries live in one `entries` tree keyed by the function's ID followed by the arguments, so
+//! each function's entries are a contiguous range that can be enumerated or dropped without
+//! scanning the others. A `functions` tree maps each hash to the function's ID and name (see
+//! [`super::ids`]), and a `stats` tree holds the per-function statistics, encoded as the redb
+//! backend encodes them.
+//!
+//! Batches are applied atomically but without the single write lock redb commits take, and sled
+//! persists them from a background thread. With [`Durability::Immediate`], each write still waits
+//! for its data to reach disk; [`Durability::Eventual`] leaves that to the background thread, so
+//! a crash can lose the most recent writes. sled locks its directory, so only one process can
+//! have a database open at a time.
+
+use std::path::PathBuf;
+
+use eyre::{Result, WrapErr};
+use sled::{transaction::TransactionError, Batch, Db, Tree};
+
+use super::{
+    ids::{self, FunctionIds, ID_LEN},
+    redb::{decode_stats, encode_stats},
+    CacheBackend, Description, WriteEntry,
+};
+use crate::{
+    config, value::StoredEntry, Durability, Function, FunctionHash, FunctionInfo, FunctionStats,
+};
+
+/// Stores entries in a sled database directory.
+pub struct SledBackend {
+    path: PathBuf,
+    db: Db,
+    entries: Tree,
+    functions: Tree,
+    stats: Tree,
+    ids: FunctionIds,
+}
+
+impl SledBackend {
+    /// Opens (or creates) the database in the directory at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be opened as a sled database, e.g. because another
+    /// process has it open.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let db =
+            sled::open(&path).wrap_err_with(|| format!("failed to open {}", path.display()))?;
+        let functions = db.open_tree("functions")?;
+        let records = functions.iter().collect::<sled::Result<Vec<_>>>()?;
+        let ids = FunctionIds::load(
+            records
+                .iter()
+                .map(|(hash, record)| (hash.as_ref(), record.as_ref())),
+        );
+        Ok(Self {
+            entries: db.open_tree("entries")?,
+            functions,
+            stats: db.open_tree("stats")?,
+            ids,
+            path,
+            db,
+        })
+    }
+
+    /// The key prefix of `function`, recording a new ID and its name if it has none.
+    fn assign(&self, function: &Function) -> Result<[u8; ID_LEN]> {
+        self.ids.assign(function.hash(), |id| {
+            let record = ids::record(id, function.name());
+            self.functions.insert(function.hash().as_slice(), record)?;
+            Ok(())
+        })
+    }
+
+    /// Waits for the writes made so far to reach disk, if commits are to be durable when they
+    /// return.
+    fn persist(&self) -> Result<()> {
+        if config::get().durability == Durability::Immediate {
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Removes every entry in `entries` whose key satisfies `remove`. Returns the number of
+    /// entries removed.
+    fn remove_where(
+        &self,
+        entries: impl Iterator<Item = sled::Result<sled::IVec>>,
+        remove: impl Fn(&[u8]) -> bool,
+    ) -> Result<u64> {
+        let mut batch = Batch::default();
+        let mut removed = 0;
+        for key in entries {
+            let key = key?;
+            if remove(&key) {
+                batch.remove(key);
+                removed += 1;
+            }
+        }
+        self.entries.apply_batch(batch)?;
+        Ok(removed)
+    }
+}
+
+fn entry_key(id: [u8; ID_LEN], key: &[u8]) -> Vec<u8> {
+    [id.as_slice(), key].concat()
+}
+
+fn hash(bytes: &[u8]) -> Option<FunctionHash> {
+    bytes.try_into().ok()
+}
+
+impl CacheBackend for SledBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let Some(id) = self.ids.get(function.hash()) else {
+            return Ok(None);
+        };
+        let entry = self.entries.get(entry_key(id, key))?;
+        Ok(entry.map(|entry| entry.to_vec().into()))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        let mut batch = Batch::default();
+        for entry in entries {
+            // IDs are recorded first, so every function with entries has one.
+            let id = self.assign(entry.function)?;
+            batch.insert(entry_key(id, entry.key), entry.entry);
+        }
+        self.entries.apply_batch(batch)?;
+        self.persist()
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        if let Some(id) = self.ids.get(function.hash()) {
+            self.entries.remove(entry_key(id, key))?;
+        }
+        self.persist()
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let removed = self.remove_where(self.entries.iter().keys(), |key| {
+            key.get(ID_LEN..).is_some_and(|key| key.starts_with(prefix))
+        })?;
+        self.persist()?;
+        Ok(removed)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let mut functions = Vec::new();
+        for item in &self.functions {
+            let (hash_bytes, record) = item?;
+            let (Some(hash), Some((id, name))) = (hash(&hash_bytes), ids::decode(&record)) else {
+                continue;
+            };
+            let mut entries = 0;
+            for key in self.entries.scan_prefix(id.to_be_bytes()).keys() {
+                key?;
+                entries += 1;
+            }
+            if entries > 0 {
+                functions.push(FunctionInfo {
+                    name,
+                    hash,
+                    entries,
+                });
+            }
+        }
+        Ok(functions)
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        // The function keeps its ID, since other threads may be about to write under it.
+        if let Some(id) = self.ids.get(function) {
+            self.remove_where(self.entries.scan_prefix(id).keys(), |_| true)?;
+        }
+        self.persist()
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        self.stats
+            .transaction(|stats| {
+                for delta in deltas {
+                    let mut merged = stats
+                        .get(delta.hash)?
+                        .and_then(|stored| decode_stats(delta.hash, &stored))
+                        .unwrap_or_else(|| FunctionStats {
+                            name: delta.name.clone(),
+                            hash: delta.hash,
+                            ..FunctionStats::default()
+                        });
+                    merged.merge(delta);
+                    stats.insert(delta.hash.as_slice(), encode_stats(&merged))?;
+                }
+                Ok(())
+            })
+            .map_err(|e: TransactionError<sled::Error>| match e {
+                TransactionError::Abort(e) | TransactionError::Storage(e) => e,
+            })?;
+        self.persist()
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        let mut stats = Vec::new();
+        for item in &self.stats {
+            let (hash_bytes, stored) = item?;
+            if let Some(hash) = hash(&hash_bytes) {
+                stats.extend(decode_stats(hash, &stored));
+            }
+        }
+        Ok(stats)
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        for key in self.entries.iter().keys() {
+            f(&key?[ID_LEN..]);
+        }
+        Ok(())
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let Some(id) = self.ids.get(function) else {
+            return Ok(());
+        };
+        for item in self.entries.scan_prefix(id) {
+            let (key, entry) = item?;
+            f(&key[ID_LEN..], &entry);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        let description = Description::new("sled").location(self.path.display().to_string());
+        match self.db.size_on_disk() {
+            Ok(size) => description.size(size),
+            Err(_) => description,
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! A SQLite database, for caches that are inspected or edited with standard SQL tooling, or
+//! programs that already ship SQLite.
+//!
+//! ```sql
+//! CREATE TABLE entries (
+//!     function_hash BLOB NOT NULL,
+//!     function_name TEXT NOT NULL,
+//!     key BLOB NOT NULL,
+//!     entry BLOB NOT NULL,
+//!     PRIMARY KEY (function_hash, key)
+//! ) WITHOUT ROWID;
+//! ```
+//!
+//! `entry` is the enveloped value, as every backend stores it; statistics are kept in a `stats`
+//! table with one row per function and durations in microseconds. The database is opened in WAL
+//! mode, so `sqlite3` can read it while programs write to it, and several processes can share
+//! it. SQLite is compiled in through rusqlite, so no system library is needed.
+
+use std::{
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use eyre::{Result, WrapErr};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use super::{CacheBackend, Description, WriteEntry};
+use crate::{value::StoredEntry, Function, FunctionHash, FunctionInfo, FunctionStats};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS entries (
+        function_hash BLOB NOT NULL,
+        function_name TEXT NOT NULL,
+        key BLOB NOT NULL,
+        entry BLOB NOT NULL,
+        PRIMARY KEY (function_hash, key)
+    ) WITHOUT ROWID;
+    CREATE TABLE IF NOT EXISTS stats (
+        function_hash BLOB PRIMARY KEY,
+        function_name TEXT NOT NULL,
+        calls INTEGER NOT NULL,
+        hits INTEGER NOT NULL,
+        compute_time_us INTEGER NOT NULL,
+        time_saved_us INTEGER NOT NULL,
+        hit_time_us INTEGER NOT NULL,
+        bytes_written INTEGER NOT NULL
+    );
+";
+
+/// How long a statement waits for another connection's write lock before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stores entries in a SQLite database file.
+pub struct SqliteBackend {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteBackend {
+    /// Opens (or creates) the database at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be opened as a SQLite database.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).wrap_err("failed to create cache directory")?;
+        }
+        let connection = Connection::open(&path)
+            .wrap_err_with(|| format!("failed to open {}", path.display()))?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.execute_batch(&format!("PRAGMA journal_mode = WAL; {SCHEMA}"))?;
+        Ok(Self {
+            path,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn int(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+fn micros(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
+
+fn count(value: i64) -> u64 {
+    u64::try_from(value).unwrap_or_default()
+}
+
+fn hash(bytes: &[u8]) -> Option<FunctionHash> {
+    bytes.try_into().ok()
+}
+
+impl CacheBackend for SqliteBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let entry = self
+            .connection()
+            .prepare_cached("SELECT entry FROM entries WHERE function_hash = ?1 AND key = ?2")?
+            .query_row(params![function.hash().as_slice(), key], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()?;
+        Ok(entry.map(Into::into))
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO entries (function_hash, function_name, key, entry) \
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for entry in entries {
+                statement.execute(params![
+                    entry.function.hash().as_slice(),
+                    entry.function.name(),
+                    entry.key,
+                    entry.entry,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        self.connection()
+            .prepare_cached("DELETE FROM entries WHERE function_hash = ?1 AND key = ?2")?
+            .execute(params![function.hash().as_slice(), key])?;
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let removed = self
+            .connection()
+            .prepare_cached("DELETE FROM entries WHERE substr(key, 1, length(?1)) = ?1")?
+            .execute([prefix])?;
+        Ok(removed as u64)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare_cached(
+            "SELECT function_hash, function_name, count(*) FROM entries \
+             GROUP BY function_hash, function_name",
+        )?;
+        let mut rows = statement.query([])?;
+        let mut functions = Vec::new();
+        while let Some(row) = rows.next()? {
+            let Some(hash) = hash(row.get_ref(0)?.as_blob()?) else {
+                continue;
+            };
+            functions.push(FunctionInfo {
+                name: row.get(1)?,
+                hash,
+                entries: count(row.get(2)?),
+            });
+        }
+        Ok(functions)
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.connection()
+            .prepare_cached("DELETE FROM entries WHERE function_hash = ?1")?
+            .execute([function.as_slice()])?;
+        Ok(())
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO stats VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) \
+                 ON CONFLICT (function_hash) DO UPDATE SET \
+                     calls = calls + excluded.calls, \
+                     hits = hits + excluded.hits, \
+                     compute_time_us = compute_time_us + excluded.compute_time_us, \
+                     time_saved_us = time_saved_us + excluded.time_saved_us, \
+                     hit_time_us = hit_time_us + excluded.hit_time_us, \
+                     bytes_written = bytes_written + excluded.bytes_written",
+            )?;
+            for delta in deltas {
+                statement.execute(params![
+                    delta.hash.as_slice(),
+                    delta.name,
+                    int(delta.calls),
+                    int(delta.hits),
+                    micros(delta.compute_time),
+                    micros(delta.time_saved),
+                    micros(delta.hit_time),
+                    int(delta.bytes_written),
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare_cached(
+            "SELECT function_hash, function_name, calls, hits, compute_time_us, time_saved_us, \
+             hit_time_us, bytes_written FROM stats",
+        )?;
+        let duration = |value: i64| Duration::from_micros(count(value));
+        let mut rows = statement.query([])?;
+        let mut stats = Vec::new();
+        while let Some(row) = rows.next()? {
+            let Some(hash) = hash(row.get_ref(0)?.as_blob()?) else {
+                continue;
+            };
+            stats.push(FunctionStats {
+                name: row.get(1)?,
+                hash,
+                calls: count(row.get(2)?),
+                hits: count(row.get(3)?),
+                compute_time: duration(row.get(4)?),
+                time_saved: duration(row.get(5)?),
+                hit_time: duration(row.get(6)?),
+                bytes_written: count(row.get(7)?),
+            });
+        }
+        Ok(stats)
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        let connection = self.connection();
+        let mut statement = connection.prepare_cached("SELECT key FROM entries")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            f(row.get_ref(0)?.as_blob()?);
+        }
+        Ok(())
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        let connection = self.connection();
+        let mut statement =
+            connection.prepare_cached("SELECT key, entry FROM entries WHERE function_hash = ?1")?;
+        let mut rows = statement.query([function.as_slice()])?;
+        while let Some(row) = rows.next()? {
+            f(row.get_ref(0)?.as_blob()?, row.get_ref(1)?.as_blob()?);
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        let description = Description::new("sqlite").location(self.path.display().to_string());
+        match std::fs::metadata(&self.path) {
+            Ok(metadata) => description.size(metadata.len()),
+            Err(_) => description,
+        }
+    }
+}
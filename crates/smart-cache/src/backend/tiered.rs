@@ -0,0 +1,301 @@
+//! Stacks several backends into one, from fastest to slowest: for example memory, then a local
+//! redb store, then a remote service.
+//!
+//! Lookups try each tier in order, and a hit in a slower tier is copied into the faster ones
+//! above it. Writes go to every tier, either before `insert_batch` returns (write-through) or,
+//! for write-back tiers, from a background thread. Removals apply to every tier once pending
+//! write-backs have landed.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, OnceLock,
+    },
+    thread,
+};
+
+use eyre::{eyre, Result};
+use tracing::warn;
+
+use super::{CacheBackend, Description, WriteEntry};
+use crate::{value::StoredEntry, Function, FunctionHash, FunctionInfo, FunctionStats};
+
+/// Number of batches queued for write-back tiers before writers block.
+const WRITE_BACK_CAPACITY: usize = 1024;
+
+/// When a tier receives new entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Entries are written before the write returns.
+    #[default]
+    WriteThrough,
+    /// Entries are written from a background thread; [`crate::flush`] waits for them.
+    WriteBack,
+}
+
+struct Tier {
+    backend: Arc<dyn CacheBackend>,
+    policy: WritePolicy,
+}
+
+/// An entry queued for the write-back tiers.
+struct OwnedEntry {
+    function: Function,
+    key: Vec<u8>,
+    entry: Vec<u8>,
+}
+
+enum Message {
+    Write(Vec<OwnedEntry>),
+    Flush(mpsc::Sender<()>),
+}
+
+/// Composes backends into tiers.
+///
+/// ```no_run
+/// use smart_cache::backend::{
+///     HttpBackend, MemoryBackend, RedbBackend, TieredBackend, WritePolicy,
+/// };
+///
+/// # fn main() -> eyre::Result<()> {
+/// let local = RedbBackend::open("cache.redb".as_ref(), 1)?;
+/// let remote = HttpBackend::new("http://cache.internal:7878")?;
+/// let tiered = TieredBackend::new()
+///     .tier(MemoryBackend::new(), WritePolicy::WriteThrough)
+///     .tier(local, WritePolicy::WriteThrough)
+///     .tier(remote, WritePolicy::WriteBack);
+/// smart_cache::Config::default().backend("cache", tiered).install()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Statistics are kept in the last tier only, which is usually the shared, durable one.
+#[derive(Default)]
+pub struct TieredBackend {
+    tiers: Vec<Tier>,
+    /// Queue to the write-back thread, started on the first write-back.
+    write_back: OnceLock<Option<SyncSender<Message>>>,
+}
+
+impl TieredBackend {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `backend` below the tiers added so far.
+    #[must_use]
+    pub fn tier(mut self, backend: impl CacheBackend + 'static, policy: WritePolicy) -> Self {
+        self.tiers.push(Tier {
+            backend: Arc::new(backend),
+            policy,
+        });
+        self
+    }
+
+    fn write_back_tiers(&self) -> Vec<Arc<dyn CacheBackend>> {
+        self.tiers
+            .iter()
+            .filter(|tier| tier.policy == WritePolicy::WriteBack)
+            .map(|tier| Arc::clone(&tier.backend))
+            .collect()
+    }
+
+    fn write_back(&self) -> Option<&SyncSender<Message>> {
+        self.write_back
+            .get_or_init(|| {
+                let tiers = self.write_back_tiers();
+                let (sender, receiver) = mpsc::sync_channel(WRITE_BACK_CAPACITY);
+                let spawned = thread::Builder::new()
+                    .name("smart-cache-write-back".to_string())
+                    .spawn(move || run_write_back(&tiers, &receiver));
+                match spawned {
+                    Ok(_) => Some(sender),
+                    Err(e) => {
+                        warn!("Failed to start write-back thread, writing through: {e:#}");
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+
+    /// Copies an entry found in a slower tier into the tiers above it.
+    fn promote(&self, above: &[Tier], function: &Function, key: &[u8], entry: &[u8]) {
+        let write = [WriteEntry {
+            function,
+            key,
+            entry,
+        }];
+        for tier in above {
+            if let Err(e) = tier.backend.insert_batch(&write) {
+                warn!("Failed to promote cache entry: {e:#}");
+            }
+        }
+    }
+
+    fn last(&self) -> Result<&Tier> {
+        self.tiers
+            .last()
+            .ok_or_else(|| eyre!("tiered cache backend has no tiers"))
+    }
+}
+
+fn run_write_back(tiers: &[Arc<dyn CacheBackend>], receiver: &Receiver<Message>) {
+    for message in receiver {
+        match message {
+            Message::Write(entries) => write_all(tiers, &entries),
+            Message::Flush(ack) => {
+                let _ = ack.send(());
+            }
+        }
+    }
+}
+
+fn write_all(tiers: &[Arc<dyn CacheBackend>], entries: &[OwnedEntry]) {
+    let batch: Vec<_> = entries
+        .iter()
+        .map(|entry| WriteEntry {
+            function: &entry.function,
+            key: &entry.key,
+            entry: &entry.entry,
+        })
+        .collect();
+    for tier in tiers {
+        if let Err(e) = tier.insert_batch(&batch) {
+            warn!("Failed to write back cache entries: {e:#}");
+        }
+    }
+}
+
+impl CacheBackend for TieredBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        for (index, tier) in self.tiers.iter().enumerate() {
+            match tier.backend.get(function, key) {
+                Ok(Some(entry)) => {
+                    self.promote(&self.tiers[..index], function, key, &entry);
+                    return Ok(Some(entry));
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Cache tier unavailable, trying the next one: {e:#}"),
+            }
+        }
+        Ok(None)
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        let mut deferred = false;
+        for tier in &self.tiers {
+            if tier.policy == WritePolicy::WriteThrough || self.write_back().is_none() {
+                tier.backend.insert_batch(entries)?;
+            } else {
+                deferred = true;
+            }
+        }
+
+        if let (true, Some(write_back)) = (deferred, self.write_back()) {
+            let owned = entries
+                .iter()
+                .map(|entry| OwnedEntry {
+                    function: Function::owned(entry.function.name(), *entry.function.hash()),
+                    key: entry.key.to_vec(),
+                    entry: entry.entry.to_vec(),
+                })
+                .collect();
+            if write_back.send(Message::Write(owned)).is_err() {
+                warn!("Write-back thread has stopped; entries were not written to every tier");
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        self.flush()?;
+        for tier in &self.tiers {
+            tier.backend.remove(function, key)?;
+        }
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        self.flush()?;
+        // Tiers hold overlapping copies, so the fullest tier's count is the best estimate.
+        let mut removed = 0;
+        for tier in &self.tiers {
+            removed = removed.max(tier.backend.remove_prefix(prefix)?);
+        }
+        Ok(removed)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        // Tiers hold overlapping copies, so each function reports its fullest tier's count.
+        let mut functions = HashMap::<FunctionHash, FunctionInfo>::new();
+        for info in self.tiers.iter().map(|tier| tier.backend.functions()) {
+            for info in info? {
+                let known = functions.entry(info.hash).or_insert_with(|| FunctionInfo {
+                    entries: 0,
+                    ..info.clone()
+                });
+                known.entries = known.entries.max(info.entries);
+            }
+        }
+        Ok(functions.into_values().collect())
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.flush()?;
+        for tier in &self.tiers {
+            tier.backend.clear_function(function)?;
+        }
+        Ok(())
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        self.last()?.backend.merge_stats(deltas)
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        self.last()?.backend.stats()
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        // Keys held by several tiers are reported once per tier.
+        for tier in &self.tiers {
+            tier.backend.for_each_key(f)?;
+        }
+        Ok(())
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        // The last tier is the most complete, since faster tiers only hold what passed through.
+        self.flush()?;
+        self.last()?.backend.for_each_function_entry(function, f)
+    }
+
+    fn flush(&self) -> Result<()> {
+        if let Some(Some(write_back)) = self.write_back.get() {
+            let (ack, done) = mpsc::channel();
+            if write_back.send(Message::Flush(ack)).is_ok() {
+                let _ = done.recv();
+            }
+        }
+        for tier in &self.tiers {
+            tier.backend.flush()?;
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Description {
+        let kinds: Vec<&str> = self
+            .tiers
+            .iter()
+            .map(|tier| tier.backend.describe().kind)
+            .collect();
+        Description::new("tiered").location(kinds.join(" > "))
+    }
+}
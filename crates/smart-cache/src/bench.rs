@@ -0,0 +1,109 @@
+//! Helpers for benchmarking cached functions with [Criterion](https://docs.rs/criterion).
+//!
+//! [`compare!`](crate::bench::compare) benchmarks a call three ways in one group: `uncached`,
+//! calling the function's body directly, `miss`, computing and storing the value with the
+//! function's entries cleared before every call, and `hit`, reading the value back once it is
+//! stored. `miss` against `uncached` is what caching costs, and `hit` against `uncached` what it
+//! saves:
+//!
+//! ```ignore
+//! use criterion::{criterion_group, criterion_main, Criterion};
+//!
+//! #[smart_cache::cached]
+//! fn parse(source: String) -> Vec<String> {
+//!     tokenize(&source)
+//! }
+//!
+//! fn benches(c: &mut Criterion) {
+//!     let source = std::fs::read_to_string("input.txt").unwrap();
+//!     smart_cache::bench::compare!(c, "parse", || parse(source.clone()), || tokenize(&source));
+//! }
+//!
+//! criterion_group!(parsing, benches);
+//! criterion_main!(parsing);
+//! ```
+//!
+//! The functions behind it time calls in the form Criterion's `Bencher::iter_custom` takes, so
+//! they work with any harness that measures a given number of iterations.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// Benchmarks `cached`, a call of the cached function named `name`, as a miss and a hit,
+/// against `uncached`, the same work without the cache, in a Criterion benchmark group named
+/// after the function. See the [module documentation](crate::bench).
+///
+/// The first argument is anything with Criterion's `benchmark_group` method, such as a
+/// `&mut Criterion`; this crate doesn't depend on Criterion itself.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __bench_compare {
+    ($criterion:expr, $name:expr, $cached:expr, $uncached:expr $(,)?) => {{
+        let name: &str = $name;
+        let mut cached = $cached;
+        let mut uncached = $uncached;
+        let mut group = $criterion.benchmark_group(name);
+        group.bench_function("uncached", |b| {
+            b.iter_custom(|iters| $crate::bench::time(iters, &mut uncached))
+        });
+        group.bench_function("miss", |b| {
+            b.iter_custom(|iters| $crate::bench::misses(iters, name, &mut cached))
+        });
+        group.bench_function("hit", |b| {
+            b.iter_custom(|iters| $crate::bench::hits(iters, name, &mut cached))
+        });
+        group.finish();
+    }};
+}
+
+#[doc(inline)]
+pub use crate::__bench_compare as compare;
+
+/// How long `iters` calls of `f` take.
+pub fn time<T>(iters: u64, mut f: impl FnMut() -> T) -> Duration {
+    let started = Instant::now();
+    for _ in 0..iters {
+        black_box(f());
+    }
+    started.elapsed()
+}
+
+/// How long `iters` calls of `cached`, a call of the cached function named `name`, take when
+/// each is a miss. The function's entries are cleared before every call, outside the time
+/// measured.
+///
+/// # Panics
+///
+/// Panics if the store can't be cleared.
+pub fn misses<T>(iters: u64, name: &str, mut cached: impl FnMut() -> T) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        clear(name);
+        let started = Instant::now();
+        black_box(cached());
+        total += started.elapsed();
+    }
+    total
+}
+
+/// How long `iters` calls of `cached`, a call of the cached function named `name`, take when
+/// each is a hit. The value is computed and stored first, outside the time measured.
+///
+/// # Panics
+///
+/// Panics if the store can't be cleared.
+pub fn hits<T>(iters: u64, name: &str, mut cached: impl FnMut() -> T) -> Duration {
+    // Starting from an empty store keeps one run's entries from counting towards the next.
+    clear(name);
+    black_box(cached());
+    crate::flush();
+    time(iters, cached)
+}
+
+fn clear(name: &str) {
+    if let Err(e) = crate::clear_function(name) {
+        panic!("failed to clear the cached values of {name}: {e:#}");
+    }
+}
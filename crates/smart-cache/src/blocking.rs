@@ -0,0 +1,118 @@
+//! A small pool of threads running store operations for async callers, so cached `async fn`s
+//! don't stall their executor on disk or network I/O.
+//!
+//! The pool is independent of any particular runtime: [`run`] returns a future that is woken
+//! once a pool thread has finished the job.
+
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::context;
+
+/// Threads in the pool. Jobs are short store operations, so a few threads keep many
+/// concurrent callers moving without competing with the executor for cores.
+const POOL_SIZE: usize = 8;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Queue to the pool, or `None` if no thread could be started and jobs run inline.
+static POOL: Lazy<Option<Sender<Job>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    let mut started = 0;
+    for index in 0..POOL_SIZE {
+        let receiver = Arc::clone(&receiver);
+        let spawned = thread::Builder::new()
+            .name(format!("smart-cache-blocking-{index}"))
+            .spawn(move || work(&receiver));
+        match spawned {
+            Ok(_) => started += 1,
+            Err(e) => warn!("Failed to start cache I/O thread: {e:#}"),
+        }
+    }
+    (started > 0).then_some(sender)
+});
+
+fn work(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = receiver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .recv();
+        match job {
+            Ok(job) => job(),
+            Err(_) => return,
+        }
+    }
+}
+
+/// Where a job leaves its result for the future awaiting it.
+struct Slot<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// The pending result of a job handed to [`run`].
+pub struct Blocking<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        match slot.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs `job` on the pool. Panics in `job` resurface when the returned future is polled.
+pub fn run<T: Send + 'static>(job: impl FnOnce() -> T + Send + 'static) -> Blocking<T> {
+    let slot = Arc::new(Mutex::new(Slot {
+        result: None,
+        waker: None,
+    }));
+
+    let finished = Arc::clone(&slot);
+    // Jobs use the caller's isolated store, if it has one, like calls on its own thread.
+    let isolated = context::store();
+    let job: Job = Box::new(move || {
+        let _isolated = isolated.map(context::isolate);
+        let result = panic::catch_unwind(AssertUnwindSafe(job));
+        let mut slot = finished.lock().unwrap_or_else(PoisonError::into_inner);
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    });
+
+    let unsent = match &*POOL {
+        Some(pool) => pool.send(job).err().map(|e| e.0),
+        None => Some(job),
+    };
+    if let Some(job) = unsent {
+        job();
+    }
+
+    Blocking { slot }
+}
@@ -0,0 +1,84 @@
+//! Caching in build scripts, so expensive code generation or asset processing is skipped by later
+//! `cargo build`s.
+//!
+//! Build scripts are programs like any other, so `#[cached]` works in them unchanged; what
+//! differs is where their store belongs. [`install`] keeps it in Cargo's target directory, next
+//! to the build's other outputs: one file per package, target and profile, which `cargo clean`
+//! removes along with everything else. It is found from `OUT_DIR`, but not kept in it, since
+//! `OUT_DIR` changes whenever the build script itself is rebuilt.
+//!
+//! ```no_run
+//! // build.rs, with smart-cache in `[build-dependencies]`
+//! use smart_cache::cached;
+//!
+//! #[cached]
+//! fn generate(schema: String) -> String {
+//!     format!("pub const SCHEMA: &str = {schema:?};")
+//! }
+//!
+//! fn main() -> eyre::Result<()> {
+//!     smart_cache::build::install()?;
+//!     println!("cargo::rerun-if-changed=schema.graphql");
+//!     let schema = std::fs::read_to_string("schema.graphql")?;
+//!     let out = std::path::Path::new(&std::env::var("OUT_DIR")?).join("schema.rs");
+//!     std::fs::write(out, generate(schema))?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::{env, path::PathBuf};
+
+use eyre::{eyre, Result, WrapErr};
+
+use crate::Config;
+
+fn var(name: &str) -> Result<String> {
+    env::var(name).wrap_err_with(|| format!("{name} is not set; is this a build script?"))
+}
+
+/// The store file of the running build script: `smart-cache/<package>.redb` in the
+/// `target/<profile>` (or `target/<triple>/<profile>`) directory its `OUT_DIR` is in, or in
+/// `OUT_DIR` itself if it isn't laid out that way.
+///
+/// # Errors
+///
+/// Fails if `OUT_DIR` or `CARGO_PKG_NAME` isn't set, as outside of build scripts.
+pub fn store_path() -> Result<PathBuf> {
+    let out_dir = PathBuf::from(var("OUT_DIR")?);
+    let package = var("CARGO_PKG_NAME")?;
+    // `OUT_DIR` is `<profile dir>/build/<package>-<hash>/out`.
+    let profile_dir = out_dir
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == "build"))
+        .and_then(|build| build.parent())
+        .unwrap_or(&out_dir);
+    Ok(profile_dir
+        .join("smart-cache")
+        .join(format!("{package}.redb")))
+}
+
+/// A [`Config`] storing entries at [`store_path`], to adjust before installing it.
+///
+/// # Errors
+///
+/// Fails if `OUT_DIR` or `CARGO_PKG_NAME` isn't set, as outside of build scripts, or if the
+/// store's directory cannot be created.
+pub fn config() -> Result<Config> {
+    let path = store_path()?;
+    let dir = path
+        .parent()
+        .ok_or_else(|| eyre!("{} has no parent directory", path.display()))?;
+    std::fs::create_dir_all(dir).wrap_err("failed to create the build cache directory")?;
+    let name = var("CARGO_PKG_NAME")?;
+    Ok(Config::default().store_name(&name).database(name, path))
+}
+
+/// Installs [`config`], so the build script's `#[cached]` functions keep their values across
+/// builds. Call it before the first cached call.
+///
+/// # Errors
+///
+/// Fails if [`config`] does, or if smart-cache is already configured.
+pub fn install() -> Result<()> {
+    config()?.install()
+}
@@ -0,0 +1,60 @@
+//! The time, as the cache reads it.
+//!
+//! Everywhere but `wasm32-unknown-unknown` this is `std::time`. There, reading the clock panics
+//! without JavaScript bindings the crate doesn't depend on, so time stands still instead:
+//! elapsed times read as zero, entries are stamped with the Unix epoch, and TTLs never run out.
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use std::time::Instant;
+use std::time::SystemTime;
+
+/// The current time.
+#[must_use]
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn now() -> SystemTime {
+    SystemTime::now()
+}
+
+/// The Unix epoch, as the browser has no clock to read.
+#[must_use]
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub const fn now() -> SystemTime {
+    std::time::UNIX_EPOCH
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub use self::frozen::Instant;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod frozen {
+    use std::{ops::Add, time::Duration};
+
+    /// A stand-in for [`std::time::Instant`] that never advances.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Instant;
+
+    impl Instant {
+        #[must_use]
+        pub const fn now() -> Self {
+            Self
+        }
+
+        #[must_use]
+        pub const fn elapsed(self) -> Duration {
+            Duration::ZERO
+        }
+
+        #[must_use]
+        pub const fn saturating_duration_since(self, _earlier: Self) -> Duration {
+            Duration::ZERO
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Self;
+
+        fn add(self, _duration: Duration) -> Self {
+            self
+        }
+    }
+}
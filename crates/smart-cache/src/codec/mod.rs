@@ -0,0 +1,120 @@
+//! Encodings of keys and values besides rkyv's, chosen per function.
+//!
+//! `#[cached(with = "serde")]` keys and stores values through serde instead, for types that
+//! implement `Serialize` and `Deserialize` but not rkyv's traits. Values are decoded in full on
+//! every hit rather than read in place, and values that no longer decode as the return type,
+//! say after a field was added, are misses. Needs the `serde` feature.
+//!
+//! ```ignore
+//! #[derive(serde::Serialize, serde::Deserialize, Debug)]
+//! struct Report {
+//!     rows: Vec<(String, f64)>,
+//! }
+//!
+//! #[smart_cache::cached(with = "serde")]
+//! fn report(query: Query) -> Report {
+//!     run(&query)
+//! }
+//! ```
+//!
+//! Arguments must implement `Serialize` and `Debug`. Neither binary [`Format`] describes
+//! itself: fields skipped with `skip_serializing_if`, and `deserialize_any`,
+//! aren't supported. Functions cached `with = "serde"` use the format set with
+//! [`crate::Config::serde_format`], [`Format::Binary`] by default; `with = "postcard"` picks
+//! [`Format::Postcard`] for one function, whatever the global setting, as `with = "json"` picks
+//! `Format::Json` with the `json` feature.
+//!
+//! `codec = "..."` is another spelling of `with`, also taking `"rkyv"`, the default, and
+//! `"borsh"`. With the `borsh` feature, `#[cached(codec = "borsh")]` encodes keys and values
+//! with borsh 1.x, for types deriving `BorshSerialize` and `BorshDeserialize`; like rkyv, borsh
+//! is the calling crate's own dependency. Arguments must implement `BorshSerialize` and `Debug`.
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub use serde;
+
+/// How functions cached through serde lay out their keys and values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Format {
+    /// bincode 2.x in its legacy configuration, which is bincode 1.x's layout: fixed-width
+    /// little-endian integers and `u64` lengths, fast to read and write.
+    #[default]
+    Binary,
+    /// postcard 1.x, with varint integers and lengths, so values of small numbers take less
+    /// space. Stored values can be read with postcard too.
+    Postcard,
+    /// JSON, as `serde_json` writes it, to read and edit stored values by hand, say in a
+    /// [`crate::backend::JsonBackend`] while debugging. Needs the `json` feature.
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// Internal function used by the macro to encode keys and values of `with = "serde"`
+/// functions.
+///
+/// # Panics
+///
+/// Panics if `value`'s `Serialize` implementation fails.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub fn to_bytes<T: serde::Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    to_bytes_in(crate::config::get().serde_format, value)
+}
+
+/// Internal function used by the macro to encode keys and values of functions cached with a
+/// format of their own, such as `with = "postcard"`.
+///
+/// # Panics
+///
+/// Panics if `value`'s `Serialize` implementation fails, or if it can't be written in `format`.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub fn to_bytes_in<T: serde::Serialize + ?Sized>(format: Format, value: &T) -> Vec<u8> {
+    #[cfg(feature = "json")]
+    if format == Format::Json {
+        return match serde_json::to_vec(value) {
+            Ok(bytes) => bytes,
+            Err(e) => panic!("failed to serialize a cached value: {e}"),
+        };
+    }
+    let bytes = match format {
+        Format::Postcard => postcard::to_allocvec(value).map_err(|e| e.to_string()),
+        _ => bincode::serde::encode_to_vec(value, bincode::config::legacy())
+            .map_err(|e| e.to_string()),
+    };
+    match bytes {
+        Ok(bytes) => bytes,
+        Err(e) => panic!("failed to serialize a cached value: {e}"),
+    }
+}
+
+/// Internal function used by the macro to decode values of `with = "serde"` functions, or
+/// `None` if `bytes` don't decode as a `T`.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub fn from_bytes<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    from_bytes_in(crate::config::get().serde_format, bytes)
+}
+
+/// Internal function used by the macro to decode values of functions cached with a format of
+/// their own, or `None` if `bytes` don't decode as a `T`.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub fn from_bytes_in<T: serde::de::DeserializeOwned>(format: Format, bytes: &[u8]) -> Option<T> {
+    #[cfg(feature = "json")]
+    if format == Format::Json {
+        return serde_json::from_slice(bytes).ok();
+    }
+    match format {
+        // Trailing bytes mean the value was written as another type.
+        Format::Postcard => match postcard::take_from_bytes(bytes) {
+            Ok((value, [])) => Some(value),
+            _ => None,
+        },
+        _ => match bincode::serde::decode_from_slice(bytes, bincode::config::legacy()) {
+            Ok((value, read)) if read == bytes.len() => Some(value),
+            _ => None,
+        },
+    }
+}
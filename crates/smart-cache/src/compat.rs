@@ -0,0 +1,22 @@
+//! A drop-in for the `cached` crate's `#[cached]`, so existing code can migrate by swapping the
+//! import:
+//!
+//! ```
+//! use smart_cache::compat::cached;
+//!
+//! #[cached(time = 60)]
+//! fn slow_square(x: u64) -> u64 {
+//!     x * x
+//! }
+//! # assert_eq!(slow_square(3), 9);
+//! ```
+//!
+//! `time` (seconds) expires entries, and `result = true` / `option = true` only store `Ok` /
+//! `Some` values. `unbound`, `sync_writes = false` and `time_refresh = false` are accepted,
+//! being what happens anyway. Options whose behavior would be lost are rejected: `size` and
+//! `name`, since entries live in the persistent store instead of a bounded, named in-memory
+//! one; `sync_writes = true` and `time_refresh = true`; custom keys (`key`, `convert`) and
+//! cache types (`ty`, `create`), since entries are always keyed by every argument. Unlike the `cached` crate, arguments and return values must
+//! implement rkyv's `Archive`, `Serialize` and `Deserialize`.
+
+pub use smart_cache_macro::cached_compat as cached;
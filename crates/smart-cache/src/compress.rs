@@ -0,0 +1,136 @@
+//! A small LZ77 codec for values that only fit the store compressed (see [`crate::Oversize`]).
+//!
+//! The output starts with the uncompressed length as a little-endian `u64`, followed by
+//! sequences in the LZ4 block layout: a token whose high and low nibbles hold the literal
+//! length and the match length minus [`MIN_MATCH`], longer lengths continued in bytes of 255,
+//! the literals, then the match as a `u16` offset back into the output. The last sequence has
+//! literals only.
+
+/// Shortest repetition worth encoding as a match.
+const MIN_MATCH: usize = 4;
+/// Furthest back a match can start.
+const MAX_OFFSET: usize = u16::MAX as usize;
+/// Entries in the table of recently seen 4-byte sequences.
+const TABLE_BITS: u32 = 16;
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn slot(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2_654_435_761) >> (32 - TABLE_BITS)) as usize
+}
+
+/// Compresses `input`.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    out.extend_from_slice(&(input.len() as u64).to_le_bytes());
+
+    // Last position + 1 each sequence was seen at, 0 for none.
+    let mut table = vec![0_usize; 1 << TABLE_BITS];
+    let mut anchor = 0;
+    let mut at = 0;
+    while at + MIN_MATCH <= input.len() {
+        let slot = slot(read_u32(input, at));
+        let candidate = table[slot].checked_sub(1);
+        table[slot] = at + 1;
+        match candidate.and_then(|from| match_len(input, from, at).map(|len| (at - from, len))) {
+            Some((offset, len)) => {
+                push_sequence(&mut out, &input[anchor..at], Some((offset, len)));
+                at += len;
+                anchor = at;
+            }
+            None => at += 1,
+        }
+    }
+    push_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+/// How many bytes starting at `at` repeat those starting at `from`, if enough to encode.
+fn match_len(input: &[u8], from: usize, at: usize) -> Option<usize> {
+    if at - from > MAX_OFFSET || read_u32(input, from) != read_u32(input, at) {
+        return None;
+    }
+    let len = input[at..]
+        .iter()
+        .zip(&input[from..])
+        .take_while(|(a, b)| a == b)
+        .count();
+    Some(len)
+}
+
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    #[allow(clippy::cast_possible_truncation)]
+    let token = (literals.len().min(15) << 4 | match_len.min(15)) as u8;
+    out.push(token);
+    push_length(out, literals.len());
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        #[allow(clippy::cast_possible_truncation)]
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        push_length(out, match_len);
+    }
+}
+
+/// Writes the part of `len` its token nibble couldn't hold.
+fn push_length(out: &mut Vec<u8>, len: usize) {
+    let Some(mut rest) = len.checked_sub(15) else {
+        return;
+    };
+    while rest >= 255 {
+        out.push(255);
+        rest -= 255;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    out.push(rest as u8);
+}
+
+/// Reads a length whose token nibble is `nibble`, consuming its continuation bytes.
+fn read_length(input: &mut &[u8], nibble: u8) -> Option<usize> {
+    let mut len = usize::from(nibble);
+    if nibble < 15 {
+        return Some(len);
+    }
+    loop {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        len = len.checked_add(byte.into())?;
+        if byte < 255 {
+            return Some(len);
+        }
+    }
+}
+
+/// Decompresses the output of [`compress`], or returns `None` if `input` isn't one.
+pub fn decompress(input: &[u8]) -> Option<Vec<u8>> {
+    let (len, mut input) = input.split_first_chunk::<8>()?;
+    let len = usize::try_from(u64::from_le_bytes(*len)).ok()?;
+    let mut out = Vec::with_capacity(len);
+    loop {
+        let (&token, rest) = input.split_first()?;
+        input = rest;
+        let literals = read_length(&mut input, token >> 4)?;
+        let (literals, rest) = input.split_at_checked(literals)?;
+        out.extend_from_slice(literals);
+        input = rest;
+        if input.is_empty() {
+            break;
+        }
+
+        let (offset, rest) = input.split_first_chunk::<2>()?;
+        input = rest;
+        let offset = usize::from(u16::from_le_bytes(*offset));
+        let match_len = read_length(&mut input, token & 15)? + MIN_MATCH;
+        let from = out.len().checked_sub(offset).filter(|_| offset > 0)?;
+        // Matches may overlap the bytes they produce, so they're copied in offset-sized steps.
+        let mut copied = 0;
+        while copied < match_len {
+            let step = offset.min(match_len - copied);
+            out.extend_from_within(from + copied..from + copied + step);
+            copied += step;
+        }
+    }
+    (out.len() == len).then_some(out)
+}
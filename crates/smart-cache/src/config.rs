@@ -0,0 +1,574 @@
+//! Runtime configuration of the global cache store.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use eyre::{bail, Result};
+use once_cell::sync::OnceCell;
+
+use crate::{archive, backend::CacheBackend, codec::Format, Oversize, Quota, Watchdog};
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// How hard a committed write is pushed to disk before the commit returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Commits are queued for persistence and land on disk shortly after. A crash can lose the
+    /// most recent writes, which for a cache only means recomputing them. Much faster for
+    /// write-heavy workloads.
+    Eventual,
+    /// Commits are fsynced before returning.
+    #[default]
+    Immediate,
+}
+
+/// What, besides a function's arguments and body, its cached values depend on; see
+/// [`Config::key_epoch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Epoch {
+    /// The commit checked out in the working directory, read once at startup.
+    GitCommit,
+}
+
+/// A store implementation registered with [`Config::backend`].
+#[derive(Clone)]
+pub(crate) struct CustomBackend(pub(crate) Arc<dyn CacheBackend>);
+
+impl Debug for CustomBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomBackend")
+    }
+}
+
+/// Configuration for the global cache store.
+///
+/// The store is opened lazily on the first cached call, so a configuration has to be installed
+/// with [`Config::install`] before that happens (typically at the top of `main`).
+///
+/// ```no_run
+/// use std::{collections::HashMap, path::PathBuf, time::Duration};
+///
+/// smart_cache::Config::default()
+///     .lock_retries(10)
+///     .lock_backoff(Duration::from_millis(20))
+///     .per_process_fallback(true)
+///     .install()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) lock_retries: u32,
+    pub(crate) lock_backoff: Duration,
+    pub(crate) per_process_fallback: bool,
+    pub(crate) write_behind: Option<usize>,
+    pub(crate) batch_size: usize,
+    pub(crate) batch_delay: Duration,
+    pub(crate) durability: Durability,
+    pub(crate) miss_filter: Option<usize>,
+    pub(crate) thread_memo: usize,
+    pub(crate) shards: usize,
+    pub(crate) deduplicate: Option<usize>,
+    pub(crate) spill: Option<usize>,
+    pub(crate) max_value_size: Option<usize>,
+    pub(crate) oversize: Oversize,
+    pub(crate) refresh_ahead: Option<f64>,
+    pub(crate) ttl_jitter: f64,
+    pub(crate) early_expiration: Option<f64>,
+    pub(crate) adaptive: bool,
+    pub(crate) min_hit_rate: Option<(f64, u64)>,
+    pub(crate) databases: HashMap<String, PathBuf>,
+    pub(crate) store_name: Option<String>,
+    pub(crate) shared_store: bool,
+    pub(crate) backends: HashMap<String, CustomBackend>,
+    pub(crate) fixtures: Option<PathBuf>,
+    pub(crate) mutation_log: Option<PathBuf>,
+    pub(crate) decision_log: Option<PathBuf>,
+    pub(crate) keep_versions: usize,
+    pub(crate) inspectable_keys: bool,
+    pub(crate) replay_logs: bool,
+    pub(crate) trace_calls: bool,
+    pub(crate) mutation_log_size: u64,
+    pub(crate) key_epoch: Option<Epoch>,
+    pub(crate) partition_by_profile: bool,
+    pub(crate) namespace_quotas: HashMap<String, Quota>,
+    pub(crate) max_computations: Option<usize>,
+    pub(crate) watchdog: Option<Watchdog>,
+    pub(crate) serde_format: Format,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            lock_retries: 5,
+            lock_backoff: Duration::from_millis(50),
+            per_process_fallback: false,
+            write_behind: None,
+            batch_size: 1,
+            batch_delay: Duration::ZERO,
+            durability: Durability::Immediate,
+            miss_filter: None,
+            thread_memo: 0,
+            shards: 1,
+            deduplicate: None,
+            spill: None,
+            max_value_size: None,
+            oversize: Oversize::Skip,
+            refresh_ahead: None,
+            ttl_jitter: 0.0,
+            early_expiration: None,
+            adaptive: false,
+            min_hit_rate: None,
+            databases: HashMap::new(),
+            store_name: None,
+            shared_store: false,
+            backends: HashMap::new(),
+            fixtures: None,
+            mutation_log: None,
+            decision_log: None,
+            keep_versions: 1,
+            inspectable_keys: false,
+            replay_logs: false,
+            trace_calls: false,
+            mutation_log_size: 64 * 1024 * 1024,
+            key_epoch: None,
+            partition_by_profile: false,
+            namespace_quotas: HashMap::new(),
+            max_computations: None,
+            watchdog: None,
+            serde_format: Format::Binary,
+        }
+    }
+}
+
+impl Config {
+    /// How many times to retry opening the database while another process holds its lock.
+    #[must_use]
+    pub const fn lock_retries(mut self, retries: u32) -> Self {
+        self.lock_retries = retries;
+        self
+    }
+
+    /// The delay before the first retry; it doubles after every failed attempt, up to 10 seconds
+    /// (or this delay, if longer).
+    #[must_use]
+    pub const fn lock_backoff(mut self, backoff: Duration) -> Self {
+        self.lock_backoff = backoff;
+        self
+    }
+
+    /// If the shared database is still locked after all retries, open a cache file private to
+    /// this process instead of disabling caching.
+    #[must_use]
+    pub const fn per_process_fallback(mut self, enabled: bool) -> Self {
+        self.per_process_fallback = enabled;
+        self
+    }
+
+    /// Persist computed values on a background thread instead of blocking the caller on a
+    /// write transaction. At most `capacity` writes are queued; past that, callers wait.
+    ///
+    /// Use [`crate::flush`] at points where everything computed so far must be durable.
+    #[must_use]
+    pub const fn write_behind(mut self, capacity: usize) -> Self {
+        self.write_behind = Some(capacity);
+        self
+    }
+
+    /// Buffer pending writes and commit them together once `max_entries` have accumulated or
+    /// `max_delay` has passed since the first one, whichever comes first. Committing in batches
+    /// avoids paying for one fsync per cached call in hot loops.
+    ///
+    /// Enables write-behind with a queue of `4 * max_entries` if it is not already enabled.
+    #[must_use]
+    pub const fn coalesce_writes(mut self, max_entries: usize, max_delay: Duration) -> Self {
+        self.batch_size = max_entries;
+        self.batch_delay = max_delay;
+        if self.write_behind.is_none() {
+            self.write_behind = Some(max_entries.saturating_mul(4));
+        }
+        self
+    }
+
+    /// Trade crash-safety for write throughput. Defaults to [`Durability::Immediate`].
+    #[must_use]
+    pub const fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Keep an in-memory Bloom filter of stored keys, sized for `expected_entries`, so lookups
+    /// that are definitely misses skip the read transaction entirely.
+    ///
+    /// The filter is populated by scanning every key when the store is opened, which is worth
+    /// it for functions with huge, mostly cold argument spaces. Only redb files opened by this
+    /// process are filtered, since no other process writes to them while it holds them; stores
+    /// registered with [`Config::backend`] may be, so their lookups always reach the store.
+    #[must_use]
+    pub const fn miss_filter(mut self, expected_entries: usize) -> Self {
+        self.miss_filter = Some(expected_entries);
+        self
+    }
+
+    /// Keep the `capacity` most recently used values in a per-thread LRU, so repeatedly calling
+    /// a cached function with the same arguments doesn't go back to the store every time.
+    ///
+    /// Each thread holds its own copies of the values, so keep this small for large results.
+    #[must_use]
+    pub const fn thread_memo(mut self, capacity: usize) -> Self {
+        self.thread_memo = capacity;
+        self
+    }
+
+    /// Spread the store across `shards` database files, routing each cached function to one of
+    /// them by its hash. Concurrent writers of functions on different shards no longer contend
+    /// for a single file's write lock.
+    ///
+    /// Changing the shard count reroutes functions, so previously cached entries of rerouted
+    /// functions become misses.
+    #[must_use]
+    pub const fn shards(mut self, shards: usize) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    /// Store values of at least `min_bytes` once per database file, however many keys they
+    /// are cached under, for workloads where many different arguments produce identical large
+    /// results. Applies to the redb stores smart-cache opens itself (see
+    /// [`crate::backend::RedbBackend::deduplicate`]).
+    #[must_use]
+    pub const fn deduplicate_values(mut self, min_bytes: usize) -> Self {
+        self.deduplicate = Some(min_bytes);
+        self
+    }
+
+    /// Store values of at least `min_bytes` in files of their own next to the database rather
+    /// than in it, so very large results don't bloat the database file and its page cache.
+    /// Applies to the redb stores smart-cache opens itself (see
+    /// [`crate::backend::RedbBackend::spill`]).
+    #[must_use]
+    pub const fn spill_values(mut self, min_bytes: usize) -> Self {
+        self.spill = Some(min_bytes);
+        self
+    }
+
+    /// Treat values larger than `bytes` as oversize, handling them as [`Config::oversize`]
+    /// says. Functions annotated with `#[cached(max_size = ...)]` use their own limit instead.
+    #[must_use]
+    pub const fn max_value_size(mut self, bytes: usize) -> Self {
+        self.max_value_size = Some(bytes);
+        self
+    }
+
+    /// What to do with values over the size limit, for functions without an
+    /// `#[cached(oversize = "...")]` of their own. Defaults to [`Oversize::Skip`].
+    #[must_use]
+    pub const fn oversize(mut self, policy: Oversize) -> Self {
+        self.oversize = policy;
+        self
+    }
+
+    /// Recompute a value in the background when it is looked up after `factor` of its TTL has
+    /// passed, e.g. 0.8 for the last fifth, so keys looked up often are replaced before they
+    /// expire and never miss. Keys looked up rarely are unlikely to be looked up in that window
+    /// and simply expire.
+    ///
+    /// Applies to synchronous functions annotated with `#[cached(ttl = "...", refresh_ahead)]`,
+    /// whose arguments are copied to the thread recomputing the value.
+    #[must_use]
+    pub const fn refresh_ahead(mut self, factor: f64) -> Self {
+        self.refresh_ahead = Some(factor);
+        self
+    }
+
+    /// Shorten the TTL of each value by a random amount of up to `fraction` of it, e.g. 0.1 for
+    /// up to a tenth, so values computed together, say while warming the cache up, don't all
+    /// expire and get recomputed at once.
+    #[must_use]
+    pub const fn ttl_jitter(mut self, fraction: f64) -> Self {
+        self.ttl_jitter = fraction;
+        self
+    }
+
+    /// Treat values as expired a little before their TTL runs out, at random, so one lookup
+    /// recomputes an expensive value early instead of every lookup missing at once when it
+    /// expires ("XFetch"). A lookup expires a value early when
+    /// `now + compute_time * beta * -ln(random)` is past its TTL, so values that took longer to
+    /// compute are recomputed earlier. A `beta` of 1.0 is a good default; larger values recompute
+    /// earlier.
+    ///
+    /// Values expired early are recomputed by the lookup itself, like any other miss, and stay
+    /// in the store until replaced.
+    #[must_use]
+    pub const fn early_expiration(mut self, beta: f64) -> Self {
+        self.early_expiration = Some(beta);
+        self
+    }
+
+    /// Stop consulting the store for functions whose hits, reading the value and deserializing
+    /// it, take at least as long on average as computing the value does, once there are enough
+    /// of both to tell. Such functions are called directly and their values aren't written for
+    /// the rest of the process; [`crate::FunctionStats::unprofitable`] reports them.
+    #[must_use]
+    pub const fn adaptive(mut self, enabled: bool) -> Self {
+        self.adaptive = enabled;
+        self
+    }
+
+    /// Stop writing the values of functions that have missed at least `after_misses` times
+    /// with fewer than `rate` of their calls hitting, e.g. 0.01 for one in a hundred, since
+    /// storing values that are almost never read again is pure overhead. Values already
+    /// stored are still read. The decision holds for the rest of the process;
+    /// [`crate::FunctionStats::rarely_hit`] reports it.
+    #[must_use]
+    pub const fn min_hit_rate(mut self, rate: f64, after_misses: u64) -> Self {
+        self.min_hit_rate = Some((rate, after_misses));
+        self
+    }
+
+    /// Run at most `n` computations of cached values at once, across every cached function;
+    /// further misses wait for one to finish, so a burst of cold keys can't take every core or
+    /// exceed a downstream API's rate limit. Hits never wait.
+    ///
+    /// Cached functions called while computing another value on the same thread count as part
+    /// of it. `async fn`s count at every level of nesting, so `n` must exceed how deeply they
+    /// call each other.
+    #[must_use]
+    pub const fn max_concurrent_computations(mut self, n: usize) -> Self {
+        self.max_computations = Some(n);
+        self
+    }
+
+    /// Store the entries of functions annotated with `#[cached(db = "<name>")]` in the redb file
+    /// at `path` instead of the shared cache file.
+    ///
+    /// Keeping, say, large ML artifacts apart from small metadata lookups means they don't share
+    /// a write lock or growth characteristics. Databases that are used but never registered
+    /// live at `<cache dir>/<name>.redb`.
+    #[must_use]
+    pub fn database(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.databases.insert(name.into(), path.into());
+        self
+    }
+
+    /// Name the store file `<cache dir>/<name>.redb` (or the path registered with
+    /// [`Config::database`]) instead of after the crate defining each cached function.
+    #[must_use]
+    pub fn store_name(mut self, name: impl Into<String>) -> Self {
+        self.store_name = Some(name.into());
+        self
+    }
+
+    /// Keep entries in the single `<cache dir>/cache.redb` shared by every program using
+    /// smart-cache, as older versions did, rather than one store per crate and version. Keys
+    /// still carry the crate and version, so programs only share the entries of functions
+    /// cached `#[cached(share_across_crates)]`.
+    #[must_use]
+    pub const fn shared_store(mut self, shared: bool) -> Self {
+        self.shared_store = shared;
+        self
+    }
+
+    /// Serve the store called `name` from `backend` instead of a redb file.
+    ///
+    /// `name` is matched like [`Config::database`]: against `#[cached(db = "<name>")]`, or
+    /// against [`Config::store_name`] to route every other cached function to `backend`.
+    #[must_use]
+    pub fn backend(
+        mut self,
+        name: impl Into<String>,
+        backend: impl CacheBackend + 'static,
+    ) -> Self {
+        self.backends
+            .insert(name.into(), CustomBackend(Arc::new(backend)));
+        self
+    }
+
+    /// Mix `epoch` into every key, so values computed under one epoch are misses under another.
+    ///
+    /// With [`Epoch::GitCommit`], checking out a different commit starts from an empty cache,
+    /// for computations that depend on code or data in the repository beyond the cached
+    /// functions' bodies. Switching back to a commit finds its entries again.
+    #[must_use]
+    pub const fn key_epoch(mut self, epoch: Epoch) -> Self {
+        self.key_epoch = Some(epoch);
+        self
+    }
+
+    /// Keep the entries of debug and release builds apart, so results that differ between them
+    /// (float optimizations, `cfg(debug_assertions)` branches) never cross over.
+    ///
+    /// Builds are told apart by whether the crate defining each cached function was built with
+    /// debug assertions, so profile overrides for some packages (`[profile.dev.package."*"]`)
+    /// don't mix their entries with the rest of the build's.
+    #[must_use]
+    pub const fn partition_by_profile(mut self, enabled: bool) -> Self {
+        self.partition_by_profile = enabled;
+        self
+    }
+
+    /// Limit what entries cached in `namespace` may take, evicting its oldest entries once it
+    /// holds more, so one tenant's or experiment's namespace can't crowd out another's. Only
+    /// the namespace's own entries are evicted, including those of namespaces nested in it.
+    ///
+    /// `namespace` is a top-level [`crate::scoped`] scope or context namespace. Usage is
+    /// tallied per process, from the entries found in the stores on its first write under the
+    /// namespace; entries other processes write meanwhile are only counted by their own.
+    #[must_use]
+    pub fn namespace_quota(mut self, namespace: impl Into<String>, quota: Quota) -> Self {
+        self.namespace_quotas.insert(namespace.into(), quota);
+        self
+    }
+
+    /// Watch the size of the stores kept in local files, warning and calling the watchdog's
+    /// callback as they grow past its thresholds, so an application can react before the disk
+    /// fills up. Sizes are measured on writes, so growth from other processes sharing the
+    /// stores is noticed on this process's next write.
+    #[must_use]
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Lay out the keys and values of functions cached `with = "serde"` in `format`, e.g.
+    /// [`Format::Postcard`] to store them as postcard does. Functions cached with a format of
+    /// their own, such as `with = "postcard"`, keep it. Entries written in another format are
+    /// misses.
+    #[must_use]
+    pub const fn serde_format(mut self, format: Format) -> Self {
+        self.serde_format = format;
+        self
+    }
+
+    /// Load every `*.archive` file in `dir` into the store on [`Config::install`], so tests can
+    /// take their cached paths without computing anything. Fixtures are written with
+    /// [`crate::export_filtered`], typically into `tests/cache-fixtures/`, and go back into the
+    /// stores they were exported from. A missing directory holds no fixtures.
+    #[must_use]
+    pub fn fixtures(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.fixtures = Some(dir.into());
+        self
+    }
+
+    /// Keep the last `n` values written under each key, the current one included, so earlier
+    /// ones can be read back with [`crate::history`]. Each write first moves the value it
+    /// replaces aside, which takes a read of every kept version; the default of 1 keeps only the
+    /// current value.
+    #[must_use]
+    pub const fn keep_versions(mut self, n: usize) -> Self {
+        self.keep_versions = n;
+        self
+    }
+
+    /// Record the `Debug` form of each `#[cached]` function's arguments alongside the values
+    /// it writes, so entries can be found by what they were computed from, e.g. to delete every
+    /// value derived from one user's data with [`crate::purge_where`], or listed with
+    /// [`crate::entries`]. Keys are otherwise only stored serialized.
+    #[must_use]
+    pub const fn inspectable_keys(mut self, enabled: bool) -> Self {
+        self.inspectable_keys = enabled;
+        self
+    }
+
+    /// Record the `tracing` events each `#[cached]` function logs while computing a value, store
+    /// them with the value, and log them again whenever a hit returns it, so what operators see
+    /// doesn't depend on whether the value was cached. Replayed events have the
+    /// `smart_cache::replay` target and a `replayed = true` field.
+    ///
+    /// Events are recorded through the subscriber in effect, and only those it enables; events
+    /// logged by `async fn`s or on other threads aren't recorded. While a value is computed,
+    /// `tracing::Span::current()` doesn't see the subscriber's spans.
+    #[must_use]
+    pub const fn replay_logs(mut self, enabled: bool) -> Self {
+        self.replay_logs = enabled;
+        self
+    }
+
+    /// Record each call of a `#[cached]` function, with its key's hash, whether it hit, and how
+    /// long it took, split on misses between computing the value and looking it up and storing
+    /// it, for [`crate::write_trace`] to write as a timeline of where a run's time went.
+    #[must_use]
+    pub const fn trace_calls(mut self, enabled: bool) -> Self {
+        self.trace_calls = enabled;
+        self
+    }
+
+    /// Append a line to the file at `path` for every entry written to the store, discarded as
+    /// expired or corrupt, or deleted, with the time, function, key hash, size and a hash of
+    /// the executable, so it can be told when a value was produced and by which binary.
+    ///
+    /// Lines are tab-separated:
+    ///
+    /// ```text
+    /// 1760000000.123  set  load_report  <function hash>  <key SHA-256>  5120  <binary SHA-256>
+    /// ```
+    ///
+    /// Entries written by other processes sharing the store are only in their own logs.
+    #[must_use]
+    pub fn mutation_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.mutation_log = Some(path.into());
+        self
+    }
+
+    /// Start a new [mutation log](Config::mutation_log) once it reaches `bytes`, 64 MiB by
+    /// default, renaming the full one after the time. Full logs are kept.
+    #[must_use]
+    pub const fn mutation_log_size(mut self, bytes: u64) -> Self {
+        self.mutation_log_size = bytes;
+        self
+    }
+
+    /// Append a JSON line to the file at `path` for every decision the cache makes: each hit
+    /// and miss and why, and each computed value written or skipped, with the function, key
+    /// hash, entry size and compute time, for analysing a run offline with tools like `jq`. A
+    /// line reads, wrapped here:
+    ///
+    /// ```text
+    /// {"time":1760000000.123,"function":"load_report","hash":"<function hash>",
+    ///  "key":"<key SHA-256>","decision":"miss","reason":"expired"}
+    /// ```
+    ///
+    /// Decisions and their reasons are:
+    ///
+    /// - `hit`: served from the thread `memo`, a `pending` write or the `store`.
+    /// - `miss`: the entry was `absent`, `filtered` out by the miss filter, `expired`, `early`
+    ///   expired, `corrupt`, of a `foreign` archive layout, of a return type `retyped` since, or
+    ///   derived from a `dependency` that changed; the store was `unavailable` or failed with an
+    ///   `error`; the function is `unprofitable` to cache; or the cache is `recording`.
+    /// - `store`: the `computed` value was written.
+    /// - `skip`: the computed value wasn't written, being `oversize` or `unprofitable`.
+    ///
+    /// Hits include the size of the entry read, where there is one, and the time computing the
+    /// value took, which is what the hit saved, as `compute_us`. The log isn't rotated.
+    #[must_use]
+    pub fn decision_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.decision_log = Some(path.into());
+        self
+    }
+
+    /// Installs this configuration for the global store.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a configuration was already installed or the store has already been opened,
+    /// or if a [fixture](Config::fixtures) cannot be loaded.
+    pub fn install(self) -> Result<()> {
+        if CONFIG.set(self).is_err() {
+            bail!("smart-cache is already configured; install the config before the first cached call");
+        }
+        if let Some(dir) = &get().fixtures {
+            archive::import_dir(dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the installed configuration, locking in the default one if none was installed.
+pub(crate) fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
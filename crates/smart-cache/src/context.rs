@@ -0,0 +1,207 @@
+//! Thread-local overrides applied to every cached call made while a [`ContextGuard`] is alive.
+
+use std::{borrow::Cow, cell::RefCell, marker::PhantomData, time::Duration};
+
+use crate::{backend::CacheBackend, epoch, Function};
+
+/// Marks the start of a namespace segment in a key prefix.
+const TAG: &[u8] = b"\0smart-cache scope\0";
+/// Precedes encoded arguments starting with [`TAG`]'s first byte or this, so arguments never
+/// start like a segment and keys outside a namespace never match one inside it.
+const ESCAPE: u8 = 0xff;
+
+#[derive(Default)]
+struct State {
+    /// Prefix of the innermost namespace, including those it is nested in.
+    prefix: Vec<u8>,
+    ttl: Option<Duration>,
+    /// The store used instead of the configured ones, set by [`crate::testing::isolated`].
+    store: Option<&'static dyn CacheBackend>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+/// Appends the segment for `namespace` to a key prefix.
+pub fn push_segment(prefix: &mut Vec<u8>, namespace: &str) {
+    // Namespaces are user-provided but short; a name over 4 GiB is a bug, not a tenant.
+    let len = u32::try_from(namespace.len()).expect("namespace too long");
+    prefix.extend_from_slice(TAG);
+    prefix.extend_from_slice(&len.to_le_bytes());
+    prefix.extend_from_slice(namespace.as_bytes());
+}
+
+/// Returns `key` of `function` prefixed with the current namespace, if any, and followed by the
+/// configured epoch and build profile, if any.
+pub fn key<'a>(function: &Function, key: &'a [u8]) -> Cow<'a, [u8]> {
+    let epoch = epoch::suffix(function);
+    let escape: &[u8] = match key.first() {
+        Some(&byte) if byte == TAG[0] || byte == ESCAPE => &[ESCAPE],
+        _ => &[],
+    };
+    STATE.with(|state| {
+        let prefix = &state.borrow().prefix;
+        if prefix.is_empty() && escape.is_empty() && epoch.is_empty() {
+            Cow::Borrowed(key)
+        } else {
+            Cow::Owned([prefix.as_slice(), escape, key, epoch].concat())
+        }
+    })
+}
+
+/// The TTL of a value computed now: the shorter of the context's TTL and the function's own
+/// `ttl`, if either is set.
+pub fn ttl(ttl: Option<Duration>) -> Option<Duration> {
+    within(context_ttl(), ttl)
+}
+
+/// The TTL the current context sets, if any.
+pub fn context_ttl() -> Option<Duration> {
+    STATE.with(|state| state.borrow().ttl)
+}
+
+/// The shorter of a context's TTL, `context`, and the function's own `ttl`, if either is set.
+pub fn within(context: Option<Duration>, ttl: Option<Duration>) -> Option<Duration> {
+    match (context, ttl) {
+        (Some(context), Some(function)) => Some(context.min(function)),
+        (context, function) => context.or(function),
+    }
+}
+
+/// The store every cached call on this thread uses instead of the configured ones, if any.
+pub fn store() -> Option<&'static dyn CacheBackend> {
+    STATE.with(|state| state.borrow().store)
+}
+
+/// Has every cached call on this thread use `store` until the guard is dropped.
+pub fn isolate(store: &'static dyn CacheBackend) -> ContextGuard {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let guard = ContextGuard::save(&state);
+        state.store = Some(store);
+        guard
+    })
+}
+
+/// Starts building a cache context; see [`Context::enter`].
+pub fn context() -> Context {
+    Context::default()
+}
+
+/// Overrides for cached calls, applied to the current thread by [`Context::enter`].
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct Context {
+    namespace: Option<String>,
+    ttl: Option<Duration>,
+}
+
+impl Context {
+    /// Partition entries under `namespace`, nested inside any namespace already in effect.
+    /// Namespaces can be purged with [`crate::purge_scope`].
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Expire values computed in this context after `ttl`.
+    pub const fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Applies the context to every cached call on this thread until the guard is dropped.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use smart_cache::cached;
+    /// #[cached]
+    /// fn train(epochs: u32) -> f64 {
+    ///     f64::from(epochs) * 0.1
+    /// }
+    ///
+    /// let _ctx = smart_cache::context()
+    ///     .namespace("experiment-42")
+    ///     .ttl(Duration::from_secs(2 * 60 * 60))
+    ///     .enter();
+    /// train(10);
+    /// ```
+    pub fn enter(self) -> ContextGuard {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let guard = ContextGuard::save(&state);
+            if let Some(namespace) = &self.namespace {
+                push_segment(&mut state.prefix, namespace);
+            }
+            state.ttl = self.ttl.or(state.ttl);
+            guard
+        })
+    }
+}
+
+/// The context in effect on one thread, to be applied to work it hands to another.
+pub struct Captured {
+    prefix: Vec<u8>,
+    ttl: Option<Duration>,
+    store: Option<&'static dyn CacheBackend>,
+}
+
+/// Captures the current thread's context.
+pub fn capture() -> Captured {
+    STATE.with(|state| {
+        let state = state.borrow();
+        Captured {
+            prefix: state.prefix.clone(),
+            ttl: state.ttl,
+            store: state.store,
+        }
+    })
+}
+
+impl Captured {
+    /// Applies the captured context, nested in the current one, until the guard is dropped.
+    pub fn enter(&self) -> ContextGuard {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let guard = ContextGuard::save(&state);
+            state.prefix.extend_from_slice(&self.prefix);
+            state.ttl = self.ttl.or(state.ttl);
+            state.store = self.store.or(state.store);
+            guard
+        })
+    }
+}
+
+/// Restores the previous context when dropped.
+#[must_use = "the context is left as soon as the guard is dropped"]
+pub struct ContextGuard {
+    prefix_len: usize,
+    ttl: Option<Duration>,
+    store: Option<&'static dyn CacheBackend>,
+    /// The context is thread-local, so the guard must be dropped on the thread that entered it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ContextGuard {
+    /// A guard restoring `state` as it is now.
+    fn save(state: &State) -> Self {
+        Self {
+            prefix_len: state.prefix.len(),
+            ttl: state.ttl,
+            store: state.store,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.prefix.truncate(self.prefix_len);
+            state.ttl = self.ttl;
+            state.store = self.store;
+        });
+    }
+}
@@ -0,0 +1,184 @@
+//! Opening (and, when necessary, recovering) the on-disk database.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use eyre::{Result, WrapErr};
+use redb::{Database, DatabaseError, StorageError, WriteTransaction};
+use tracing::{debug, warn};
+
+use crate::{
+    clock,
+    config::{self, Config, Durability},
+};
+
+/// Whether a function compiled for tests has been called, which moves the cache directory to a
+/// temporary one of this process's.
+static FOR_TESTS: AtomicBool = AtomicBool::new(false);
+
+/// Moves the cache directory to a temporary one of this process's, so tests neither see nor
+/// leave values in the user's cache. Stores already opened stay where they are, and stores
+/// given a path with [`Config::database`] are unaffected.
+pub fn redirect_for_tests() {
+    if !FOR_TESTS.swap(true, Ordering::Relaxed) {
+        debug!("Running tests; caching in {}", test_dir().display());
+    }
+}
+
+fn test_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("smart-cache-tests-{}", process::id()))
+}
+
+pub fn cache_dir() -> Result<PathBuf> {
+    let cache_dir = if FOR_TESTS.load(Ordering::Relaxed) {
+        test_dir()
+    } else {
+        dirs::cache_dir()
+            .unwrap_or_else(|| PathBuf::from(".cache"))
+            .join("smart-cache")
+    };
+    std::fs::create_dir_all(&cache_dir).wrap_err("failed to create cache directory")?;
+
+    Ok(cache_dir)
+}
+
+/// Moves a corrupted database file out of the way so a fresh one can be created in its place.
+fn quarantine(path: &Path) -> Result<PathBuf> {
+    let timestamp = clock::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+
+    let mut quarantined = path.as_os_str().to_owned();
+    quarantined.push(format!(".corrupt-{timestamp}"));
+    let quarantined = PathBuf::from(quarantined);
+
+    std::fs::rename(path, &quarantined).wrap_err("failed to move corrupted database aside")?;
+    Ok(quarantined)
+}
+
+/// The longest the delay between attempts to open a locked database grows to, unless
+/// `Config::lock_backoff` starts above it.
+const MAX_LOCK_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Creates the database at `path`, waiting with exponential backoff while another process
+/// holds its lock.
+fn create_with_retry(path: &Path, config: &Config) -> Result<Database, DatabaseError> {
+    let mut backoff = config.lock_backoff;
+
+    for attempt in 1..=config.lock_retries {
+        match Database::create(path) {
+            Err(DatabaseError::DatabaseAlreadyOpen) => {
+                debug!("Cache database is locked (attempt {attempt}), retrying in {backoff:?}");
+                thread::sleep(backoff);
+                backoff = backoff
+                    .saturating_mul(2)
+                    .min(MAX_LOCK_BACKOFF.max(config.lock_backoff));
+            }
+            result => return result,
+        }
+    }
+
+    Database::create(path)
+}
+
+/// Why the database at `path` couldn't be opened, if it is because the file is damaged: its
+/// pages are corrupted, or it doesn't even start with redb's magic number. Only `.redb` files
+/// count as damaged for the latter, so a path naming some other file is never moved aside.
+fn corruption(path: &Path, error: &DatabaseError) -> Option<String> {
+    match error {
+        DatabaseError::Storage(StorageError::Corrupted(reason)) => Some(reason.clone()),
+        DatabaseError::Storage(StorageError::Io(e))
+            if e.kind() == ErrorKind::InvalidData
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == "redb") =>
+        {
+            Some("not a redb file".to_string())
+        }
+        _ => None,
+    }
+}
+
+fn create(path: &Path, config: &Config) -> Result<Database> {
+    let error = match create_with_retry(path, config) {
+        Ok(db) => return Ok(db),
+        Err(e) => e,
+    };
+    let Some(reason) = corruption(path, &error) else {
+        return Err(error).wrap_err("failed to create cache database");
+    };
+    let quarantined = quarantine(path)?;
+    warn!(
+        "Cache database at {} is corrupted ({reason}); moved it to {} and starting fresh",
+        path.display(),
+        quarantined.display(),
+    );
+    Database::create(path).wrap_err("failed to recreate cache database")
+}
+
+/// Returns `path` with `suffix` appended to its file stem, e.g. `cache.redb` -> `cache-1.redb`.
+pub fn with_stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(suffix);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// How many private files [`open`] tries before giving up on caching.
+const PRIVATE_FILES: usize = 16;
+
+/// Opens the database file at `path`, creating it and its parent directories if needed, and
+/// returns it with the path of the file actually opened.
+///
+/// With [`Config::per_process_fallback`], a shared file that can't be opened is replaced by the
+/// first of `<stem>-private<N>` that no other process holds. Files are taken over once their
+/// process exits, so their number stays bounded by the peak of concurrent processes rather
+/// than growing with every run.
+pub fn open(path: &Path) -> Result<(Database, PathBuf)> {
+    let config = config::get();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).wrap_err("failed to create cache directory")?;
+    }
+
+    let e = match create(path, config) {
+        Ok(db) => return Ok((db, path.to_path_buf())),
+        Err(e) if config.per_process_fallback => e,
+        Err(e) => return Err(e),
+    };
+    for n in 1..=PRIVATE_FILES {
+        let private = with_stem_suffix(path, &format!("-private{n}"));
+        match Database::create(&private) {
+            Ok(db) => {
+                warn!(
+                    "Failed to open the shared cache database ({e:#}); using {} for this process",
+                    private.display(),
+                );
+                return Ok((db, private));
+            }
+            Err(DatabaseError::DatabaseAlreadyOpen) => {}
+            Err(_) => return Ok((create(&private, config)?, private)),
+        }
+    }
+    Err(e).wrap_err_with(|| {
+        format!("all {PRIVATE_FILES} per-process fallback databases are in use as well")
+    })
+}
+
+/// Begins a write transaction with the configured durability.
+pub fn begin_write(db: &Database) -> Result<WriteTransaction> {
+    let mut txn = db.begin_write()?;
+    txn.set_durability(match config::get().durability {
+        Durability::Eventual => redb::Durability::Eventual,
+        Durability::Immediate => redb::Durability::Immediate,
+    });
+    Ok(txn)
+}
@@ -0,0 +1,116 @@
+//! A log of every decision the cache makes, enabled with [`crate::Config::decision_log`], for
+//! analysing offline why calls hit or missed and what was written.
+//!
+//! Each line is a JSON object with the time in seconds since the Unix epoch (with
+//! milliseconds), the function's name and hex hash, the SHA-256 of the key (as in the
+//! [mutation log](crate::Config::mutation_log)), the `decision` and its `reason`, the size of the
+//! entry in bytes and the time computing the value took, in microseconds, where they are known:
+//!
+//! - `hit`: the value was served, from the thread `memo`, a `pending` write or the `store`.
+//! - `miss`: the value has to be computed, because it was `absent`, `filtered` out by the miss
+//!   filter, `expired`, `early` expired, `corrupt`, of a `foreign` archive layout, derived from a
+//!   `dependency` that changed, the store was `unavailable` or failed with an `error`, the
+//!   function is `unprofitable` to cache, or the cache is `recording`.
+//! - `store`: a computed value was written, because it was `computed`.
+//! - `skip`: a computed value wasn't written, because it was `oversize` or the function is
+//!   `unprofitable` to cache.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    sync::{Mutex, PoisonError},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{backend::to_hex, clock, config, Function};
+
+/// The log being appended to, if one is configured and could be opened.
+static LOG: Lazy<Option<Mutex<File>>> = Lazy::new(|| {
+    let path = config::get().decision_log.as_ref()?;
+    let opened = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| OpenOptions::new().create(true).append(true).open(path));
+    match opened {
+        Ok(file) => Some(Mutex::new(file)),
+        Err(e) => {
+            warn!("Failed to open the decision log {}: {e:#}", path.display());
+            None
+        }
+    }
+});
+
+/// Logs a value served from `source`, an entry of `size` bytes if it was read from one.
+pub fn hit(
+    function: &Function,
+    key: &[u8],
+    source: &str,
+    size: Option<usize>,
+    compute_time: Duration,
+) {
+    record(function, key, "hit", source, size, Some(compute_time));
+}
+
+/// Logs a lookup that found no value to serve.
+pub fn miss(function: &Function, key: &[u8], reason: &str) {
+    record(function, key, "miss", reason, None, None);
+}
+
+/// Logs a computed value written as an entry of `size` bytes.
+pub fn store(function: &Function, key: &[u8], size: usize, compute_time: Duration) {
+    record(
+        function,
+        key,
+        "store",
+        "computed",
+        Some(size),
+        Some(compute_time),
+    );
+}
+
+/// Logs a computed value that isn't written.
+pub fn skip(function: &Function, key: &[u8], reason: &str, compute_time: Duration) {
+    record(function, key, "skip", reason, None, Some(compute_time));
+}
+
+fn record(
+    function: &Function,
+    key: &[u8],
+    decision: &str,
+    reason: &str,
+    size: Option<usize>,
+    compute_time: Option<Duration>,
+) {
+    let Some(log) = LOG.as_ref() else {
+        return;
+    };
+    let now = clock::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut line = format!(
+        "{{\"time\":{}.{:03},\"function\":\"{}\",\"hash\":\"{}\",\"key\":\"{}\",\
+         \"decision\":\"{decision}\",\"reason\":\"{reason}\"",
+        now.as_secs(),
+        now.subsec_millis(),
+        function.name().escape_default(),
+        to_hex(function.hash()),
+        to_hex(&Sha256::digest(key)),
+    );
+    if let Some(size) = size {
+        let _ = write!(line, ",\"size\":{size}");
+    }
+    if let Some(compute_time) = compute_time {
+        let _ = write!(line, ",\"compute_us\":{}", compute_time.as_micros());
+    }
+    line.push_str("}\n");
+    let written = log
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .write_all(line.as_bytes());
+    if let Err(e) = written {
+        warn!("Failed to write to the decision log: {e:#}");
+    }
+}
@@ -0,0 +1,146 @@
+//! Tracking which cached functions each computation called, so a composite result is
+//! recomputed once a function it was derived from changes, even if its own body did not.
+//!
+//! While a cached function computes, every cached function it looks up is recorded as a
+//! dependency of the value, along with that function's own dependencies. The list is stored in
+//! the entry, and a lookup treats the entry as a miss when one of them now has another body
+//! hash. A function's current hash is known once this process has called it; before that, the
+//! hash recorded in the manifest by the last run that called it is used. Functions are told
+//! apart by their [`Function::path`], so those of the same name in different modules don't
+//! share a hash.
+//!
+//! Only synchronous functions are tracked: an `async fn` may be suspended and resumed on
+//! another thread, so its lookups can't be attributed to the computation around them.
+
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+use once_cell::sync::Lazy;
+
+use crate::{function, manifest, Function, FunctionHash};
+
+/// A cached function a value was derived from, and the body hash it had at the time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// The function's [`Function::path`].
+    pub name: Box<str>,
+    pub hash: FunctionHash,
+}
+
+impl Dependency {
+    fn of(function: &Function) -> Self {
+        Self {
+            name: function.path().into(),
+            hash: *function.hash(),
+        }
+    }
+
+    /// Whether the function still has the hash it had when the value was computed.
+    fn is_current(&self) -> bool {
+        let hashes = function::registered_hashes(&self.name);
+        if !hashes.is_empty() {
+            return hashes.contains(&self.hash);
+        }
+        RECORDED
+            .get(&*self.name)
+            .is_none_or(|hash| *hash == self.hash)
+    }
+}
+
+/// The hashes the manifest held when this process first needed one, by function path.
+static RECORDED: Lazy<HashMap<String, FunctionHash>> = Lazy::new(manifest::recorded_hashes);
+
+/// A computation in progress on this thread.
+struct Frame {
+    function: FunctionHash,
+    dependencies: Vec<Dependency>,
+}
+
+impl Frame {
+    fn add(&mut self, dependency: Dependency) {
+        if dependency.hash != self.function && !self.dependencies.contains(&dependency) {
+            self.dependencies.push(dependency);
+        }
+    }
+}
+
+thread_local! {
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Marks the computation of a cached value; dropping it hands the dependencies it collected
+/// to the computation that called it.
+#[doc(hidden)]
+#[must_use]
+pub struct Tracking {
+    depth: usize,
+}
+
+impl Drop for Tracking {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| unwind(&mut frames.borrow_mut(), self.depth));
+    }
+}
+
+/// Pops the frames above `depth`, passing each one's dependencies to the frame below it. Frames
+/// are normally popped one at a time; more are left only if a [`Tracking`] was leaked.
+fn unwind(frames: &mut Vec<Frame>, depth: usize) {
+    while frames.len() > depth {
+        let Some(frame) = frames.pop() else { return };
+        let Some(caller) = frames.last_mut() else {
+            continue;
+        };
+        for dependency in frame.dependencies {
+            caller.add(dependency);
+        }
+    }
+}
+
+/// Internal function used by the macro to start collecting the dependencies of a value about
+/// to be computed.
+#[doc(hidden)]
+pub fn track_dependencies(function: &'static Function) -> Tracking {
+    FRAMES.with(|frames| {
+        let mut frames = frames.borrow_mut();
+        let depth = frames.len();
+        if let Some(caller) = frames.last_mut() {
+            caller.add(Dependency::of(function));
+        }
+        frames.push(Frame {
+            function: *function.hash(),
+            dependencies: Vec::new(),
+        });
+        Tracking { depth }
+    })
+}
+
+/// Records that the computation in progress on this thread read a value of `function`, which
+/// was itself derived from `dependencies`.
+pub fn observe(function: &Function, dependencies: &[Dependency]) {
+    FRAMES.with(|frames| {
+        if let Some(caller) = frames.borrow_mut().last_mut() {
+            caller.add(Dependency::of(function));
+            for dependency in dependencies {
+                caller.add(dependency.clone());
+            }
+        }
+    });
+}
+
+/// The dependencies collected for the value of `function` being computed on this thread.
+pub fn collected(function: &Function) -> Arc<[Dependency]> {
+    FRAMES.with(|frames| {
+        frames
+            .borrow()
+            .last()
+            .filter(|frame| frame.function == *function.hash())
+            .map_or_else(
+                || Arc::from([]),
+                |frame| frame.dependencies.as_slice().into(),
+            )
+    })
+}
+
+/// Whether every function in `dependencies` still has the hash the value was computed with.
+pub fn are_current(dependencies: &[Dependency]) -> bool {
+    dependencies.iter().all(Dependency::is_current)
+}
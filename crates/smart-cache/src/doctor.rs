@@ -0,0 +1,121 @@
+//! Checking every stored entry and optionally removing the unusable ones.
+
+use eyre::Result;
+use rkyv::util::AlignedVec;
+
+use crate::{
+    backend::{self, CacheBackend},
+    entry::{self, Rejected},
+    function, journal, memo, FunctionInfo,
+};
+
+/// What [`doctor`] found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DoctorReport {
+    /// Entries checked.
+    pub checked: u64,
+    /// Entries that are truncated or fail checksum verification.
+    pub corrupt: u64,
+    /// Entries past their expiry.
+    pub expired: u64,
+    /// Entries whose value is not a valid archive of their function's return type. Only
+    /// functions this process has called can be checked this way.
+    pub undeserializable: u64,
+    /// Intact entries archived with a layout this build cannot read. They are never removed,
+    /// since other machines sharing the store may read them.
+    pub foreign: u64,
+    /// Entries removed, when repairing.
+    pub removed: u64,
+}
+
+/// Walks every entry of every known store, verifying its checksum and expiry and, for
+/// functions this process has called, that the value still deserializes as the function's
+/// return type. With `repair`, corrupt, expired and undeserializable entries are removed;
+/// otherwise they are only counted.
+///
+/// ```no_run
+/// let report = smart_cache::doctor(true)?;
+/// println!("removed {} of {} entries", report.removed, report.checked);
+/// # Ok::<(), eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if a store cannot be read, or written when repairing.
+pub fn doctor(repair: bool) -> Result<DoctorReport> {
+    crate::flush();
+
+    let mut report = DoctorReport::default();
+    for backend in backend::all() {
+        for function in backend.functions()? {
+            let unusable = examine(backend, &function, &mut report)?;
+            if repair {
+                report.removed += remove(backend, &function, &unusable)?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Checks the entries of one function, returning the keys of those worth removing.
+fn examine(
+    backend: &dyn CacheBackend,
+    function: &FunctionInfo,
+    report: &mut DoctorReport,
+) -> Result<Vec<Vec<u8>>> {
+    let descriptor = function::registered(&function.hash);
+    let mut unusable = Vec::new();
+    backend.for_each_function_entry(&function.hash, &mut |key, stored| {
+        report.checked += 1;
+        let usable = match entry::decode(stored) {
+            Err(Rejected::Corrupt) => {
+                report.corrupt += 1;
+                false
+            }
+            Err(Rejected::Foreign) => {
+                report.foreign += 1;
+                true
+            }
+            Ok((header, _)) if header.is_expired() => {
+                report.expired += 1;
+                false
+            }
+            Ok((header, offset)) => {
+                let valid = match entry::value(stored, &header, offset) {
+                    Some(value) => descriptor
+                        .and_then(|descriptor| descriptor.validate(&aligned(&value)))
+                        .unwrap_or(true),
+                    None => false,
+                };
+                if !valid {
+                    report.undeserializable += 1;
+                }
+                valid
+            }
+        };
+        if !usable {
+            unusable.push(key.to_vec());
+        }
+    })?;
+    Ok(unusable)
+}
+
+fn remove(backend: &dyn CacheBackend, function: &FunctionInfo, keys: &[Vec<u8>]) -> Result<u64> {
+    if keys.is_empty() {
+        return Ok(0);
+    }
+    memo::invalidate();
+    let descriptor = function::registered(&function.hash)
+        .unwrap_or_else(|| function::intern(&function.name, function.hash));
+    for key in keys {
+        backend.remove(descriptor, key)?;
+        journal::evict(descriptor, key, None);
+    }
+    Ok(keys.len() as u64)
+}
+
+fn aligned(value: &[u8]) -> AlignedVec<16> {
+    let mut aligned = AlignedVec::with_capacity(value.len());
+    aligned.extend_from_slice(value);
+    aligned
+}
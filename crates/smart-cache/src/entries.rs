@@ -0,0 +1,67 @@
+//! Listing the entries of a cached function along with the arguments they were computed from.
+
+use std::time::SystemTime;
+
+use eyre::Result;
+
+use crate::{backend, entry, FunctionHash};
+
+/// One stored entry, as returned by [`entries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// Hash of the function body the entry was computed by.
+    pub hash: FunctionHash,
+    /// The `Debug` form of the function's arguments, if the entry was written with
+    /// [`crate::Config::inspectable_keys`].
+    pub debug_key: Option<String>,
+    /// Size of the stored key and entry, including its envelope.
+    pub bytes: u64,
+    /// When the entry was written.
+    pub created_at: Option<SystemTime>,
+    /// When the entry expires, if it does.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Lists the entries of the function(s) named `name` in every known store, across all versions
+/// of their bodies, oldest first.
+///
+/// Keys are stored serialized, so an entry only says which arguments produced it if it was
+/// written with [`crate::Config::inspectable_keys`].
+///
+/// ```no_run
+/// for entry in smart_cache::entries("embed_text")? {
+///     println!("{}", entry.debug_key.as_deref().unwrap_or("?"));
+/// }
+/// # Ok::<(), eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if a store cannot be read.
+pub fn entries(name: &str) -> Result<Vec<EntryInfo>> {
+    crate::flush();
+
+    let mut entries = Vec::new();
+    for backend in backend::all() {
+        let functions = backend.functions()?;
+        for function in functions.iter().filter(|function| function.name == name) {
+            backend.for_each_function_entry(&function.hash, &mut |key, stored| {
+                entries.extend(describe(function.hash, key, stored));
+            })?;
+        }
+    }
+    entries.sort_by_key(|entry| entry.created_at);
+    Ok(entries)
+}
+
+/// What [`entries`] reports about `stored`, or `None` if it isn't a valid entry.
+fn describe(hash: FunctionHash, key: &[u8], stored: &[u8]) -> Option<EntryInfo> {
+    let (header, offset) = entry::decode(stored).ok()?;
+    Some(EntryInfo {
+        hash,
+        debug_key: entry::debug_key(stored, offset).map(str::to_string),
+        bytes: (key.len() + stored.len()) as u64,
+        created_at: header.created_at,
+        expires_at: header.expires_at,
+    })
+}
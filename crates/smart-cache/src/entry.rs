@@ -0,0 +1,311 @@
+//! Envelope wrapped around every value written to the store.
+//!
+//! Each entry is laid out as `[checksum: 32 bytes][header: 48 bytes][dependencies][value bytes]`,
+//! where the checksum is the SHA-256 of a format tag followed by everything after it. Verifying
+//! it on read means bit rot, a torn write, or an entry written in an older layout is detected
+//! before the bytes ever reach `rkyv::access`.
+//!
+//! The dependencies list the cached functions the value was derived from (see
+//! [`crate::dependency`]), each as its 32-byte hash, a `u16` name length and the name, qualified
+//! by its module's path. With
+//! [`crate::Config::inspectable_keys`], they are preceded by the `Debug` form of the key, as a
+//! `u32` length and the UTF-8 text, and a flag in the header says so. With
+//! [`crate::Config::replay_logs`], the key is followed by the events logged while computing the
+//! value, as a `u32` length and the bytes of [`crate::replay`], flagged the same way. The
+//! header and the dependencies are padded so the value starts at a multiple of 16 bytes into the
+//! entry, which keeps it aligned for `rkyv` whenever the entry itself is.
+//!
+//! The header also records how `rkyv` lays out archives in the writing build (byte order,
+//! width of `usize`, alignment), which depends on `rkyv`'s features. Entries written with a
+//! different layout, say by a machine sharing a remote store but built with other features, are
+//! misses for this build rather than values it would misread. The header's last layout byte
+//! holds flags instead: whether the value is compressed, whether it should be spilled, and
+//! whether the entry records its key's `Debug` form and logged events.
+//!
+//! Its last 16 bytes hold the hash of the return type's layout the value was written with (see
+//! [`crate::type_layout`]), or zeroes for values written without one. Values whose type has
+//! since changed shape, say because a field was added, are misses rather than bytes that fail
+//! to validate or, worse, validate and mean something else.
+
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::{clock, compress, dependency::Dependency, FunctionHash};
+
+/// Version of the entry layout, reported by [`crate::status`]; bump it with [`FORMAT_TAG`].
+pub const FORMAT_VERSION: u32 = 6;
+
+/// Mixed into every checksum; changing it invalidates entries written with a different layout.
+const FORMAT_TAG: &[u8] = b"smart-cache entry v6";
+
+const CHECKSUM_LEN: usize = 32;
+const HEADER_LEN: usize = 48;
+const VALUE_OFFSET: usize = CHECKSUM_LEN + HEADER_LEN;
+/// Where the archive layout sits in the header.
+const LAYOUT_RANGE: std::ops::Range<usize> = 24..27;
+/// Where the [`FLAG_COMPRESSED`], [`FLAG_SPILL`], [`FLAG_DEBUG_KEY`] and [`FLAG_EVENTS`] bits
+/// sit in the header.
+const FLAGS: usize = 27;
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_SPILL: u8 = 2;
+const FLAG_DEBUG_KEY: u8 = 4;
+const FLAG_EVENTS: u8 = 8;
+/// Where the length of the dependencies, padding included, sits in the header.
+const DEPENDENCIES_RANGE: std::ops::Range<usize> = 28..32;
+/// Where the hash of the value's type layout sits in the header.
+const TYPE_LAYOUT_RANGE: std::ops::Range<usize> = 32..48;
+/// Alignment of the value inside the entry.
+const VALUE_ALIGNMENT: usize = 16;
+
+/// The archive layout of this build: whether integers are big-endian, the archived size of
+/// `usize`, and the archived alignment of `u64` (1 when `rkyv` is built unaligned).
+static LAYOUT: Lazy<[u8; 3]> = Lazy::new(|| {
+    let one = rkyv::to_bytes::<rkyv::rancor::Error>(&1_u32).expect("archiving a u32 can't fail");
+    let big_endian = u8::from(one.first() != Some(&1));
+    let usize_width = size_of::<rkyv::Archived<usize>>();
+    let u64_align = align_of::<rkyv::Archived<u64>>();
+    #[allow(clippy::cast_possible_truncation)]
+    [big_endian, usize_width as u8, u64_align as u8]
+});
+
+/// Metadata stored alongside each value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Header {
+    /// How long the function took to compute the value.
+    pub compute_time: Duration,
+    /// When the value stops being served, if it was computed with a TTL.
+    pub expires_at: Option<SystemTime>,
+    /// When the value was computed.
+    pub created_at: Option<SystemTime>,
+    /// Whether the value was compressed with [`crate::compress`] to fit the size limit.
+    pub compressed: bool,
+    /// Whether backends able to should keep the value in a file of its own, whatever its size.
+    pub spill: bool,
+    /// Hash of the layout of the type the value was written as, if known.
+    pub type_layout: Option<u128>,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let nanos = u64::try_from(self.compute_time.as_nanos()).unwrap_or(u64::MAX);
+        let mut bytes = [0; HEADER_LEN];
+        bytes[..8].copy_from_slice(&nanos.to_le_bytes());
+        bytes[8..16].copy_from_slice(&to_secs(self.expires_at).to_le_bytes());
+        bytes[16..24].copy_from_slice(&to_secs(self.created_at).to_le_bytes());
+        bytes[LAYOUT_RANGE].copy_from_slice(&*LAYOUT);
+        bytes[FLAGS] =
+            (u8::from(self.compressed) * FLAG_COMPRESSED) | (u8::from(self.spill) * FLAG_SPILL);
+        bytes[TYPE_LAYOUT_RANGE].copy_from_slice(&self.type_layout.unwrap_or(0).to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let nanos = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?);
+        let expires_at = u64::from_le_bytes(bytes.get(8..16)?.try_into().ok()?);
+        let created_at = u64::from_le_bytes(bytes.get(16..24)?.try_into().ok()?);
+        let flags = *bytes.get(FLAGS)?;
+        let type_layout = u128::from_le_bytes(bytes.get(TYPE_LAYOUT_RANGE)?.try_into().ok()?);
+        Some(Self {
+            compute_time: Duration::from_nanos(nanos),
+            expires_at: from_secs(expires_at),
+            created_at: from_secs(created_at),
+            compressed: flags & FLAG_COMPRESSED != 0,
+            spill: flags & FLAG_SPILL != 0,
+            type_layout: (type_layout != 0).then_some(type_layout),
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= clock::now())
+    }
+}
+
+/// Seconds since the epoch, with 0 standing for `None`.
+fn to_secs(time: Option<SystemTime>) -> u64 {
+    time.map_or(0, |at| {
+        at.duration_since(UNIX_EPOCH)
+            .map_or(1, |since| since.as_secs().max(1))
+    })
+}
+
+fn from_secs(secs: u64) -> Option<SystemTime> {
+    (secs != 0)
+        .then(|| UNIX_EPOCH.checked_add(Duration::from_secs(secs)))
+        .flatten()
+}
+
+fn checksum(contents: &[u8]) -> [u8; CHECKSUM_LEN] {
+    Sha256::new()
+        .chain_update(FORMAT_TAG)
+        .chain_update(contents)
+        .finalize()
+        .into()
+}
+
+/// Wraps `value`, derived from `dependencies`, in an envelope ready to be written to the store,
+/// along with the `Debug` form of its key and the events logged computing it, if given.
+pub fn encode(
+    header: Header,
+    dependencies: &[Dependency],
+    debug_key: Option<&str>,
+    events: Option<&[u8]>,
+    value: &[u8],
+) -> Vec<u8> {
+    let mut entry = vec![0; VALUE_OFFSET];
+    entry[CHECKSUM_LEN..VALUE_OFFSET].copy_from_slice(&header.to_bytes());
+    if let Some(debug_key) = debug_key {
+        entry[CHECKSUM_LEN + FLAGS] |= FLAG_DEBUG_KEY;
+        push_section(&mut entry, debug_key.as_bytes());
+    }
+    if let Some(events) = events {
+        entry[CHECKSUM_LEN + FLAGS] |= FLAG_EVENTS;
+        push_section(&mut entry, events);
+    }
+    for dependency in dependencies {
+        // Names are Rust paths, far shorter than a `u16` can count.
+        let name = dependency.name.as_bytes();
+        let name = &name[..name.len().min(u16::MAX.into())];
+        entry.extend_from_slice(&dependency.hash);
+        #[allow(clippy::cast_possible_truncation)]
+        entry.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        entry.extend_from_slice(name);
+    }
+    entry.resize(entry.len().next_multiple_of(VALUE_ALIGNMENT), 0);
+    let dependencies_len = u32::try_from(entry.len() - VALUE_OFFSET).unwrap_or(u32::MAX);
+    entry[CHECKSUM_LEN + DEPENDENCIES_RANGE.start..CHECKSUM_LEN + DEPENDENCIES_RANGE.end]
+        .copy_from_slice(&dependencies_len.to_le_bytes());
+    entry.extend_from_slice(value);
+
+    let checksum = checksum(&entry[CHECKSUM_LEN..]);
+    entry[..CHECKSUM_LEN].copy_from_slice(&checksum);
+    entry
+}
+
+/// Appends a section of `bytes` prefixed with its `u32` length, truncating what doesn't fit.
+fn push_section(entry: &mut Vec<u8>, bytes: &[u8]) {
+    let len = u32::try_from(bytes.len()).unwrap_or(u32::MAX);
+    entry.extend_from_slice(&len.to_le_bytes());
+    entry.extend_from_slice(&bytes[..len as usize]);
+}
+
+/// Why [`decode`] rejected an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejected {
+    /// The entry is truncated or its checksum does not match.
+    Corrupt,
+    /// The entry is intact but was archived with a different layout than this build uses.
+    Foreign,
+}
+
+/// Returns the header and the offset of the value inside `entry`.
+pub fn decode(entry: &[u8]) -> Result<(Header, usize), Rejected> {
+    if entry.len() < VALUE_OFFSET {
+        return Err(Rejected::Corrupt);
+    }
+
+    let (expected, contents) = entry.split_at(CHECKSUM_LEN);
+    if checksum(contents) != *expected {
+        return Err(Rejected::Corrupt);
+    }
+    if contents[LAYOUT_RANGE] != *LAYOUT {
+        return Err(Rejected::Foreign);
+    }
+
+    let header = Header::from_bytes(&contents[..HEADER_LEN]).ok_or(Rejected::Corrupt)?;
+    let dependencies_len = u32::from_le_bytes(
+        contents[DEPENDENCIES_RANGE]
+            .try_into()
+            .map_err(|_| Rejected::Corrupt)?,
+    );
+    let offset = usize::try_from(dependencies_len)
+        .ok()
+        .and_then(|len| VALUE_OFFSET.checked_add(len))
+        .filter(|offset| *offset <= entry.len())
+        .ok_or(Rejected::Corrupt)?;
+    Ok((header, offset))
+}
+
+/// The value of an entry [`decode`] accepted, whose value starts at `offset`, decompressed if
+/// it was stored compressed. Returns `None` if it doesn't decompress.
+pub fn value<'a>(entry: &'a [u8], header: &Header, offset: usize) -> Option<Cow<'a, [u8]>> {
+    let value = &entry[offset..];
+    if header.compressed {
+        compress::decompress(value).map(Cow::Owned)
+    } else {
+        Some(Cow::Borrowed(value))
+    }
+}
+
+/// The optional sections of an entry, and the dependencies after them.
+struct Sections<'a> {
+    debug_key: Option<&'a str>,
+    events: Option<&'a [u8]>,
+    dependencies: &'a [u8],
+}
+
+/// Splits the part of an entry [`decode`] accepted between the header and the value, which
+/// starts at `offset`, into its sections.
+fn sections(entry: &[u8], offset: usize) -> Sections<'_> {
+    let flags = entry[CHECKSUM_LEN + FLAGS];
+    let rest = entry.get(VALUE_OFFSET..offset).unwrap_or_default();
+    let (debug_key, rest) = section(rest, flags & FLAG_DEBUG_KEY != 0);
+    let (events, rest) = section(rest, flags & FLAG_EVENTS != 0);
+    Sections {
+        debug_key: debug_key.and_then(|debug_key| std::str::from_utf8(debug_key).ok()),
+        events,
+        dependencies: rest,
+    }
+}
+
+/// Splits a length-prefixed section off the front of `bytes`, if the entry has it. A section
+/// that doesn't fit leaves nothing after it.
+fn section(bytes: &[u8], present: bool) -> (Option<&[u8]>, &[u8]) {
+    if !present {
+        return (None, bytes);
+    }
+    let Some((len, rest)) = bytes.split_first_chunk::<4>() else {
+        return (None, &[]);
+    };
+    let len = usize::try_from(u32::from_le_bytes(*len)).unwrap_or(usize::MAX);
+    rest.split_at_checked(len)
+        .map_or((None, &[]), |(section, rest)| (Some(section), rest))
+}
+
+/// The `Debug` form of the key recorded in an entry [`decode`] accepted, whose value starts at
+/// `offset`, if it was written with [`crate::Config::inspectable_keys`].
+pub fn debug_key(entry: &[u8], offset: usize) -> Option<&str> {
+    sections(entry, offset).debug_key
+}
+
+/// The events logged computing the value of an entry [`decode`] accepted, whose value starts at
+/// `offset`, if it was written with [`crate::Config::replay_logs`].
+pub fn events(entry: &[u8], offset: usize) -> Option<&[u8]> {
+    sections(entry, offset).events
+}
+
+/// The dependencies recorded in an entry [`decode`] accepted, whose value starts at `offset`.
+pub fn dependencies(entry: &[u8], offset: usize) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+    let mut rest = sections(entry, offset).dependencies;
+    while let Some((dependency, remaining)) = next_dependency(rest) {
+        dependencies.push(dependency);
+        rest = remaining;
+    }
+    dependencies
+}
+
+fn next_dependency(bytes: &[u8]) -> Option<(Dependency, &[u8])> {
+    let (hash, rest) = bytes.split_first_chunk::<32>()?;
+    let (name_len, rest) = rest.split_first_chunk::<2>()?;
+    let (name, rest) = rest.split_at_checked(u16::from_le_bytes(*name_len).into())?;
+    let dependency = Dependency {
+        name: std::str::from_utf8(name).ok()?.into(),
+        hash: FunctionHash::from(*hash),
+    };
+    Some((dependency, rest))
+}
@@ -0,0 +1,74 @@
+//! What gets mixed into every key besides the arguments: the configured [`Epoch`] and build
+//! profile, resolved once per process.
+
+use std::{
+    process::{self, Command},
+    time::UNIX_EPOCH,
+};
+
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::{clock, config, Epoch, Function};
+
+/// Marks the epoch appended to a key, after the serialized arguments.
+const TAG: &[u8] = b"\0smart-cache epoch\0";
+/// Marks the build profile appended to a key, after the epoch.
+const PROFILE_TAG: &[u8] = b"\0smart-cache profile\0";
+
+/// The suffixes of debug and release builds' keys.
+static SUFFIXES: Lazy<[Vec<u8>; 2]> = Lazy::new(|| {
+    let config = config::get();
+    let mut suffix = Vec::new();
+    if let Some(epoch) = config.key_epoch {
+        let id = match epoch {
+            Epoch::GitCommit => git_commit(),
+        };
+        suffix.extend_from_slice(TAG);
+        suffix.extend_from_slice(id.as_bytes());
+    }
+    [b"debug".as_slice(), b"release"].map(|profile| {
+        let mut suffix = suffix.clone();
+        if config.partition_by_profile {
+            suffix.extend_from_slice(PROFILE_TAG);
+            suffix.extend_from_slice(profile);
+        }
+        suffix
+    })
+});
+
+/// Bytes appended to every key of `function`; empty unless [`crate::Config::key_epoch`] or
+/// [`crate::Config::partition_by_profile`] is set. The profile is that of the crate defining
+/// `function`, which needn't be this crate's.
+pub fn suffix(function: &Function) -> &'static [u8] {
+    let [debug, release] = &*SUFFIXES;
+    if function.debug_build() {
+        debug
+    } else {
+        release
+    }
+}
+
+/// The commit checked out in the working directory. If it cannot be read, entries are scoped
+/// to this process instead, since sharing them across commits is what the epoch rules out.
+fn git_commit() -> String {
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output();
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+        Ok(output) => unknown_commit(&String::from_utf8_lossy(&output.stderr)),
+        Err(e) => unknown_commit(&e.to_string()),
+    }
+}
+
+fn unknown_commit(reason: &str) -> String {
+    warn!(
+        "Failed to read the git commit, caching for this process only: {}",
+        reason.trim()
+    );
+    let started = clock::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos());
+    format!("process {} {started}", process::id())
+}
@@ -0,0 +1,279 @@
+//! A C ABI over the store, so non-Rust components of a program can read and write the same
+//! cache. The declarations are in `include/smart_cache.h`.
+//!
+//! Functions are identified by name and, optionally, the 32-byte hash their entries were
+//! stored under; without a hash, the SHA-256 of the name is used. Keys and values are opaque
+//! bytes, and values go through the same envelope (checksum, expiry) as Rust-cached ones.
+//!
+//! A panic can't unwind into C, where it would abort the process, so every function catches
+//! them and reports failure instead: a miss while replaying (see [`crate::Mode::Replay`]) is an
+//! error here rather than a panic.
+//!
+//! To link from C, build a shared or static library with, for example,
+//! `cargo rustc -p smart-cache --release --features ffi --crate-type cdylib`.
+
+use std::{
+    ffi::{c_char, c_int, CStr},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{function, Config, Function, FunctionHash};
+
+/// Name of the store [`smart_cache_open`] points at a file.
+const FFI_STORE: &str = "ffi";
+
+/// Returned when the call succeeded or the value was found.
+const OK: c_int = 0;
+/// Returned by [`smart_cache_get`] when no value is stored.
+const MISS: c_int = 1;
+/// Returned for null pointers, names that aren't UTF-8, store failures and panics.
+const ERROR: c_int = -1;
+
+/// Runs the body of an exported function, returning `failed` if it panics rather than
+/// unwinding into the caller.
+fn guarded<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|_| {
+        warn!("A smart-cache call from C panicked; reporting failure");
+        failed
+    })
+}
+
+/// Borrows `len` bytes at `ptr`, which may be null when `len` is 0.
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        // SAFETY: the caller guarantees `ptr` points to `len` readable bytes.
+        (false, _) => Some(unsafe { slice::from_raw_parts(ptr, len) }),
+    }
+}
+
+unsafe fn function(name: *const c_char, hash: *const u8) -> Option<&'static Function> {
+    if name.is_null() {
+        return None;
+    }
+    // SAFETY: the caller guarantees `name` is a NUL-terminated string.
+    let name = unsafe { CStr::from_ptr(name) }.to_str().ok()?;
+    let hash: FunctionHash = if hash.is_null() {
+        Sha256::digest(name.as_bytes()).into()
+    } else {
+        // SAFETY: the caller guarantees a non-null `hash` points to 32 readable bytes.
+        unsafe { *hash.cast::<FunctionHash>() }
+    };
+    Some(function::intern(name, hash))
+}
+
+/// Reads and writes the redb file at `path` for every function, as if configured with
+/// [`crate::Config::store_name`] and [`crate::Config::database`]. Must be called before any
+/// other function here; returns 0 on success and -1 if the cache is already in use.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn smart_cache_open(path: *const c_char) -> c_int {
+    guarded(ERROR, || {
+        if path.is_null() {
+            return ERROR;
+        }
+        // SAFETY: the caller guarantees `path` is a NUL-terminated string.
+        let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+            return ERROR;
+        };
+
+        let config = Config::default()
+            .store_name(FFI_STORE)
+            .database(FFI_STORE, path);
+        match config.install() {
+            Ok(()) => OK,
+            Err(e) => {
+                warn!("Failed to open {path}: {e:#}");
+                ERROR
+            }
+        }
+    })
+}
+
+/// Looks up the value stored under `key` for the function `name`.
+///
+/// On a hit, returns 0 and sets `*value` and `*value_len` to a copy of the value, which must
+/// be released with [`smart_cache_free`]. Returns 1 on a miss and -1 on invalid arguments or
+/// failure.
+///
+/// # Safety
+///
+/// `name` must be a NUL-terminated string, `hash` null or 32 readable bytes, `key` valid for
+/// `key_len` bytes (or null if `key_len` is 0), and `value` and `value_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn smart_cache_get(
+    name: *const c_char,
+    hash: *const u8,
+    key: *const u8,
+    key_len: usize,
+    value: *mut *mut u8,
+    value_len: *mut usize,
+) -> c_int {
+    guarded(ERROR, || {
+        // SAFETY: forwarded from this function's contract.
+        let arguments = unsafe { (function(name, hash), bytes(key, key_len)) };
+        let (Some(function), Some(key)) = arguments else {
+            return ERROR;
+        };
+        if value.is_null() || value_len.is_null() {
+            return ERROR;
+        }
+
+        let Some(cached) = crate::get_cached(function, key) else {
+            return MISS;
+        };
+        let copy: Box<[u8]> = cached.to_vec().into_boxed_slice();
+        // SAFETY: both pointers were checked to be non-null and are writable per the contract.
+        unsafe {
+            *value_len = copy.len();
+            *value = Box::into_raw(copy).cast::<u8>();
+        }
+        OK
+    })
+}
+
+/// Stores `value` under `key` for the function `name`. Returns 0 on success and -1 on invalid
+/// arguments or if the store could not be written.
+///
+/// # Safety
+///
+/// `name` must be a NUL-terminated string, `hash` null or 32 readable bytes, and `key` and
+/// `value` valid for `key_len` and `value_len` bytes (or null if the length is 0).
+#[no_mangle]
+pub unsafe extern "C" fn smart_cache_set(
+    name: *const c_char,
+    hash: *const u8,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> c_int {
+    guarded(ERROR, || {
+        // SAFETY: forwarded from this function's contract.
+        let arguments = unsafe {
+            (
+                function(name, hash),
+                bytes(key, key_len),
+                bytes(value, value_len),
+            )
+        };
+        let (Some(function), Some(key), Some(value)) = arguments else {
+            return ERROR;
+        };
+
+        match crate::set_cached(function, key, value, Duration::ZERO, false, None) {
+            Ok(()) => OK,
+            Err(e) => {
+                warn!("Failed to cache value for {}: {e:#}", function.name());
+                ERROR
+            }
+        }
+    })
+}
+
+/// Removes every entry of the function(s) named `name`, as [`crate::clear_function`] does.
+/// Returns the number of function versions cleared, or -1 on failure.
+///
+/// # Safety
+///
+/// `name` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn smart_cache_clear(name: *const c_char) -> i64 {
+    guarded(ERROR.into(), || {
+        if name.is_null() {
+            return ERROR.into();
+        }
+        // SAFETY: the caller guarantees `name` is a NUL-terminated string.
+        let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else {
+            return ERROR.into();
+        };
+
+        match crate::clear_function(name) {
+            Ok(cleared) => i64::try_from(cleared).unwrap_or(i64::MAX),
+            Err(e) => {
+                warn!("Failed to clear cached function {name}: {e:#}");
+                ERROR.into()
+            }
+        }
+    })
+}
+
+/// Waits for pending writes to reach the store, as [`crate::flush`] does. Call this before
+/// exiting when write-behind is enabled.
+#[no_mangle]
+pub extern "C" fn smart_cache_flush() {
+    guarded((), crate::flush);
+}
+
+/// Returns the statistics of every function, as [`crate::stats`] does, one line per function:
+/// `name`, hex hash, calls, hits, compute and saved nanoseconds, bytes written, and hit
+/// nanoseconds, separated by tabs. Returns 0 and sets `*text` and `*text_len` (release with
+/// [`smart_cache_free`]), or -1 on failure.
+///
+/// # Safety
+///
+/// `text` and `text_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn smart_cache_stats(text: *mut *mut u8, text_len: *mut usize) -> c_int {
+    guarded(ERROR, || {
+        if text.is_null() || text_len.is_null() {
+            return ERROR;
+        }
+        let stats = match crate::stats() {
+            Ok(stats) => stats,
+            Err(e) => {
+                warn!("Failed to read cache statistics: {e:#}");
+                return ERROR;
+            }
+        };
+
+        let mut lines = String::new();
+        for stats in stats {
+            let hash: String = stats
+                .hash
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            lines.push_str(&format!(
+                "{}\t{hash}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                stats.name,
+                stats.calls,
+                stats.hits,
+                stats.compute_time.as_nanos(),
+                stats.time_saved.as_nanos(),
+                stats.bytes_written,
+                stats.hit_time.as_nanos(),
+            ));
+        }
+        let copy = lines.into_bytes().into_boxed_slice();
+        // SAFETY: both pointers were checked to be non-null and are writable per the contract.
+        unsafe {
+            *text_len = copy.len();
+            *text = Box::into_raw(copy).cast::<u8>();
+        }
+        OK
+    })
+}
+
+/// Releases a value returned by [`smart_cache_get`] or [`smart_cache_stats`].
+///
+/// # Safety
+///
+/// `value` and `value_len` must come from one successful [`smart_cache_get`] or
+/// [`smart_cache_stats`] call, and the value must not be used or freed again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn smart_cache_free(value: *mut u8, value_len: usize) {
+    if !value.is_null() {
+        // SAFETY: `value` was produced by `Box::into_raw` on a slice of `value_len` bytes.
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(value, value_len)) });
+    }
+}
@@ -0,0 +1,91 @@
+//! In-memory Bloom filter over the keys in the store, so definite misses never touch the disk.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::{backend::CacheBackend, config};
+
+/// Number of probes per key; optimal for the ~1% false-positive rate targeted below.
+const PROBES: u64 = 7;
+
+/// Bits reserved per expected entry for a ~1% false-positive rate.
+const BITS_PER_ENTRY: usize = 10;
+
+/// The filter, when enabled in the [`crate::Config`]. Stores opened from files add their keys to
+/// it as they are opened.
+static FILTER: Lazy<Option<MissFilter>> =
+    Lazy::new(|| config::get().miss_filter.map(MissFilter::with_capacity));
+
+pub fn global() -> Option<&'static MissFilter> {
+    FILTER.as_ref()
+}
+
+/// Adds every key in `backend` to the filter.
+pub fn populate(backend: &dyn CacheBackend) {
+    let Some(filter) = global() else {
+        return;
+    };
+
+    if let Err(e) = backend.for_each_key(&mut |key| filter.insert(key)) {
+        // Without all keys the filter would report false misses forever, so saturate it
+        // instead, which turns every lookup into a "maybe".
+        warn!("Failed to populate the miss filter, disabling it: {e:#}");
+        filter.saturate();
+    }
+}
+
+pub struct MissFilter {
+    words: Box<[AtomicU64]>,
+}
+
+impl MissFilter {
+    pub fn with_capacity(expected_entries: usize) -> Self {
+        let bits = expected_entries.max(1).saturating_mul(BITS_PER_ENTRY);
+        let words = (0..bits.div_ceil(64)).map(|_| AtomicU64::new(0)).collect();
+        Self { words }
+    }
+
+    /// Yields the bit positions probed for `key`, using double hashing.
+    fn probes(&self, key: &[u8]) -> impl Iterator<Item = u64> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let first = hasher.finish();
+        0xff_u8.hash(&mut hasher);
+        let second = hasher.finish() | 1;
+
+        let bits = self.words.len() as u64 * 64;
+        (0..PROBES).map(move |i| first.wrapping_add(i.wrapping_mul(second)) % bits)
+    }
+
+    pub fn insert(&self, key: &[u8]) {
+        for bit in self.probes(key) {
+            self.words[word(bit)].fetch_or(mask(bit), Ordering::Relaxed);
+        }
+    }
+
+    fn saturate(&self) {
+        for word in &self.words {
+            word.store(u64::MAX, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns `false` only if `key` was definitely never inserted.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.probes(key)
+            .all(|bit| self.words[word(bit)].load(Ordering::Relaxed) & mask(bit) != 0)
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn word(bit: u64) -> usize {
+    (bit / 64) as usize
+}
+
+const fn mask(bit: u64) -> u64 {
+    1 << (bit % 64)
+}
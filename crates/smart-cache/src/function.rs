@@ -0,0 +1,418 @@
+//! Identity of a cached function, as seen by the store.
+
+use std::{
+    any::type_name,
+    borrow::Cow,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::{Mutex, PoisonError, RwLock},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::{db, manifest, Oversize, Retry};
+
+/// SHA-256 of a cached function's tokens; changes whenever the function body does.
+pub type FunctionHash = [u8; 32];
+
+/// Describes a `#[cached]` function. The macro emits one of these per annotated function.
+///
+/// Two descriptors are equal when their hashes are; the name is only informational.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Function {
+    name: Cow<'static, str>,
+    hash: FunctionHash,
+    module: Option<&'static str>,
+    package: Option<&'static str>,
+    db: Option<&'static str>,
+    ttl: Option<Duration>,
+    negative_ttl: Option<Duration>,
+    stale_for: Option<Duration>,
+    refresh_ahead: bool,
+    timeout: Option<Duration>,
+    max_size: Option<usize>,
+    oversize: Option<Oversize>,
+    retry: Option<Retry>,
+    validator: Option<fn(&[u8]) -> bool>,
+    type_layout: Option<fn() -> u128>,
+    for_tests: bool,
+    debug_assertions: Option<bool>,
+}
+
+impl Function {
+    #[must_use]
+    pub const fn new(name: &'static str, hash: FunctionHash) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            hash,
+            module: None,
+            package: None,
+            db: None,
+            ttl: None,
+            negative_ttl: None,
+            stale_for: None,
+            refresh_ahead: false,
+            timeout: None,
+            max_size: None,
+            oversize: None,
+            retry: None,
+            validator: None,
+            type_layout: None,
+            for_tests: false,
+            debug_assertions: None,
+        }
+    }
+
+    /// A descriptor for a function named at runtime (e.g. by a remote client), which owns a
+    /// copy of its name instead of borrowing a `'static` one.
+    #[must_use]
+    pub fn owned(name: &str, hash: FunctionHash) -> Self {
+        Self {
+            name: Cow::Owned(name.to_string()),
+            ..Self::new("", hash)
+        }
+    }
+
+    /// Records the path of the module defining this function (`module_path!()`), which tells it
+    /// apart from cached functions of the same name elsewhere.
+    #[must_use]
+    pub const fn module(mut self, module: &'static str) -> Self {
+        self.module = Some(module);
+        self
+    }
+
+    /// Records the `<name>-<version>` of the crate defining this function.
+    #[must_use]
+    pub const fn package(mut self, package: &'static str) -> Self {
+        self.package = Some(package);
+        self
+    }
+
+    /// Stores this function's entries in the named database (`#[cached(db = "...")]`).
+    #[must_use]
+    pub const fn db(mut self, db: &'static str) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Lets this function's entries expire `ttl` after they are computed.
+    #[must_use]
+    pub const fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Lets this function's [`crate::Negative`] results expire `ttl` after they are computed
+    /// (`#[cached(negative_ttl = "...")]`).
+    #[must_use]
+    pub const fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+
+    /// Keeps serving this function's entries for `stale_for` after they expire, recomputing
+    /// them in the background (`#[cached(stale_for = "...")]`).
+    #[must_use]
+    pub const fn stale_for(mut self, stale_for: Duration) -> Self {
+        self.stale_for = Some(stale_for);
+        self
+    }
+
+    /// Recomputes this function's entries in the background once they are close to expiring,
+    /// as set by [`Config::refresh_ahead`](crate::Config::refresh_ahead)
+    /// (`#[cached(refresh_ahead)]`).
+    #[must_use]
+    pub const fn refresh_ahead(mut self) -> Self {
+        self.refresh_ahead = true;
+        self
+    }
+
+    /// Keeps this function's expired entries until they are replaced, and serves them when
+    /// recomputing the value takes longer than `timeout`, which then finishes in the background
+    /// (`#[cached(timeout = "...", on_timeout = "stale")]`).
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Treats values of this function larger than `bytes` as oversize
+    /// (`#[cached(max_size = ...)]`).
+    #[must_use]
+    pub const fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Handles oversize values of this function as `policy` says (`#[cached(oversize = "...")]`).
+    #[must_use]
+    pub const fn oversize(mut self, policy: Oversize) -> Self {
+        self.oversize = Some(policy);
+        self
+    }
+
+    /// Computes [`crate::Negative`] results of this function again as `policy` says, before
+    /// returning them (`#[cached(retry = ...)]`).
+    #[must_use]
+    pub const fn retry(mut self, policy: Retry) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Checks that an (aligned) stored value is a valid archive of the function's return type,
+    /// for [`crate::doctor`].
+    #[must_use]
+    pub const fn validator(mut self, validator: fn(&[u8]) -> bool) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    /// Stamps this function's entries with the hash of its return type's layout, from
+    /// [`type_layout`], so entries written before the type changed are misses. `type_layout`
+    /// is called on every lookup, so should remember the hash rather than compute it each time.
+    #[must_use]
+    pub const fn type_layout(mut self, type_layout: fn() -> u128) -> Self {
+        self.type_layout = Some(type_layout);
+        self
+    }
+
+    /// Marks this function as compiled for tests (`cfg(test)`), which moves the cache directory
+    /// to a temporary one once it is called.
+    #[must_use]
+    pub const fn for_tests(mut self, for_tests: bool) -> Self {
+        self.for_tests = for_tests;
+        self
+    }
+
+    /// Records whether the crate defining this function was built with debug assertions, which
+    /// [`crate::Config::partition_by_profile`] keys its entries by. Functions without it are
+    /// taken to be built like this crate.
+    #[must_use]
+    pub const fn debug_assertions(mut self, enabled: bool) -> Self {
+        self.debug_assertions = Some(enabled);
+        self
+    }
+
+    /// The function's name as written in the source.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub const fn hash(&self) -> &FunctionHash {
+        &self.hash
+    }
+
+    /// The function's name qualified by the path of the module defining it, if known.
+    #[must_use]
+    pub fn path(&self) -> Cow<'_, str> {
+        match self.module {
+            Some(module) => Cow::Owned(format!("{module}::{}", self.name)),
+            None => Cow::Borrowed(&self.name),
+        }
+    }
+
+    /// Whether [`Self::path`] is `path`.
+    fn has_path(&self, path: &str) -> bool {
+        match self.module {
+            Some(module) => path
+                .strip_prefix(module)
+                .and_then(|name| name.strip_prefix("::"))
+                .is_some_and(|name| name == self.name),
+            None => path == self.name,
+        }
+    }
+
+    /// The `<name>-<version>` of the crate defining this function, if known.
+    #[must_use]
+    pub const fn package_name(&self) -> Option<&'static str> {
+        self.package
+    }
+
+    /// The named database this function's entries live in, if not the default one.
+    #[must_use]
+    pub const fn database(&self) -> Option<&'static str> {
+        self.db
+    }
+
+    /// How long this function's entries stay valid, if they expire.
+    #[must_use]
+    pub const fn time_to_live(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// How long this function's negative results stay valid, if set apart from other results.
+    #[must_use]
+    pub const fn negative_time_to_live(&self) -> Option<Duration> {
+        self.negative_ttl
+    }
+
+    /// How long this function's entries are served after they expire, if they are.
+    #[must_use]
+    pub const fn stale_period(&self) -> Option<Duration> {
+        self.stale_for
+    }
+
+    /// Whether this function's entries are recomputed before they expire.
+    #[must_use]
+    pub const fn refreshes_ahead(&self) -> bool {
+        self.refresh_ahead
+    }
+
+    /// How long recomputing an expired value may take before the expired one is served, if it
+    /// may be.
+    #[must_use]
+    pub const fn compute_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// How this function's negative results are retried, if they are.
+    pub(crate) const fn retry_policy(&self) -> Option<Retry> {
+        self.retry
+    }
+
+    /// The size limit set for this function and its policy for values over it, if set.
+    pub(crate) const fn size_limit(&self) -> (Option<usize>, Option<Oversize>) {
+        (self.max_size, self.oversize)
+    }
+
+    /// Whether `value` is a valid archive of the function's return type, or `None` if the
+    /// descriptor has no validator.
+    pub(crate) fn validate(&self, value: &[u8]) -> Option<bool> {
+        self.validator.map(|validator| validator(value))
+    }
+
+    /// Whether the crate defining this function was built with debug assertions.
+    pub(crate) const fn debug_build(&self) -> bool {
+        match self.debug_assertions {
+            Some(enabled) => enabled,
+            None => cfg!(debug_assertions),
+        }
+    }
+
+    /// The hash of the return type's layout, if the descriptor has one.
+    pub(crate) fn layout(&self) -> Option<u128> {
+        self.type_layout.map(|type_layout| type_layout())
+    }
+}
+
+/// Internal function used by the macro to hash the layout of a function's return type `T`,
+/// stored as `S`: their names, sizes and alignments. Adding, removing or retyping a field
+/// changes it in all but rare cases, such as a field fitting in what was padding, or a change
+/// behind a pointer (the items of a `Vec`), which rkyv's validation catches instead.
+#[doc(hidden)]
+#[must_use]
+pub fn type_layout<T, S>() -> u128 {
+    let hash: [u8; 32] = Sha256::new()
+        .chain_update(type_name::<T>())
+        .chain_update([0])
+        .chain_update(type_name::<S>())
+        .chain_update([0])
+        .chain_update((size_of::<T>() as u64).to_le_bytes())
+        .chain_update((align_of::<T>() as u64).to_le_bytes())
+        .chain_update((size_of::<S>() as u64).to_le_bytes())
+        .chain_update((align_of::<S>() as u64).to_le_bytes())
+        .finalize()
+        .into();
+    let mut layout = [0; 16];
+    layout.copy_from_slice(&hash[..16]);
+    // 0 stands for entries written without a layout.
+    u128::from_le_bytes(layout).max(1)
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+    }
+}
+
+impl Eq for Function {}
+
+impl Hash for Function {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+/// Descriptors for functions known only at runtime (from remote clients or other languages),
+/// leaked once each to get the `'static` lifetime the store expects. Few distinct functions
+/// are ever seen, so this stays small.
+static INTERNED: Lazy<Mutex<HashMap<(String, FunctionHash), &'static Function>>> =
+    Lazy::new(Mutex::default);
+
+/// A `'static` descriptor for the function with this name and hash, for callers that name
+/// functions at runtime, such as `smart-cache-buildtime`.
+#[doc(hidden)]
+pub fn intern(name: &str, hash: FunctionHash) -> &'static Function {
+    let mut interned = INTERNED.lock().unwrap_or_else(PoisonError::into_inner);
+    interned.entry((name.to_string(), hash)).or_insert_with(|| {
+        let name = Box::leak(name.to_string().into_boxed_str());
+        Box::leak(Box::new(Function::new(name, hash)))
+    })
+}
+
+/// Small IDs for the function hashes this process has seen, in the order it first saw them, so
+/// in-memory indexes shared by all functions can tell them apart without keeping 32-byte
+/// hashes.
+static IDS: Lazy<RwLock<HashMap<FunctionHash, u32>>> = Lazy::new(RwLock::default);
+
+/// The ID of the function with this hash in this process.
+pub(crate) fn id(hash: &FunctionHash) -> u32 {
+    let ids = IDS.read().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&id) = ids.get(hash) {
+        return id;
+    }
+    drop(ids);
+    let mut ids = IDS.write().unwrap_or_else(PoisonError::into_inner);
+    let next = u32::try_from(ids.len()).expect("fewer than 2^32 functions");
+    *ids.entry(*hash).or_insert(next)
+}
+
+/// Every macro-generated descriptor this process has looked values up for, by hash.
+static REGISTERED: Lazy<RwLock<HashMap<FunctionHash, &'static Function>>> =
+    Lazy::new(RwLock::default);
+
+/// Remembers `function` so maintenance passes can find its descriptor by hash, and records its
+/// hash in the manifest the first time.
+pub(crate) fn register(function: &'static Function) {
+    if registered(&function.hash).is_none() {
+        if function.for_tests {
+            db::redirect_for_tests();
+        }
+        let mut registered = REGISTERED.write().unwrap_or_else(PoisonError::into_inner);
+        registered.insert(function.hash, function);
+        drop(registered);
+        manifest::record(function);
+    }
+}
+
+/// The descriptor registered for `hash`, if any.
+pub(crate) fn registered(hash: &FunctionHash) -> Option<&'static Function> {
+    let registered = REGISTERED.read().unwrap_or_else(PoisonError::into_inner);
+    registered.get(hash).copied()
+}
+
+/// The hashes of the registered descriptors whose [`Function::path`] is `path`.
+pub(crate) fn registered_hashes(path: &str) -> Vec<FunctionHash> {
+    let registered = REGISTERED.read().unwrap_or_else(PoisonError::into_inner);
+    registered
+        .values()
+        .filter(|function| function.has_path(path))
+        .map(|function| function.hash)
+        .collect()
+}
+
+/// A function with entries in the store, as returned by [`crate::functions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionInfo {
+    /// The function's name as written in the source.
+    pub name: String,
+    /// Hash of the function body the entries were computed with.
+    pub hash: FunctionHash,
+    /// Number of stored entries.
+    pub entries: u64,
+}
@@ -0,0 +1,123 @@
+//! Earlier values of a key, kept with [`crate::Config::keep_versions`].
+//!
+//! Before an entry is overwritten, it is moved to the first of the slots stored next to it
+//! under keys of their own, and the versions already there move one slot along, the oldest
+//! dropping out of the last. Keeping `n` versions costs `n - 1` reads on every write.
+
+use std::time::SystemTime;
+
+use eyre::Result;
+use rkyv::{api::high::HighValidator, bytecheck::CheckBytes, rancor, Archive, Portable};
+use tracing::warn;
+
+use crate::{
+    backend::{self, CacheBackend, WriteEntry},
+    config, context, entry, ArchivedValue, Function,
+};
+
+/// A value stored under a key at some point; see [`history`].
+pub struct Version<T> {
+    /// When the value was computed, to the second, if the entry recorded it.
+    pub created_at: Option<SystemTime>,
+    /// The value, in its archived form.
+    pub value: ArchivedValue<T>,
+}
+
+/// The key of `key`'s version slot `index`.
+fn slot_key(key: &[u8], index: usize) -> Vec<u8> {
+    let mut slot = key.to_vec();
+    slot.extend_from_slice(b"\0version");
+    slot.extend_from_slice(&(index as u64).to_le_bytes());
+    slot
+}
+
+/// Moves the entry stored under `key`, about to be overwritten, into the first version slot,
+/// shifting the versions there along by one and dropping the oldest. Failures are logged, since
+/// they shouldn't keep the new entry from being written.
+pub fn retain(backend: &dyn CacheBackend, function: &Function, key: &[u8]) {
+    let slots = config::get().keep_versions.saturating_sub(1);
+    if slots == 0 {
+        return;
+    }
+    if let Err(e) = shift(backend, function, key, slots) {
+        warn!(
+            "Failed to keep the previous value of {}: {e:#}",
+            function.name()
+        );
+    }
+}
+
+fn shift(backend: &dyn CacheBackend, function: &Function, key: &[u8], slots: usize) -> Result<()> {
+    let Some(current) = backend.get(function, key)? else {
+        return Ok(());
+    };
+    let mut shifted = vec![(slot_key(key, 0), current)];
+    for index in 1..slots {
+        let Some(entry) = backend.get(function, &slot_key(key, index - 1))? else {
+            break;
+        };
+        shifted.push((slot_key(key, index), entry));
+    }
+    let entries: Vec<_> = shifted
+        .iter()
+        .map(|(key, entry)| WriteEntry {
+            function,
+            key,
+            entry,
+        })
+        .collect();
+    backend.insert_batch(&entries)
+}
+
+/// Returns the values kept for `function` under `key` with [`crate::Config::keep_versions`],
+/// newest first, starting with the current one. Expired values are included, so it can be told
+/// what changed between one computation and the next.
+///
+/// ```no_run
+/// use smart_cache::Function;
+///
+/// static PRICES: Function = Function::new("prices", [0; 32]);
+///
+/// # fn main() -> eyre::Result<()> {
+/// let versions = smart_cache::history::<Vec<u64>>(&PRICES, b"catalog")?;
+/// if let [today, yesterday, ..] = &versions[..] {
+///     println!("{} prices then, {} now", yesterday.value.len(), today.value.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the store cannot be read, or a stored value is not a valid archived `T`.
+pub fn history<T>(function: &'static Function, key: &[u8]) -> Result<Vec<Version<T>>>
+where
+    T: Archive,
+    T::Archived: Portable + for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+{
+    crate::flush();
+    let Some(backend) = backend::for_function(function) else {
+        return Ok(Vec::new());
+    };
+    let key = context::key(function, key);
+    let slots = config::get().keep_versions.saturating_sub(1);
+
+    let mut versions = Vec::new();
+    let keys = std::iter::once(key.to_vec()).chain((0..slots).map(|index| slot_key(&key, index)));
+    for key in keys {
+        let Some(stored) = backend.get(function, &key)? else {
+            continue;
+        };
+        let Ok((header, offset)) = entry::decode(&stored) else {
+            continue;
+        };
+        let Some(value) = crate::cached_value(function, stored, &header, offset) else {
+            continue;
+        };
+        versions.push(Version {
+            created_at: header.created_at,
+            value: ArchivedValue::validated(function, value)?,
+        });
+    }
+    Ok(versions)
+}
@@ -0,0 +1,267 @@
+//! Minimal blocking HTTP/1.1 for the remote backends: one connection per request. `https://`
+//! endpoints need the `tls` feature; without it, remote stores are expected to be reached over a
+//! trusted network or through a TLS-terminating proxy.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An `http://` or `https://host[:port][/path]` URL.
+#[derive(Debug, Clone)]
+pub struct Url {
+    /// `host:port`, as sent in the `Host` header.
+    pub host: String,
+    /// Whether requests go over TLS.
+    pub tls: bool,
+    /// Path without a trailing slash; empty for the root.
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self> {
+        let (rest, tls) = match url.split_once("://") {
+            Some(("http", rest)) => (rest, false),
+            Some(("https", rest)) if cfg!(feature = "tls") => (rest, true),
+            Some(("https", _)) => bail!(
+                "https needs smart-cache's `tls` feature for {url:?}; enable it, or use an \
+                 http:// endpoint or a TLS-terminating proxy"
+            ),
+            _ => bail!("expected an http:// or https:// URL, got {url:?}"),
+        };
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let host = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:{}", if tls { 443 } else { 80 })
+        };
+
+        Ok(Self {
+            host,
+            tls,
+            path: format!("/{path}").trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub const fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    /// Fails unless the status is 2xx, including the body in the error.
+    pub fn error_for_status(self) -> Result<Self> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        let body = String::from_utf8_lossy(&self.body);
+        Err(eyre!("HTTP {}: {}", self.status, body.trim()))
+    }
+}
+
+/// Connects to the first of `address`'s resolved addresses that accepts within `timeout`, and
+/// applies `timeout` to reads and writes on the connection too.
+pub fn connect(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for resolved in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&resolved, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{address} resolves to no address"),
+        )
+    }))
+}
+
+/// Opens a connection to `url`'s host, over TLS if its scheme is `https`.
+pub fn open(url: &Url) -> io::Result<Box<dyn ReadWrite>> {
+    let stream = connect(&url.host, TIMEOUT)?;
+    #[cfg(feature = "tls")]
+    if url.tls {
+        let name = url
+            .host
+            .rsplit_once(':')
+            .map_or(url.host.as_str(), |(name, _)| name);
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        return Ok(Box::new(crate::tls::TlsStream::connect(stream, name)?));
+    }
+    Ok(Box::new(stream))
+}
+
+/// A connection to a remote store, over TLS or not.
+pub trait ReadWrite: Read + Write + Send {}
+
+impl<T: Read + Write + Send> ReadWrite for T {}
+
+/// Sends a request for `path_and_query` (starting with `/`) to `url`'s host.
+pub fn request(
+    method: &str,
+    url: &Url,
+    path_and_query: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> Result<Response> {
+    let host = &url.host;
+    let mut stream = open(url).wrap_err_with(|| format!("failed to connect to {host}"))?;
+
+    let mut head = format!(
+        "{method} {path_and_query} HTTP/1.1\r\nHost: {host}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n",
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    read_response(&mut BufReader::new(stream))
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.ends_with("\r\n") {
+        bail!("connection closed mid-response");
+    }
+    line.truncate(line.len() - 2);
+    Ok(line)
+}
+
+/// The start line and headers of a request or response.
+pub struct Head {
+    pub start: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Head {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn content_length(&self) -> Result<Option<usize>> {
+        self.header("content-length")
+            .map(|length| length.parse().wrap_err("malformed Content-Length"))
+            .transpose()
+    }
+
+    fn is_chunked(&self) -> bool {
+        self.header("transfer-encoding")
+            .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    }
+}
+
+pub fn read_head(reader: &mut impl BufRead) -> Result<Head> {
+    let start = read_line(reader)?;
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(Head { start, headers });
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+    }
+}
+
+fn read_chunked(reader: &mut impl BufRead, limit: usize) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let size = read_line(reader)?;
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).wrap_err("malformed chunk size")?;
+        if size == 0 {
+            // Skip trailers.
+            while !read_line(reader)?.is_empty() {}
+            return Ok(body);
+        }
+        let start = body.len();
+        if size > limit - start {
+            bail!("body exceeds {limit} bytes");
+        }
+        body.resize(start + size + 2, 0);
+        reader.read_exact(&mut body[start..])?;
+        body.truncate(start + size);
+    }
+}
+
+/// Reads a body framed by `Content-Length` or chunked encoding, failing if it is longer than
+/// `limit` bytes. Without either, requests have no body and responses extend to the end of the
+/// connection.
+pub fn read_body(
+    reader: &mut impl BufRead,
+    head: &Head,
+    is_request: bool,
+    limit: usize,
+) -> Result<Vec<u8>> {
+    if head.is_chunked() {
+        return read_chunked(reader, limit);
+    }
+    let mut body = Vec::new();
+    match head.content_length()? {
+        Some(length) if length > limit => bail!("body of {length} bytes exceeds {limit}"),
+        Some(length) => {
+            body.resize(length, 0);
+            reader.read_exact(&mut body)?;
+        }
+        None if is_request => {}
+        None => {
+            reader.read_to_end(&mut body)?;
+        }
+    }
+    Ok(body)
+}
+
+fn read_response(reader: &mut impl BufRead) -> Result<Response> {
+    let head = read_head(reader)?;
+    let status = head
+        .start
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| eyre!("malformed HTTP status line {:?}", head.start))?;
+
+    let body = read_body(reader, &head, false, usize::MAX)?;
+    Ok(Response { status, body })
+}
+
+/// Percent-encodes `value` for a URL, leaving unreserved characters (and `/` if `keep_slash`)
+/// as they are.
+#[cfg(feature = "s3")]
+pub fn encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(char::from(byte));
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,161 @@
+//! An append-only log of the writes and deletions made to the store, enabled with
+//! [`crate::Config::mutation_log`], to tell when a value was produced and by which binary.
+//!
+//! Each line records one mutation, separated by tabs: the time in seconds since the Unix
+//! epoch (with milliseconds), the operation, the function's name and hex hash, the SHA-256 of
+//! the key, the size of the entry in bytes, and the SHA-256 of the executable that made it.
+//! Operations are `set` for a written entry, `evict` for one discarded as expired or corrupt,
+//! and `delete` for entries removed on request; `*` stands for every function or key affected,
+//! or a size that isn't known. Once the log reaches its size limit it is renamed after the time
+//! and a new one is started; rotated logs are never removed.
+
+use std::{
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::UNIX_EPOCH,
+};
+
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{backend::to_hex, clock, config, Function, FunctionHash};
+
+/// The log being appended to, if one is configured and could be opened.
+static JOURNAL: Lazy<Option<Mutex<Journal>>> = Lazy::new(|| {
+    let config = config::get();
+    let path = config.mutation_log.as_ref()?;
+    match Journal::open(path.clone(), config.mutation_log_size) {
+        Ok(journal) => Some(Mutex::new(journal)),
+        Err(e) => {
+            warn!("Failed to open the mutation log {}: {e:#}", path.display());
+            None
+        }
+    }
+});
+
+/// SHA-256 of the running executable, or `-` if it can't be read.
+static BINARY: Lazy<String> = Lazy::new(|| {
+    std::env::current_exe().and_then(fs::read).map_or_else(
+        |_| "-".to_string(),
+        |binary| to_hex(&Sha256::digest(binary)),
+    )
+});
+
+struct Journal {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_size: u64,
+}
+
+impl Journal {
+    fn open(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = append(&path)?;
+        Ok(Self {
+            written: file.metadata()?.len(),
+            path,
+            file,
+            max_size,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.written > 0 && self.written + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    /// Renames the full log after the current time and starts a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        let now = clock::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", now.as_nanos()));
+        fs::rename(&self.path, rotated)?;
+        self.file = append(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Logs a written entry.
+pub fn set(function: &Function, key: &[u8], size: usize) {
+    record(|| entry("set", function.name(), function.hash(), key, &size));
+}
+
+/// Logs an entry discarded as expired or corrupt, of `size` bytes if known.
+pub fn evict(function: &Function, key: &[u8], size: Option<usize>) {
+    record(|| {
+        let size = size.map_or_else(|| "*".to_string(), |size| size.to_string());
+        entry("evict", function.name(), function.hash(), key, &size)
+    });
+}
+
+/// Logs the removal of an entry on request.
+pub fn delete(function: &Function, key: &[u8]) {
+    record(|| entry("delete", function.name(), function.hash(), key, &"*"));
+}
+
+/// Logs the removal of every entry of a function.
+pub fn clear(name: &str, hash: &FunctionHash) {
+    record(|| format!("delete\t{name}\t{}\t*\t*", to_hex(hash)));
+}
+
+/// Logs the removal of `removed` entries under a key prefix, of any function.
+pub fn purge(prefix: &[u8], removed: u64) {
+    record(|| format!("delete\t*\t*\t{}\t{removed}", key_hash(prefix)));
+}
+
+fn entry(
+    operation: &str,
+    name: &str,
+    hash: &FunctionHash,
+    key: &[u8],
+    size: &dyn Display,
+) -> String {
+    format!(
+        "{operation}\t{name}\t{}\t{}\t{size}",
+        to_hex(hash),
+        key_hash(key)
+    )
+}
+
+fn key_hash(key: &[u8]) -> String {
+    to_hex(&Sha256::digest(key))
+}
+
+/// Appends the mutation `describe` returns, prefixed with the time and suffixed with the
+/// binary, to the log if there is one.
+fn record(describe: impl FnOnce() -> String) {
+    let Some(journal) = JOURNAL.as_ref() else {
+        return;
+    };
+    let now = clock::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let line = format!(
+        "{}.{:03}\t{}\t{}\n",
+        now.as_secs(),
+        now.subsec_millis(),
+        describe(),
+        *BINARY
+    );
+    let written = journal
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .write(&line);
+    if let Err(e) = written {
+        warn!("Failed to write to the mutation log: {e:#}");
+    }
+}
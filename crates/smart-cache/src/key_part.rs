@@ -0,0 +1,334 @@
+//! Keying arguments by what identifies a computation rather than by their whole value.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
+
+/// A value that can be part of a cache key, through a canonical encoding of what identifies it.
+///
+/// Arguments of `#[cached]` functions marked `#[cache_key]` are keyed by this encoding instead
+/// of their archived value, so they needn't implement rkyv's traits, and may leave out what
+/// doesn't change the result, such as a connection or a logger. Derive it for structs and
+/// enums:
+///
+/// ```ignore
+/// use smart_cache::{cached, CacheKeyPart};
+///
+/// #[derive(CacheKeyPart)]
+/// struct Query {
+///     table: String,
+///     columns: Vec<String>,
+///     #[cache_key(skip)]
+///     client: Client,
+/// }
+///
+/// #[cached]
+/// fn run(#[cache_key] query: &Query) -> Vec<Row> {
+///     query.client.run(&query.table, &query.columns)
+/// }
+/// ```
+///
+/// Derived encodings write each field's name with its value, in the order of their names, so
+/// reordering fields keeps the keys they make; renaming or adding one changes them. Enums write
+/// their variant's name first. Fields marked `#[cache_key(skip)]` aren't part of the key.
+///
+/// Implementations must write the same bytes for values that should share an entry on every
+/// platform, and, for values that shouldn't, different bytes that don't prefix one another.
+pub trait CacheKeyPart {
+    /// Appends the encoding of `self` to `key`.
+    fn write_key(&self, key: &mut Vec<u8>);
+}
+
+/// Defines a newtype around a type of another crate, which can't implement [`CacheKeyPart`]
+/// or rkyv's traits there, keyed by what a closure picks out of it. The newtype derefs to the
+/// wrapped value and converts from and into it:
+///
+/// ```ignore
+/// smart_cache::key_wrapper!(pub DocumentKey(search::Document) => |doc| (doc.id, doc.revision));
+///
+/// #[smart_cache::cached]
+/// fn summarize(#[cache_key] doc: &DocumentKey) -> String {
+///     summarizer::run(doc)
+/// }
+///
+/// let summary = summarize(&document.into());
+/// ```
+///
+/// The closure takes a reference to the wrapped value and returns anything implementing
+/// [`CacheKeyPart`], such as a tuple of its identifying fields.
+#[macro_export]
+macro_rules! key_wrapper {
+    ($(#[$attr:meta])* $vis:vis $name:ident($inner:ty) => |$value:ident| $key:expr $(,)?) => {
+        $(#[$attr])*
+        $vis struct $name(pub $inner);
+
+        impl $crate::CacheKeyPart for $name {
+            fn write_key(&self, key: &mut ::std::vec::Vec<u8>) {
+                let $value: &$inner = &self.0;
+                $crate::CacheKeyPart::write_key(&$key, key);
+            }
+        }
+
+        impl ::std::convert::From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl ::std::ops::Deref for $name {
+            type Target = $inner;
+
+            fn deref(&self) -> &$inner {
+                &self.0
+            }
+        }
+
+        impl ::std::convert::AsRef<$inner> for $name {
+            fn as_ref(&self) -> &$inner {
+                &self.0
+            }
+        }
+
+        impl $name {
+            /// The wrapped value.
+            #[allow(dead_code)]
+            $vis fn into_inner(self) -> $inner {
+                self.0
+            }
+        }
+    };
+}
+
+/// The encoding of `part`, as stored in the key of a `#[cache_key]` argument.
+#[doc(hidden)]
+pub fn key_bytes<T: CacheKeyPart + ?Sized>(part: &T) -> Vec<u8> {
+    let mut key = Vec::new();
+    part.write_key(&mut key);
+    key
+}
+
+/// Writes a length ahead of the items it counts.
+fn write_len(len: usize, key: &mut Vec<u8>) {
+    (len as u64).write_key(key);
+}
+
+macro_rules! little_endian {
+    ($($ty:ty),*) => {$(
+        impl CacheKeyPart for $ty {
+            fn write_key(&self, key: &mut Vec<u8>) {
+                key.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+    )*};
+}
+
+little_endian!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// Pointer-sized integers are widened, so keys don't depend on the platform.
+impl CacheKeyPart for usize {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        (*self as u64).write_key(key);
+    }
+}
+
+impl CacheKeyPart for isize {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        (*self as i64).write_key(key);
+    }
+}
+
+// Floats that compare equal share a key, as do all NaNs.
+impl CacheKeyPart for f32 {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        f64::from(*self).write_key(key);
+    }
+}
+
+impl CacheKeyPart for f64 {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        let canonical = if self.is_nan() {
+            Self::NAN
+        } else if *self == 0.0 {
+            0.0
+        } else {
+            *self
+        };
+        canonical.to_bits().write_key(key);
+    }
+}
+
+impl CacheKeyPart for bool {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        key.push(u8::from(*self));
+    }
+}
+
+impl CacheKeyPart for char {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        u32::from(*self).write_key(key);
+    }
+}
+
+impl CacheKeyPart for () {
+    fn write_key(&self, _key: &mut Vec<u8>) {}
+}
+
+impl CacheKeyPart for str {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        self.as_bytes().write_key(key);
+    }
+}
+
+impl CacheKeyPart for String {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        self.as_str().write_key(key);
+    }
+}
+
+impl CacheKeyPart for Path {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        self.to_string_lossy().write_key(key);
+    }
+}
+
+impl CacheKeyPart for PathBuf {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        self.as_path().write_key(key);
+    }
+}
+
+impl<T: CacheKeyPart> CacheKeyPart for [T] {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        write_len(self.len(), key);
+        for item in self {
+            item.write_key(key);
+        }
+    }
+}
+
+impl<T: CacheKeyPart, const N: usize> CacheKeyPart for [T; N] {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        self.as_slice().write_key(key);
+    }
+}
+
+impl<T: CacheKeyPart> CacheKeyPart for Vec<T> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        self.as_slice().write_key(key);
+    }
+}
+
+impl<T: CacheKeyPart> CacheKeyPart for Option<T> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        match self {
+            None => key.push(0),
+            Some(value) => {
+                key.push(1);
+                value.write_key(key);
+            }
+        }
+    }
+}
+
+impl<T: CacheKeyPart, E: CacheKeyPart> CacheKeyPart for Result<T, E> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        match self {
+            Ok(value) => {
+                key.push(0);
+                value.write_key(key);
+            }
+            Err(error) => {
+                key.push(1);
+                error.write_key(key);
+            }
+        }
+    }
+}
+
+impl<T: CacheKeyPart + ?Sized> CacheKeyPart for &T {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        (**self).write_key(key);
+    }
+}
+
+impl<T: CacheKeyPart + ?Sized> CacheKeyPart for Box<T> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        (**self).write_key(key);
+    }
+}
+
+impl<T: CacheKeyPart + ?Sized> CacheKeyPart for Rc<T> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        (**self).write_key(key);
+    }
+}
+
+impl<T: CacheKeyPart + ?Sized> CacheKeyPart for Arc<T> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        (**self).write_key(key);
+    }
+}
+
+impl<K: CacheKeyPart, V: CacheKeyPart> CacheKeyPart for BTreeMap<K, V> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        write_len(self.len(), key);
+        for entry in self {
+            entry.write_key(key);
+        }
+    }
+}
+
+impl<T: CacheKeyPart> CacheKeyPart for BTreeSet<T> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        write_len(self.len(), key);
+        for item in self {
+            item.write_key(key);
+        }
+    }
+}
+
+/// Writes the encodings of `items` in sorted order, so iteration order doesn't matter.
+fn write_unordered<T: CacheKeyPart>(len: usize, items: impl Iterator<Item = T>, key: &mut Vec<u8>) {
+    let mut encoded: Vec<_> = items.map(|item| key_bytes(&item)).collect();
+    encoded.sort_unstable();
+    write_len(len, key);
+    for item in encoded {
+        key.extend_from_slice(&item);
+    }
+}
+
+impl<K: CacheKeyPart, V: CacheKeyPart, S> CacheKeyPart for HashMap<K, V, S> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        write_unordered(self.len(), self.iter(), key);
+    }
+}
+
+impl<T: CacheKeyPart, S> CacheKeyPart for HashSet<T, S> {
+    fn write_key(&self, key: &mut Vec<u8>) {
+        write_unordered(self.len(), self.iter(), key);
+    }
+}
+
+macro_rules! tuple {
+    ($($name:ident)+) => {
+        impl<$($name: CacheKeyPart),+> CacheKeyPart for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn write_key(&self, key: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.write_key(key);)+
+            }
+        }
+    };
+}
+
+tuple!(A);
+tuple!(A B);
+tuple!(A B C);
+tuple!(A B C D);
+tuple!(A B C D E);
+tuple!(A B C D E F);
+tuple!(A B C D E F G);
+tuple!(A B C D E F G H);
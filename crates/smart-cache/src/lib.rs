@@ -1,70 +1,890 @@
-use std::path::PathBuf;
+mod archive;
+mod archived;
+mod audit;
+pub mod backend;
+pub mod bench;
+mod blocking;
+pub mod build;
+#[doc(hidden)]
+pub mod clock;
+pub mod codec;
+pub mod compat;
+mod compress;
+mod config;
+mod context;
+mod db;
+mod decisions;
+mod dependency;
+mod doctor;
+mod entries;
+mod entry;
+mod epoch;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod filter;
+mod function;
+mod history;
+mod http;
+mod journal;
+mod key_part;
+mod limit;
+mod manifest;
+#[cfg(unix)]
+mod mapped;
+mod memo;
+mod mode;
+mod negative;
+mod oversize;
+mod prefetch;
+mod purge;
+mod quota;
+mod replay;
+mod retry;
+mod revalidate;
+mod scope;
+mod snapshot;
+mod stats;
+mod status;
+mod stream;
+pub mod testing;
+mod timeout;
+#[cfg(feature = "tls")]
+mod tls;
+mod trace;
+mod usage;
+mod value;
+mod warm;
+mod watchdog;
+mod writer;
+
+pub use archive::{export, export_filtered, import, import_into, ExportFilter};
+pub use archived::{get_archived, insert_archived, ArchivedValue};
+pub use audit::{audit, auditing, divergences, Divergence};
+pub use config::{Config, Durability, Epoch};
+pub use context::{context, Context, ContextGuard};
+#[doc(hidden)]
+pub use dependency::{track_dependencies, Tracking};
+pub use doctor::{doctor, DoctorReport};
+pub use entries::{entries, EntryInfo};
+#[doc(hidden)]
+pub use function::intern;
+pub use function::{type_layout, Function, FunctionHash, FunctionInfo};
+pub use history::{history, Version};
+pub use key_part::{key_bytes, CacheKeyPart};
+pub use limit::{compute_permit, compute_permit_async, with_permit, Permit};
+pub use manifest::{hash_changes, HashChange};
+pub use mode::{mode, Mode};
+pub use negative::Negative;
+pub use oversize::Oversize;
+pub use prefetch::prefetch;
+pub use purge::{purge_where, StoredKey};
+pub use quota::Quota;
+pub use replay::{capture_events, Capture};
+#[doc(hidden)]
+pub use retry::{retry, retry_async, Backoff, Retry};
+pub use revalidate::revalidate;
+pub use scope::{purge_scope, scoped};
+pub use smart_cache_macro::{cached, cached_mod, test, CacheKeyPart};
+pub use snapshot::{delete_snapshot, restore, snapshot, SnapshotId};
+pub use stats::FunctionStats;
+pub use status::{status, Status, StoreStatus};
+pub use stream::{stream_reader, stream_writer, ValueReader, ValueWriter};
+pub use timeout::{compute_or_expired, expired_value};
+pub use trace::{trace_call, write_trace};
+pub use usage::{usage, FunctionUsage};
+pub use value::{Aligned, CachedValue};
+#[cfg(feature = "rayon")]
+pub use warm::warm_parallel;
+pub use watchdog::{SizeAlert, Watchdog};
 
-pub use smart_cache_macro::cached;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    future::Future,
+    hash::{BuildHasher, RandomState},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use eyre::Result;
 use once_cell::sync::Lazy;
-use redb::{Database, TableDefinition};
-use tracing::{debug, trace};
-
-// Define the table that will store our cache entries
-const CACHE_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("cache");
+use tracing::{debug, trace, warn};
 
-static DB: Lazy<Database> = Lazy::new(|| {
-    let cache_dir = dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from(".cache"))
-        .join("smart-cache");
-    std::fs::create_dir_all(&cache_dir).expect("failed to create cache directory");
+use crate::{
+    backend::{CacheBackend, StoredEntry, WriteEntry},
+    dependency::Dependency,
+};
 
-    let db_path = cache_dir.join("cache.redb");
-    Database::create(db_path).expect("failed to create cache database")
+/// The background writer, when write-behind is enabled in the [`Config`].
+static WRITER: Lazy<Option<writer::Writer>> = Lazy::new(|| {
+    let config = config::get();
+    let capacity = config.write_behind?;
+    match writer::Writer::spawn(capacity, config.batch_size, config.batch_delay) {
+        Ok(writer) => Some(writer),
+        Err(e) => {
+            warn!("Failed to start cache writer thread, writing synchronously: {e:#}");
+            None
+        }
+    }
 });
 
+/// Blocks until every value computed so far has been written to the store and made durable,
+/// logging failures; see [`try_flush`].
+pub fn flush() {
+    if let Err(e) = try_flush() {
+        warn!("Failed to flush cache store: {e:#}");
+    }
+}
+
+/// Blocks until every value computed so far has been written to the store and made durable,
+/// e.g. before the process forks, exits or snapshots the store.
+///
+/// This only matters when write-behind is enabled (see [`Config::write_behind`]), commits are
+/// [`Durability::Eventual`], or a backend defers writes (such as a [`backend::TieredBackend`]
+/// with write-back tiers); otherwise values are persisted before the cached function returns.
+/// Values still being computed in the background, such as prefetches, aren't waited for.
+///
+/// # Errors
+///
+/// Fails if a store fails to flush. Every store is flushed regardless, and the first error is
+/// returned.
+pub fn try_flush() -> Result<()> {
+    if let Some(writer) = WRITER.as_ref() {
+        writer.flush();
+    }
+    stats::persist();
+    let mut flushed = Ok(());
+    for backend in backend::opened() {
+        flushed = flushed.and(backend.flush());
+    }
+    flushed
+}
+
+/// Returns cumulative hit/miss statistics for every cached function, including those recorded
+/// by earlier runs of the program.
+///
+/// Statistics are merged into the store every few seconds and on [`flush`], so the most recent
+/// calls of a process that exits without flushing are not counted.
+///
+/// # Errors
+///
+/// Fails if the store cannot be read.
+pub fn stats() -> Result<Vec<FunctionStats>> {
+    stats::persist();
+
+    let mut stats = Vec::new();
+    for backend in backend::all() {
+        stats.extend(backend.stats()?);
+    }
+    Ok(stats)
+}
+
+/// Lists every cached function that has entries in the store, including entries computed by
+/// earlier versions of a function's body.
+///
+/// Only stores this process has used are listed, along with those set up in the [`Config`]:
+/// a crate's own store is opened on the first call to one of its cached functions.
+///
+/// # Errors
+///
+/// Fails if the store cannot be read.
+pub fn functions() -> Result<Vec<FunctionInfo>> {
+    flush();
+
+    let mut functions = Vec::new();
+    for backend in backend::all() {
+        functions.extend(backend.functions()?);
+    }
+    Ok(functions)
+}
+
+/// Removes every cached entry of the function(s) named `name`, across all versions of their
+/// bodies. Returns the number of function versions cleared.
+///
+/// # Errors
+///
+/// Fails if the store cannot be read or written.
+pub fn clear_function(name: &str) -> Result<usize> {
+    flush();
+    memo::invalidate();
+    quota::reset();
+
+    let mut cleared = 0;
+    for backend in backend::all() {
+        for function in backend.functions()? {
+            if function.name == name {
+                backend.clear_function(&function.hash)?;
+                journal::clear(&function.name, &function.hash);
+                cleared += 1;
+            }
+        }
+    }
+    Ok(cleared)
+}
+
+/// The target triple this crate was built for, mixed into the keys of
+/// `#[cached(per_target)]` functions.
+#[doc(hidden)]
+pub const TARGET: &str = env!("SMART_CACHE_TARGET");
+
 /// Internal function used by the macro to get a cached value
 #[doc(hidden)]
-pub fn get_cached(key_bytes: &[u8]) -> Option<Vec<u8>> {
-    trace!("Attempting cache lookup");
-
-    match DB.begin_read() {
-        Ok(txn) => match txn.open_table(CACHE_TABLE) {
-            Ok(table) => match table.get(key_bytes) {
-                Ok(Some(value)) => {
-                    debug!("Cache hit");
-                    Some(value.value().to_vec())
-                }
-                Ok(None) => {
-                    debug!("Cache miss");
-                    None
-                }
-                Err(e) => {
-                    debug!("Cache error: {}", e);
-                    None
-                }
-            },
-            Err(e) => {
-                debug!("Failed to open table: {}", e);
+pub fn get_cached(function: &'static Function, key_bytes: &[u8]) -> Option<CachedValue> {
+    trace!("Attempting cache lookup for {}", function.name());
+    function::register(function);
+    if testing::uncached() {
+        return None;
+    }
+    let key_bytes = &*context::key(function, key_bytes).into_owned();
+
+    match mode::current() {
+        Mode::Normal | Mode::Audit if stats::unprofitable(function) => {
+            decisions::miss(function, key_bytes, "unprofitable");
+            None
+        }
+        Mode::Normal | Mode::Audit => lookup(function, key_bytes),
+        Mode::Record => {
+            decisions::miss(function, key_bytes, "recording");
+            None
+        }
+        Mode::Replay => {
+            Some(lookup(function, key_bytes).unwrap_or_else(|| mode::replay_miss(function)))
+        }
+    }
+}
+
+/// Looks `key_bytes` up in the miss filter, the calling thread's memo and the store.
+fn lookup(function: &'static Function, key_bytes: &[u8]) -> Option<CachedValue> {
+    // Opening the store first makes sure its keys are in the miss filter.
+    let Some(backend) = backend::for_function(function) else {
+        decisions::miss(function, key_bytes, "unavailable");
+        return None;
+    };
+    if filtered_out(function, key_bytes) {
+        return None;
+    }
+    let draw = random_unit();
+    if let Some(value) = lookup_memo(function, key_bytes, draw) {
+        return Some(value);
+    }
+    let (value, memo) = lookup_store(function, backend, key_bytes, draw)?;
+    if let Some((header, dependencies)) = memo {
+        memo::insert(
+            config::get().thread_memo,
+            function,
+            key_bytes,
+            &value,
+            header,
+            dependencies,
+        );
+    }
+    Some(value)
+}
+
+/// The key a cached `async fn` call looks its value up and stores it under, with the TTL and
+/// test isolation of the context the call started in. The context is thread-local, and by the
+/// time the value is stored, the call may have resumed on another thread or outlived it.
+#[doc(hidden)]
+pub struct AsyncKey {
+    key: Vec<u8>,
+    context_ttl: Option<Duration>,
+    uncached: bool,
+}
+
+/// Internal function used by the macro to resolve the key of a cached `async fn` call on the
+/// thread the call starts on, for [`get_cached_async`] and [`set_cached_async`].
+#[doc(hidden)]
+pub fn async_key(function: &Function, key: &[u8]) -> AsyncKey {
+    AsyncKey {
+        key: context::key(function, key).into_owned(),
+        context_ttl: context::context_ttl(),
+        uncached: testing::uncached(),
+    }
+}
+
+/// Async counterpart of [`get_cached`], used by the macro for `async fn`s. Store I/O runs on
+/// a separate thread pool, so the caller's executor keeps running meanwhile.
+#[doc(hidden)]
+pub fn get_cached_async(
+    function: &'static Function,
+    key: &AsyncKey,
+) -> impl Future<Output = Option<CachedValue>> {
+    trace!("Attempting cache lookup for {}", function.name());
+    function::register(function);
+    let key_bytes = key.key.clone();
+    let mode = mode::current();
+    let uncached = key.uncached;
+
+    async move {
+        match mode {
+            _ if uncached => None,
+            Mode::Normal | Mode::Audit if stats::unprofitable(function) => {
+                decisions::miss(function, &key_bytes, "unprofitable");
                 None
             }
-        },
+            Mode::Normal | Mode::Audit => lookup_async(function, key_bytes).await,
+            Mode::Record => {
+                decisions::miss(function, &key_bytes, "recording");
+                None
+            }
+            Mode::Replay => Some(
+                lookup_async(function, key_bytes)
+                    .await
+                    .unwrap_or_else(|| mode::replay_miss(function)),
+            ),
+        }
+    }
+}
+
+/// Async counterpart of [`lookup`].
+async fn lookup_async(function: &'static Function, key_bytes: Vec<u8>) -> Option<CachedValue> {
+    let backend = match backend::opened_for(function) {
+        Some(backend) => backend,
+        None => blocking::run(move || backend::for_function(function)).await,
+    };
+    let Some(backend) = backend else {
+        decisions::miss(function, &key_bytes, "unavailable");
+        return None;
+    };
+    if filtered_out(function, &key_bytes) {
+        return None;
+    }
+    let draw = random_unit();
+    if let Some(value) = lookup_memo(function, &key_bytes, draw) {
+        return Some(value);
+    }
+    let (value, memo, key_bytes) = blocking::run(move || {
+        let (value, memo) = lookup_store(function, backend, &key_bytes, draw)?;
+        Some((value, memo, key_bytes))
+    })
+    .await?;
+    if let Some((header, dependencies)) = memo {
+        memo::insert(
+            config::get().thread_memo,
+            function,
+            &key_bytes,
+            &value,
+            header,
+            dependencies,
+        );
+    }
+    Some(value)
+}
+
+/// Whether the miss filter rules out `key_bytes` being stored.
+fn filtered_out(function: &Function, key_bytes: &[u8]) -> bool {
+    let filtered = backend::filterable(function)
+        && filter::global().is_some_and(|filter| !filter.may_contain(key_bytes));
+    if filtered {
+        debug!("Cache miss (filtered)");
+        decisions::miss(function, key_bytes, "filtered");
+    }
+    filtered
+}
+
+/// Looks `key_bytes` up in the calling thread's memo. `draw` is the lookup's random number for
+/// [`expires_early`].
+fn lookup_memo(function: &'static Function, key_bytes: &[u8], draw: f64) -> Option<CachedValue> {
+    // Values due to be recomputed are looked up in the store, which has the new value once it's
+    // ready. Values expired early are too, and miss there with the same draw.
+    let (value, header, dependencies) =
+        memo::get(function, key_bytes).filter(|(_, header, _)| {
+            !header.is_expired()
+                && !is_due(function, header)
+                && !expires_early(function, key_bytes, header, draw)
+        })?;
+    if !derived_from_current(function, key_bytes, &dependencies) {
+        return None;
+    }
+    debug!("Cache hit (thread memo)");
+    stats::record_hit(function, header.compute_time);
+    decisions::hit(function, key_bytes, "memo", None, header.compute_time);
+    Some(CachedValue::memo(value))
+}
+
+/// What the calling thread's memo keeps about a value besides its bytes.
+type MemoInfo = (entry::Header, Arc<[Dependency]>);
+
+/// Looks `key_bytes` up in the pending and warmed writes, and the store. Returns the header and
+/// dependencies along with values read from the store, so the caller can memoize them. `draw`
+/// is the lookup's random number for [`expires_early`].
+fn lookup_store(
+    function: &'static Function,
+    backend: &dyn CacheBackend,
+    key_bytes: &[u8],
+    draw: f64,
+) -> Option<(CachedValue, Option<MemoInfo>)> {
+    let pending = WRITER
+        .as_ref()
+        .and_then(|writer| writer.pending(function.hash(), key_bytes))
+        .or_else(|| warm::held(function.hash(), key_bytes));
+    if let Some(entry) = pending {
+        let (header, offset) = verify(function, key_bytes, &entry)?;
+        if expires_early(function, key_bytes, &header, draw)
+            || !derived_from_current(function, key_bytes, &entry::dependencies(&entry, offset))
+        {
+            return None;
+        }
+        stats::record_hit(function, header.compute_time);
+        decisions::hit(
+            function,
+            key_bytes,
+            "pending",
+            Some(entry.len()),
+            header.compute_time,
+        );
+        replay_events(function, &entry, offset);
+        let value = cached_value(function, entry.into(), &header, offset)?;
+        return Some((value.revalidate(is_due(function, &header)), None));
+    }
+
+    match backend.get(function, key_bytes) {
+        Ok(Some(entry)) => {
+            let (header, offset) = verify(function, key_bytes, &entry)?;
+            if expires_early(function, key_bytes, &header, draw) {
+                return None;
+            }
+            let dependencies: Arc<[Dependency]> = entry::dependencies(&entry, offset).into();
+            if !derived_from_current(function, key_bytes, &dependencies) {
+                return None;
+            }
+            stats::record_hit(function, header.compute_time);
+            decisions::hit(
+                function,
+                key_bytes,
+                "store",
+                Some(entry.len()),
+                header.compute_time,
+            );
+            replay_events(function, &entry, offset);
+            let value = cached_value(function, entry, &header, offset)?;
+            Some((
+                value.revalidate(is_due(function, &header)),
+                Some((header, dependencies)),
+            ))
+        }
+        Ok(None) => {
+            debug!("Cache miss");
+            decisions::miss(function, key_bytes, "absent");
+            None
+        }
         Err(e) => {
-            debug!("Failed to begin read transaction: {}", e);
+            debug!("Cache error: {e:#}");
+            decisions::miss(function, key_bytes, "error");
             None
         }
     }
 }
 
-/// Internal function used by the macro to set a cached value
+/// Logs the events recorded computing a hit's value again, with [`Config::replay_logs`].
+fn replay_events(function: &Function, entry: &[u8], offset: usize) {
+    if !config::get().replay_logs {
+        return;
+    }
+    if let Some(events) = entry::events(entry, offset) {
+        replay::replay(function, events);
+    }
+}
+
+/// `ttl` shortened by a random part of the [`Config::ttl_jitter`] fraction of it.
+fn jittered(ttl: Duration) -> Duration {
+    let fraction = config::get().ttl_jitter.clamp(0.0, 1.0);
+    if fraction == 0.0 {
+        return ttl;
+    }
+    ttl.mul_f64(1.0 - fraction * random_unit())
+}
+
+/// A random number in `(0, 1]`.
+#[allow(clippy::cast_precision_loss)]
+fn random_unit() -> f64 {
+    // Each `RandomState` is seeded differently, which is all the randomness this needs.
+    let random = RandomState::new().hash_one(());
+    (random as f64 + 1.0) / (u64::MAX as f64 + 1.0)
+}
+
+/// When a value of `function` with this header stops being fresh: its expiry, less the period
+/// it is kept to be served stale.
+fn fresh_until(function: &Function, header: &entry::Header) -> Option<SystemTime> {
+    let expires_at = header.expires_at?;
+    Some(
+        expires_at
+            .checked_sub(function.stale_period().unwrap_or_default())
+            .unwrap_or(expires_at),
+    )
+}
+
+/// Whether [`Config::early_expiration`] has a lookup that drew `draw` treat a value of
+/// `function` with this header as expired. Values that took longer to compute, and values
+/// closer to expiring, are more likely to be.
+fn expires_early(function: &Function, key_bytes: &[u8], header: &entry::Header, draw: f64) -> bool {
+    let (Some(beta), Some(fresh_until)) = (
+        config::get().early_expiration,
+        fresh_until(function, header),
+    ) else {
+        return false;
+    };
+    let head_start = header.compute_time.as_secs_f64() * beta.max(0.0) * -draw.ln();
+    let early = Duration::try_from_secs_f64(head_start)
+        .ok()
+        .and_then(|head_start| clock::now().checked_add(head_start))
+        .is_none_or(|now| now >= fresh_until);
+    if early {
+        debug!("Recomputing a value of {} early", function.name());
+        decisions::miss(function, key_bytes, "early");
+    }
+    early
+}
+
+/// Whether a value of `function` with this header should be recomputed in the background:
+/// it is past its TTL and only kept to be served meanwhile, or it is within the last part of its
+/// TTL and [`Config::refresh_ahead`] is set.
+fn is_due(function: &Function, header: &entry::Header) -> bool {
+    let Some(fresh_until) = fresh_until(function, header) else {
+        return false;
+    };
+    let now = clock::now();
+    if function.stale_period().is_some() && fresh_until <= now {
+        return true;
+    }
+    if !function.refreshes_ahead() {
+        return false;
+    }
+
+    let (Some(factor), Some(created_at)) = (config::get().refresh_ahead, header.created_at) else {
+        return false;
+    };
+    let Ok(ttl) = fresh_until.duration_since(created_at) else {
+        return false;
+    };
+    now.duration_since(created_at)
+        .is_ok_and(|age| age.as_secs_f64() >= ttl.as_secs_f64() * factor)
+}
+
+/// The value of a verified entry, decompressed into memory of its own if it was stored
+/// compressed.
+fn cached_value(
+    function: &Function,
+    entry: StoredEntry,
+    header: &entry::Header,
+    offset: usize,
+) -> Option<CachedValue> {
+    if !header.compressed {
+        return Some(CachedValue::new(entry, offset));
+    }
+    let Some(value) = entry::value(&entry, header, offset) else {
+        warn!("Cache entry for {} failed to decompress", function.name());
+        return None;
+    };
+    Some(CachedValue::copied(&value))
+}
+
+/// Checks the entry's checksum and expiry, returning its header and the offset of the value
+/// inside it. Corrupted and expired entries are deleted so they get recomputed.
+fn verify(function: &Function, key_bytes: &[u8], stored: &[u8]) -> Option<(entry::Header, usize)> {
+    match entry::decode(stored) {
+        Ok((header, offset)) if header.is_expired() => {
+            decisions::miss(function, key_bytes, "expired");
+            if function.compute_timeout().is_some() {
+                debug!("Cache entry for {} expired; keeping it", function.name());
+                if let Some(value) = entry::value(stored, &header, offset) {
+                    timeout::keep_expired(function, CachedValue::copied(&value));
+                }
+                return None;
+            }
+            debug!("Cache entry for {} expired; discarding it", function.name());
+        }
+        Ok((header, _))
+            if header
+                .type_layout
+                .zip(function.layout())
+                .is_some_and(|(stored, current)| stored != current) =>
+        {
+            // Left in place for builds still on the old type, and replaced once recomputed.
+            decisions::miss(function, key_bytes, "retyped");
+            debug!(
+                "Cache entry for {} was written with another layout of its return type; ignoring it",
+                function.name()
+            );
+            return None;
+        }
+        Ok(decoded) => {
+            debug!("Cache hit");
+            return Some(decoded);
+        }
+        Err(entry::Rejected::Foreign) => {
+            // Left in place for the machines that can read it.
+            decisions::miss(function, key_bytes, "foreign");
+            debug!(
+                "Cache entry for {} has a different archive layout; ignoring it",
+                function.name()
+            );
+            return None;
+        }
+        Err(entry::Rejected::Corrupt) => {
+            warn!(
+                "Cache entry for {} failed checksum verification; discarding it",
+                function.name()
+            );
+            decisions::miss(function, key_bytes, "corrupt");
+        }
+    }
+
+    match remove_cached(function, key_bytes) {
+        Ok(()) => journal::evict(function, key_bytes, Some(stored.len())),
+        Err(e) => warn!("Failed to remove corrupted cache entry: {}", e),
+    }
+    None
+}
+
+/// Whether a value of `function` derived from `dependencies` can be served, noting them as
+/// dependencies of the computation in progress if so. Values derived from a function whose
+/// body has since changed are misses, and get recomputed.
+fn derived_from_current(
+    function: &Function,
+    key_bytes: &[u8],
+    dependencies: &[Dependency],
+) -> bool {
+    if !dependency::are_current(dependencies) {
+        debug!(
+            "Cache entry for {} depends on a changed function; recomputing it",
+            function.name()
+        );
+        decisions::miss(function, key_bytes, "dependency");
+        return false;
+    }
+    dependency::observe(function, dependencies);
+    true
+}
+
+fn remove_cached(function: &Function, key: &[u8]) -> Result<()> {
+    memo::invalidate();
+    quota::forget(function, key);
+
+    match backend::for_function(function) {
+        Some(backend) => backend.remove(function, key),
+        None => Ok(()),
+    }
+}
+
+/// Internal function used by the macro to tell whether to pass the `Debug` form of keys to
+/// [`set_cached`], for [`Config::inspectable_keys`].
+#[doc(hidden)]
+pub fn inspectable_keys() -> bool {
+    config::get().inspectable_keys
+}
+
+/// Internal function used by the macro to record how long a hit took, from looking the value
+/// up to deserializing it.
 #[doc(hidden)]
-pub fn set_cached(key: &[u8], value: &[u8]) -> Result<()> {
-    trace!("Caching value");
+pub fn record_hit_time(function: &'static Function, hit_time: Duration) {
+    stats::record_hit_time(function, hit_time);
+}
 
-    let write_txn = DB.begin_write()?;
+/// Internal function used by the macro to set a cached value. `negative` values, see
+/// [`Negative`], expire after the function's negative TTL if it has one. `debug_key` is the
+/// `Debug` form of the key, recorded with [`Config::inspectable_keys`].
+#[doc(hidden)]
+pub fn set_cached(
+    function: &'static Function,
+    key: &[u8],
+    value: &[u8],
+    compute_time: Duration,
+    negative: bool,
+    debug_key: Option<&str>,
+) -> Result<()> {
+    trace!("Caching value for {}", function.name());
+    let ttl = time_to_live(function, negative);
+    if testing::uncached() {
+        return Ok(());
+    }
+    let key = &*context::key(function, key);
+    match prepare_entry(function, key, value, compute_time, ttl, debug_key)? {
+        Some(entry) => store_entry(function, key, entry),
+        None => Ok(()),
+    }
+}
+
+/// Async counterpart of [`set_cached`], used by the macro for `async fn`s. Store I/O runs on
+/// a separate thread pool, so the caller's executor keeps running meanwhile.
+#[doc(hidden)]
+pub fn set_cached_async(
+    function: &'static Function,
+    key: &AsyncKey,
+    value: &[u8],
+    compute_time: Duration,
+    negative: bool,
+    debug_key: Option<&str>,
+) -> impl Future<Output = Result<()>> {
+    trace!("Caching value for {}", function.name());
+    let ttl = context::within(key.context_ttl, function_ttl(function, negative));
+    let uncached = key.uncached;
+    let key = key.key.clone();
+    let entry = if uncached {
+        Ok(None)
+    } else {
+        prepare_entry(function, &key, value, compute_time, ttl, debug_key)
+    };
+    blocking::run(move || match entry? {
+        Some(entry) => store_entry(function, &key, entry),
+        None => Ok(()),
+    })
+}
+
+thread_local! {
+    /// The TTL the function's `ttl_fn` computed for the value about to be stored on this
+    /// thread.
+    static RESULT_TTL: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// Internal function used by the macro to have the value it stores next on this thread expire
+/// after `ttl`, computed from the value with the function's `ttl_fn`.
+#[doc(hidden)]
+pub fn expire_after(ttl: Duration) {
+    RESULT_TTL.set(Some(ttl));
+}
+
+/// The TTL given with [`expire_after`] to the value about to be stored on this thread, if any.
+pub(crate) fn result_ttl() -> Option<Duration> {
+    RESULT_TTL.take()
+}
+
+/// How long a value of `function` stays fresh, given its `ttl_fn`, whether it is [`Negative`]
+/// and the calling thread's context.
+fn time_to_live(function: &Function, negative: bool) -> Option<Duration> {
+    context::ttl(function_ttl(function, negative))
+}
+
+/// How long a value of `function` stays fresh, given its `ttl_fn` and whether it is
+/// [`Negative`], before any context shortens it.
+fn function_ttl(function: &Function, negative: bool) -> Option<Duration> {
+    if let Some(ttl) = result_ttl() {
+        Some(ttl)
+    } else if negative {
+        function.negative_time_to_live().or(function.time_to_live())
+    } else {
+        function.time_to_live()
+    }
+}
+
+/// Records the miss, remembers the value in the calling thread's memo, and encodes the entry
+/// to store, or returns `None` if the value is over its size limit and skipped or its function
+/// no longer writes to the store ([`Config::adaptive`], [`Config::min_hit_rate`]). The entry
+/// expires `ttl` from now, plus the function's stale period if it has one.
+fn prepare_entry(
+    function: &'static Function,
+    key: &[u8],
+    value: &[u8],
+    compute_time: Duration,
+    ttl: Option<Duration>,
+    debug_key: Option<&str>,
+) -> Result<Option<Vec<u8>>> {
+    let fitted = oversize::fit(function, value);
+    let written = fitted
+        .as_ref()
+        .ok()
+        .and_then(Option::as_ref)
+        .map_or(0, |fitted| fitted.value.len());
+    stats::record_miss(function, compute_time, written);
+    let Some(fitted) = fitted? else {
+        decisions::skip(function, key, "oversize", compute_time);
+        return Ok(None);
+    };
+    if mode::current() != Mode::Record
+        && (stats::unprofitable(function) || stats::rarely_hit(function))
     {
-        let mut table = write_txn.open_table(CACHE_TABLE)?;
-        table.insert(key, value)?;
+        debug!("Not caching a value of {}", function.name());
+        decisions::skip(function, key, "unprofitable", compute_time);
+        return Ok(None);
+    }
+
+    if let Some(filter) = filter::global() {
+        filter.insert(key);
+    }
+
+    let header = entry::Header {
+        compute_time,
+        expires_at: ttl
+            .map(jittered)
+            .and_then(|ttl| ttl.checked_add(function.stale_period().unwrap_or_default()))
+            .and_then(|ttl| clock::now().checked_add(ttl)),
+        created_at: Some(clock::now()),
+        compressed: fitted.compressed,
+        spill: fitted.spill,
+        type_layout: function.layout(),
+    };
+    let dependencies = dependency::collected(function);
+    let debug_key = debug_key.filter(|_| config::get().inspectable_keys);
+    let events = replay::captured();
+    let entry = entry::encode(
+        header,
+        &dependencies,
+        debug_key,
+        events.as_deref(),
+        &fitted.value,
+    );
+    memo::insert(
+        config::get().thread_memo,
+        function,
+        key,
+        value,
+        header,
+        dependencies,
+    );
+    decisions::store(function, key, entry.len(), compute_time);
+    Ok(Some(entry))
+}
+
+fn store_entry(function: &'static Function, key: &[u8], entry: Vec<u8>) -> Result<()> {
+    let Some(entry) = warm::buffer(function, key, entry) else {
+        return Ok(());
+    };
+    if let Some(writer) = WRITER.as_ref() {
+        writer.submit(function, key.to_vec(), entry);
+        return Ok(());
     }
-    write_txn.commit()?;
 
+    if let Some(backend) = backend::for_function(function) {
+        history::retain(backend, function, key);
+        backend.insert_batch(&[WriteEntry {
+            function,
+            key,
+            entry: &entry,
+        }])?;
+        journal::set(function, key, entry.len());
+        quota::admit(backend, function, key, entry.len());
+        watchdog::written();
+    }
     debug!("Successfully cached value");
     Ok(())
 }
+
+/// Commits many entries, in a single write transaction per database where possible.
+fn insert_batch(batch: &writer::Batch) -> Result<()> {
+    let mut by_store: HashMap<_, Vec<_>> = HashMap::new();
+    for ((function, key), entry) in batch {
+        by_store
+            .entry(backend::store_name(function))
+            .or_default()
+            .push(WriteEntry {
+                function,
+                key,
+                entry,
+            });
+    }
+
+    for (store, entries) in by_store {
+        if let Some(backend) = backend::named(store) {
+            for entry in &entries {
+                history::retain(backend, entry.function, entry.key);
+            }
+            backend.insert_batch(&entries)?;
+            for entry in &entries {
+                journal::set(entry.function, entry.key, entry.entry.len());
+                quota::admit(backend, entry.function, entry.key, entry.entry.len());
+            }
+        }
+    }
+    watchdog::written();
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! The limit on computations running at once, set with
+//! [`crate::Config::max_concurrent_computations`].
+//!
+//! Each miss takes a permit before computing its value and returns it once the value is
+//! computed; misses beyond the limit wait for one. Values recomputed on threads of their own
+//! (`stale_for`, `on_timeout = "stale"`) take one there. Cached functions called while computing
+//! another value share its permit, so nesting can't exhaust the permits on its own. The
+//! computation of an `async fn` may move between threads, so its permit goes with it: its
+//! thread holds the permit while it is polled ([`with_permit`]).
+
+use std::{
+    cell::Cell,
+    future::{self, Future},
+    pin::{pin, Pin},
+    sync::{Condvar, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+use tracing::debug;
+
+use crate::config;
+
+struct Permits {
+    taken: usize,
+    /// Async misses waiting for a permit, woken whenever one is returned.
+    waiting: Vec<Waker>,
+}
+
+static PERMITS: Mutex<Permits> = Mutex::new(Permits {
+    taken: 0,
+    waiting: Vec::new(),
+});
+static RETURNED: Condvar = Condvar::new();
+
+thread_local! {
+    /// Whether this thread holds a permit for the computation it is running.
+    static HOLDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A permit to compute a value, returned when dropped.
+#[doc(hidden)]
+pub struct Permit {
+    /// Whether this permit marks its thread as [`HOLDING`] one.
+    thread_bound: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.thread_bound {
+            HOLDING.with(|holding| holding.set(false));
+        }
+        let waiting = {
+            let mut permits = PERMITS.lock().unwrap_or_else(PoisonError::into_inner);
+            permits.taken -= 1;
+            std::mem::take(&mut permits.waiting)
+        };
+        RETURNED.notify_one();
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+}
+
+/// Takes a permit if one is free, or registers `waker` to try again once one is returned.
+fn try_take(limit: usize, waker: &Waker) -> bool {
+    let mut permits = PERMITS.lock().unwrap_or_else(PoisonError::into_inner);
+    if permits.taken < limit {
+        permits.taken += 1;
+        return true;
+    }
+    permits.waiting.push(waker.clone());
+    false
+}
+
+/// Internal function used by the macro to wait for a permit before computing a value. Returns
+/// `None` without waiting if there is no limit, or the thread already holds a permit.
+#[doc(hidden)]
+pub fn compute_permit() -> Option<Permit> {
+    let limit = config::get().max_computations?;
+    if HOLDING.with(Cell::get) {
+        return None;
+    }
+    let mut permits = PERMITS.lock().unwrap_or_else(PoisonError::into_inner);
+    if permits.taken >= limit {
+        debug!("{limit} computations are running; waiting for one to finish");
+    }
+    while permits.taken >= limit {
+        permits = RETURNED
+            .wait(permits)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    permits.taken += 1;
+    HOLDING.with(|holding| holding.set(true));
+    Some(Permit { thread_bound: true })
+}
+
+/// Async counterpart of [`compute_permit`], used by the macro for `async fn`s. Resolves to
+/// `None` without waiting if there is no limit, or the computation polling it already holds a
+/// permit.
+#[doc(hidden)]
+pub fn compute_permit_async() -> impl Future<Output = Option<Permit>> {
+    PermitFuture {
+        limit: config::get().max_computations,
+    }
+}
+
+struct PermitFuture {
+    limit: Option<usize>,
+}
+
+impl Future for PermitFuture {
+    type Output = Option<Permit>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Permit>> {
+        let Some(limit) = self.limit.filter(|_| !HOLDING.with(Cell::get)) else {
+            return Poll::Ready(None);
+        };
+        if try_take(limit, cx.waker()) {
+            Poll::Ready(Some(Permit {
+                thread_bound: false,
+            }))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Marks the thread as holding a permit until dropped, restoring what it was before.
+struct Holding(bool);
+
+impl Holding {
+    fn enter() -> Self {
+        Self(HOLDING.with(|holding| holding.replace(true)))
+    }
+}
+
+impl Drop for Holding {
+    fn drop(&mut self) {
+        HOLDING.with(|holding| holding.set(self.0));
+    }
+}
+
+/// Internal function used by the macro to compute the value of an `async fn` under `permit`:
+/// whichever thread polls `computation` holds the permit meanwhile, so the cached calls it
+/// makes share it. The permit is returned once `computation` completes.
+#[doc(hidden)]
+pub async fn with_permit<F: Future>(permit: Option<Permit>, computation: F) -> F::Output {
+    let mut computation = pin!(computation);
+    let output = future::poll_fn(|cx| {
+        let _holding = permit.as_ref().map(|_| Holding::enter());
+        computation.as_mut().poll(cx)
+    })
+    .await;
+    drop(permit);
+    output
+}
@@ -0,0 +1,204 @@
+//! Remembering the body hash each cached function ran with, so [`hash_changes`] can explain
+//! why a run recomputed values its predecessors had cached.
+//!
+//! The manifest is a text file in the cache directory. Its first line is `run <n>`, counting
+//! the processes that have called a cached function; each further line describes one function,
+//! separated by tabs:
+//!
+//! ```text
+//! <path> <current hash> <previous hash, or -> <run that last changed it>
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs, io,
+    path::PathBuf,
+    process,
+    sync::{Mutex, PoisonError},
+};
+
+use eyre::{Result, WrapErr};
+use once_cell::sync::OnceCell;
+use tracing::warn;
+
+use crate::{
+    backend::{from_hex, to_hex},
+    db, Function, FunctionHash,
+};
+
+/// A function whose body hash changed in the most recent run, as returned by [`hash_changes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashChange {
+    /// The function's name as written in the source.
+    pub name: String,
+    /// The hash it had before, or `None` if the run was the first to call it.
+    pub previous: Option<FunctionHash>,
+    /// The hash it ran with.
+    pub current: FunctionHash,
+}
+
+struct Recorded {
+    current: FunctionHash,
+    previous: Option<FunctionHash>,
+    changed_in: u64,
+}
+
+#[derive(Default)]
+struct Manifest {
+    run: u64,
+    functions: BTreeMap<String, Recorded>,
+}
+
+impl Manifest {
+    fn path() -> Result<PathBuf> {
+        Ok(db::cache_dir()?.join("functions.manifest"))
+    }
+
+    /// Reads the manifest, which is empty if no run has been recorded. Unreadable lines are
+    /// skipped rather than failing, since the manifest is only diagnostic.
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+        let text = match fs::read_to_string(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            text => text.wrap_err_with(|| format!("reading {}", path.display()))?,
+        };
+
+        let mut lines = text.lines();
+        let run = lines
+            .next()
+            .and_then(|line| line.strip_prefix("run "))
+            .and_then(|run| run.parse().ok())
+            .unwrap_or_default();
+        let functions = lines.filter_map(parse_line).collect();
+        Ok(Self { run, functions })
+    }
+
+    /// Replaces the manifest atomically, so concurrent readers never see a partial file.
+    fn save(&self) -> Result<()> {
+        let mut text = format!("run {}\n", self.run);
+        for (name, recorded) in &self.functions {
+            let previous = recorded
+                .previous
+                .as_ref()
+                .map_or("-".to_string(), |hash| to_hex(hash));
+            let _ = writeln!(
+                text,
+                "{name}\t{}\t{previous}\t{}",
+                to_hex(&recorded.current),
+                recorded.changed_in
+            );
+        }
+
+        let path = Self::path()?;
+        let temporary = path.with_extension(format!("manifest.{}", process::id()));
+        fs::write(&temporary, text).wrap_err_with(|| format!("writing {}", temporary.display()))?;
+        fs::rename(&temporary, &path).wrap_err_with(|| format!("replacing {}", path.display()))
+    }
+}
+
+fn parse_line(line: &str) -> Option<(String, Recorded)> {
+    let mut fields = line.split('\t');
+    let name = fields.next()?.to_string();
+    let hash = |field: &str| FunctionHash::try_from(from_hex(field)?).ok();
+    let current = hash(fields.next()?)?;
+    let previous = hash(fields.next()?);
+    let changed_in = fields.next()?.parse().ok()?;
+    Some((
+        name,
+        Recorded {
+            current,
+            previous,
+            changed_in,
+        },
+    ))
+}
+
+/// The hash last recorded for every function by [`Function::path`], or none if the manifest
+/// can't be read.
+pub(crate) fn recorded_hashes() -> HashMap<String, FunctionHash> {
+    match Manifest::load() {
+        Ok(manifest) => manifest
+            .functions
+            .into_iter()
+            .map(|(name, recorded)| (name, recorded.current))
+            .collect(),
+        Err(e) => {
+            warn!("Failed to read the function manifest: {e:#}");
+            HashMap::new()
+        }
+    }
+}
+
+/// This process's run number, assigned when it records its first function.
+static RUN: OnceCell<u64> = OnceCell::new();
+
+/// Serializes this process's read-modify-write cycles on the manifest.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Notes that `function` ran in this process, marking it changed if its hash differs from the
+/// one last recorded under its path.
+pub(crate) fn record(function: &Function) {
+    if let Err(e) = try_record(function) {
+        warn!("Failed to record the hash of {}: {e:#}", function.name());
+    }
+}
+
+fn try_record(function: &Function) -> Result<()> {
+    let _guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut manifest = Manifest::load()?;
+    let run = *RUN.get_or_init(|| manifest.run + 1);
+    manifest.run = manifest.run.max(run);
+
+    let current = *function.hash();
+    let path = function.path();
+    match manifest.functions.get_mut(&*path) {
+        Some(recorded) if recorded.current == current => return manifest.save(),
+        Some(recorded) => {
+            recorded.previous = Some(recorded.current);
+            recorded.current = current;
+            recorded.changed_in = run;
+        }
+        None => {
+            manifest.functions.insert(
+                path.into_owned(),
+                Recorded {
+                    current,
+                    previous: None,
+                    changed_in: run,
+                },
+            );
+        }
+    }
+    manifest.save()
+}
+
+/// Lists the cached functions whose body hash changed in the most recent run that called a
+/// cached function, including those it called for the first time. Every entry a changed
+/// function had stored was computed by another version of its body, so the run recomputed them.
+///
+/// Cached functions with the same name in different modules are listed separately.
+///
+/// ```no_run
+/// for change in smart_cache::hash_changes()? {
+///     println!("{} changed since the previous run", change.name);
+/// }
+/// # Ok::<(), eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if the manifest exists but cannot be read.
+pub fn hash_changes() -> Result<Vec<HashChange>> {
+    let manifest = Manifest::load()?;
+    Ok(manifest
+        .functions
+        .into_iter()
+        .filter(|(_, recorded)| recorded.changed_in == manifest.run)
+        .map(|(path, recorded)| HashChange {
+            name: path.rsplit("::").next().unwrap_or_default().to_string(),
+            previous: recorded.previous,
+            current: recorded.current,
+        })
+        .collect())
+}
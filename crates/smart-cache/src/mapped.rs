@@ -0,0 +1,117 @@
+//! Memory-mapping spilled values, so hits on multi-gigabyte entries read them straight from the
+//! page cache instead of copying them into the heap.
+//!
+//! Entries are handed around as one contiguous slice, envelope first, but a spilled entry keeps
+//! its envelope in the database and its value in a file. A [`Mapping`] reserves an anonymous
+//! region, copies the envelope to the end of its first pages and maps the file over the rest,
+//! so the envelope runs straight into the value. The value starts on a page boundary, which
+//! also satisfies any alignment `rkyv::access` asks for.
+
+use std::{fs::File, io, ops::Deref, os::fd::AsRawFd, ptr, slice};
+
+/// A file mapped read-only behind a copy of a prefix.
+///
+/// Spilled files are written once under a temporary name and renamed into place, and are only
+/// ever deleted afterwards, which leaves existing mappings intact. The mapped bytes therefore
+/// never change while a `Mapping` is alive.
+pub struct Mapping {
+    base: *mut u8,
+    len: usize,
+    start: usize,
+}
+
+// SAFETY: the region is owned by the `Mapping` and never written after construction.
+unsafe impl Send for Mapping {}
+// SAFETY: as above; shared access only ever reads.
+unsafe impl Sync for Mapping {}
+
+fn page_size() -> usize {
+    // SAFETY: `sysconf` has no preconditions.
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(page).unwrap_or(4096)
+}
+
+impl Mapping {
+    /// Maps `file` so that it directly follows a copy of `prefix`. Fails for empty files, which
+    /// cannot be mapped.
+    pub fn with_prefix(prefix: &[u8], file: &File) -> io::Result<Self> {
+        let file_len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::other("file is too large to map"))?;
+        if file_len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "empty files cannot be mapped",
+            ));
+        }
+        let page = page_size();
+        let head = prefix.len().div_ceil(page) * page;
+        let len = head + file_len;
+
+        // SAFETY: a fresh anonymous mapping, placed wherever the kernel chooses.
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANON,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // Owning the region from here on unmaps it if the file can't be mapped.
+        let mapping = Self {
+            base: base.cast(),
+            len,
+            start: head - prefix.len(),
+        };
+
+        // SAFETY: the first `head` bytes of the region are writable and `start + prefix.len()`
+        // is `head`.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                prefix.as_ptr(),
+                mapping.base.add(mapping.start),
+                prefix.len(),
+            );
+        }
+        // SAFETY: `MAP_FIXED` replaces the last `file_len` bytes of the region this mapping
+        // owns, which is page-aligned because `head` is a multiple of the page size.
+        let mapped = unsafe {
+            libc::mmap(
+                mapping.base.add(head).cast(),
+                file_len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE | libc::MAP_FIXED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if mapped == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(mapping)
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the bytes from `start` to `len` are the prefix and the mapped file, both
+        // initialized and alive until the mapping is dropped.
+        unsafe { slice::from_raw_parts(self.base.add(self.start), self.len - self.start) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `base` and `len` describe the region mapped in `with_prefix`, which nothing
+        // borrows anymore.
+        unsafe {
+            libc::munmap(self.base.cast(), self.len);
+        }
+    }
+}
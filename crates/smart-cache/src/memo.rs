@@ -0,0 +1,139 @@
+//! Per-thread LRU of recently used values, so tight loops calling a cached function with the
+//! same arguments skip the store altogether.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use rkyv::util::AlignedVec;
+
+use crate::{dependency::Dependency, entry::Header, function, Function};
+
+/// Bumped whenever entries are removed from the store; memos built under an older generation
+/// are discarded so they never resurrect deleted values.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static MEMO: RefCell<Memo> = RefCell::new(Memo::default());
+}
+
+/// When a memoized value was last used, its bytes, header and dependencies.
+type Memoized = (u64, Arc<AlignedVec>, Header, Arc<[Dependency]>);
+
+/// Keys only identify an entry among those of one function, so entries are filed under the
+/// function's [`function::id`] too.
+#[derive(Default)]
+struct Memo {
+    generation: u64,
+    tick: u64,
+    entries: HashMap<u32, HashMap<Vec<u8>, Memoized>>,
+    len: usize,
+}
+
+impl Memo {
+    fn sync_generation(&mut self) {
+        let generation = GENERATION.load(Ordering::Acquire);
+        if self.generation != generation {
+            self.entries.clear();
+            self.len = 0;
+            self.generation = generation;
+        }
+    }
+
+    fn get(
+        &mut self,
+        function: u32,
+        key: &[u8],
+    ) -> Option<(Arc<AlignedVec>, Header, Arc<[Dependency]>)> {
+        self.sync_generation();
+        self.tick += 1;
+        let (last_used, value, header, dependencies) =
+            self.entries.get_mut(&function)?.get_mut(key)?;
+        *last_used = self.tick;
+        Some((Arc::clone(value), *header, Arc::clone(dependencies)))
+    }
+
+    fn insert(
+        &mut self,
+        capacity: usize,
+        function: u32,
+        key: &[u8],
+        value: &[u8],
+        header: Header,
+        dependencies: Arc<[Dependency]>,
+    ) {
+        self.sync_generation();
+        let known = self
+            .entries
+            .get(&function)
+            .is_some_and(|entries| entries.contains_key(key));
+        if self.len >= capacity && !known {
+            self.evict_least_recently_used();
+        }
+
+        let mut bytes = AlignedVec::with_capacity(value.len());
+        bytes.extend_from_slice(value);
+        self.tick += 1;
+        let replaced = self.entries.entry(function).or_default().insert(
+            key.to_vec(),
+            (self.tick, Arc::new(bytes), header, dependencies),
+        );
+        if replaced.is_none() {
+            self.len += 1;
+        }
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .flat_map(|(function, entries)| {
+                entries
+                    .iter()
+                    .map(move |(key, (last_used, ..))| (*last_used, *function, key))
+            })
+            .min_by_key(|(last_used, ..)| *last_used)
+            .map(|(_, function, key)| (function, key.clone()));
+        if let Some((function, key)) = oldest {
+            if let Some(entries) = self.entries.get_mut(&function) {
+                entries.remove(&key);
+                self.len -= 1;
+            }
+        }
+    }
+}
+
+pub fn get(
+    function: &Function,
+    key: &[u8],
+) -> Option<(Arc<AlignedVec>, Header, Arc<[Dependency]>)> {
+    let function = function::id(function.hash());
+    MEMO.with(|memo| memo.borrow_mut().get(function, key))
+}
+
+pub fn insert(
+    capacity: usize,
+    function: &Function,
+    key: &[u8],
+    value: &[u8],
+    header: Header,
+    dependencies: Arc<[Dependency]>,
+) {
+    if capacity > 0 {
+        let function = function::id(function.hash());
+        MEMO.with(|memo| {
+            memo.borrow_mut()
+                .insert(capacity, function, key, value, header, dependencies);
+        });
+    }
+}
+
+/// Invalidates every thread's memo.
+pub fn invalidate() {
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+}
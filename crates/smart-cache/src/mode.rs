@@ -0,0 +1,63 @@
+//! Record/replay: turns cached functions that wrap external services into deterministic test
+//! fixtures.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::Function;
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Normal as u8);
+
+/// How cached functions use the store; see [`mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mode {
+    /// Serve stored values and compute and store the rest.
+    #[default]
+    Normal,
+    /// Compute every call, ignoring stored values, and store each result.
+    Record,
+    /// Serve only stored values; a call with nothing stored panics instead of computing.
+    Replay,
+    /// Like [`Mode::Normal`], but also compute the value of every hit and compare it with the
+    /// stored one, reporting functions that return something else for the same arguments;
+    /// see [`crate::divergences`].
+    Audit,
+}
+
+/// Switches every cached function in the process to `mode`.
+///
+/// Record once against the real services, ship the store (or an [`crate::export`] of it),
+/// then replay in tests:
+///
+/// ```no_run
+/// # use smart_cache::cached;
+/// #[cached]
+/// fn forecast(city: String) -> String {
+///     // Calls out to a weather service.
+///     # city
+/// }
+///
+/// smart_cache::mode(smart_cache::Mode::Replay);
+/// let forecast = forecast("Oslo".to_string());
+/// # let _ = forecast;
+/// ```
+pub fn mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub(crate) fn current() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => Mode::Record,
+        2 => Mode::Replay,
+        3 => Mode::Audit,
+        _ => Mode::Normal,
+    }
+}
+
+/// Fails a call that has nothing recorded while replaying.
+pub(crate) fn replay_miss(function: &Function) -> ! {
+    panic!(
+        "smart-cache is replaying, but nothing was recorded for this call to {}",
+        function.name()
+    )
+}
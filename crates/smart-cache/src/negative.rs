@@ -0,0 +1,25 @@
+//! Telling negative results apart, for functions cached with `#[cached(negative_ttl = ...)]`.
+
+/// A result that records something being absent or failing, such as `None` or `Err`, rather
+/// than a value. Functions annotated with `#[cached(negative_ttl = "30s")]` cache negative
+/// results for that long instead of their usual TTL, so repeated lookups of a missing key don't
+/// all reach the source, while the key is looked up again soon in case it appears.
+///
+/// Implement it for return types with negative results of their own, such as a `NotFound`
+/// variant.
+pub trait Negative {
+    /// Whether this result is negative.
+    fn is_negative(&self) -> bool;
+}
+
+impl<T> Negative for Option<T> {
+    fn is_negative(&self) -> bool {
+        self.is_none()
+    }
+}
+
+impl<T, E> Negative for Result<T, E> {
+    fn is_negative(&self) -> bool {
+        self.is_err()
+    }
+}
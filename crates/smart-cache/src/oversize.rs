@@ -0,0 +1,93 @@
+//! Applying the size limit set with [`crate::Config::max_value_size`] or
+//! `#[cached(max_size = ...)]` to values about to be written.
+
+use std::borrow::Cow;
+
+use eyre::{bail, Result};
+use tracing::{debug, warn};
+
+use crate::{compress, config, Function};
+
+/// What to do with a value larger than its function's size limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Oversize {
+    /// Don't cache the value; the function computes it again on the next call.
+    Skip,
+    /// Keep the value in a file of its own rather than in the database, as
+    /// [`crate::Config::spill_values`] does for every large value. Stores that can't hold files
+    /// store it as usual.
+    Spill,
+    /// Compress the value, and skip it if it is still over the limit. Hits decompress it into
+    /// memory, so they cost a copy of the value and the time to decompress it.
+    Compress,
+    /// Don't cache the value, and fail the write. `#[cached]` functions don't report failed
+    /// writes to their caller, so this logs a warning for them.
+    Error,
+}
+
+/// A value as it is to be written, once the size limit is applied.
+pub struct Fitted<'a> {
+    pub value: Cow<'a, [u8]>,
+    pub compressed: bool,
+    pub spill: bool,
+}
+
+impl<'a> Fitted<'a> {
+    const fn new(value: Cow<'a, [u8]>) -> Self {
+        Self {
+            value,
+            compressed: false,
+            spill: false,
+        }
+    }
+}
+
+/// Applies `function`'s size limit to `value`, returning what to write or `None` to skip it.
+pub fn fit<'a>(function: &Function, value: &'a [u8]) -> Result<Option<Fitted<'a>>> {
+    let config = config::get();
+    let (max_size, policy) = function.size_limit();
+    let Some(max_size) = max_size.or(config.max_value_size) else {
+        return Ok(Some(Fitted::new(Cow::Borrowed(value))));
+    };
+    if value.len() <= max_size {
+        return Ok(Some(Fitted::new(Cow::Borrowed(value))));
+    }
+
+    let name = function.name();
+    match policy.unwrap_or(config.oversize) {
+        Oversize::Skip => {
+            debug!(
+                "Not caching a {}-byte value of {name}: over the {max_size}-byte limit",
+                value.len()
+            );
+            Ok(None)
+        }
+        Oversize::Spill => Ok(Some(Fitted {
+            spill: true,
+            ..Fitted::new(Cow::Borrowed(value))
+        })),
+        Oversize::Compress => {
+            let compressed = compress::compress(value);
+            if compressed.len() > max_size {
+                debug!(
+                    "Not caching a value of {name}: over the {max_size}-byte limit even compressed"
+                );
+                return Ok(None);
+            }
+            Ok(Some(Fitted {
+                compressed: true,
+                ..Fitted::new(Cow::Owned(compressed))
+            }))
+        }
+        Oversize::Error => {
+            warn!(
+                "Not caching a {}-byte value of {name}: over the {max_size}-byte limit",
+                value.len()
+            );
+            bail!(
+                "a {}-byte value of {name} is over the {max_size}-byte limit",
+                value.len()
+            );
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Computing values ahead of the calls that will need them, for functions cached with
+//! `#[cached(prefetch)]`.
+//!
+//! Prefetches are queued for a few background threads, which call the cached function for each:
+//! values already cached are hits, and the others are computed and stored. The threads are
+//! started on the first prefetch. Prefetches are hints, so once [`CAPACITY`] are waiting, more
+//! are dropped rather than queued. Calls are made in the context (namespace and TTL) of the
+//! thread that queued them.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+
+use once_cell::sync::Lazy;
+use tracing::{debug, warn};
+
+use crate::context;
+
+/// How many threads compute prefetched values.
+const WORKERS: usize = 4;
+
+/// How many prefetches may wait for a thread before more are dropped.
+const CAPACITY: usize = 1024;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static QUEUE: Lazy<Option<SyncSender<Job>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::sync_channel::<Job>(CAPACITY);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let mut workers = 0;
+    for _ in 0..WORKERS {
+        let receiver = Arc::clone(&receiver);
+        let spawned = thread::Builder::new()
+            .name("smart-cache-prefetch".to_string())
+            .spawn(move || work(&receiver));
+        match spawned {
+            Ok(_) => workers += 1,
+            Err(e) => warn!("Failed to start a prefetch thread: {e:#}"),
+        }
+    }
+    if workers == 0 {
+        warn!("No prefetch thread could be started; prefetches are ignored");
+    }
+    (workers > 0).then_some(sender)
+});
+
+/// One prefetch thread: runs queued calls until the queue is dropped, which a static never is.
+fn work(jobs: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = jobs.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        let Ok(job) = job else {
+            return;
+        };
+        // A panicking computation only loses its own prefetch.
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+/// Internal function used by the macro to queue a call of a cached function with owned copies
+/// of its arguments, for `<name>_prefetch`. The call is made in the caller's context, unless
+/// the queue is full.
+#[doc(hidden)]
+pub fn prefetch(call: impl FnOnce() + Send + 'static) {
+    let Some(queue) = QUEUE.as_ref() else {
+        return;
+    };
+    let context = context::capture();
+    let call = move || {
+        let _context = context.enter();
+        call();
+    };
+    if let Err(TrySendError::Full(_)) = queue.try_send(Box::new(call)) {
+        debug!("{CAPACITY} prefetches are waiting; dropping another");
+    }
+}
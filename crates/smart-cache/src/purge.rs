@@ -0,0 +1,79 @@
+//! Removing the entries whose keys match a predicate, such as every value derived from one
+//! user's data.
+
+use eyre::Result;
+
+use crate::{
+    backend::{self, CacheBackend},
+    entry, function, journal, memo, quota, FunctionInfo,
+};
+
+/// What [`purge_where`] knows about a stored entry.
+#[derive(Debug, Clone, Copy)]
+pub struct StoredKey<'a> {
+    /// The name of the function the entry belongs to.
+    pub function: &'a str,
+    /// The `Debug` form of the function's arguments, recorded with
+    /// [`crate::Config::inspectable_keys`].
+    pub debug_key: &'a str,
+}
+
+/// Removes every entry of every known store whose key `predicate` matches, returning the
+/// number of entries removed. Only entries written with [`crate::Config::inspectable_keys`]
+/// record a key to match; the others are kept.
+///
+/// ```no_run
+/// let user_id = "user-4711";
+/// let removed = smart_cache::purge_where(|entry| entry.debug_key.contains(user_id))?;
+/// println!("removed {removed} entries");
+/// # Ok::<(), eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if a store cannot be read or written.
+pub fn purge_where(mut predicate: impl FnMut(&StoredKey<'_>) -> bool) -> Result<u64> {
+    crate::flush();
+    memo::invalidate();
+    quota::reset();
+
+    let mut removed = 0;
+    for backend in backend::all() {
+        for function in backend.functions()? {
+            let keys = matching(backend, &function, &mut predicate)?;
+            let descriptor = function::registered(&function.hash)
+                .unwrap_or_else(|| function::intern(&function.name, function.hash));
+            for key in &keys {
+                backend.remove(descriptor, key)?;
+                journal::delete(descriptor, key);
+            }
+            removed += keys.len() as u64;
+        }
+    }
+    Ok(removed)
+}
+
+/// The keys of the entries of `function` whose recorded `Debug` key `predicate` matches.
+fn matching(
+    backend: &dyn CacheBackend,
+    function: &FunctionInfo,
+    predicate: &mut impl FnMut(&StoredKey<'_>) -> bool,
+) -> Result<Vec<Vec<u8>>> {
+    let mut keys = Vec::new();
+    backend.for_each_function_entry(&function.hash, &mut |key, stored| {
+        let debug_key = entry::decode(stored)
+            .ok()
+            .and_then(|(_, offset)| entry::debug_key(stored, offset));
+        let Some(debug_key) = debug_key else {
+            return;
+        };
+        let stored_key = StoredKey {
+            function: &function.name,
+            debug_key,
+        };
+        if predicate(&stored_key) {
+            keys.push(key.to_vec());
+        }
+    })?;
+    Ok(keys)
+}
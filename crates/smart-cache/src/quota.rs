@@ -0,0 +1,248 @@
+//! Limits on what each namespace may keep, set with [`crate::Config::namespace_quota`].
+//!
+//! Writes under a namespace with a quota are tallied per namespace, starting from a scan of the
+//! stores the first time the namespace is written to. Once a namespace holds more than its
+//! quota, its oldest entries are evicted until it fits again; entries of other namespaces, and
+//! those outside any namespace, are never evicted to make room.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use once_cell::sync::Lazy;
+use tracing::{debug, warn};
+
+use crate::{
+    backend::{self, CacheBackend},
+    config, context, entry, function, journal, memo, Function, FunctionHash,
+};
+
+/// How much a namespace may keep before its oldest entries are evicted.
+///
+/// ```no_run
+/// use smart_cache::Quota;
+///
+/// smart_cache::Config::default()
+///     .namespace_quota("experiments", Quota::new().max_bytes(512 * 1024 * 1024))
+///     .namespace_quota("scratch", Quota::new().max_entries(10_000))
+///     .install()?;
+/// # Ok::<(), eyre::Report>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    max_bytes: Option<u64>,
+    max_entries: Option<u64>,
+}
+
+impl Quota {
+    /// A quota without limits; add them with [`Quota::max_bytes`] and [`Quota::max_entries`].
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_bytes: None,
+            max_entries: None,
+        }
+    }
+
+    /// Evict once the namespace's entries take more than `bytes`, as stored.
+    #[must_use]
+    pub const fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Evict once the namespace holds more than `entries` entries.
+    #[must_use]
+    pub const fn max_entries(mut self, entries: u64) -> Self {
+        self.max_entries = Some(entries);
+        self
+    }
+
+    fn exceeded_by(&self, usage: &Usage) -> bool {
+        self.max_bytes.is_some_and(|max| usage.bytes > max)
+            || self
+                .max_entries
+                .is_some_and(|max| usage.order.len() as u64 > max)
+    }
+}
+
+/// An entry counted against a namespace's quota.
+struct Tracked {
+    backend: &'static dyn CacheBackend,
+    function: &'static Function,
+    key: Vec<u8>,
+    size: u64,
+}
+
+/// The entries of one namespace, oldest first.
+#[derive(Default)]
+struct Usage {
+    bytes: u64,
+    next: u64,
+    order: BTreeMap<u64, Tracked>,
+    positions: HashMap<(FunctionHash, Vec<u8>), u64>,
+}
+
+impl Usage {
+    fn insert(&mut self, tracked: Tracked) {
+        self.remove(tracked.function.hash(), &tracked.key);
+        self.bytes += tracked.size;
+        self.positions
+            .insert((*tracked.function.hash(), tracked.key.clone()), self.next);
+        self.order.insert(self.next, tracked);
+        self.next += 1;
+    }
+
+    fn remove(&mut self, function: &FunctionHash, key: &[u8]) {
+        let Some(position) = self.positions.remove(&(*function, key.to_vec())) else {
+            return;
+        };
+        if let Some(tracked) = self.order.remove(&position) {
+            self.bytes -= tracked.size;
+        }
+    }
+
+    fn pop_oldest(&mut self) -> Option<Tracked> {
+        let (_, tracked) = self.order.pop_first()?;
+        self.positions
+            .remove(&(*tracked.function.hash(), tracked.key.clone()));
+        self.bytes -= tracked.size;
+        Some(tracked)
+    }
+}
+
+/// The key prefix of each namespace with a quota.
+static PREFIXES: Lazy<Vec<(Vec<u8>, Quota)>> = Lazy::new(|| {
+    config::get()
+        .namespace_quotas
+        .iter()
+        .map(|(namespace, quota)| {
+            let mut prefix = Vec::new();
+            context::push_segment(&mut prefix, namespace);
+            (prefix, *quota)
+        })
+        .collect()
+});
+
+/// The usage of each namespace written to so far, by key prefix.
+static USAGE: Lazy<Mutex<HashMap<Vec<u8>, Usage>>> = Lazy::new(Mutex::default);
+
+/// The namespace with a quota that `key` belongs to, if any.
+fn namespace(key: &[u8]) -> Option<&'static (Vec<u8>, Quota)> {
+    PREFIXES.iter().find(|(prefix, _)| key.starts_with(prefix))
+}
+
+/// Counts an entry of `size` bytes just written to `backend` against its namespace's quota,
+/// evicting the namespace's oldest entries if it is now over.
+pub fn admit(backend: &'static dyn CacheBackend, function: &Function, key: &[u8], size: usize) {
+    let Some((prefix, quota)) = namespace(key) else {
+        return;
+    };
+    let function = descriptor(function.name(), *function.hash());
+
+    let mut usage = USAGE.lock().unwrap_or_else(PoisonError::into_inner);
+    let usage = usage.entry(prefix.clone()).or_insert_with(|| scan(prefix));
+    usage.insert(Tracked {
+        backend,
+        function,
+        key: key.to_vec(),
+        size: size as u64,
+    });
+
+    let mut evicted = 0;
+    while quota.exceeded_by(usage) {
+        let Some(oldest) = usage.pop_oldest() else {
+            break;
+        };
+        match oldest.backend.remove(oldest.function, &oldest.key) {
+            Ok(()) => journal::evict(
+                oldest.function,
+                &oldest.key,
+                usize::try_from(oldest.size).ok(),
+            ),
+            Err(e) => warn!(
+                "Failed to evict an entry of {} over its namespace's quota: {e:#}",
+                oldest.function.name()
+            ),
+        }
+        evicted += 1;
+    }
+    if evicted > 0 {
+        memo::invalidate();
+        debug!("Evicted {evicted} entries over their namespace's quota");
+    }
+}
+
+/// Stops counting an entry that was removed from the store.
+pub fn forget(function: &Function, key: &[u8]) {
+    let Some((prefix, _)) = namespace(key) else {
+        return;
+    };
+    if let Some(usage) = USAGE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_mut(prefix)
+    {
+        usage.remove(function.hash(), key);
+    }
+}
+
+/// Forgets every namespace's usage after entries were removed in bulk, so it is scanned again
+/// on the next write.
+pub fn reset() {
+    if !PREFIXES.is_empty() {
+        USAGE.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
+
+/// A descriptor of the function `hash` that outlives the write that named it.
+fn descriptor(name: &str, hash: FunctionHash) -> &'static Function {
+    function::registered(&hash).unwrap_or_else(|| function::intern(name, hash))
+}
+
+/// The entries already in the stores under `prefix`, oldest first.
+fn scan(prefix: &[u8]) -> Usage {
+    let mut found = Vec::new();
+    for backend in backend::all() {
+        if let Err(e) = scan_backend(backend, prefix, &mut found) {
+            warn!("Failed to read a store to count its namespace usage: {e:#}");
+        }
+    }
+    found.sort_by_key(|(created_at, _)| *created_at);
+
+    let mut usage = Usage::default();
+    for (_, tracked) in found {
+        usage.insert(tracked);
+    }
+    usage
+}
+
+fn scan_backend(
+    backend: &'static dyn CacheBackend,
+    prefix: &[u8],
+    found: &mut Vec<(Option<SystemTime>, Tracked)>,
+) -> eyre::Result<()> {
+    for info in backend.functions()? {
+        let function = descriptor(&info.name, info.hash);
+        backend.for_each_function_entry(&info.hash, &mut |key, stored| {
+            if !key.starts_with(prefix) {
+                return;
+            }
+            let created_at = entry::decode(stored)
+                .ok()
+                .and_then(|(header, _)| header.created_at);
+            found.push((
+                created_at,
+                Tracked {
+                    backend,
+                    function,
+                    key: key.to_vec(),
+                    size: stored.len() as u64,
+                },
+            ));
+        })?;
+    }
+    Ok(())
+}
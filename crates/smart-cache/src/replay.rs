@@ -0,0 +1,262 @@
+//! Logging again on hits what computing the value logged, with [`crate::Config::replay_logs`].
+//!
+//! While a `#[cached]` function computes a value, a subscriber wrapping the current one records
+//! the `tracing` events it lets through, and the events are stored in the entry alongside the
+//! value. Hits read from the store emit them again under the `smart_cache::replay` target, with
+//! `replayed = true`, the function's name and the original target as fields.
+//!
+//! Events are stored as a sequence of a level byte (0 for `TRACE` up to 4 for `ERROR`), the
+//! target as a `u16` length and UTF-8 text, and the message followed by the other fields as
+//! `name=value`, as a `u32` length and UTF-8 text.
+//!
+//! Only events on the computing thread are recorded, so `async fn`s and threads spawned by the
+//! computation record none. Values recomputed in the background (`stale_for`,
+//! `on_timeout = "stale"`) are recorded on their own thread, under the subscriber of the thread
+//! that started them. Hits served from the thread memo replay nothing. While recording,
+//! `tracing::Span::current()` returns a disabled span.
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Write as _},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tracing::{
+    dispatcher::{self, DefaultGuard},
+    field::{Field, Visit},
+    span, Dispatch, Event, Level, Metadata, Subscriber,
+};
+
+use crate::{config, Function};
+
+/// Events recorded for one computation, encoded as described in the module documentation.
+type Recording = Arc<Mutex<Vec<u8>>>;
+
+thread_local! {
+    /// The recordings of the computations in progress on this thread, innermost last.
+    static RECORDINGS: RefCell<Vec<Recording>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records the events the wrapped subscriber enables, and passes everything on to it but
+/// [`Subscriber::current_span`], whose return type `tracing` doesn't export.
+struct Recorder {
+    inner: Dispatch,
+    recording: Recording,
+}
+
+impl Subscriber for Recorder {
+    fn register_callsite(
+        &self,
+        metadata: &'static Metadata<'static>,
+    ) -> tracing::subscriber::Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        self.inner.new_span(span)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        self.inner.record(span, values);
+    }
+
+    fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+        self.inner.record_follows_from(span, follows);
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        encode(
+            &mut self
+                .recording
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            event,
+        );
+        self.inner.event(event);
+    }
+
+    fn enter(&self, span: &span::Id) {
+        self.inner.enter(span);
+    }
+
+    fn exit(&self, span: &span::Id) {
+        self.inner.exit(span);
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        self.inner.clone_span(id)
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        self.inner.try_close(id)
+    }
+}
+
+/// Collects an event's message and fields as text.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={value}", field.name());
+        }
+    }
+}
+
+const LEVELS: [Level; 5] = [
+    Level::TRACE,
+    Level::DEBUG,
+    Level::INFO,
+    Level::WARN,
+    Level::ERROR,
+];
+
+fn encode(recording: &mut Vec<u8>, event: &Event<'_>) {
+    let metadata = event.metadata();
+    let mut fields = Fields::default();
+    event.record(&mut fields);
+    let text = fields.message + &fields.fields;
+    let target = metadata.target().as_bytes();
+    let target = &target[..target.len().min(u16::MAX.into())];
+    let text = &text.as_bytes()[..text.len().min(u32::MAX as usize)];
+
+    let level = LEVELS
+        .iter()
+        .position(|level| level == metadata.level())
+        .unwrap_or_default();
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        recording.push(level as u8);
+        recording.extend_from_slice(&(target.len() as u16).to_le_bytes());
+        recording.extend_from_slice(target);
+        recording.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    }
+    recording.extend_from_slice(text);
+}
+
+/// Records the events logged while computing a value until dropped.
+#[doc(hidden)]
+pub struct Capture {
+    _default: DefaultGuard,
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        RECORDINGS.with(|recordings| recordings.borrow_mut().pop());
+    }
+}
+
+/// Internal function used by the macro to record the events logged while it computes a value,
+/// for [`crate::Config::replay_logs`]. Returns `None` if logs aren't replayed.
+#[doc(hidden)]
+pub fn capture_events() -> Option<Capture> {
+    if !config::get().replay_logs {
+        return None;
+    }
+    let recording = Recording::default();
+    let recorder = Recorder {
+        inner: dispatcher::get_default(Dispatch::clone),
+        recording: Arc::clone(&recording),
+    };
+    let default = dispatcher::set_default(&Dispatch::new(recorder));
+    RECORDINGS.with(|recordings| recordings.borrow_mut().push(recording));
+    Some(Capture { _default: default })
+}
+
+/// The events recorded so far by the innermost computation on this thread, if it records any.
+pub fn captured() -> Option<Vec<u8>> {
+    RECORDINGS.with(|recordings| {
+        let recordings = recordings.borrow();
+        let recording = recordings.last()?;
+        let recording = recording.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(recording.clone())
+    })
+}
+
+/// Emits the events recorded computing a value of `function` again.
+pub fn replay(function: &Function, mut events: &[u8]) {
+    while let Some((level, target, text, rest)) = next_event(events) {
+        let name = function.name();
+        match level {
+            0 => {
+                tracing::trace!(
+                    target: "smart_cache::replay",
+                    replayed = true,
+                    function = name,
+                    original_target = target,
+                    "{text}"
+                )
+            }
+            1 => {
+                tracing::debug!(
+                    target: "smart_cache::replay",
+                    replayed = true,
+                    function = name,
+                    original_target = target,
+                    "{text}"
+                )
+            }
+            2 => {
+                tracing::info!(
+                    target: "smart_cache::replay",
+                    replayed = true,
+                    function = name,
+                    original_target = target,
+                    "{text}"
+                )
+            }
+            3 => {
+                tracing::warn!(
+                    target: "smart_cache::replay",
+                    replayed = true,
+                    function = name,
+                    original_target = target,
+                    "{text}"
+                )
+            }
+            _ => {
+                tracing::error!(
+                    target: "smart_cache::replay",
+                    replayed = true,
+                    function = name,
+                    original_target = target,
+                    "{text}"
+                )
+            }
+        }
+        events = rest;
+    }
+}
+
+fn next_event(bytes: &[u8]) -> Option<(u8, &str, &str, &[u8])> {
+    let (&level, rest) = bytes.split_first()?;
+    let (target_len, rest) = rest.split_first_chunk::<2>()?;
+    let (target, rest) = rest.split_at_checked(u16::from_le_bytes(*target_len).into())?;
+    let (text_len, rest) = rest.split_first_chunk::<4>()?;
+    let (text, rest) =
+        rest.split_at_checked(usize::try_from(u32::from_le_bytes(*text_len)).ok()?)?;
+    Some((
+        level,
+        std::str::from_utf8(target).ok()?,
+        std::str::from_utf8(text).ok()?,
+        rest,
+    ))
+}
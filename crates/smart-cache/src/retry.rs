@@ -0,0 +1,162 @@
+//! Retrying failed computations, for functions cached with `#[cached(retry = ...)]`.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+use tracing::debug;
+
+use crate::{Function, Negative};
+
+/// How the delay between attempts grows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// Wait the same delay before every retry.
+    Constant,
+    /// Wait the delay times the number of attempts so far.
+    Linear,
+    /// Double the delay after every attempt.
+    Exponential,
+}
+
+/// How often, and how patiently, a function's [`Negative`] results are computed again before
+/// being returned (`#[cached(retry = 3, backoff = "exponential")]`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    retries: u32,
+    backoff: Backoff,
+    delay: Duration,
+}
+
+impl Retry {
+    /// Retries up to `retries` times, waiting `delay` before the first retry and growing the
+    /// delay as `backoff` says.
+    #[must_use]
+    pub const fn new(retries: u32, backoff: Backoff, delay: Duration) -> Self {
+        Self {
+            retries,
+            backoff,
+            delay,
+        }
+    }
+
+    /// The delay before retrying after `attempt` failed attempts.
+    fn delay(&self, attempt: u32) -> Duration {
+        match self.backoff {
+            Backoff::Constant => self.delay,
+            Backoff::Linear => self.delay.saturating_mul(attempt),
+            Backoff::Exponential => self
+                .delay
+                .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1))),
+        }
+    }
+}
+
+/// Internal function used by the macro to compute a value of `function`, retrying it while the
+/// result is negative.
+#[doc(hidden)]
+pub fn retry<T: Negative>(function: &Function, mut compute: impl FnMut() -> T) -> T {
+    let Some(policy) = function.retry_policy() else {
+        return compute();
+    };
+    let mut attempt = 1;
+    loop {
+        let result = compute();
+        if !result.is_negative() || attempt > policy.retries {
+            return result;
+        }
+        let delay = policy.delay(attempt);
+        debug!(
+            "Attempt {attempt} at computing {} failed, retrying in {delay:?}",
+            function.name()
+        );
+        thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+/// Async counterpart of [`retry`], used by the macro for `async fn`s.
+#[doc(hidden)]
+pub async fn retry_async<T: Negative, F: Future<Output = T>>(
+    function: &Function,
+    mut compute: impl FnMut() -> F,
+) -> T {
+    let Some(policy) = function.retry_policy() else {
+        return compute().await;
+    };
+    let mut attempt = 1;
+    loop {
+        let result = compute().await;
+        if !result.is_negative() || attempt > policy.retries {
+            return result;
+        }
+        let delay = policy.delay(attempt);
+        debug!(
+            "Attempt {attempt} at computing {} failed, retrying in {delay:?}",
+            function.name()
+        );
+        Sleep::new(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Whether a [`Sleep`] is over, and the task to wake when it is.
+type Timer = (bool, Option<Waker>);
+
+/// A future that is ready after a delay, without depending on a particular runtime: a thread
+/// of its own sleeps through the delay. Retries are rare enough for a thread each.
+struct Sleep {
+    delay: Option<Duration>,
+    timer: Arc<Mutex<Timer>>,
+}
+
+impl Sleep {
+    fn new(delay: Duration) -> Self {
+        Self {
+            delay: Some(delay),
+            timer: Arc::default(),
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut timer = self.timer.lock().unwrap_or_else(PoisonError::into_inner);
+        if timer.0 {
+            return Poll::Ready(());
+        }
+        timer.1 = Some(cx.waker().clone());
+        drop(timer);
+
+        if let Some(delay) = self.delay.take() {
+            let timer = Arc::clone(&self.timer);
+            let spawned = thread::Builder::new()
+                .name("smart-cache-retry".to_string())
+                .spawn(move || {
+                    thread::sleep(delay);
+                    expire(&timer);
+                });
+            // Without a thread to wait on, retry right away.
+            if spawned.is_err() {
+                return Poll::Ready(());
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Marks a [`Sleep`] as over and wakes the task awaiting it.
+fn expire(timer: &Mutex<Timer>) {
+    let mut timer = timer.lock().unwrap_or_else(PoisonError::into_inner);
+    timer.0 = true;
+    if let Some(waker) = timer.1.take() {
+        waker.wake();
+    }
+}
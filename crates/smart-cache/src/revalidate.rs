@@ -0,0 +1,115 @@
+//! Recomputing stale values in the background, for functions cached with `stale_for`.
+//!
+//! A lookup that finds a value past its TTL but within its stale period returns it right away
+//! and hands the computation to [`revalidate`], which runs it on a thread of its own and
+//! replaces the entry once it finishes. Only one recomputation per key runs at a time; lookups
+//! meanwhile keep getting the stale value.
+
+use std::{
+    collections::HashSet,
+    sync::{Mutex, PoisonError},
+    thread,
+};
+
+use once_cell::sync::Lazy;
+use tracing::{debug, dispatcher, warn, Dispatch};
+
+use crate::{clock::Instant, context, dependency, replay, Function, FunctionHash};
+
+/// A key being recomputed, and the function it belongs to.
+type Recomputation = (FunctionHash, Vec<u8>);
+
+static IN_FLIGHT: Lazy<Mutex<HashSet<Recomputation>>> = Lazy::new(Mutex::default);
+
+/// Removes a key from [`IN_FLIGHT`] when its recomputation ends, even by panicking.
+pub struct InFlight(Recomputation);
+
+/// Marks `key` of `function` as being recomputed, or returns `None` if it already is.
+pub fn claim(function: &Function, key: &[u8]) -> Option<InFlight> {
+    let in_flight = (*function.hash(), key.to_vec());
+    let claimed = IN_FLIGHT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(in_flight.clone());
+    claimed.then(|| InFlight(in_flight))
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.0);
+    }
+}
+
+/// Internal function used by the macro to recompute a stale value of `function` in the
+/// background. `compute` returns the serialized value and whether it is
+/// [`crate::Negative`], or `None` if it shouldn't be stored. `debug_key` is the `Debug` form
+/// of the key, as for [`crate::set_cached`].
+#[doc(hidden)]
+pub fn revalidate(
+    function: &'static Function,
+    key_bytes: &[u8],
+    debug_key: Option<String>,
+    compute: impl FnOnce() -> Option<(Vec<u8>, bool)> + Send + 'static,
+) {
+    // The context is thread-local, so the key and TTLs are resolved before leaving this thread.
+    let key = context::key(function, key_bytes).into_owned();
+    let ttls = (
+        crate::time_to_live(function, false),
+        crate::time_to_live(function, true),
+    );
+    let isolated = context::store();
+    let dispatch = dispatcher::get_default(Dispatch::clone);
+    let Some(in_flight) = claim(function, &key) else {
+        return;
+    };
+
+    debug!(
+        "Recomputing a stale value of {} in the background",
+        function.name()
+    );
+    let spawned = thread::Builder::new()
+        .name("smart-cache-revalidate".to_string())
+        .spawn(move || {
+            let _isolated = isolated.map(context::isolate);
+            let _in_flight = in_flight;
+            let _permit = crate::compute_permit();
+            // Logged under the caller's subscriber, and kept with the value like a miss's.
+            let _dispatch = dispatcher::set_default(&dispatch);
+            let _events = replay::capture_events();
+            // Kept until the entry is encoded, which records the dependencies collected.
+            let _dependencies = dependency::track_dependencies(function);
+            let started = Instant::now();
+            let value = compute();
+            let compute_time = started.elapsed();
+            let Some((value, negative)) = value else {
+                return;
+            };
+            let ttl = crate::result_ttl().or(if negative { ttls.1 } else { ttls.0 });
+            let stored = crate::prepare_entry(
+                function,
+                &key,
+                &value,
+                compute_time,
+                ttl,
+                debug_key.as_deref(),
+            )
+            .and_then(|entry| {
+                entry.map_or(Ok(()), |entry| crate::store_entry(function, &key, entry))
+            });
+            if let Err(e) = stored {
+                warn!(
+                    "Failed to store a recomputed value of {}: {e:#}",
+                    function.name()
+                );
+            }
+        });
+    if let Err(e) = spawned {
+        warn!(
+            "Failed to start recomputing a stale value of {}: {e:#}",
+            function.name()
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Saving the whole cache and rolling back to it later, so benchmark scenarios can each start
+//! from the same state without recomputing it.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use eyre::{bail, Result, WrapErr};
+
+use crate::{archive, backend, db, memo};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a snapshot taken with [`snapshot`] by this process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SnapshotId(u64);
+
+impl SnapshotId {
+    /// Snapshots are archives in the cache directory, named after the process so concurrent
+    /// programs don't overwrite each other's.
+    fn path(self) -> Result<PathBuf> {
+        let dir = db::cache_dir()?.join("snapshots");
+        fs::create_dir_all(&dir).wrap_err("failed to create snapshot directory")?;
+        Ok(dir.join(format!("{}-{}.archive", process::id(), self.0)))
+    }
+}
+
+impl Display for SnapshotId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "snapshot {}", self.0)
+    }
+}
+
+/// Copies every live entry of every known store, as [`crate::export`] does, so [`restore`] can
+/// bring the cache back to this state.
+///
+/// ```no_run
+/// let warm = smart_cache::snapshot()?;
+/// for scenario in ["cold-start", "steady-state"] {
+///     smart_cache::restore(warm)?;
+///     // Run the scenario.
+///     # let _ = scenario;
+/// }
+/// smart_cache::delete_snapshot(warm)?;
+/// # Ok::<(), eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if a store cannot be read or the snapshot cannot be written.
+pub fn snapshot() -> Result<SnapshotId> {
+    let id = SnapshotId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    archive::export(id.path()?)?;
+    Ok(id)
+}
+
+/// Replaces the contents of every known store with the entries saved in snapshot `id`. The
+/// snapshot is kept, so it can be restored again.
+///
+/// # Errors
+///
+/// Fails if the snapshot does not exist or a store cannot be written.
+pub fn restore(id: SnapshotId) -> Result<()> {
+    let path = id.path()?;
+    if !path.exists() {
+        bail!("{id} does not exist");
+    }
+
+    crate::flush();
+    memo::invalidate();
+    for backend in backend::all() {
+        for function in backend.functions()? {
+            backend.clear_function(&function.hash)?;
+        }
+    }
+    archive::import(path)?;
+    Ok(())
+}
+
+/// Deletes snapshot `id`.
+///
+/// # Errors
+///
+/// Fails if the snapshot does not exist or cannot be deleted.
+pub fn delete_snapshot(id: SnapshotId) -> Result<()> {
+    fs::remove_file(id.path()?).wrap_err_with(|| format!("failed to delete {id}"))
+}
@@ -0,0 +1,219 @@
+//! Per-function statistics, accumulated in memory and periodically merged into the store so
+//! they survive restarts.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::{backend, clock::Instant, config, Function, FunctionHash};
+
+/// How often accumulated statistics are merged into the store, at most.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Hits and misses each needed before [`FunctionStats::unprofitable`] judges a function.
+const MIN_SAMPLES: u64 = 10;
+
+/// Cumulative statistics for one cached function, as returned by [`crate::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    /// The function's name as written in the source.
+    pub name: String,
+    /// Hash of the function body these statistics were collected for.
+    pub hash: FunctionHash,
+    /// Calls that consulted the cache.
+    pub calls: u64,
+    /// Calls answered from the cache.
+    pub hits: u64,
+    /// Total time spent computing values on misses.
+    pub compute_time: Duration,
+    /// Total compute time avoided by hits, based on how long each value originally took.
+    pub time_saved: Duration,
+    /// Total time hits spent reading and deserializing values.
+    pub hit_time: Duration,
+    /// Total size of the values written.
+    pub bytes_written: u64,
+}
+
+impl FunctionStats {
+    /// Calls that had to compute their result.
+    #[must_use]
+    pub const fn misses(&self) -> u64 {
+        self.calls.saturating_sub(self.hits)
+    }
+
+    /// How long a miss took to compute its value, on average.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn average_compute_time(&self) -> Option<Duration> {
+        let misses = self.misses();
+        (misses > 0).then(|| self.compute_time.div_f64(misses as f64))
+    }
+
+    /// How long a hit took to read and deserialize its value, on average.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn average_hit_time(&self) -> Option<Duration> {
+        (self.hits > 0).then(|| self.hit_time.div_f64(self.hits as f64))
+    }
+
+    /// Whether hits take at least as long as computing the value on average, so caching the
+    /// function doesn't pay off. With [`crate::Config::adaptive`], such functions stop
+    /// consulting the store.
+    #[must_use]
+    pub fn unprofitable(&self) -> bool {
+        if self.hits < MIN_SAMPLES || self.misses() < MIN_SAMPLES {
+            return false;
+        }
+        match (self.average_hit_time(), self.average_compute_time()) {
+            (Some(hit), Some(compute)) => hit >= compute,
+            _ => false,
+        }
+    }
+
+    /// The share of calls answered from the cache.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_rate(&self) -> Option<f64> {
+        (self.calls > 0).then(|| self.hits as f64 / self.calls as f64)
+    }
+
+    /// Whether the function hits too rarely for its values to be worth writing, by the
+    /// threshold set with [`crate::Config::min_hit_rate`].
+    #[must_use]
+    pub fn rarely_hit(&self) -> bool {
+        config::get()
+            .min_hit_rate
+            .is_some_and(|(rate, after_misses)| {
+                self.misses() >= after_misses && self.hit_rate().is_some_and(|hits| hits < rate)
+            })
+    }
+
+    /// Adds `other`'s counters to these.
+    pub fn merge(&mut self, other: &Self) {
+        self.calls += other.calls;
+        self.hits += other.hits;
+        self.compute_time += other.compute_time;
+        self.time_saved += other.time_saved;
+        self.hit_time += other.hit_time;
+        self.bytes_written += other.bytes_written;
+    }
+}
+
+struct Pending {
+    deltas: HashMap<&'static Function, FunctionStats>,
+    last_persisted: Instant,
+}
+
+static PENDING: Lazy<Mutex<Pending>> = Lazy::new(|| {
+    Mutex::new(Pending {
+        deltas: HashMap::new(),
+        last_persisted: Instant::now(),
+    })
+});
+
+/// Everything recorded for each function since the process started, which
+/// [`Config::adaptive`](crate::Config::adaptive) and
+/// [`Config::min_hit_rate`](crate::Config::min_hit_rate) judge functions by. Only kept when one
+/// of them is set.
+static TOTALS: Lazy<Mutex<HashMap<&'static Function, FunctionStats>>> = Lazy::new(Mutex::default);
+
+fn empty(function: &'static Function) -> FunctionStats {
+    FunctionStats {
+        name: function.name().to_string(),
+        hash: *function.hash(),
+        ..FunctionStats::default()
+    }
+}
+
+fn record(function: &'static Function, update: impl Fn(&mut FunctionStats)) {
+    let config = config::get();
+    if config.adaptive || config.min_hit_rate.is_some() {
+        let mut totals = TOTALS.lock().unwrap_or_else(PoisonError::into_inner);
+        update(totals.entry(function).or_insert_with(|| empty(function)));
+    }
+    let due = {
+        let mut pending = PENDING.lock().unwrap_or_else(PoisonError::into_inner);
+        update(
+            pending
+                .deltas
+                .entry(function)
+                .or_insert_with(|| empty(function)),
+        );
+        pending.last_persisted.elapsed() >= PERSIST_INTERVAL
+    };
+
+    if due {
+        persist();
+    }
+}
+
+pub fn record_hit(function: &'static Function, compute_time: Duration) {
+    record(function, |stats| {
+        stats.calls += 1;
+        stats.hits += 1;
+        stats.time_saved += compute_time;
+    });
+}
+
+pub fn record_hit_time(function: &'static Function, hit_time: Duration) {
+    record(function, |stats| stats.hit_time += hit_time);
+}
+
+/// Whether [`crate::Config::adaptive`] is set and hits of `function` have turned out not to pay
+/// off in this process.
+pub fn unprofitable(function: &'static Function) -> bool {
+    config::get().adaptive
+        && TOTALS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(function)
+            .is_some_and(FunctionStats::unprofitable)
+}
+
+/// Whether [`crate::Config::min_hit_rate`] is set and `function` has turned out to hit too
+/// rarely in this process for its values to be written.
+pub fn rarely_hit(function: &'static Function) -> bool {
+    config::get().min_hit_rate.is_some()
+        && TOTALS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(function)
+            .is_some_and(FunctionStats::rarely_hit)
+}
+
+pub fn record_miss(function: &'static Function, compute_time: Duration, bytes: usize) {
+    record(function, |stats| {
+        stats.calls += 1;
+        stats.compute_time += compute_time;
+        stats.bytes_written += bytes as u64;
+    });
+}
+
+/// Merges the accumulated statistics into the stores of their functions.
+pub fn persist() {
+    let mut by_store: HashMap<_, Vec<_>> = HashMap::new();
+    {
+        let mut pending = PENDING.lock().unwrap_or_else(PoisonError::into_inner);
+        pending.last_persisted = Instant::now();
+        for (function, delta) in pending.deltas.drain() {
+            by_store
+                .entry(backend::store_name(function))
+                .or_default()
+                .push(delta);
+        }
+    }
+
+    for (store, deltas) in by_store {
+        let Some(backend) = backend::named(store) else {
+            continue;
+        };
+        if let Err(e) = backend.merge_stats(&deltas) {
+            warn!("Failed to persist cache statistics: {e:#}");
+        }
+    }
+}
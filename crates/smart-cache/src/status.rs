@@ -0,0 +1,142 @@
+//! Reporting where the cache lives and whether it works, for applications' diagnostics.
+
+use std::path::PathBuf;
+
+use eyre::Result;
+
+use crate::{
+    backend::{self, CacheBackend, WriteEntry},
+    db, entry, Function,
+};
+
+/// Written and cleared again to check that a store accepts writes.
+static PROBE: Function = Function::new(
+    "smart-cache status probe",
+    *b"smart-cache status probe\0\0\0\0\0\0\0\0",
+);
+
+/// What [`status`] found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// The directory stores are created in when no path is configured for them.
+    pub cache_dir: Option<PathBuf>,
+    /// Version of the entry layout this build reads and writes.
+    pub entry_format: u32,
+    /// Every known store.
+    pub stores: Vec<StoreStatus>,
+}
+
+impl Status {
+    /// Whether every store could be read and written.
+    #[must_use]
+    pub fn healthy(&self) -> bool {
+        self.stores.iter().all(StoreStatus::healthy)
+    }
+}
+
+/// What [`status`] found about one store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreStatus {
+    /// The store's name, as given to [`crate::Config::backend`] or [`crate::Config::database`].
+    pub name: String,
+    /// The kind of backend, e.g. `redb` or `redis`.
+    pub backend: &'static str,
+    /// Where the backend keeps its entries: a path, an address or a URL.
+    pub location: Option<String>,
+    /// Bytes the store occupies on disk, for backends that keep local files.
+    pub size: Option<u64>,
+    /// Version of the backend's storage layout, if it records one.
+    pub layout_version: Option<u64>,
+    /// Entries stored, if the store could be listed.
+    pub entries: Option<u64>,
+    /// Whether the store's functions could be listed.
+    pub readable: bool,
+    /// Whether an entry could be written to the store and removed again.
+    pub writable: bool,
+    /// The first error met while checking the store.
+    pub error: Option<String>,
+}
+
+impl StoreStatus {
+    /// Whether the store could be read and written.
+    #[must_use]
+    pub const fn healthy(&self) -> bool {
+        self.readable && self.writable
+    }
+}
+
+/// Describes every known store: its backend, location, size and entry count, and whether it
+/// can be read and written. Meant for applications to include in their own diagnostics:
+///
+/// ```no_run
+/// let status = smart_cache::status();
+/// for store in &status.stores {
+///     println!(
+///         "cache {}: {} at {}, {} entries, {}",
+///         store.name,
+///         store.backend,
+///         store.location.as_deref().unwrap_or("-"),
+///         store.entries.unwrap_or(0),
+///         if store.healthy() { "ok" } else { "unhealthy" },
+///     );
+/// }
+/// ```
+///
+/// Checking that a store is writable writes a small entry to it and removes it again. Stores
+/// named after a crate are only known once one of that crate's cached functions has been
+/// called.
+#[must_use]
+pub fn status() -> Status {
+    crate::flush();
+
+    Status {
+        cache_dir: db::cache_dir().ok(),
+        entry_format: entry::FORMAT_VERSION,
+        stores: backend::all_named()
+            .into_iter()
+            .map(|(name, backend)| store_status(name, backend))
+            .collect(),
+    }
+}
+
+fn store_status(name: String, backend: &dyn CacheBackend) -> StoreStatus {
+    let description = backend.describe();
+    let mut error = None;
+
+    let entries = match backend.functions() {
+        Ok(functions) => Some(functions.iter().map(|function| function.entries).sum()),
+        Err(e) => {
+            error = Some(format!("{e:#}"));
+            None
+        }
+    };
+    let writable = match probe(backend) {
+        Ok(()) => true,
+        Err(e) => {
+            error.get_or_insert_with(|| format!("{e:#}"));
+            false
+        }
+    };
+
+    StoreStatus {
+        name,
+        backend: description.kind,
+        location: description.location,
+        size: description.size,
+        layout_version: description.layout_version,
+        entries,
+        readable: entries.is_some(),
+        writable,
+        error,
+    }
+}
+
+fn probe(backend: &dyn CacheBackend) -> Result<()> {
+    backend.insert_batch(&[WriteEntry {
+        function: &PROBE,
+        key: b"probe",
+        entry: b"probe",
+    }])?;
+    backend.flush()?;
+    backend.clear_function(PROBE.hash())
+}
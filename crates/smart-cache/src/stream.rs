@@ -0,0 +1,268 @@
+//! Caching values too large to hold in memory at once, by writing and reading them in chunks.
+//!
+//! A streamed value is stored as one entry per [`CHUNK_LEN`] bytes, keyed by the value's key
+//! followed by [`CHUNK_MARKER`] and the chunk's index, and a final entry under the key itself
+//! recording how many chunks there are. That entry is written last, so readers never see a
+//! value that is still being written. Every chunk carries its own envelope, so corruption is
+//! detected chunk by chunk as the value is read.
+
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, SystemTime},
+};
+
+use eyre::{bail, Result};
+
+use crate::{
+    backend::{self, WriteEntry},
+    clock::{self, Instant},
+    context,
+    entry::{self, Header},
+    filter, function, journal, mode, stats, Function, Mode,
+};
+
+/// Bytes per chunk, which bounds the memory used while streaming.
+const CHUNK_LEN: usize = 4 * 1024 * 1024;
+
+/// Separates a value's key from the index of one of its chunks.
+const CHUNK_MARKER: &[u8] = b"\0chunk";
+
+/// Starts the value of the entry listing a streamed value's chunks, followed by the chunk
+/// count as a `u32` and the value's length as a `u64`, little-endian.
+const CHUNKS_MAGIC: &[u8] = b"smart-cache chunks v1\0";
+
+fn chunk_key(key: &[u8], index: u32) -> Vec<u8> {
+    [key, CHUNK_MARKER, &index.to_le_bytes()].concat()
+}
+
+/// Writes a value into the store as it is produced; see [`stream_writer`].
+pub struct ValueWriter {
+    function: &'static Function,
+    key: Vec<u8>,
+    chunk: Vec<u8>,
+    chunks: u32,
+    len: u64,
+    started: Instant,
+    expires_at: Option<SystemTime>,
+}
+
+impl ValueWriter {
+    fn store(&self, key: &[u8], value: &[u8], compute_time: Duration) -> Result<()> {
+        let Some(backend) = backend::for_function(self.function) else {
+            return Ok(());
+        };
+        let header = Header {
+            compute_time,
+            expires_at: self.expires_at,
+            created_at: Some(clock::now()),
+            ..Header::default()
+        };
+        let entry = entry::encode(header, &[], None, None, value);
+        backend.insert_batch(&[WriteEntry {
+            function: self.function,
+            key,
+            entry: &entry,
+        }])?;
+        journal::set(self.function, key, entry.len());
+        Ok(())
+    }
+
+    fn store_chunk(&mut self) -> Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let key = chunk_key(&self.key, self.chunks);
+        self.store(&key, &self.chunk, Duration::ZERO)?;
+        self.chunks += 1;
+        self.chunk.clear();
+        Ok(())
+    }
+
+    /// Stores the last chunk and makes the value visible to readers. Dropping the writer
+    /// without finishing it leaves the value uncached.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be written.
+    pub fn finish(mut self) -> Result<()> {
+        self.store_chunk()?;
+        let compute_time = self.started.elapsed();
+        let mut value = CHUNKS_MAGIC.to_vec();
+        value.extend_from_slice(&self.chunks.to_le_bytes());
+        value.extend_from_slice(&self.len.to_le_bytes());
+        self.store(&self.key, &value, compute_time)?;
+
+        stats::record_miss(
+            self.function,
+            compute_time,
+            usize::try_from(self.len).unwrap_or(usize::MAX),
+        );
+        if let Some(filter) = filter::global() {
+            filter.insert(&self.key);
+        }
+        Ok(())
+    }
+}
+
+impl Write for ValueWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let written = bytes.len().min(CHUNK_LEN - self.chunk.len());
+        self.chunk.extend_from_slice(&bytes[..written]);
+        self.len += written as u64;
+        if self.chunk.len() == CHUNK_LEN {
+            self.store_chunk().map_err(io::Error::other)?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Starts caching a value of `function` under `key` without holding all of it in memory.
+/// Write the value into the returned handle, then call [`ValueWriter::finish`]. The compute
+/// time recorded for the value is the time between this call and `finish`.
+///
+/// Values streamed this way must be read back with [`stream_reader`].
+///
+/// ```no_run
+/// use std::io::Write;
+///
+/// use smart_cache::Function;
+///
+/// static RENDER: Function = Function::new("render", [0; 32]);
+///
+/// # fn main() -> eyre::Result<()> {
+/// let mut writer = smart_cache::stream_writer(&RENDER, b"frame-1");
+/// writer.write_all(&vec![0; 64 * 1024 * 1024])?;
+/// writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub fn stream_writer(function: &'static Function, key: &[u8]) -> ValueWriter {
+    function::register(function);
+    ValueWriter {
+        function,
+        key: context::key(function, key).into_owned(),
+        chunk: Vec::with_capacity(CHUNK_LEN),
+        chunks: 0,
+        len: 0,
+        started: Instant::now(),
+        expires_at: context::ttl(function.time_to_live())
+            .map(crate::jittered)
+            .and_then(|ttl| clock::now().checked_add(ttl)),
+    }
+}
+
+/// Reads a value cached with [`stream_writer`] chunk by chunk; see [`stream_reader`].
+pub struct ValueReader {
+    function: &'static Function,
+    key: Vec<u8>,
+    chunks: u32,
+    len: u64,
+    next: u32,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl ValueReader {
+    /// The length of the whole value, in bytes.
+    #[must_use]
+    pub const fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the value is empty.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn load_next(&mut self) -> Result<()> {
+        let key = chunk_key(&self.key, self.next);
+        let Some((_, chunk)) = read_entry(self.function, &key)? else {
+            bail!(
+                "chunk {} of a {} value is missing",
+                self.next,
+                self.function.name()
+            );
+        };
+        self.chunk = chunk;
+        self.position = 0;
+        self.next += 1;
+        Ok(())
+    }
+}
+
+impl Read for ValueReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            if self.next == self.chunks {
+                return Ok(0);
+            }
+            self.load_next()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        let read = buf.len().min(self.chunk.len() - self.position);
+        buf[..read].copy_from_slice(&self.chunk[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Reads the value of the live entry stored under `key`, without its envelope. Only one chunk
+/// is held at a time, so the copy is bounded by [`CHUNK_LEN`].
+fn read_entry(function: &Function, key: &[u8]) -> Result<Option<(Header, Vec<u8>)>> {
+    let Some(backend) = backend::for_function(function) else {
+        return Ok(None);
+    };
+    let Some(stored) = backend.get(function, key)? else {
+        return Ok(None);
+    };
+    match entry::decode(&stored) {
+        Ok((header, _)) if header.is_expired() => Ok(None),
+        Ok((header, offset)) => match entry::value(&stored, &header, offset) {
+            Some(value) => Ok(Some((header, value.into_owned()))),
+            None => bail!("cached entry for {} is corrupt", function.name()),
+        },
+        Err(_) => bail!("cached entry for {} is corrupt", function.name()),
+    }
+}
+
+/// Looks up a value of `function` cached under `key` with [`stream_writer`], returning a
+/// handle that reads it one chunk at a time, or `None` on a miss. In [`Mode::Record`] every
+/// lookup misses, as it does for `#[cached]` functions.
+///
+/// # Errors
+///
+/// Fails if the store cannot be read or the entry was not written by [`stream_writer`].
+pub fn stream_reader(function: &'static Function, key: &[u8]) -> Result<Option<ValueReader>> {
+    function::register(function);
+    if mode::current() == Mode::Record {
+        return Ok(None);
+    }
+    let key = context::key(function, key).into_owned();
+    let Some((header, listing)) = read_entry(function, &key)? else {
+        return Ok(None);
+    };
+    let counts = listing
+        .strip_prefix(CHUNKS_MAGIC)
+        .and_then(|counts| counts.split_first_chunk::<4>())
+        .and_then(|(chunks, len)| Some((*chunks, *len.first_chunk::<8>()?)));
+    let Some((chunks, len)) = counts else {
+        bail!("cached value of {} was not streamed", function.name());
+    };
+
+    stats::record_hit(function, header.compute_time);
+    Ok(Some(ValueReader {
+        function,
+        key,
+        chunks: u32::from_le_bytes(chunks),
+        len: u64::from_le_bytes(len),
+        next: 0,
+        chunk: Vec::new(),
+        position: 0,
+    }))
+}
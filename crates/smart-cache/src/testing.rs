@@ -0,0 +1,382 @@
+//! Helpers for testing cached functions.
+//!
+//! Caching a function is only sound if it returns the same value every time it is called with
+//! the same arguments. [`assert_deterministic`] checks that before a function is annotated,
+//! or keeps checking it after:
+//!
+//! ```no_run
+//! # use smart_cache::cached;
+//! #[cached]
+//! fn render(template: String) -> String {
+//!     # template
+//! }
+//!
+//! smart_cache::testing::assert_deterministic(|| render("invoice".to_string()));
+//! ```
+//!
+//! Cached functions compiled for tests, those in `#[cfg(test)]` code or in the files of
+//! integration tests, move the cache directory to a temporary one of the process's once one of
+//! them is called, so `cargo test` doesn't use the user's cache. Stores opened before then, and
+//! those given a path with [`crate::Config::database`], stay where they are.
+//!
+//! Tests in one process otherwise share its stores, so values cached by one test are hits in
+//! the next. [`isolated`], or the [`crate::test`] attribute, gives a test a
+//! fresh store of its own, and [`isolated_with`] one of the test's choosing, such as a
+//! [`MemoryBackend`] recording whether calls hit:
+//!
+//! ```no_run
+//! # use smart_cache::cached;
+//! # #[cached]
+//! # fn render(template: String) -> String {
+//! #     template
+//! # }
+//! #[smart_cache::test]
+//! fn renders_invoices() {
+//!     assert_eq!(render("invoice".to_string()), "invoice");
+//! }
+//! ```
+
+use std::{
+    cell::Cell,
+    fs,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use eyre::Result;
+use rkyv::{api::high::HighSerializer, rancor, ser::allocator::ArenaHandle, util::AlignedVec};
+
+use crate::{
+    backend::{self, CacheBackend, Description, StoredEntry, WriteEntry},
+    config, context, memo, ContextGuard, Function, FunctionHash, FunctionInfo, FunctionStats,
+};
+
+/// Runs of the function [`assert_deterministic`] compares.
+pub const RUNS: usize = 8;
+
+thread_local! {
+    static UNCACHED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether cached functions called on this thread compute their values without reading or
+/// writing the store, because [`assert_deterministic`] is running them.
+pub(crate) fn uncached() -> bool {
+    UNCACHED.get()
+}
+
+/// Resets [`UNCACHED`] when a run ends, even by panicking.
+struct Uncached(bool);
+
+impl Uncached {
+    fn enter() -> Self {
+        Self(UNCACHED.replace(true))
+    }
+}
+
+impl Drop for Uncached {
+    fn drop(&mut self) {
+        UNCACHED.set(self.0);
+    }
+}
+
+/// Calls `f` [`RUNS`] times with caching turned off on this thread, and panics unless every
+/// call returns a value that serializes to the same bytes. Cached functions called by `f`,
+/// directly or not, compute their values every time and leave the store untouched.
+///
+/// # Panics
+///
+/// Panics if two calls return different values, or a value can't be serialized.
+pub fn assert_deterministic<T>(f: impl FnMut() -> T)
+where
+    T: for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    assert_deterministic_runs(RUNS, f);
+}
+
+/// [`assert_deterministic`], calling `f` `runs` times.
+///
+/// # Panics
+///
+/// Panics if two calls return different values, or a value can't be serialized.
+pub fn assert_deterministic_runs<T>(runs: usize, mut f: impl FnMut() -> T)
+where
+    T: for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    let _uncached = Uncached::enter();
+    let mut first: Option<AlignedVec> = None;
+    for run in 0..runs {
+        let value = rkyv::to_bytes::<rancor::Error>(&f())
+            .unwrap_or_else(|e| panic!("failed to serialize the value of run {run}: {e}"));
+        let Some(first) = &first else {
+            first = Some(value);
+            continue;
+        };
+        if value[..] != first[..] {
+            let offset = first
+                .iter()
+                .zip(value.iter())
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| first.len().min(value.len()));
+            panic!(
+                "run {run} returned a different value than run 0: {} bytes instead of {}, first \
+                 differing at byte {offset}",
+                value.len(),
+                first.len()
+            );
+        }
+    }
+}
+
+/// Stores handed out by [`isolated`] so far in this process, numbering their directories.
+static ISOLATED: AtomicU64 = AtomicU64::new(0);
+
+/// A store of its own for the cached calls on one thread, removed when dropped; see
+/// [`isolated`].
+#[must_use = "the store is only used until the guard is dropped"]
+pub struct Isolated {
+    /// The temporary directory holding the store, if [`isolated`] created one.
+    dir: Option<PathBuf>,
+    _context: ContextGuard,
+}
+
+/// Has every cached call on this thread use a fresh store in a new temporary directory, until
+/// the returned guard is dropped and the directory removed. Maintenance functions such as
+/// [`crate::clear_function`] called on the thread see only that store too.
+///
+/// Calls on threads the caller starts use the configured stores as usual, except the
+/// computations this crate starts for it (background recomputations, prefetches and the
+/// store I/O of `async fn`s). With [`crate::Config::write_behind`], entries are written to the
+/// configured stores.
+///
+/// ```no_run
+/// # use smart_cache::cached;
+/// # #[cached]
+/// # fn render(template: String) -> String {
+/// #     template
+/// # }
+/// let _isolated = smart_cache::testing::isolated();
+/// assert_eq!(render("invoice".to_string()), "invoice");
+/// ```
+///
+/// # Panics
+///
+/// Panics if the directory or the store in it can't be created.
+pub fn isolated() -> Isolated {
+    let dir = std::env::temp_dir().join(format!(
+        "smart-cache-test-{}-{}",
+        process::id(),
+        ISOLATED.fetch_add(1, Ordering::Relaxed)
+    ));
+    let store = backend::open_file(&dir.join("cache.redb"), config::get())
+        .unwrap_or_else(|e| panic!("failed to create a store in {}: {e:#}", dir.display()));
+    isolate(store, Some(dir))
+}
+
+/// [`isolated`], using `store` rather than a new store in a temporary directory, such as a
+/// [`MemoryBackend`] the test inspects afterwards.
+pub fn isolated_with(store: impl CacheBackend + 'static) -> Isolated {
+    isolate(Box::new(store), None)
+}
+
+fn isolate(store: Box<dyn CacheBackend>, dir: Option<PathBuf>) -> Isolated {
+    // Values memoized from the previous store would otherwise be hits in this one.
+    memo::invalidate();
+    Isolated {
+        dir,
+        _context: context::isolate(Box::leak(store)),
+    }
+}
+
+impl Drop for Isolated {
+    fn drop(&mut self) {
+        memo::invalidate();
+        if let Some(dir) = &self.dir {
+            // The store stays open, so this fails where open files can't be removed.
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// A [`crate::backend::MemoryBackend`] recording the lookups and writes made to it, so tests
+/// can tell whether a call hit the cache without touching the filesystem.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use smart_cache::cached;
+/// #[cached]
+/// fn render(template: String) -> String {
+///     # template
+/// }
+///
+/// let backend = Arc::new(smart_cache::testing::MemoryBackend::new());
+/// let _isolated = smart_cache::testing::isolated_with(Arc::clone(&backend));
+/// render("invoice".to_string());
+/// render("invoice".to_string());
+/// assert_eq!(backend.found("render"), 1);
+/// assert_eq!(backend.written("render"), 1);
+/// ```
+#[derive(Default)]
+pub struct MemoryBackend {
+    inner: backend::MemoryBackend,
+    lookups: Mutex<Vec<Lookup>>,
+    writes: Mutex<Vec<Write>>,
+}
+
+/// A lookup made to a [`MemoryBackend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    /// The name of the function looked up.
+    pub function: String,
+    /// The key looked up, including any namespace prefix.
+    pub key: Vec<u8>,
+    /// Whether an entry was stored under the key. Entries found can still be misses, if they
+    /// expired or are corrupt.
+    pub found: bool,
+}
+
+/// An entry written to a [`MemoryBackend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Write {
+    /// The name of the function whose value was written.
+    pub function: String,
+    /// The key written, including any namespace prefix.
+    pub key: Vec<u8>,
+    /// The size of the entry in bytes.
+    pub size: usize,
+}
+
+impl MemoryBackend {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every lookup made so far, oldest first.
+    pub fn lookups(&self) -> Vec<Lookup> {
+        self.lookups
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Every entry written so far, oldest first.
+    pub fn writes(&self) -> Vec<Write> {
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// How many lookups of the function named `function` found an entry.
+    pub fn found(&self, function: &str) -> usize {
+        self.count_lookups(function, true)
+    }
+
+    /// How many lookups of the function named `function` found no entry.
+    pub fn not_found(&self, function: &str) -> usize {
+        self.count_lookups(function, false)
+    }
+
+    /// How many entries of the function named `function` were written.
+    pub fn written(&self, function: &str) -> usize {
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|write| write.function == function)
+            .count()
+    }
+
+    /// Forgets the lookups and writes recorded so far, keeping the entries.
+    pub fn reset(&self) {
+        self.lookups
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    fn count_lookups(&self, function: &str, found: bool) -> usize {
+        self.lookups
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|lookup| lookup.function == function && lookup.found == found)
+            .count()
+    }
+}
+
+impl CacheBackend for MemoryBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let entry = self.inner.get(function, key)?;
+        self.lookups
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Lookup {
+                function: function.name().to_string(),
+                key: key.to_vec(),
+                found: entry.is_some(),
+            });
+        Ok(entry)
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        self.inner.insert_batch(entries)?;
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(entries.iter().map(|entry| Write {
+                function: entry.function.name().to_string(),
+                key: entry.key.to_vec(),
+                size: entry.entry.len(),
+            }));
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        self.inner.remove(function, key)
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        self.inner.remove_prefix(prefix)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        self.inner.functions()
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.inner.clear_function(function)
+    }
+
+    fn merge_stats(&self, deltas: &[FunctionStats]) -> Result<()> {
+        self.inner.merge_stats(deltas)
+    }
+
+    fn stats(&self) -> Result<Vec<FunctionStats>> {
+        self.inner.stats()
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.inner.for_each_key(f)
+    }
+
+    fn for_each_function_entry(
+        &self,
+        function: &FunctionHash,
+        f: &mut dyn FnMut(&[u8], &[u8]),
+    ) -> Result<()> {
+        self.inner.for_each_function_entry(function, f)
+    }
+
+    fn describe(&self) -> Description {
+        self.inner.describe()
+    }
+}
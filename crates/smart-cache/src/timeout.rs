@@ -0,0 +1,118 @@
+//! Serving expired values when recomputing them is slow, for functions cached with
+//! `#[cached(timeout = "...", on_timeout = "stale")]`.
+//!
+//! Expired entries of such functions are kept in the store rather than discarded, and a lookup
+//! that finds one hands it to the calling thread's miss. The miss computes the new value on a
+//! thread of its own and waits for it up to the timeout; past that, it returns the expired
+//! value and leaves the computation to store the new one when it finishes. Misses for the same
+//! key meanwhile get the expired value without waiting.
+
+use std::{cell::RefCell, sync::mpsc, thread};
+
+use tracing::{debug, dispatcher, warn, Dispatch};
+
+use crate::{
+    clock::Instant, context, dependency, replay, revalidate, CachedValue, Function, FunctionHash,
+};
+
+thread_local! {
+    /// The expired value the last lookup on this thread found, and the function it belongs to.
+    static EXPIRED: RefCell<Option<(FunctionHash, CachedValue)>> = const { RefCell::new(None) };
+}
+
+/// Keeps an expired value of `function` for the miss that follows on this thread.
+pub fn keep_expired(function: &Function, value: CachedValue) {
+    EXPIRED.with(|expired| *expired.borrow_mut() = Some((*function.hash(), value)));
+}
+
+/// Internal function used by the macro to take the expired value of `function` its lookup
+/// found, if any.
+#[doc(hidden)]
+pub fn expired_value(function: &Function) -> Option<CachedValue> {
+    let (hash, value) = EXPIRED.with(|expired| expired.borrow_mut().take())?;
+    (hash == *function.hash()).then_some(value)
+}
+
+/// Internal function used by the macro to compute a value of `function` for a miss that found
+/// the `expired` one, returning the expired value if computing takes longer than the function's
+/// timeout. `compute` returns the serialized value, and whether it is [`crate::Negative`] if it
+/// should be stored; it is stored once computed either way. `debug_key` is the `Debug` form of
+/// the key, as for [`crate::set_cached`].
+#[doc(hidden)]
+pub fn compute_or_expired(
+    function: &'static Function,
+    key_bytes: &[u8],
+    debug_key: Option<String>,
+    expired: CachedValue,
+    compute: impl FnOnce() -> (Vec<u8>, Option<bool>) + Send + 'static,
+) -> CachedValue {
+    let Some(timeout) = function.compute_timeout() else {
+        return expired;
+    };
+    // The context is thread-local, so the key and TTLs are resolved before leaving this thread.
+    let key = context::key(function, key_bytes).into_owned();
+    let ttls = (
+        crate::time_to_live(function, false),
+        crate::time_to_live(function, true),
+    );
+    let isolated = context::store();
+    let dispatch = dispatcher::get_default(Dispatch::clone);
+
+    // Misses meanwhile get the expired value right away rather than computing it again.
+    let Some(in_flight) = revalidate::claim(function, &key) else {
+        return expired;
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .name("smart-cache-compute".to_string())
+        .spawn(move || {
+            let _isolated = isolated.map(context::isolate);
+            let _in_flight = in_flight;
+            let _permit = crate::compute_permit();
+            // Logged under the caller's subscriber, and kept with the value like a miss's.
+            let _dispatch = dispatcher::set_default(&dispatch);
+            let _events = replay::capture_events();
+            let _dependencies = dependency::track_dependencies(function);
+            let started = Instant::now();
+            let (value, negative) = compute();
+            let compute_time = started.elapsed();
+            if let Some(negative) = negative {
+                let ttl = crate::result_ttl().or(if negative { ttls.1 } else { ttls.0 });
+                let stored = crate::prepare_entry(
+                    function,
+                    &key,
+                    &value,
+                    compute_time,
+                    ttl,
+                    debug_key.as_deref(),
+                )
+                .and_then(|entry| {
+                    entry.map_or(Ok(()), |entry| crate::store_entry(function, &key, entry))
+                });
+                if let Err(e) = stored {
+                    warn!("Failed to store a value of {}: {e:#}", function.name());
+                }
+            }
+            // The miss may have stopped waiting, and only needed the value stored.
+            let _ = sender.send(value);
+        });
+    if let Err(e) = spawned {
+        warn!(
+            "Failed to start computing a value of {}; serving the expired one: {e:#}",
+            function.name()
+        );
+        return expired;
+    }
+
+    match receiver.recv_timeout(timeout) {
+        Ok(value) => CachedValue::copied(&value),
+        Err(_) => {
+            debug!(
+                "Computing a value of {} took longer than {timeout:?}; serving the expired one",
+                function.name()
+            );
+            expired
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! TLS for `https://` endpoints, through rustls. Certificates are verified against the
+//! platform's trust store, or only those in `SSL_CERT_FILE` and `SSL_CERT_DIR` if either is set,
+//! and the certificate must name the host being connected to.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::Arc,
+};
+
+use once_cell::sync::Lazy;
+use rustls::{
+    crypto::ring, pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+use tracing::debug;
+
+/// The client configuration shared by every connection.
+static CONFIG: Lazy<Result<Arc<ClientConfig>, String>> = Lazy::new(|| {
+    let native = rustls_native_certs::load_native_certs();
+    for error in &native.errors {
+        debug!("Skipping trusted certificates: {error}");
+    }
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(native.certs);
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|error| error.to_string())?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+});
+
+/// A TLS session over a TCP connection.
+pub struct TlsStream(StreamOwned<ClientConnection, TcpStream>);
+
+impl TlsStream {
+    /// Performs a handshake over `stream`, verifying that the server's certificate is trusted
+    /// and issued for `host` (a DNS name or IP address, without a port).
+    pub fn connect(mut stream: TcpStream, host: &str) -> io::Result<Self> {
+        let config = CONFIG
+            .as_ref()
+            .map_err(|error| io::Error::other(error.clone()))?;
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        let mut session =
+            ClientConnection::new(Arc::clone(config), name).map_err(io::Error::other)?;
+
+        // Handshake now, so an untrusted certificate fails the connection rather than the first
+        // read from it.
+        while session.is_handshaking() {
+            session.complete_io(&mut stream).map_err(|error| {
+                let reason = error
+                    .get_ref()
+                    .and_then(|inner| inner.downcast_ref::<rustls::Error>());
+                match reason {
+                    Some(rustls::Error::InvalidCertificate(reason)) => io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("certificate verification failed for {host}: {reason}"),
+                    ),
+                    _ => timed_out(error),
+                }
+            })?;
+        }
+        Ok(Self(StreamOwned::new(session, stream)))
+    }
+}
+
+/// Reports the socket's read or write timeout elapsing as such.
+fn timed_out(error: io::Error) -> io::Error {
+    if error.kind() == io::ErrorKind::WouldBlock {
+        io::Error::new(io::ErrorKind::TimedOut, "TLS connection timed out")
+    } else {
+        error
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            // Responses are framed by their headers; a peer closing without `close_notify` ends
+            // a body read to the end of the connection, as it does over plain TCP.
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            read => read.map_err(timed_out),
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf).map_err(timed_out)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().map_err(timed_out)
+    }
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        let StreamOwned { conn, sock } = &mut self.0;
+        conn.send_close_notify();
+        while conn.wants_write() {
+            if conn.write_tls(sock).is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! A timeline of cached calls, recorded with [`crate::Config::trace_calls`] and written with
+//! [`write_trace`] in the Chrome trace event format, which `chrome://tracing` and
+//! [Perfetto](https://ui.perfetto.dev) open.
+//!
+//! Each call is one complete (`"ph": "X"`) event on its thread, named after the function, with
+//! the key's hash, whether it hit, and for misses the time spent computing the value apart from
+//! the time spent looking it up and storing it.
+
+use std::{
+    cell::Cell,
+    fmt::Write as _,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use eyre::{Result, WrapErr};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{backend::to_hex, clock::Instant, config, Function};
+
+/// How many calls are kept; later ones are dropped.
+const MAX_CALLS: usize = 1 << 20;
+
+struct Call {
+    function: &'static str,
+    key_hash: String,
+    thread: u64,
+    started: Duration,
+    duration: Duration,
+    compute_time: Option<Duration>,
+}
+
+/// When the process started tracing; timestamps are relative to it.
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+static CALLS: Mutex<Vec<Call>> = Mutex::new(Vec::new());
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// This thread's number in the trace, assigned on its first call.
+    static THREAD: Cell<u64> = const { Cell::new(0) };
+}
+
+fn thread_number() -> u64 {
+    THREAD.with(|thread| {
+        if thread.get() == 0 {
+            thread.set(NEXT_THREAD.fetch_add(1, Ordering::Relaxed));
+        }
+        thread.get()
+    })
+}
+
+/// Internal function used by the macro to record a call of `function` that started at
+/// `started` and has just returned, for [`crate::Config::trace_calls`]. `compute_time` is how
+/// long computing the value took, for misses.
+#[doc(hidden)]
+pub fn trace_call(
+    function: &'static Function,
+    key_bytes: &[u8],
+    started: Instant,
+    compute_time: Option<Duration>,
+) {
+    if !config::get().trace_calls {
+        return;
+    }
+    let call = Call {
+        function: function.name(),
+        key_hash: to_hex(&Sha256::digest(key_bytes)[..8]),
+        thread: thread_number(),
+        started: started.saturating_duration_since(*START),
+        duration: started.elapsed(),
+        compute_time,
+    };
+    let mut calls = CALLS.lock().unwrap_or_else(PoisonError::into_inner);
+    if calls.len() == MAX_CALLS {
+        warn!("Traced {MAX_CALLS} cached calls; dropping later ones");
+    }
+    if calls.len() < MAX_CALLS {
+        calls.push(call);
+    }
+}
+
+/// Writes the cached calls traced so far to `path` as a Chrome trace, to open in
+/// `chrome://tracing` or Perfetto. Calls are only traced with [`crate::Config::trace_calls`],
+/// and stay recorded, so later traces include them too.
+///
+/// ```no_run
+/// smart_cache::Config::default().trace_calls(true).install()?;
+/// // ... run the pipeline ...
+/// smart_cache::write_trace("trace.json")?;
+/// # Ok::<(), eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if the file cannot be written.
+pub fn write_trace(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut trace = String::from("{\"traceEvents\":[");
+    let calls = CALLS.lock().unwrap_or_else(PoisonError::into_inner);
+    for (i, call) in calls.iter().enumerate() {
+        if i > 0 {
+            trace.push(',');
+        }
+        let outcome = if call.compute_time.is_some() {
+            "miss"
+        } else {
+            "hit"
+        };
+        let _ = write!(
+            trace,
+            "\n{{\"name\":\"{}\",\"cat\":\"{outcome}\",\"ph\":\"X\",\"pid\":{},\"tid\":{},\
+             \"ts\":{},\"dur\":{},\"args\":{{\"key\":\"{}\",\"outcome\":\"{outcome}\"",
+            call.function,
+            std::process::id(),
+            call.thread,
+            call.started.as_micros(),
+            call.duration.as_micros(),
+            call.key_hash,
+        );
+        if let Some(compute_time) = call.compute_time {
+            let _ = write!(
+                trace,
+                ",\"compute_us\":{},\"overhead_us\":{}",
+                compute_time.as_micros(),
+                call.duration.saturating_sub(compute_time).as_micros()
+            );
+        } else {
+            let _ = write!(trace, ",\"lookup_us\":{}", call.duration.as_micros());
+        }
+        trace.push_str("}}");
+    }
+    drop(calls);
+    trace.push_str("\n],\"displayTimeUnit\":\"ms\"}\n");
+    fs::write(path, trace).wrap_err_with(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,66 @@
+//! Measuring how much of the store each cached function occupies.
+
+use std::{cmp::Reverse, time::SystemTime};
+
+use eyre::Result;
+
+use crate::{backend, entry, FunctionHash};
+
+/// Space one version of a cached function occupies, as returned by [`usage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionUsage {
+    /// The function's name as written in the source.
+    pub name: String,
+    /// Hash of the function body the entries were computed by.
+    pub hash: FunctionHash,
+    /// Number of stored entries.
+    pub entries: u64,
+    /// Total size of the stored keys and entries, including their envelopes.
+    pub bytes: u64,
+    /// When the newest entry was written. Entries are written on misses, so this is the last
+    /// time the function had to compute a value.
+    pub last_written: Option<SystemTime>,
+}
+
+/// Measures every cached function in every known store, largest first.
+///
+/// Unlike [`crate::functions`], this reads every entry, so it takes time proportional to the
+/// size of the store.
+///
+/// ```no_run
+/// for usage in smart_cache::usage()?.iter().take(5) {
+///     println!("{}: {} bytes in {} entries", usage.name, usage.bytes, usage.entries);
+/// }
+/// # Ok::<(), eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if a store cannot be read.
+pub fn usage() -> Result<Vec<FunctionUsage>> {
+    crate::flush();
+
+    let mut usage = Vec::new();
+    for backend in backend::all() {
+        for function in backend.functions()? {
+            let mut measured = FunctionUsage {
+                name: function.name,
+                hash: function.hash,
+                entries: 0,
+                bytes: 0,
+                last_written: None,
+            };
+            backend.for_each_function_entry(&function.hash, &mut |key, stored| {
+                measured.entries += 1;
+                measured.bytes += (key.len() + stored.len()) as u64;
+                let created_at = entry::decode(stored)
+                    .ok()
+                    .and_then(|(header, _)| header.created_at);
+                measured.last_written = measured.last_written.max(created_at);
+            })?;
+            usage.push(measured);
+        }
+    }
+    usage.sort_by_key(|usage| Reverse(usage.bytes));
+    Ok(usage)
+}
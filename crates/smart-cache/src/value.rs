@@ -0,0 +1,175 @@
+//! Zero-copy handles to entries read from the store.
+
+use std::{ops::Deref, sync::Arc};
+
+use redb::{AccessGuard, ReadOnlyTable};
+use rkyv::util::AlignedVec;
+
+#[cfg(unix)]
+use crate::mapped::Mapping;
+
+/// Alignment `rkyv::access` can rely on for any archived root.
+const ALIGNMENT: usize = 16;
+
+// Boxing the redb variant would reintroduce the per-hit allocation this type exists to avoid.
+#[allow(clippy::large_enum_variant)]
+enum Source {
+    /// Borrowed straight out of a redb page. The table is kept alongside the guard so the read
+    /// transaction stays alive for as long as the bytes are in use.
+    Redb {
+        guard: AccessGuard<'static, &'static [u8]>,
+        _table: ReadOnlyTable<&'static [u8], &'static [u8]>,
+    },
+    /// Owned bytes, e.g. still queued in the background writer or returned by a backend that
+    /// cannot lend out its storage.
+    Shared(Arc<[u8]>),
+    /// Served from this thread's memo, or otherwise copied into aligned memory.
+    Memo(Arc<AlignedVec<ALIGNMENT>>),
+    /// A spilled value mapped into memory behind its envelope.
+    #[cfg(unix)]
+    Mapped(Mapping),
+}
+
+/// Raw entry bytes returned by a backend, envelope included.
+pub struct StoredEntry(Source);
+
+impl StoredEntry {
+    pub(crate) const fn redb(
+        guard: AccessGuard<'static, &'static [u8]>,
+        table: ReadOnlyTable<&'static [u8], &'static [u8]>,
+    ) -> Self {
+        Self(Source::Redb {
+            guard,
+            _table: table,
+        })
+    }
+}
+
+impl From<Arc<[u8]>> for StoredEntry {
+    fn from(bytes: Arc<[u8]>) -> Self {
+        Self(Source::Shared(bytes))
+    }
+}
+
+impl From<Vec<u8>> for StoredEntry {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Source::Shared(bytes.into()))
+    }
+}
+
+#[cfg(unix)]
+impl From<Mapping> for StoredEntry {
+    fn from(mapping: Mapping) -> Self {
+        Self(Source::Mapped(mapping))
+    }
+}
+
+impl Deref for StoredEntry {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Source::Redb { guard, .. } => guard.value(),
+            Source::Shared(bytes) => bytes,
+            Source::Memo(bytes) => bytes,
+            #[cfg(unix)]
+            Source::Mapped(mapping) => mapping,
+        }
+    }
+}
+
+/// A verified cache entry, handed to the macro without copying it out of the store.
+#[doc(hidden)]
+pub struct CachedValue {
+    entry: StoredEntry,
+    /// Length of the envelope header preceding the value bytes.
+    offset: usize,
+    /// Whether the value should be recomputed in the background while it is served.
+    revalidate: bool,
+}
+
+impl CachedValue {
+    pub(crate) const fn new(entry: StoredEntry, offset: usize) -> Self {
+        Self {
+            entry,
+            offset,
+            revalidate: false,
+        }
+    }
+
+    pub(crate) const fn memo(value: Arc<AlignedVec<ALIGNMENT>>) -> Self {
+        Self {
+            entry: StoredEntry(Source::Memo(value)),
+            offset: 0,
+            revalidate: false,
+        }
+    }
+
+    pub(crate) const fn revalidate(mut self, revalidate: bool) -> Self {
+        self.revalidate = revalidate;
+        self
+    }
+
+    /// Whether the value is stale or close to expiring, and should be recomputed in the
+    /// background while it is served.
+    pub const fn should_revalidate(&self) -> bool {
+        self.revalidate
+    }
+
+    /// A copy of `value`, e.g. after decompressing it.
+    pub(crate) fn copied(value: &[u8]) -> Self {
+        let mut copy = AlignedVec::with_capacity(value.len());
+        copy.extend_from_slice(value);
+        Self::memo(Arc::new(copy))
+    }
+
+    /// The value bytes, suitably aligned for `rkyv::access`.
+    ///
+    /// Values live at arbitrary offsets inside database pages, so this only copies when the
+    /// stored slice happens to be misaligned.
+    pub fn aligned(&self) -> Aligned<'_> {
+        let bytes = &**self;
+        if bytes.as_ptr().align_offset(ALIGNMENT) == 0 {
+            return Aligned::Borrowed(bytes);
+        }
+
+        let mut copy = AlignedVec::with_capacity(bytes.len());
+        copy.extend_from_slice(bytes);
+        Aligned::Copied(copy)
+    }
+
+    /// The same value, copied into memory of its own if the stored slice is misaligned.
+    pub(crate) fn into_aligned(self) -> Self {
+        let copy = match self.aligned() {
+            Aligned::Borrowed(_) => None,
+            Aligned::Copied(copy) => Some(copy),
+        };
+        copy.map_or(self, |copy| Self::memo(Arc::new(copy)))
+    }
+}
+
+impl Deref for CachedValue {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.entry[self.offset..]
+    }
+}
+
+/// Value bytes returned by [`CachedValue::aligned`].
+#[doc(hidden)]
+pub enum Aligned<'a> {
+    Borrowed(&'a [u8]),
+    Copied(AlignedVec<ALIGNMENT>),
+}
+
+impl Deref for Aligned<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(bytes) => bytes,
+            Self::Copied(bytes) => bytes,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Computing many values up front on a rayon pool, for pre-computation jobs.
+//!
+//! Each worker holds back the entries it writes and commits them [`BATCH`] at a time, one write
+//! transaction per store, rather than one transaction per value. Lookups on any thread find the
+//! entries held back meanwhile, as they find those queued for the background writer.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use once_cell::sync::Lazy;
+#[cfg(feature = "rayon")]
+use rayon::{
+    iter::{ParallelBridge, ParallelIterator},
+    ThreadPool, ThreadPoolBuilder,
+};
+use tracing::{debug, warn};
+
+use crate::{writer::Batch, Function, FunctionHash};
+
+/// How many entries a worker writes at a time.
+const BATCH: usize = 256;
+
+thread_local! {
+    /// Entries written by this thread while it is warming, not yet committed.
+    static BUFFER: RefCell<Option<Batch>> = const { RefCell::new(None) };
+}
+
+/// Buffered entries, by function and key.
+type Held = HashMap<FunctionHash, HashMap<Vec<u8>, Arc<[u8]>>>;
+
+/// Every warming thread's buffered entries, for lookups to find.
+static HELD: Lazy<Mutex<Held>> = Lazy::new(Mutex::default);
+
+/// The entry for `key` a warming thread holds back, if any.
+pub fn held(function: &FunctionHash, key: &[u8]) -> Option<Arc<[u8]>> {
+    HELD.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(function)?
+        .get(key)
+        .cloned()
+}
+
+/// Has every thread of a warming pool buffer the entries it writes until dropped, when each
+/// commits what it still holds back, even if a computation panicked.
+#[cfg(feature = "rayon")]
+struct Buffering<'a>(&'a ThreadPool);
+
+#[cfg(feature = "rayon")]
+impl<'a> Buffering<'a> {
+    fn start(pool: &'a ThreadPool) -> Self {
+        pool.broadcast(|_| BUFFER.with(|buffer| *buffer.borrow_mut() = Some(Batch::new())));
+        Self(pool)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Drop for Buffering<'_> {
+    fn drop(&mut self) {
+        self.0.broadcast(|_| {
+            if let Some(batch) = BUFFER.with(|buffer| buffer.borrow_mut().take()) {
+                commit(&batch);
+            }
+        });
+    }
+}
+
+fn commit(batch: &Batch) {
+    if batch.is_empty() {
+        return;
+    }
+    debug!("Writing {} warmed entries", batch.len());
+    if let Err(e) = crate::insert_batch(batch) {
+        warn!("Failed to store {} warmed values: {e:#}", batch.len());
+    }
+    let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+    for ((function, key), entry) in batch {
+        release(&mut held, function.hash(), key, entry);
+    }
+}
+
+/// Forgets `entry` once committed, unless another thread has held back a newer one since.
+fn release(held: &mut Held, function: &FunctionHash, key: &[u8], entry: &Arc<[u8]>) {
+    let Some(entries) = held.get_mut(function) else {
+        return;
+    };
+    if entries
+        .get(key)
+        .is_some_and(|held| Arc::ptr_eq(held, entry))
+    {
+        entries.remove(key);
+    }
+}
+
+/// Holds back an entry written while the current thread is warming, returning it if the thread
+/// isn't, to be written as usual.
+pub fn buffer(function: &'static Function, key: &[u8], entry: Vec<u8>) -> Option<Vec<u8>> {
+    let full = BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        let Some(batch) = buffer.as_mut() else {
+            return Err(entry);
+        };
+        let entry: Arc<[u8]> = entry.into();
+        HELD.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(*function.hash())
+            .or_default()
+            .insert(key.to_vec(), Arc::clone(&entry));
+        batch.insert((function, key.to_vec()), entry);
+        Ok((batch.len() >= BATCH).then(|| std::mem::take(batch)))
+    });
+    match full {
+        Err(entry) => Some(entry),
+        Ok(full) => {
+            if let Some(batch) = full {
+                commit(&batch);
+            }
+            None
+        }
+    }
+}
+
+/// Calls `warm` with each of `args` on a rayon pool of `threads` threads, or rayon's default
+/// of one per CPU if `threads` is 0, so the values of the cached functions it calls are stored
+/// before they are first needed. Arguments already cached are hits and cost a lookup. Needs the
+/// `rayon` feature.
+///
+/// Values computed on the pool are written in batches, and show up in the store as each batch
+/// is committed; all of them are stored by the time this returns. Until then, calls on any
+/// thread find them as they would a pending write, so workers don't compute each other's values
+/// again. The pool is built for the call, so its threads buffer only what `warm` writes.
+///
+/// ```no_run
+/// # use smart_cache::cached;
+/// #[cached]
+/// fn render(page: u32, locale: &str) -> String {
+///     format!("page {page} in {locale}")
+/// }
+///
+/// let pages = (1..=1000).flat_map(|page| ["en", "de"].map(|locale| (page, locale)));
+/// smart_cache::warm_parallel(8, pages, |(page, locale)| {
+///     render(page, locale);
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if the pool's threads can't be started, or if `warm` panics.
+#[cfg(feature = "rayon")]
+pub fn warm_parallel<A, I>(threads: usize, args: I, warm: impl Fn(A) + Sync + Send)
+where
+    A: Send,
+    I: IntoIterator<Item = A>,
+    I::IntoIter: Send,
+{
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|index| format!("smart-cache-warm-{index}"))
+        .build()
+        .expect("failed to start the warming threads");
+    let args = args.into_iter();
+    let _buffering = Buffering::start(&pool);
+    pool.install(|| args.par_bridge().for_each(warm));
+}
@@ -0,0 +1,158 @@
+//! Warning before the stores fill the disk, set up with [`crate::Config::watchdog`].
+//!
+//! Writes check the size of the stores kept in local files, at most once per
+//! [`Watchdog::interval`]. When the total grows past a threshold it hadn't passed at the
+//! previous check, a warning is logged and the watchdog's callback is called; once the stores
+//! shrink back below a threshold, crossing it again alerts again.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use tracing::warn;
+
+use crate::{backend, clock::Instant, config};
+
+type Callback = Arc<dyn Fn(&SizeAlert) + Send + Sync>;
+
+/// Thresholds on the total size of the stores, and what to do when one is crossed.
+///
+/// ```no_run
+/// use smart_cache::Watchdog;
+///
+/// smart_cache::Config::default()
+///     .watchdog(
+///         Watchdog::new()
+///             .threshold(8 * 1024 * 1024 * 1024)
+///             .threshold(16 * 1024 * 1024 * 1024)
+///             .on_alert(|alert| eprintln!("cache is {} bytes", alert.size)),
+///     )
+///     .install()?;
+/// # Ok::<(), eyre::Report>(())
+/// ```
+#[derive(Clone)]
+pub struct Watchdog {
+    thresholds: Vec<u64>,
+    interval: Duration,
+    on_alert: Option<Callback>,
+}
+
+impl Debug for Watchdog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("thresholds", &self.thresholds)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    /// A watchdog without thresholds, checking at most once a minute; add them with
+    /// [`Watchdog::threshold`].
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            thresholds: Vec::new(),
+            interval: Duration::from_secs(60),
+            on_alert: None,
+        }
+    }
+
+    /// Alert once the stores take more than `bytes` on disk.
+    #[must_use]
+    pub fn threshold(mut self, bytes: u64) -> Self {
+        self.thresholds.push(bytes);
+        self.thresholds.sort_unstable();
+        self.thresholds.dedup();
+        self
+    }
+
+    /// Measure the stores on a write only if `interval` has passed since the last measurement.
+    #[must_use]
+    pub const fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Call `f` when a threshold is crossed, in addition to logging a warning. It runs on the
+    /// thread whose write triggered the check.
+    #[must_use]
+    pub fn on_alert(mut self, f: impl Fn(&SizeAlert) + Send + Sync + 'static) -> Self {
+        self.on_alert = Some(Arc::new(f));
+        self
+    }
+}
+
+/// A threshold of a [`Watchdog`] the stores have grown past.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeAlert {
+    /// Bytes the stores take on disk.
+    pub size: u64,
+    /// The highest threshold below `size`.
+    pub threshold: u64,
+}
+
+struct State {
+    checked: Option<Instant>,
+    /// Thresholds below the size at the last check.
+    crossed: usize,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    checked: None,
+    crossed: 0,
+});
+
+/// Checks the size of the stores after a write, if a watchdog is configured and its interval
+/// has passed.
+pub fn written() {
+    let Some(watchdog) = &config::get().watchdog else {
+        return;
+    };
+
+    let alert = {
+        let mut state = STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        if state
+            .checked
+            .is_some_and(|checked| checked.elapsed() < watchdog.interval)
+        {
+            return;
+        }
+        state.checked = Some(Instant::now());
+
+        let size = backend::all()
+            .iter()
+            .filter_map(|backend| backend.describe().size)
+            .sum();
+        let crossed = watchdog
+            .thresholds
+            .iter()
+            .take_while(|&&threshold| size > threshold)
+            .count();
+        let previous = std::mem::replace(&mut state.crossed, crossed);
+        if crossed <= previous {
+            return;
+        }
+        SizeAlert {
+            size,
+            threshold: watchdog.thresholds[crossed - 1],
+        }
+    };
+
+    warn!(
+        "Cache stores take {} bytes on disk, past the watchdog threshold of {} bytes",
+        alert.size, alert.threshold,
+    );
+    // Called without the lock, so the callback may itself use the cache.
+    if let Some(on_alert) = &watchdog.on_alert {
+        on_alert(&alert);
+    }
+}
@@ -0,0 +1,185 @@
+//! Optional background writer that persists computed values off the caller's thread.
+//!
+//! Writes are coalesced: the writer keeps collecting submissions until it has
+//! `Config::coalesce_writes`'s entry count or delay, then commits them all in one transaction.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+use eyre::Result;
+use tracing::{debug, warn};
+
+use crate::{clock::Instant, Function, FunctionHash};
+
+enum Message {
+    Write {
+        function: &'static Function,
+        key: Vec<u8>,
+        entry: Arc<[u8]>,
+    },
+    Flush(mpsc::Sender<()>),
+}
+
+/// Entries handed to the writer but not yet committed, so lookups can still see them.
+type Pending = Arc<Mutex<HashMap<FunctionHash, HashMap<Vec<u8>, Arc<[u8]>>>>>;
+
+/// Writes collected for the next commit; later writes to a key replace earlier ones.
+pub type Batch = HashMap<(&'static Function, Vec<u8>), Arc<[u8]>>;
+
+pub struct Writer {
+    sender: SyncSender<Message>,
+    pending: Pending,
+}
+
+impl Writer {
+    /// Spawns the writer thread. `capacity` bounds the number of queued writes; once it is
+    /// reached, callers block until the writer catches up.
+    pub fn spawn(capacity: usize, batch_size: usize, batch_delay: Duration) -> Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let pending = Pending::default();
+
+        let batcher = Batcher {
+            receiver,
+            pending: Arc::clone(&pending),
+            batch: HashMap::new(),
+            batch_size: batch_size.max(1),
+            batch_delay,
+            deadline: Instant::now(),
+        };
+        thread::Builder::new()
+            .name("smart-cache-writer".to_string())
+            .spawn(move || batcher.run())?;
+
+        Ok(Self { sender, pending })
+    }
+
+    pub fn submit(&self, function: &'static Function, key: Vec<u8>, entry: Vec<u8>) {
+        let entry: Arc<[u8]> = entry.into();
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(*function.hash())
+            .or_default()
+            .insert(key.clone(), Arc::clone(&entry));
+
+        let message = Message::Write {
+            function,
+            key,
+            entry,
+        };
+        if self.sender.send(message).is_err() {
+            warn!("Cache writer thread has stopped; value was not persisted");
+        }
+    }
+
+    pub fn pending(&self, function: &FunctionHash, key: &[u8]) -> Option<Arc<[u8]>> {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(function)?
+            .get(key)
+            .cloned()
+    }
+
+    /// Blocks until every write submitted so far has been committed.
+    pub fn flush(&self) {
+        let (ack, done) = mpsc::channel();
+        if self.sender.send(Message::Flush(ack)).is_ok() {
+            let _ = done.recv();
+        }
+    }
+}
+
+struct Batcher {
+    receiver: Receiver<Message>,
+    pending: Pending,
+    batch: Batch,
+    batch_size: usize,
+    batch_delay: Duration,
+    /// When the currently open batch must be committed.
+    deadline: Instant,
+}
+
+impl Batcher {
+    fn run(mut self) {
+        loop {
+            match self.receive() {
+                Ok(message) => self.handle(message),
+                Err(RecvTimeoutError::Timeout) => self.commit(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        self.commit();
+        debug!("Cache writer thread exiting");
+    }
+
+    /// Waits for the next message, but no longer than the current batch may stay open.
+    fn receive(&self) -> Result<Message, RecvTimeoutError> {
+        if self.batch.is_empty() {
+            return self
+                .receiver
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected);
+        }
+
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        self.receiver.recv_timeout(remaining)
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Write {
+                function,
+                key,
+                entry,
+            } => {
+                if self.batch.is_empty() {
+                    self.deadline = Instant::now() + self.batch_delay;
+                }
+                self.batch.insert((function, key), entry);
+                if self.batch.len() >= self.batch_size {
+                    self.commit();
+                }
+            }
+            Message::Flush(ack) => {
+                self.commit();
+                let _ = ack.send(());
+            }
+        }
+    }
+
+    fn commit(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let batch = std::mem::take(&mut self.batch);
+        debug!("Committing {} cached values", batch.len());
+        if let Err(e) = crate::insert_batch(&batch) {
+            warn!("Failed to persist {} cached values: {e}", batch.len());
+        }
+
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        for ((function, key), entry) in &batch {
+            let Some(keys) = pending.get_mut(function.hash()) else {
+                continue;
+            };
+            // Keep entries that were overwritten by a newer submission in the meantime.
+            if keys
+                .get(key)
+                .is_some_and(|current| Arc::ptr_eq(current, entry))
+            {
+                keys.remove(key);
+            }
+        }
+        pending.retain(|_, keys| !keys.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use smart_cache::{backend::MemoryBackend, cached, Mode};
+
+static CHEAP_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn cheap(x: u64) -> u64 {
+    CHEAP_CALLS.fetch_add(1, Ordering::SeqCst);
+    x + 1
+}
+
+static EXPENSIVE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn expensive(x: u64) -> u64 {
+    EXPENSIVE_CALLS.fetch_add(1, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(5));
+    x * 2
+}
+
+#[test]
+fn functions_whose_hits_cost_more_stop_using_the_store() {
+    smart_cache::Config::default()
+        .store_name("adaptive")
+        .backend("adaptive", Arc::new(MemoryBackend::new()))
+        .adaptive(true)
+        .install()
+        .unwrap();
+
+    for _ in 0..2 {
+        for x in 0..10 {
+            assert_eq!(cheap(x), x + 1);
+            assert_eq!(expensive(x), x * 2);
+        }
+    }
+    assert_eq!(CHEAP_CALLS.load(Ordering::SeqCst), 10);
+    assert_eq!(EXPENSIVE_CALLS.load(Ordering::SeqCst), 10);
+
+    // Reading `cheap`'s values back costs more than adding one, so it is now called directly.
+    assert_eq!(cheap(0), 1);
+    assert_eq!(CHEAP_CALLS.load(Ordering::SeqCst), 11);
+    assert_eq!(expensive(0), 0);
+    assert_eq!(EXPENSIVE_CALLS.load(Ordering::SeqCst), 10);
+
+    let stats = smart_cache::stats().unwrap();
+    let unprofitable = |name: &str| {
+        stats
+            .iter()
+            .find(|stats| stats.name == name)
+            .is_some_and(smart_cache::FunctionStats::unprofitable)
+    };
+    assert!(unprofitable("cheap"));
+    assert!(!unprofitable("expensive"));
+
+    // Audits recompute hits, which doesn't make the hits themselves any slower.
+    smart_cache::mode(Mode::Audit);
+    for _ in 0..5 {
+        for x in 0..10 {
+            assert_eq!(expensive(x), x * 2);
+        }
+    }
+    smart_cache::mode(Mode::Normal);
+    let stats = smart_cache::stats().unwrap();
+    let audited = stats
+        .iter()
+        .find(|stats| stats.name == "expensive")
+        .unwrap();
+    assert!(audited.average_hit_time().unwrap() < Duration::from_millis(1));
+    assert!(!audited.unprofitable());
+}
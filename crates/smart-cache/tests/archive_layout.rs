@@ -0,0 +1,63 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use sha2::{Digest, Sha256};
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, WriteEntry},
+    cached, Function,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn total(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x + 1
+}
+
+/// Rewrites `entry` as if a big-endian build had archived it, with a valid checksum.
+fn as_foreign(entry: &[u8]) -> Vec<u8> {
+    let mut entry = entry.to_vec();
+    entry[32 + 24] ^= 1;
+    let checksum: [u8; 32] = Sha256::new()
+        .chain_update(b"smart-cache entry v6")
+        .chain_update(&entry[32..])
+        .finalize()
+        .into();
+    entry[..32].copy_from_slice(&checksum);
+    entry
+}
+
+#[test]
+fn entries_with_another_layout_are_misses() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("layout")
+        .backend("layout", Arc::clone(&memory))
+        .install()
+        .unwrap();
+
+    assert_eq!(total(1), 2);
+    let function = smart_cache::functions().unwrap().remove(0);
+    let mut stored = Vec::new();
+    memory
+        .for_each_function_entry(&function.hash, &mut |key, entry| {
+            stored.push((key.to_vec(), as_foreign(entry)));
+        })
+        .unwrap();
+    let descriptor = Function::new("total", function.hash);
+    let writes: Vec<_> = stored
+        .iter()
+        .map(|(key, entry)| WriteEntry {
+            function: &descriptor,
+            key,
+            entry,
+        })
+        .collect();
+    memory.insert_batch(&writes).unwrap();
+
+    assert_eq!(total(1), 2);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
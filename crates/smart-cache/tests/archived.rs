@@ -0,0 +1,49 @@
+use std::{fs, process};
+
+use smart_cache::Function;
+
+static EMBEDDINGS: Function = Function::new("embeddings", [9; 32]);
+
+#[test]
+fn spilled_values_are_read_in_place() {
+    let root = std::env::temp_dir().join(format!("smart-cache-archived-{}", process::id()));
+    smart_cache::Config::default()
+        .database("archived", root.join("archived.redb"))
+        .store_name("archived")
+        .spill_values(1024)
+        .install()
+        .unwrap();
+
+    let small: Vec<u32> = vec![1, 2, 3];
+    let large: Vec<u32> = (0..1 << 20).collect();
+    smart_cache::insert_archived(&EMBEDDINGS, b"small", &small).unwrap();
+    smart_cache::insert_archived(&EMBEDDINGS, b"large", &large).unwrap();
+    smart_cache::flush();
+    assert!(
+        smart_cache::get_archived::<Vec<u32>>(&EMBEDDINGS, b"missing")
+            .unwrap()
+            .is_none()
+    );
+
+    let archived = smart_cache::get_archived::<Vec<u32>>(&EMBEDDINGS, b"small")
+        .unwrap()
+        .unwrap();
+    assert_eq!(archived.as_slice(), small.as_slice());
+
+    let archived = smart_cache::get_archived::<Vec<u32>>(&EMBEDDINGS, b"large")
+        .unwrap()
+        .unwrap();
+    assert_eq!(archived.len(), large.len());
+    assert!(archived
+        .iter()
+        .zip(&large)
+        .all(|(a, b)| a.to_native() == *b));
+
+    // The mapping outlives the spilled file it was made from.
+    fs::remove_dir_all(root.join("archived.values")).unwrap();
+    assert_eq!(archived[12345].to_native(), 12345);
+    drop(archived);
+
+    assert!(smart_cache::get_archived::<String>(&EMBEDDINGS, b"small").is_err());
+    fs::remove_dir_all(&root).unwrap();
+}
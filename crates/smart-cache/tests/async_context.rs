@@ -0,0 +1,65 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls `future` once inside `namespace`'s context, and to completion after it has ended.
+fn run_leaving_context<F: Future>(namespace: &str, future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    let guard = smart_cache::context().namespace(namespace).enter();
+    let mut poll = future.as_mut().poll(&mut context);
+    drop(guard);
+    loop {
+        match poll {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+        poll = future.as_mut().poll(&mut context);
+    }
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+async fn greet(name: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("hello {name}")
+}
+
+#[test]
+fn async_values_are_stored_in_the_context_the_call_started_in() {
+    smart_cache::Config::default()
+        .store_name("async-context")
+        .backend("async-context", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(
+        run_leaving_context("tenant", greet("ada".to_string())),
+        "hello ada"
+    );
+    smart_cache::flush();
+    assert_eq!(
+        run_leaving_context("tenant", greet("ada".to_string())),
+        "hello ada"
+    );
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+}
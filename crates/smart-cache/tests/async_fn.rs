@@ -0,0 +1,118 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+};
+
+use eyre::Result;
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, StoredEntry, WriteEntry},
+    Function, FunctionHash, FunctionInfo,
+};
+use smart_cache_macro::cached;
+
+/// Records which threads touch the store.
+#[derive(Default)]
+struct ThreadRecorder {
+    inner: MemoryBackend,
+    threads: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl ThreadRecorder {
+    fn record(&self) {
+        let name = thread::current().name().map(str::to_string);
+        self.threads.lock().unwrap().push(name);
+    }
+}
+
+impl CacheBackend for ThreadRecorder {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        self.record();
+        self.inner.get(function, key)
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        self.record();
+        self.inner.insert_batch(entries)
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        self.inner.remove(function, key)
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        self.inner.remove_prefix(prefix)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        self.inner.functions()
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.inner.clear_function(function)
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.inner.for_each_key(f)
+    }
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+async fn double(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * 2
+}
+
+#[test]
+fn async_functions_are_cached_off_the_calling_thread() {
+    let recorder = ThreadRecorder::default();
+    let threads = Arc::clone(&recorder.threads);
+    smart_cache::Config::default()
+        .store_name("recorded")
+        .backend("recorded", recorder)
+        .install()
+        .unwrap();
+
+    assert_eq!(block_on(double(21)), 42);
+    assert_eq!(block_on(double(21)), 42);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let threads = threads.lock().unwrap();
+    assert!(!threads.is_empty());
+    assert!(threads
+        .iter()
+        .all(|name| name.as_deref() != thread::current().name()));
+}
+
+#[test]
+fn cached_futures_are_send() {
+    fn assert_send<T: Send>(_: T) {}
+    assert_send(double(1));
+}
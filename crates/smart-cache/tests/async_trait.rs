@@ -0,0 +1,145 @@
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+trait Repository {
+    fn fetch<'life0, 'async_trait>(&'life0 self, id: u32) -> BoxFuture<'async_trait, String>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait;
+
+    fn find<'life0, 'life1, 'async_trait>(
+        &'life0 self,
+        name: &'life1 str,
+    ) -> Pin<Box<dyn Future<Output = Option<u32>> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait;
+}
+
+#[derive(Default)]
+struct Remote {
+    calls: AtomicUsize,
+}
+
+// The methods as `#[async_trait]` expands them, with the `#[cached]` it leaves in place, from
+//
+//     #[cached(self_is_context)]
+//     async fn fetch(&self, id: u32) -> String { .. }
+//
+// async-trait itself isn't a dependency of this crate.
+impl Repository for Remote {
+    #[cached(self_is_context)]
+    fn fetch<'life0, 'async_trait>(
+        &'life0 self,
+        id: u32,
+    ) -> ::core::pin::Pin<
+        Box<dyn ::core::future::Future<Output = String> + ::core::marker::Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        Box::pin(async move {
+            if let ::core::option::Option::Some(__ret) = ::core::option::Option::None::<String> {
+                #[allow(unreachable_code)]
+                return __ret;
+            }
+            let __self = self;
+            let id = id;
+            let __ret: String = {
+                __self.calls.fetch_add(1, Ordering::SeqCst);
+                format!("record-{id}")
+            };
+            #[allow(unreachable_code)]
+            __ret
+        })
+    }
+
+    #[cached(self_is_context)]
+    fn find<'life0, 'life1, 'async_trait>(
+        &'life0 self,
+        name: &'life1 str,
+    ) -> ::core::pin::Pin<
+        Box<dyn ::core::future::Future<Output = Option<u32>> + ::core::marker::Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        Box::pin(async move {
+            if let ::core::option::Option::Some(__ret) = ::core::option::Option::None::<Option<u32>>
+            {
+                #[allow(unreachable_code)]
+                return __ret;
+            }
+            let __self = self;
+            let __ret: Option<u32> = {
+                __self.calls.fetch_add(1, Ordering::SeqCst);
+                name.strip_prefix("record-")?.parse().ok()
+            };
+            #[allow(unreachable_code)]
+            __ret
+        })
+    }
+}
+
+#[test]
+fn async_trait_methods_are_cached() {
+    smart_cache::Config::default()
+        .store_name("async-trait")
+        .backend("async-trait", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    let remote = Remote::default();
+    let repository: &dyn Repository = &remote;
+    assert_eq!(block_on(repository.fetch(7)), "record-7");
+    assert_eq!(block_on(repository.fetch(7)), "record-7");
+    assert_eq!(remote.calls.load(Ordering::SeqCst), 1);
+
+    let name = "record-7".to_string();
+    assert_eq!(block_on(repository.find(&name)), Some(7));
+    assert_eq!(block_on(repository.find(&name)), Some(7));
+    assert_eq!(block_on(repository.find("other")), None);
+    assert_eq!(remote.calls.load(Ordering::SeqCst), 3);
+
+    // The futures are still `Send`, as the trait requires.
+    let fetched = thread::spawn(move || block_on(Remote::default().fetch(7)))
+        .join()
+        .unwrap();
+    assert_eq!(fetched, "record-7");
+}
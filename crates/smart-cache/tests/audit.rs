@@ -0,0 +1,42 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, cached, Mode};
+
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+#[cached]
+fn pure(x: u64) -> u64 {
+    x * 2
+}
+
+#[cached]
+fn impure(x: u64) -> u64 {
+    // Reads state its arguments don't capture.
+    x + NOW.fetch_add(1, Ordering::SeqCst)
+}
+
+#[test]
+fn audits_report_functions_that_diverge() {
+    smart_cache::Config::default()
+        .store_name("audit")
+        .backend("audit", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(pure(1), 2);
+    assert_eq!(impure(1), 1);
+
+    smart_cache::mode(Mode::Audit);
+    // Hits still return the stored value.
+    assert_eq!(pure(1), 2);
+    assert_eq!(impure(1), 1);
+    smart_cache::mode(Mode::Normal);
+
+    let divergences = smart_cache::divergences();
+    assert_eq!(divergences.len(), 1);
+    assert_eq!(divergences[0].name, "impure");
+    assert_ne!(divergences[0].stored, divergences[0].computed);
+}
@@ -0,0 +1,91 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn checksum(n: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    (0..n).fold(0, |sum, i| sum ^ i.wrapping_mul(0x9e37_79b9))
+}
+
+#[cached]
+fn cached_checksum(n: u64) -> u64 {
+    checksum(n)
+}
+
+/// Stands in for Criterion, running every benchmark for a few iterations.
+#[derive(Default)]
+struct Harness {
+    ran: Vec<String>,
+}
+
+struct Group<'a>(&'a mut Harness, String);
+
+struct Bencher;
+
+impl Harness {
+    fn benchmark_group(&mut self, name: &str) -> Group<'_> {
+        Group(self, name.to_string())
+    }
+}
+
+impl Group<'_> {
+    fn bench_function(&mut self, id: &str, mut f: impl FnMut(&mut Bencher)) {
+        f(&mut Bencher);
+        self.0.ran.push(format!("{}/{id}", self.1));
+    }
+
+    fn finish(self) {}
+}
+
+impl Bencher {
+    #[allow(clippy::unused_self)]
+    fn iter_custom(&mut self, mut routine: impl FnMut(u64) -> Duration) {
+        routine(3);
+    }
+}
+
+fn calls() -> usize {
+    CALLS.swap(0, Ordering::SeqCst)
+}
+
+#[test]
+fn misses_and_hits_are_measured_separately() {
+    smart_cache::Config::default()
+        .store_name("bench")
+        .backend("bench", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    // Every miss computes the value again.
+    smart_cache::bench::misses(4, "cached_checksum", || cached_checksum(1000));
+    assert_eq!(calls(), 4);
+
+    // Only the value stored before timing is computed.
+    smart_cache::bench::hits(4, "cached_checksum", || cached_checksum(1000));
+    assert_eq!(calls(), 1);
+
+    let mut harness = Harness::default();
+    smart_cache::bench::compare!(
+        &mut harness,
+        "cached_checksum",
+        || cached_checksum(1000),
+        || checksum(1000),
+    );
+    assert_eq!(
+        harness.ran,
+        [
+            "cached_checksum/uncached",
+            "cached_checksum/miss",
+            "cached_checksum/hit"
+        ]
+    );
+    assert_eq!(calls(), 3 + 3 + 1);
+}
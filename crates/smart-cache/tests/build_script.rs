@@ -0,0 +1,37 @@
+use std::{
+    env, fs, process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn generate(schema: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("pub const SCHEMA: &str = {schema:?};")
+}
+
+#[test]
+fn build_scripts_cache_in_the_target_directory() {
+    let target = env::temp_dir().join(format!("smart-cache-build-{}", process::id()));
+    let out_dir = target.join("debug/build/codegen-0123456789abcdef/out");
+    fs::create_dir_all(&out_dir).unwrap();
+    // What Cargo sets for build scripts.
+    env::set_var("OUT_DIR", &out_dir);
+    env::set_var("CARGO_PKG_NAME", "codegen");
+
+    let store = target.join("debug/smart-cache/codegen.redb");
+    assert_eq!(smart_cache::build::store_path().unwrap(), store);
+    smart_cache::build::install().unwrap();
+
+    let generated = generate("type Query".to_string());
+    assert_eq!(generate("type Query".to_string()), generated);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    smart_cache::flush();
+    assert!(store.exists());
+    assert_eq!(smart_cache::functions().unwrap()[0].name, "generate");
+
+    fs::remove_dir_all(target).unwrap();
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use smart_cache::{backend::MemoryBackend, cached, key_bytes, CacheKeyPart};
+
+/// Not archivable, and not part of what identifies a query.
+struct Client {
+    queries: AtomicUsize,
+}
+
+#[derive(CacheKeyPart)]
+struct Query<'a> {
+    table: String,
+    filters: HashMap<String, i64>,
+    #[cache_key(skip)]
+    client: &'a Client,
+}
+
+#[derive(CacheKeyPart)]
+enum Shape {
+    Circle { radius: u32 },
+    Rect(u32, u32),
+    Empty,
+}
+
+#[cached]
+fn count(#[cache_key] query: &Query<'_>, limit: u32) -> u64 {
+    query.client.queries.fetch_add(1, Ordering::SeqCst);
+    u64::from(limit)
+        + query
+            .filters
+            .values()
+            .map(|v| v.unsigned_abs())
+            .sum::<u64>()
+}
+
+static AREAS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn area(#[cache_key] shape: Shape) -> u64 {
+    AREAS.fetch_add(1, Ordering::SeqCst);
+    match shape {
+        Shape::Circle { radius } => 3 * u64::from(radius).pow(2),
+        Shape::Rect(w, h) => u64::from(w) * u64::from(h),
+        Shape::Empty => 0,
+    }
+}
+
+#[test]
+fn arguments_are_keyed_by_what_identifies_them() {
+    smart_cache::Config::default()
+        .store_name("cache-key-part")
+        .backend("cache-key-part", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    let first = Client {
+        queries: AtomicUsize::new(0),
+    };
+    let second = Client {
+        queries: AtomicUsize::new(0),
+    };
+    let query = |client, filters: &[(&str, i64)]| Query {
+        table: "users".to_string(),
+        filters: filters
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), *v))
+            .collect(),
+        client,
+    };
+
+    assert_eq!(count(&query(&first, &[("age", 30), ("id", -2)]), 10), 42);
+    // Another client, and filters inserted in another order, make the same key.
+    assert_eq!(count(&query(&second, &[("id", -2), ("age", 30)]), 10), 42);
+    assert_eq!(first.queries.load(Ordering::SeqCst), 1);
+    assert_eq!(second.queries.load(Ordering::SeqCst), 0);
+    // Other arguments are still part of the key.
+    assert_eq!(count(&query(&second, &[("age", 30), ("id", -2)]), 11), 43);
+    assert_eq!(second.queries.load(Ordering::SeqCst), 1);
+
+    assert_eq!(area(Shape::Rect(2, 3)), 6);
+    assert_eq!(area(Shape::Rect(3, 2)), 6);
+    assert_eq!(area(Shape::Circle { radius: 2 }), 12);
+    assert_eq!(area(Shape::Empty), 0);
+    assert_eq!(area(Shape::Rect(2, 3)), 6);
+    assert_eq!(AREAS.load(Ordering::SeqCst), 4);
+}
+
+#[derive(CacheKeyPart)]
+struct Before {
+    a: u32,
+    b: String,
+}
+
+#[derive(CacheKeyPart)]
+struct After {
+    b: String,
+    a: u32,
+}
+
+#[test]
+fn reordering_fields_keeps_the_encoding() {
+    let before = Before {
+        a: 1,
+        b: "x".to_string(),
+    };
+    let after = After {
+        b: "x".to_string(),
+        a: 1,
+    };
+    assert_eq!(key_bytes(&before), key_bytes(&after));
+    assert_ne!(key_bytes(&1.5_f64), key_bytes(&2.5_f64));
+    assert_eq!(key_bytes(&0.0_f64), key_bytes(&-0.0_f64));
+    assert_ne!(key_bytes(&("ab", "c")), key_bytes(&("a", "bc")));
+}
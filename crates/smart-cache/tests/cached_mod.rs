@@ -0,0 +1,90 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend},
+    cached_mod,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached_mod(db = "pipeline")]
+mod pipeline {
+    use std::sync::atomic::Ordering;
+
+    use smart_cache::cached;
+
+    use super::CALLS;
+
+    pub fn load(path: String) -> Vec<u32> {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        path.bytes().map(u32::from).collect()
+    }
+
+    pub fn total(values: Vec<u32>) -> u32 {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        values.iter().sum()
+    }
+
+    #[cached(db = "raw")]
+    pub fn checksum(values: Vec<u32>) -> u32 {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        values
+            .iter()
+            .fold(0, |sum, value| sum.rotate_left(5) ^ value)
+    }
+
+    #[cached(skip)]
+    pub fn now_ms() -> u128 {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        0
+    }
+
+    // Returns nothing, so there is nothing to cache.
+    pub fn log(message: &str) {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        let _ = message;
+    }
+}
+
+fn names(backend: &MemoryBackend) -> Vec<String> {
+    let mut names: Vec<_> = backend
+        .functions()
+        .unwrap()
+        .into_iter()
+        .map(|function| function.name)
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn every_function_of_the_module_is_cached() {
+    let pipeline = Arc::new(MemoryBackend::new());
+    let raw = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .backend("pipeline", Arc::clone(&pipeline))
+        .backend("raw", Arc::clone(&raw))
+        .install()
+        .unwrap();
+
+    for _ in 0..2 {
+        let values = pipeline::load("abc".to_string());
+        assert_eq!(pipeline::total(values.clone()), 294);
+        pipeline::checksum(values);
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    // Opted out, or not eligible.
+    for _ in 0..2 {
+        pipeline::now_ms();
+        pipeline::log("done");
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 7);
+
+    // The function's own options override the module's.
+    assert_eq!(names(&pipeline), ["load", "total"]);
+    assert_eq!(names(&raw), ["checksum"]);
+}
@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, WriteEntry},
+    cached, Function,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn total(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x + 1
+}
+
+fn stored(memory: &MemoryBackend, function: &Function) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut stored = Vec::new();
+    memory
+        .for_each_function_entry(function.hash(), &mut |key, entry| {
+            stored.push((key.to_vec(), entry.to_vec()));
+        })
+        .unwrap();
+    stored
+}
+
+#[test]
+fn corrupted_values_are_recomputed() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("checksum")
+        .backend("checksum", Arc::clone(&memory))
+        .install()
+        .unwrap();
+
+    assert_eq!(total(1), 2);
+    let function = smart_cache::functions().unwrap().remove(0);
+    let descriptor = Function::new("total", function.hash);
+    let [(key, mut entry)] = <[_; 1]>::try_from(stored(&memory, &descriptor)).unwrap();
+    let valid = entry.clone();
+
+    // Bit rot in the value, past the checksum that covers it.
+    *entry.last_mut().unwrap() ^= 0xff;
+    memory
+        .insert_batch(&[WriteEntry {
+            function: &descriptor,
+            key: &key,
+            entry: &entry,
+        }])
+        .unwrap();
+
+    assert_eq!(total(1), 2);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    // The corrupted entry was replaced by the recomputed value, which is served from then on.
+    let [(_, rewritten)] = <[_; 1]>::try_from(stored(&memory, &descriptor)).unwrap();
+    assert_ne!(rewritten, entry);
+    assert_eq!(rewritten[80..], valid[80..]);
+    assert_eq!(total(1), 2);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
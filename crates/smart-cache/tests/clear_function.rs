@@ -0,0 +1,18 @@
+use smart_cache_macro::cached;
+
+#[cached]
+fn shout(s: String) -> String {
+    s.to_uppercase()
+}
+
+#[test]
+fn clear_function_drops_all_entries() {
+    assert_eq!(shout("hello".to_string()), "HELLO");
+
+    let stored = smart_cache::functions().unwrap();
+    assert!(stored.iter().any(|f| f.name == "shout" && f.entries > 0));
+
+    assert!(smart_cache::clear_function("shout").unwrap() >= 1);
+    let stored = smart_cache::functions().unwrap();
+    assert!(!stored.iter().any(|f| f.name == "shout"));
+}
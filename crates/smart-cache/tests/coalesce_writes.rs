@@ -0,0 +1,86 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use eyre::Result;
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, StoredEntry, WriteEntry},
+    cached, Function, FunctionHash, FunctionInfo,
+};
+
+/// A memory backend recording the size of every batch written to it.
+#[derive(Default)]
+struct Recording {
+    inner: MemoryBackend,
+    batches: Mutex<Vec<usize>>,
+}
+
+impl CacheBackend for Recording {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        self.inner.get(function, key)
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        self.batches.lock().unwrap().push(entries.len());
+        self.inner.insert_batch(entries)
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        self.inner.remove(function, key)
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        self.inner.remove_prefix(prefix)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        self.inner.functions()
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.inner.clear_function(function)
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.inner.for_each_key(f)
+    }
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn writes_are_committed_in_batches() {
+    let backend = Arc::new(Recording::default());
+    smart_cache::Config::default()
+        .store_name("recording")
+        .backend("recording", Arc::clone(&backend) as Arc<dyn CacheBackend>)
+        .coalesce_writes(4, Duration::from_millis(200))
+        .install()
+        .unwrap();
+
+    for x in 0..10 {
+        assert_eq!(square(x), x * x);
+    }
+    // Full batches are committed right away, the rest once the delay has passed.
+    thread::sleep(Duration::from_secs(1));
+    assert_eq!(*backend.batches.lock().unwrap(), [4, 4, 2]);
+
+    // Everything was written, so nothing is computed again.
+    for x in 0..10 {
+        assert_eq!(square(x), x * x);
+    }
+    smart_cache::flush();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 10);
+    assert_eq!(*backend.batches.lock().unwrap(), [4, 4, 2]);
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smart_cache::compat::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(result = true)]
+fn checked_half(x: u32) -> Result<u32, String> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    if x.is_multiple_of(2) {
+        Ok(x / 2)
+    } else {
+        Err(format!("{x} is odd"))
+    }
+}
+
+#[test]
+fn only_ok_results_are_stored() {
+    smart_cache::Config::default()
+        .store_name("compat")
+        .backend("compat", smart_cache::backend::MemoryBackend::new())
+        .install()
+        .unwrap();
+
+    assert_eq!(checked_half(8), Ok(4));
+    assert_eq!(checked_half(8), Ok(4));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    assert!(checked_half(7).is_err());
+    assert!(checked_half(7).is_err());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,8 @@
+use smart_cache::cached;
+
+#[cached(prefetch)]
+async fn fetch(id: u32) -> u32 {
+    id
+}
+
+fn main() {}
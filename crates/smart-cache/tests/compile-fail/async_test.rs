@@ -0,0 +1,4 @@
+#[smart_cache::test]
+async fn fetches() {}
+
+fn main() {}
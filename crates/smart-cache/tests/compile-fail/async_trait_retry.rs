@@ -0,0 +1,25 @@
+use std::{future::Future, pin::Pin};
+
+use smart_cache::cached;
+
+struct Remote;
+
+impl Remote {
+    #[cached(self_is_context, retry = 2)]
+    fn fetch<'life0, 'async_trait>(
+        &'life0 self,
+        id: u32,
+    ) -> Pin<Box<dyn Future<Output = Option<u32>> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+    {
+        Box::pin(async move {
+            let __self = self;
+            let id = id;
+            let __ret: Option<u32> = Some(id);
+            __ret
+        })
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use smart_cache::compat::cached;
+
+#[cached(key = "u32", convert = r#"{ x }"#)]
+fn double(x: u32) -> u32 {
+    x * 2
+}
+
+fn main() {
+    double(1);
+}
@@ -0,0 +1,10 @@
+use smart_cache::compat::cached;
+
+#[cached(size = 50, time = 3600)]
+fn label(id: u32) -> String {
+    format!("label-{id}")
+}
+
+fn main() {
+    label(1);
+}
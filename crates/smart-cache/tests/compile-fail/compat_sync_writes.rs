@@ -0,0 +1,10 @@
+use smart_cache::compat::cached;
+
+#[cached(time = 3600, sync_writes = true)]
+fn label(id: u32) -> String {
+    format!("label-{id}")
+}
+
+fn main() {
+    label(1);
+}
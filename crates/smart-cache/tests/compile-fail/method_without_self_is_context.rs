@@ -0,0 +1,12 @@
+use smart_cache::cached;
+
+struct Parser;
+
+impl Parser {
+    #[cached]
+    fn parse(&self, input: String) -> usize {
+        input.len()
+    }
+}
+
+fn main() {}
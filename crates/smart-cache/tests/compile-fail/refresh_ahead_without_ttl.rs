@@ -0,0 +1,10 @@
+use smart_cache_macro::cached;
+
+#[cached(refresh_ahead)]
+fn latest(x: u32) -> u32 {
+    x
+}
+
+fn main() {
+    latest(1);
+}
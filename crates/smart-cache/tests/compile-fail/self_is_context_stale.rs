@@ -0,0 +1,12 @@
+use smart_cache::cached;
+
+struct Parser;
+
+impl Parser {
+    #[cached(self_is_context, ttl = "1m", stale_for = "1h")]
+    fn parse(&mut self, input: String) -> usize {
+        input.len()
+    }
+}
+
+fn main() {}
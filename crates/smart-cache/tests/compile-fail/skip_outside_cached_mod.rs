@@ -0,0 +1,8 @@
+use smart_cache::cached;
+
+#[cached(skip)]
+fn load(path: String) -> usize {
+    path.len()
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use smart_cache_macro::cached;
+
+#[cached(stale_for = "1h")]
+fn latest(x: u32) -> u32 {
+    x
+}
+
+fn main() {
+    latest(1);
+}
@@ -0,0 +1,8 @@
+use smart_cache::cached;
+
+#[cached(timeout = "30s", on_timeout = "stale")]
+fn fetch(id: u32) -> u32 {
+    id
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use smart_cache_macro::cached;
+
+#[cached(retry = 3, backoff = "fibonacci")]
+fn fetch(x: u32) -> Result<u32, String> {
+    Ok(x)
+}
+
+fn main() {
+    let _ = fetch(1);
+}
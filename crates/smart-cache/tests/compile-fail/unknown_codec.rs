@@ -0,0 +1,10 @@
+use smart_cache_macro::cached;
+
+#[cached(codec = "bincode")]
+fn double(x: u32) -> u32 {
+    x * 2
+}
+
+fn main() {
+    let _ = double(1);
+}
@@ -0,0 +1,10 @@
+use smart_cache_macro::cached;
+
+#[cached(colour = "blue")]
+fn paint(x: u32) -> u32 {
+    x
+}
+
+fn main() {
+    paint(1);
+}
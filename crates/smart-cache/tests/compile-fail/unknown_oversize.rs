@@ -0,0 +1,10 @@
+use smart_cache_macro::cached;
+
+#[cached(max_size = 1024, oversize = "truncate")]
+fn render(x: u32) -> Vec<u8> {
+    vec![0; x as usize]
+}
+
+fn main() {
+    render(1);
+}
@@ -0,0 +1,14 @@
+use smart_cache_macro::cached;
+
+struct Session;
+
+impl Session {
+    #[cached(self_is_context, warm)]
+    fn finish(self, code: u32) -> u32 {
+        code
+    }
+}
+
+fn main() {
+    Session.finish(0);
+}
@@ -0,0 +1,129 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static FORECASTS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn geocode(address: u32) -> (u32, u32) {
+    let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+    PEAK.fetch_max(running, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(50));
+    RUNNING.fetch_sub(1, Ordering::SeqCst);
+    (address, address * 2)
+}
+
+#[cached(ttl = "1s", stale_for = "1h")]
+fn forecast(city: u32) -> u32 {
+    let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+    PEAK.fetch_max(running, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(50));
+    RUNNING.fetch_sub(1, Ordering::SeqCst);
+    FORECASTS.fetch_add(1, Ordering::SeqCst);
+    city
+}
+
+#[cached]
+fn route(from: u32, to: u32) -> u32 {
+    // Nested calls run under the caller's permit rather than waiting for another.
+    geocode(from).1 + geocode(to).1
+}
+
+#[cached]
+async fn parse(source: u32) -> u32 {
+    source + 1
+}
+
+#[cached]
+async fn check(source: u32) -> u32 {
+    parse(source).await * 2
+}
+
+#[cached]
+async fn compile(source: u32) -> u32 {
+    // Nested deeper than the limit, sharing the caller's permit all the way down.
+    check(source).await + parse(source + 1).await
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn computations_beyond_the_limit_wait() {
+    smart_cache::Config::default()
+        .store_name("concurrency-limit")
+        .backend("concurrency-limit", Arc::new(MemoryBackend::new()))
+        .max_concurrent_computations(2)
+        .install()
+        .unwrap();
+
+    let threads: Vec<_> = (0..8)
+        .map(|address| thread::spawn(move || geocode(address)))
+        .collect();
+    for (address, thread) in (0..).zip(threads) {
+        assert_eq!(thread.join().unwrap(), (address, address * 2));
+    }
+    assert_eq!(PEAK.load(Ordering::SeqCst), 2);
+
+    let threads: Vec<_> = (0..4)
+        .map(|from| thread::spawn(move || route(from + 100, from + 200)))
+        .collect();
+    for (from, thread) in (0..).zip(threads) {
+        assert_eq!(thread.join().unwrap(), (from + 100) * 2 + (from + 200) * 2);
+    }
+    assert_eq!(PEAK.load(Ordering::SeqCst), 2);
+
+    for city in 0..8 {
+        forecast(city);
+    }
+    thread::sleep(Duration::from_millis(1500));
+    // Each stale value is recomputed on a thread of its own, which waits for a permit too.
+    for city in 0..8 {
+        assert_eq!(forecast(city), city);
+    }
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while FORECASTS.load(Ordering::SeqCst) < 16 {
+        assert!(
+            Instant::now() < deadline,
+            "stale values were never recomputed"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(PEAK.load(Ordering::SeqCst), 2);
+
+    let threads: Vec<_> = (0..4)
+        .map(|source| thread::spawn(move || block_on(compile(source))))
+        .collect();
+    for (source, thread) in (0..).zip(threads) {
+        assert_eq!(thread.join().unwrap(), (source + 1) * 2 + source + 2);
+    }
+}
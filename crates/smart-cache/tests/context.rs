@@ -0,0 +1,36 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn experiment(seed: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    seed * 2
+}
+
+#[test]
+fn context_applies_namespace_and_ttl_until_dropped() {
+    experiment(0);
+    smart_cache::purge_scope("experiment-ttl").unwrap();
+    let calls = CALLS.load(Ordering::SeqCst);
+
+    {
+        let _ctx = smart_cache::context()
+            .namespace("experiment-ttl")
+            .ttl(Duration::from_secs(1))
+            .enter();
+        assert_eq!(experiment(21), 42);
+        assert_eq!(experiment(21), 42);
+        assert_eq!(CALLS.load(Ordering::SeqCst), calls + 1);
+
+        thread::sleep(Duration::from_millis(2100));
+        experiment(21);
+        assert_eq!(CALLS.load(Ordering::SeqCst), calls + 2);
+    }
+}
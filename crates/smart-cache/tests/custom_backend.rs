@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use eyre::Result;
+use smart_cache::{
+    backend::{CacheBackend, StoredEntry, WriteEntry},
+    Function, FunctionHash, FunctionInfo,
+};
+use smart_cache_macro::cached;
+
+type Entries = HashMap<(FunctionHash, Vec<u8>), Arc<[u8]>>;
+
+#[derive(Default)]
+struct MemoryBackend {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl CacheBackend for MemoryBackend {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&(*function.hash(), key.to_vec()));
+        Ok(entry.map(|entry| Arc::clone(entry).into()))
+    }
+
+    fn insert_batch(&self, batch: &[WriteEntry<'_>]) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        for entry in batch {
+            let key = (*entry.function.hash(), entry.key.to_vec());
+            entries.insert(key, entry.entry.into());
+        }
+        Ok(())
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(&(*function.hash(), key.to_vec()));
+        Ok(())
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|(_, key), _| !key.starts_with(prefix));
+        Ok((before - entries.len()) as u64)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        Ok(Vec::new())
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(hash, _), _| hash != function);
+        Ok(())
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        for (_, key) in self.entries.lock().unwrap().keys() {
+            f(key);
+        }
+        Ok(())
+    }
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn cube(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x * x
+}
+
+#[test]
+fn custom_backend_serves_the_configured_store() {
+    let backend = MemoryBackend::default();
+    let entries = Arc::clone(&backend.entries);
+    smart_cache::Config::default()
+        .store_name("memory")
+        .backend("memory", backend)
+        .install()
+        .unwrap();
+
+    assert_eq!(cube(3), 27);
+    assert_eq!(cube(3), 27);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(entries.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,54 @@
+use std::{fs, process, sync::Arc, thread, time::Duration};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached(ttl = "1s")]
+fn quote(symbol: String) -> u64 {
+    symbol.len() as u64
+}
+
+#[test]
+fn decisions_are_logged_as_json_lines() {
+    let path = std::env::temp_dir().join(format!("smart-cache-decisions-{}.jsonl", process::id()));
+    smart_cache::Config::default()
+        .store_name("decision-log")
+        .backend("decision-log", Arc::new(MemoryBackend::new()))
+        .decision_log(&path)
+        .install()
+        .unwrap();
+
+    quote("ACME".to_string());
+    quote("ACME".to_string());
+    thread::sleep(Duration::from_millis(1100));
+    quote("ACME".to_string());
+
+    let log = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let decisions: Vec<_> = log
+        .lines()
+        .map(|line| {
+            assert!(line.starts_with('{') && line.ends_with('}'), "{line}");
+            assert!(line.contains("\"function\":\"quote\""), "{line}");
+            let field = |name: &str| {
+                let value = line.split(&format!("\"{name}\":\"")).nth(1).unwrap();
+                value[..value.find('"').unwrap()].to_string()
+            };
+            (field("decision"), field("reason"))
+        })
+        .collect();
+    let decisions: Vec<_> = decisions
+        .iter()
+        .map(|(decision, reason)| (decision.as_str(), reason.as_str()))
+        .collect();
+    assert_eq!(
+        decisions,
+        [
+            ("miss", "absent"),
+            ("store", "computed"),
+            ("hit", "store"),
+            ("miss", "expired"),
+            ("store", "computed"),
+        ]
+    );
+    assert!(log.lines().nth(1).unwrap().contains("\"size\":"));
+}
@@ -0,0 +1,69 @@
+use std::{path::Path, process, sync::Arc};
+
+use redb::{Database, ReadableTableMetadata, TableDefinition};
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, RedbBackend, WriteEntry},
+    cached, Function,
+};
+
+const BLOBS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("blobs");
+
+#[cached]
+fn report(id: u64) -> Vec<u8> {
+    let _ = id;
+    vec![7; 4096]
+}
+
+fn blob_count(path: &Path) -> u64 {
+    let db = Database::open(path).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    read_txn.open_table(BLOBS).unwrap().len().unwrap()
+}
+
+#[test]
+fn identical_values_are_stored_once() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("deduplicate")
+        .backend("deduplicate", Arc::clone(&memory))
+        .install()
+        .unwrap();
+
+    for id in 1..=3 {
+        report(id);
+    }
+    let hash = smart_cache::functions().unwrap()[0].hash;
+    let mut entries = Vec::new();
+    memory
+        .for_each_function_entry(&hash, &mut |key, entry| {
+            entries.push((key.to_vec(), entry.to_vec()));
+        })
+        .unwrap();
+    assert_eq!(entries.len(), 3);
+
+    let path = std::env::temp_dir().join(format!("smart-cache-dedup-{}.redb", process::id()));
+    let function = Function::new("report", hash);
+    let backend = RedbBackend::open(&path, 1).unwrap().deduplicate(1024);
+    let batch: Vec<_> = entries
+        .iter()
+        .map(|(key, entry)| WriteEntry {
+            function: &function,
+            key,
+            entry,
+        })
+        .collect();
+    backend.insert_batch(&batch).unwrap();
+    for (key, entry) in &entries {
+        assert_eq!(&*backend.get(&function, key).unwrap().unwrap(), &entry[..]);
+    }
+    backend.remove(&function, &entries[0].0).unwrap();
+    drop(backend);
+    assert_eq!(blob_count(&path), 1);
+
+    let backend = RedbBackend::open(&path, 1).unwrap();
+    backend.clear_function(&hash).unwrap();
+    drop(backend);
+    assert_eq!(blob_count(&path), 0);
+
+    std::fs::remove_file(&path).unwrap();
+}
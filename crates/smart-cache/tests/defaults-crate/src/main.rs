@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn calls() -> usize {
+    CALLS.swap(0, Ordering::SeqCst)
+}
+
+// `max_size = 16`: longer values aren't stored.
+#[cached]
+fn pad(text: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("{text:>32}")
+}
+
+#[cached(max_size = 1024)]
+fn pad_stored(text: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("{text:>32}")
+}
+
+// `warm = true`: `square_warm` is generated.
+#[cached]
+fn square(n: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    n * n
+}
+
+// `warm = false` overrides the default, leaving the name free.
+#[cached(warm = false)]
+fn cube(n: u64) -> u64 {
+    n * n * n
+}
+
+#[allow(dead_code)]
+fn cube_warm() {}
+
+fn main() {
+    smart_cache::Config::default()
+        .store_name("defaults")
+        .backend("defaults", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    pad("a".to_string());
+    pad("a".to_string());
+    assert_eq!(calls(), 2);
+    pad_stored("a".to_string());
+    pad_stored("a".to_string());
+    assert_eq!(calls(), 1);
+
+    square_warm(1..=3);
+    assert_eq!(calls(), 3);
+    assert_eq!(square(3), 9);
+    assert_eq!(calls(), 0);
+    assert_eq!(cube(2), 8);
+}
@@ -0,0 +1,17 @@
+use std::{path::Path, process::Command};
+
+#[test]
+fn manifest_defaults_apply_below_function_options() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/defaults-crate");
+    // Its own target directory, as the build running this test holds the workspace's.
+    let status = Command::new(env!("CARGO"))
+        .args(["run", "--offline", "--quiet", "--manifest-path"])
+        .arg(fixture.join("Cargo.toml"))
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join("defaults"),
+        )
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
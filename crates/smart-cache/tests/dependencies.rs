@@ -0,0 +1,141 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use sha2::{Digest, Sha256};
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, WriteEntry},
+    cached, Function,
+};
+
+static TOTALS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn price(item: u64) -> u64 {
+    item * 10
+}
+
+#[cached]
+fn total(items: u64) -> u64 {
+    TOTALS.fetch_add(1, Ordering::SeqCst);
+    (1..=items).map(price).sum()
+}
+
+mod domestic {
+    #[smart_cache::cached]
+    pub fn rate(item: u64) -> u64 {
+        item
+    }
+}
+
+mod export {
+    #[smart_cache::cached]
+    pub fn rate(item: u64) -> u64 {
+        item * 2
+    }
+}
+
+static INVOICES: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn invoice(items: u64) -> u64 {
+    INVOICES.fetch_add(1, Ordering::SeqCst);
+    (1..=items).map(domestic::rate).sum()
+}
+
+/// Replaces the hash recorded for the first dependency of a stored entry, keeping the envelope
+/// valid, as if the entry had been computed by another version of that function.
+fn with_stale_dependency(entry: &[u8]) -> Vec<u8> {
+    let mut hash: [u8; 32] = entry[80..112].try_into().unwrap();
+    hash[0] ^= 0xff;
+    with_dependency(entry, &hash)
+}
+
+/// Replaces the hash recorded for the first dependency of a stored entry with `hash`, keeping
+/// the envelope valid.
+fn with_dependency(entry: &[u8], hash: &[u8; 32]) -> Vec<u8> {
+    let mut entry = entry.to_vec();
+    entry[80..112].copy_from_slice(hash);
+    let checksum: [u8; 32] = Sha256::new()
+        .chain_update(b"smart-cache entry v6")
+        .chain_update(&entry[32..])
+        .finalize()
+        .into();
+    entry[..32].copy_from_slice(&checksum);
+    entry
+}
+
+#[test]
+fn entries_are_recomputed_when_a_dependency_changes() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("dependencies")
+        .backend("dependencies", Arc::clone(&memory))
+        .install()
+        .unwrap();
+
+    assert_eq!(total(3), 60);
+    assert_eq!(total(3), 60);
+    assert_eq!(TOTALS.load(Ordering::SeqCst), 1);
+
+    let function = smart_cache::functions()
+        .unwrap()
+        .into_iter()
+        .find(|function| function.name == "total")
+        .unwrap();
+    let mut stored = Vec::new();
+    memory
+        .for_each_function_entry(&function.hash, &mut |key, entry| {
+            stored.push((key.to_vec(), entry.to_vec()));
+        })
+        .unwrap();
+    let (key, entry) = stored.remove(0);
+    assert!(entry.windows(5).any(|window| window == b"price"));
+
+    let descriptor = Function::new("total", function.hash);
+    memory
+        .insert_batch(&[WriteEntry {
+            function: &descriptor,
+            key: &key,
+            entry: &with_stale_dependency(&entry),
+        }])
+        .unwrap();
+
+    assert_eq!(total(3), 60);
+    assert_eq!(TOTALS.load(Ordering::SeqCst), 2);
+
+    // A function of the same name in another module is another function: an entry derived from
+    // `domestic::rate` with `export::rate`'s hash is stale.
+    assert_eq!(invoice(2), 3);
+    assert_eq!(export::rate(1), 2);
+    let functions = smart_cache::functions().unwrap();
+    // `domestic::rate` has two entries, `export::rate` one.
+    let hash = |name: &str, entries: u64| {
+        functions
+            .iter()
+            .find(|function| function.name == name && function.entries == entries)
+            .unwrap()
+            .hash
+    };
+    let invoice_hash = hash("invoice", 1);
+    let export_hash = hash("rate", 1);
+    let mut stored = Vec::new();
+    memory
+        .for_each_function_entry(&invoice_hash, &mut |key, entry| {
+            stored.push((key.to_vec(), entry.to_vec()));
+        })
+        .unwrap();
+    let (key, entry) = stored.remove(0);
+    let descriptor = Function::new("invoice", invoice_hash);
+    memory
+        .insert_batch(&[WriteEntry {
+            function: &descriptor,
+            key: &key,
+            entry: &with_dependency(&entry, &export_hash),
+        }])
+        .unwrap();
+
+    assert_eq!(invoice(2), 3);
+    assert_eq!(INVOICES.load(Ordering::SeqCst), 2);
+}
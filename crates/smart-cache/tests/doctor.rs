@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, WriteEntry},
+    cached, DoctorReport, Function,
+};
+
+#[cached]
+fn greeting(name: String) -> String {
+    format!("hello {name}")
+}
+
+/// Replaces the value of a stored entry with `value`, keeping the envelope valid.
+fn with_value(entry: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut entry = entry[..80].to_vec();
+    entry.extend_from_slice(value);
+    let checksum: [u8; 32] = Sha256::new()
+        .chain_update(b"smart-cache entry v6")
+        .chain_update(&entry[32..])
+        .finalize()
+        .into();
+    entry[..32].copy_from_slice(&checksum);
+    entry
+}
+
+#[test]
+fn doctor_finds_and_removes_broken_entries() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("doctor")
+        .backend("doctor", Arc::clone(&memory))
+        .install()
+        .unwrap();
+
+    assert_eq!(greeting("ada".to_string()), "hello ada");
+    let function = smart_cache::functions().unwrap().remove(0);
+    let mut stored = Vec::new();
+    memory
+        .for_each_function_entry(&function.hash, &mut |_, entry| stored.push(entry.to_vec()))
+        .unwrap();
+
+    let descriptor = Function::new("greeting", function.hash);
+    // Too short to hold an archived `String`.
+    let garbled = with_value(&stored[0], &[0xff; 3]);
+    memory
+        .insert_batch(&[
+            WriteEntry {
+                function: &descriptor,
+                key: b"corrupt",
+                entry: b"not an entry",
+            },
+            WriteEntry {
+                function: &descriptor,
+                key: b"garbled",
+                entry: &garbled,
+            },
+        ])
+        .unwrap();
+
+    let report = smart_cache::doctor(false).unwrap();
+    assert_eq!(
+        report,
+        DoctorReport {
+            checked: 3,
+            corrupt: 1,
+            undeserializable: 1,
+            ..DoctorReport::default()
+        }
+    );
+
+    assert_eq!(smart_cache::doctor(true).unwrap().removed, 2);
+    let report = smart_cache::doctor(false).unwrap();
+    assert_eq!(report.checked, 1);
+    assert_eq!(report.removed, 0);
+    assert_eq!(greeting("ada".to_string()), "hello ada");
+}
@@ -0,0 +1,54 @@
+use std::{
+    fs, process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{
+    backend::{CacheBackend, RedbBackend, WriteEntry},
+    cached, Durability, Function,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn eventual_commits_are_served_and_persisted_by_flush() {
+    let dir = std::env::temp_dir().join(format!("smart-cache-durability-{}", process::id()));
+    smart_cache::Config::default()
+        .store_name("store")
+        .database("store", dir.join("store.redb"))
+        .durability(Durability::Eventual)
+        .install()
+        .unwrap();
+
+    // Values committed without an fsync are visible right away.
+    assert_eq!(square(12), 144);
+    assert_eq!(square(12), 144);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    smart_cache::flush();
+    assert_eq!(smart_cache::entries("square").unwrap().len(), 1);
+
+    // Flushing makes them durable, so they are still there when the file is opened again.
+    let path = dir.join("other.redb");
+    let function = Function::new("other", [7; 32]);
+    let backend = RedbBackend::open(&path, 1).unwrap();
+    backend
+        .insert_batch(&[WriteEntry {
+            function: &function,
+            key: b"key",
+            entry: b"entry",
+        }])
+        .unwrap();
+    backend.flush().unwrap();
+    drop(backend);
+
+    let backend = RedbBackend::open(&path, 1).unwrap();
+    assert_eq!(&*backend.get(&function, b"key").unwrap().unwrap(), b"entry");
+    drop(backend);
+    fs::remove_dir_all(dir).unwrap();
+}
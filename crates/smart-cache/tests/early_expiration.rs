@@ -0,0 +1,36 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicU64 = AtomicU64::new(0);
+
+#[cached(ttl = "1h")]
+fn expensive(n: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(50));
+    n * 2
+}
+
+#[test]
+fn expensive_values_are_recomputed_before_they_expire() {
+    smart_cache::Config::default()
+        .store_name("early-expiration")
+        .backend("early-expiration", Arc::new(MemoryBackend::new()))
+        // Large enough that 50ms of compute time reaches an hour ahead on almost every lookup.
+        .early_expiration(1e8)
+        .install()
+        .unwrap();
+
+    assert_eq!(expensive(21), 42);
+    for _ in 0..5 {
+        assert_eq!(expensive(21), 42);
+    }
+    assert!(CALLS.load(Ordering::SeqCst) > 1);
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached]
+fn greeting(name: String, excited: bool) -> String {
+    format!("hello {name}{}", if excited { "!" } else { "" })
+}
+
+#[test]
+fn entries_show_the_arguments_they_were_computed_from() {
+    smart_cache::Config::default()
+        .store_name("entries")
+        .backend("entries", Arc::new(MemoryBackend::new()))
+        .inspectable_keys(true)
+        .install()
+        .unwrap();
+
+    greeting("ada".to_string(), true);
+    greeting("grace".to_string(), false);
+
+    let entries = smart_cache::entries("greeting").unwrap();
+    assert_eq!(entries.len(), 2);
+    let mut keys: Vec<_> = entries
+        .iter()
+        .map(|entry| entry.debug_key.as_deref().unwrap())
+        .collect();
+    keys.sort_unstable();
+    assert!(
+        keys[0].contains("name: \"ada\", excited: true"),
+        "{}",
+        keys[0]
+    );
+    assert!(
+        keys[1].contains("name: \"grace\", excited: false"),
+        "{}",
+        keys[1]
+    );
+    assert!(entries
+        .iter()
+        .all(|entry| entry.bytes > 0 && entry.created_at.is_some()));
+
+    assert!(smart_cache::entries("missing").unwrap().is_empty());
+}
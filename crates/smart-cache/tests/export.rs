@@ -0,0 +1,54 @@
+use std::{
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use smart_cache::{cached, ExportFilter};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn squared(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[cached]
+fn cubed(x: u64) -> u64 {
+    x * x * x
+}
+
+#[test]
+fn imported_entries_are_hits() {
+    smart_cache::Config::default()
+        .store_name("export")
+        .backend("export", smart_cache::backend::MemoryBackend::new())
+        .install()
+        .unwrap();
+
+    assert_eq!(squared(3), 9);
+    assert_eq!(squared(4), 16);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    let path = std::env::temp_dir().join(format!("smart-cache-export-{}.archive", process::id()));
+    assert_eq!(smart_cache::export(&path).unwrap(), 2);
+
+    assert_eq!(smart_cache::clear_function("squared").unwrap(), 1);
+    assert!(smart_cache::functions().unwrap().is_empty());
+
+    assert_eq!(smart_cache::import(&path).unwrap(), 2);
+
+    assert_eq!(squared(3), 9);
+    assert_eq!(squared(4), 16);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    // Keys start with the arguments, and a lone `u64` serializes to its little-endian bytes.
+    assert_eq!(cubed(3), 27);
+    let filter = ExportFilter::function("squared")
+        .newer_than(Duration::from_secs(60 * 60))
+        .key(|key| key.starts_with(&3_u64.to_le_bytes()));
+    assert_eq!(smart_cache::export_filtered(&path, &filter).unwrap(), 1);
+    assert_eq!(smart_cache::import(&path).unwrap(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "ffi")]
+
+use std::{ffi::CString, process, ptr};
+
+use smart_cache::ffi::{
+    smart_cache_clear, smart_cache_free, smart_cache_get, smart_cache_open, smart_cache_set,
+    smart_cache_stats,
+};
+
+#[test]
+fn values_round_trip_through_the_c_abi() {
+    let path = std::env::temp_dir().join(format!("smart-cache-ffi-{}.redb", process::id()));
+    let path = CString::new(path.to_str().unwrap()).unwrap();
+    let name = CString::new("ffi_lookup").unwrap();
+    let key = b"input";
+    let (mut value, mut value_len) = (ptr::null_mut(), 0);
+
+    unsafe {
+        assert_eq!(smart_cache_open(path.as_ptr()), 0);
+        assert_eq!(smart_cache_open(path.as_ptr()), -1);
+
+        let get = |value: &mut *mut u8, value_len: &mut usize| {
+            smart_cache_get(
+                name.as_ptr(),
+                ptr::null(),
+                key.as_ptr(),
+                key.len(),
+                value,
+                value_len,
+            )
+        };
+        assert_eq!(get(&mut value, &mut value_len), 1);
+
+        let stored = b"output";
+        let set = smart_cache_set(
+            name.as_ptr(),
+            ptr::null(),
+            key.as_ptr(),
+            key.len(),
+            stored.as_ptr(),
+            stored.len(),
+        );
+        assert_eq!(set, 0);
+
+        assert_eq!(get(&mut value, &mut value_len), 0);
+        assert_eq!(std::slice::from_raw_parts(value, value_len), stored);
+        smart_cache_free(value, value_len);
+
+        assert_eq!(smart_cache_stats(&mut value, &mut value_len), 0);
+        let stats = String::from_utf8_lossy(std::slice::from_raw_parts(value, value_len));
+        assert!(stats.starts_with("ffi_lookup\t"));
+        smart_cache_free(value, value_len);
+
+        // Misses panic while replaying, which is reported rather than unwound into C.
+        smart_cache::mode(smart_cache::Mode::Replay);
+        let missing = b"missing";
+        let replayed = smart_cache_get(
+            name.as_ptr(),
+            ptr::null(),
+            missing.as_ptr(),
+            missing.len(),
+            &mut value,
+            &mut value_len,
+        );
+        assert_eq!(replayed, -1);
+        smart_cache::mode(smart_cache::Mode::Normal);
+
+        assert_eq!(smart_cache_clear(name.as_ptr()), 1);
+        assert_eq!(
+            smart_cache_get(
+                ptr::null(),
+                ptr::null(),
+                key.as_ptr(),
+                key.len(),
+                &mut value,
+                &mut value_len
+            ),
+            -1
+        );
+    }
+    std::fs::remove_file(path.to_str().unwrap()).unwrap();
+}
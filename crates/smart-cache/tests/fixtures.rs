@@ -0,0 +1,33 @@
+// The fixture was recorded with rkyv's default archive layout.
+#![cfg(not(feature = "portable"))]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smart_cache::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Stands in for a slow or networked computation; its results for 1 and 2 are in
+/// `tests/cache-fixtures/golden.archive`.
+#[cached]
+fn fetch_score(id: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    id * 100
+}
+
+#[test]
+fn fixtures_are_served_without_computing() {
+    smart_cache::Config::default()
+        .store_name("fixtures")
+        .backend("fixtures", smart_cache::backend::MemoryBackend::new())
+        .fixtures("tests/cache-fixtures")
+        .install()
+        .unwrap();
+
+    assert_eq!(fetch_score(1), 100);
+    assert_eq!(fetch_score(2), 200);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+
+    assert_eq!(fetch_score(3), 300);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,32 @@
+use std::{env, process, time::Duration};
+
+use smart_cache::{cached, Durability};
+
+#[cached(db = "flush")]
+fn square(x: u64) -> u64 {
+    x * x
+}
+
+#[test]
+fn flushing_persists_deferred_writes() {
+    let dir = env::temp_dir().join(format!("smart-cache-flush-{}", process::id()));
+    smart_cache::Config::default()
+        .database("flush", dir.join("flush.redb"))
+        .durability(Durability::Eventual)
+        // Committing once everything is computed keeps reads and commits from overlapping,
+        // which redb's debug assertions sometimes mistake for reusing pages still being read.
+        .coalesce_writes(64, Duration::from_secs(60))
+        .install()
+        .unwrap();
+
+    for x in 0..32 {
+        assert_eq!(square(x), x * x);
+    }
+    smart_cache::try_flush().unwrap();
+
+    let functions = smart_cache::functions().unwrap();
+    let square = functions.iter().find(|f| f.name == "square").unwrap();
+    assert_eq!(square.entries, 32);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,39 @@
+use std::{
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::backend::FsBackend;
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn reverse(s: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    s.chars().rev().collect()
+}
+
+#[test]
+fn entries_are_stored_as_files() {
+    let root = std::env::temp_dir().join(format!("smart-cache-fs-{}", process::id()));
+    smart_cache::Config::default()
+        .store_name("files")
+        .backend("files", FsBackend::open(&root).unwrap())
+        .install()
+        .unwrap();
+
+    assert_eq!(reverse("abc".to_string()), "cba");
+    assert_eq!(reverse("abc".to_string()), "cba");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let functions = smart_cache::functions().unwrap();
+    assert!(functions
+        .iter()
+        .any(|f| f.name == "reverse" && f.entries == 1));
+
+    assert_eq!(smart_cache::clear_function("reverse").unwrap(), 1);
+    assert!(smart_cache::functions().unwrap().is_empty());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,60 @@
+use std::{fs, process};
+
+use smart_cache::{
+    backend::{CacheBackend, RedbBackend, WriteEntry},
+    Function,
+};
+
+fn entries_of(backend: &RedbBackend, function: &Function) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut entries = Vec::new();
+    backend
+        .for_each_function_entry(function.hash(), &mut |key, entry| {
+            entries.push((key.to_vec(), entry.to_vec()));
+        })
+        .unwrap();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn functions_keep_their_entries_apart() {
+    let dir = std::env::temp_dir().join(format!("smart-cache-tables-{}", process::id()));
+    let backend = RedbBackend::open(&dir.join("store.redb"), 1).unwrap();
+    let kept = Function::new("kept", [1; 32]);
+    let cleared = Function::new("cleared", [2; 32]);
+
+    // The same keys, written by two functions.
+    let mut batch = Vec::new();
+    for function in [&kept, &cleared] {
+        for key in [&b"a"[..], b"b"] {
+            batch.push(WriteEntry {
+                function,
+                key,
+                entry: function.name().as_bytes(),
+            });
+        }
+    }
+    backend.insert_batch(&batch).unwrap();
+
+    let expected = |function: &Function| {
+        [b"a", b"b"].map(|key| (key.to_vec(), function.name().as_bytes().to_vec()))
+    };
+    assert_eq!(entries_of(&backend, &kept), expected(&kept));
+    assert_eq!(entries_of(&backend, &cleared), expected(&cleared));
+
+    // Clearing one function leaves the other's entries under the same keys alone.
+    backend.clear_function(cleared.hash()).unwrap();
+    assert!(entries_of(&backend, &cleared).is_empty());
+    assert!(backend.get(&cleared, b"a").unwrap().is_none());
+    assert_eq!(entries_of(&backend, &kept), expected(&kept));
+    let names: Vec<_> = backend
+        .functions()
+        .unwrap()
+        .into_iter()
+        .map(|function| function.name)
+        .collect();
+    assert_eq!(names, ["kept"]);
+
+    drop(backend);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,113 @@
+#![cfg(feature = "grpc")]
+
+use std::{
+    net::TcpListener,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use smart_cache::{
+    backend::{CacheBackend, GrpcBackend, GrpcServer, MemoryBackend, RedbBackend, WriteEntry},
+    Function,
+};
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn whisper(s: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    s.to_lowercase()
+}
+
+#[test]
+fn entries_round_trip_through_the_service() {
+    let root = std::env::temp_dir().join(format!("smart-cache-grpc-{}", process::id()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    smart_cache::Config::default()
+        .store_name("remote")
+        .backend("remote", GrpcBackend::new(&url).unwrap().token("secret"))
+        .install()
+        .unwrap();
+
+    let store = RedbBackend::open(&root.join("server.redb"), 1).unwrap();
+    let server = GrpcServer::new(store).token("secret");
+    thread::spawn(move || server.serve(&listener));
+
+    // A client without the token is turned away.
+    let anonymous = GrpcBackend::new(&url).unwrap();
+    let error = anonymous.functions().unwrap_err();
+    assert!(format!("{error:#}").contains("status 16"), "{error:#}");
+
+    assert_eq!(whisper("HEY".to_string()), "hey");
+    assert_eq!(whisper("HEY".to_string()), "hey");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    smart_cache::flush();
+    let stats = smart_cache::stats().unwrap();
+    let whisper_stats = stats.iter().find(|s| s.name == "whisper").unwrap();
+    assert_eq!((whisper_stats.calls, whisper_stats.hits), (2, 1));
+
+    let functions = smart_cache::functions().unwrap();
+    assert!(functions
+        .iter()
+        .any(|f| f.name == "whisper" && f.entries == 1));
+
+    assert_eq!(smart_cache::clear_function("whisper").unwrap(), 1);
+    assert!(smart_cache::functions().unwrap().is_empty());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn every_backend_call_maps_to_the_service() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = GrpcServer::new(MemoryBackend::new())
+        .max_message(1024)
+        .timeout(Duration::from_millis(200));
+    thread::spawn(move || server.serve(&listener));
+    let client = GrpcBackend::new(&url).unwrap();
+
+    let function = Function::new("f", [3; 32]);
+    let keys: [&[u8]; 3] = [b"apple", b"apricot", b"banana"];
+    let entries: Vec<_> = keys
+        .iter()
+        .map(|key| WriteEntry {
+            function: &function,
+            key,
+            entry: b"value",
+        })
+        .collect();
+    client.insert_batch(&entries).unwrap();
+    let entry = client.get(&function, b"apple").unwrap().unwrap();
+    assert_eq!(&*entry, b"value");
+    assert!(client.get(&function, b"cherry").unwrap().is_none());
+
+    let mut listed = Vec::new();
+    client
+        .for_each_key(&mut |key| listed.push(key.to_vec()))
+        .unwrap();
+    assert_eq!(listed.len(), 3);
+
+    // Idle connections are closed by the server, and the client reconnects.
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(client.remove_prefix(b"ap").unwrap(), 2);
+    client.remove(&function, b"banana").unwrap();
+    assert!(client.get(&function, b"banana").unwrap().is_none());
+
+    // Requests over the limit fail without taking the connection down with them.
+    let big = vec![0; 2048];
+    let error = client
+        .insert_batch(&[WriteEntry {
+            function: &function,
+            key: b"big",
+            entry: &big,
+        }])
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("status 11"), "{error:#}");
+    assert!(client.get(&function, b"big").unwrap().is_none());
+}
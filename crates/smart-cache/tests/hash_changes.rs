@@ -0,0 +1,33 @@
+#![cfg(target_os = "linux")]
+
+use std::{env, process, sync::Arc};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached]
+fn recorded(id: u64) -> u64 {
+    id + 1
+}
+
+#[test]
+fn first_run_reports_new_functions() {
+    // The manifest lives in the cache directory, which follows XDG_CACHE_HOME on Linux.
+    let home = env::temp_dir().join(format!("smart-cache-manifest-{}", process::id()));
+    env::set_var("XDG_CACHE_HOME", &home);
+    smart_cache::Config::default()
+        .store_name("manifest")
+        .backend("manifest", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert!(smart_cache::hash_changes().unwrap().is_empty());
+    assert_eq!(recorded(1), 2);
+    assert_eq!(recorded(2), 3);
+
+    let changes = smart_cache::hash_changes().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].name, "recorded");
+    assert_eq!(changes[0].previous, None);
+
+    let _ = std::fs::remove_dir_all(home);
+}
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use smart_cache::{backend::MemoryBackend, Function};
+
+static PRICES: Function = Function::new("prices", [7; 32]);
+
+#[test]
+fn earlier_values_are_kept() {
+    smart_cache::Config::default()
+        .store_name("history")
+        .backend("history", Arc::new(MemoryBackend::new()))
+        .keep_versions(3)
+        .install()
+        .unwrap();
+
+    assert!(smart_cache::history::<u64>(&PRICES, b"widget")
+        .unwrap()
+        .is_empty());
+    for price in [10_u64, 11, 12, 13] {
+        smart_cache::insert_archived(&PRICES, b"widget", &price).unwrap();
+    }
+
+    let versions = smart_cache::history::<u64>(&PRICES, b"widget").unwrap();
+    let prices: Vec<u64> = versions
+        .iter()
+        .map(|version| version.value.to_native())
+        .collect();
+    assert_eq!(prices, [13, 12, 11]);
+    assert!(versions[0].created_at >= versions[1].created_at);
+
+    // The current value is still what lookups see.
+    let current = smart_cache::get_archived::<u64>(&PRICES, b"widget")
+        .unwrap()
+        .unwrap();
+    assert_eq!(current.to_native(), 13);
+}
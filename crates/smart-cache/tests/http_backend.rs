@@ -0,0 +1,96 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use smart_cache::backend::{CacheBackend, HttpBackend, HttpServer, MemoryBackend, RedbBackend};
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn shout(s: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    s.to_uppercase()
+}
+
+#[test]
+fn entries_round_trip_through_the_server() {
+    let root = std::env::temp_dir().join(format!("smart-cache-http-{}", process::id()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    smart_cache::Config::default()
+        .store_name("remote")
+        .backend("remote", HttpBackend::new(&url).unwrap().token("secret"))
+        .install()
+        .unwrap();
+
+    let store = RedbBackend::open(&root.join("server.redb"), 1).unwrap();
+    let server = HttpServer::new(store).token("secret");
+    thread::spawn(move || server.serve(&listener));
+
+    // A client without the token is turned away.
+    let anonymous = HttpBackend::new(&url).unwrap();
+    assert!(anonymous.functions().is_err());
+
+    assert_eq!(shout("hey".to_string()), "HEY");
+    assert_eq!(shout("hey".to_string()), "HEY");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    smart_cache::flush();
+    let stats = smart_cache::stats().unwrap();
+    let shout_stats = stats.iter().find(|s| s.name == "shout").unwrap();
+    assert_eq!((shout_stats.calls, shout_stats.hits), (2, 1));
+
+    let functions = smart_cache::functions().unwrap();
+    assert!(functions
+        .iter()
+        .any(|f| f.name == "shout" && f.entries == 1));
+
+    assert_eq!(smart_cache::clear_function("shout").unwrap(), 1);
+    assert!(smart_cache::functions().unwrap().is_empty());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+/// Sends `head` (a request without its body) and returns the status line of the response.
+fn status(address: &str, head: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(head.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[test]
+fn the_server_bounds_what_clients_can_make_it_hold() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = HttpServer::new(MemoryBackend::new())
+        .token("secret")
+        .max_body(16)
+        .max_connections(2)
+        .timeout(Duration::from_millis(200));
+    thread::spawn(move || server.serve(&listener));
+
+    // Connections that stall fill the server up, then time out. Further ones are answered
+    // as soon as they are accepted.
+    let stalled = [(); 2].map(|()| TcpStream::connect(&address).unwrap());
+    assert_eq!(status(&address, ""), "HTTP/1.1 503 Service Unavailable");
+    thread::sleep(Duration::from_secs(1));
+    drop(stalled);
+    let head = "GET /v1/functions HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n";
+    assert_eq!(status(&address, head), "HTTP/1.1 200 OK");
+
+    // Without the token, the body is never read, however long it claims to be.
+    let put = format!("PUT /v1/entries/{}/00 HTTP/1.1\r\n", "00".repeat(32));
+    let head = format!("{put}Content-Length: 1000000000000\r\n\r\n");
+    assert_eq!(status(&address, &head), "HTTP/1.1 401 Unauthorized");
+
+    let head = format!("{put}Authorization: Bearer secret\r\nContent-Length: 17\r\n\r\n");
+    assert_eq!(status(&address, &head), "HTTP/1.1 413 Payload Too Large");
+}
@@ -0,0 +1,44 @@
+use std::cell::Cell;
+
+use smart_cache::cached;
+
+thread_local! {
+    // Tests run on threads of their own, so each counts only its own calls.
+    static CALLS: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cached]
+fn square(n: u64) -> u64 {
+    CALLS.set(CALLS.get() + 1);
+    n * n
+}
+
+#[smart_cache::test]
+fn first_test_starts_from_an_empty_store() {
+    assert_eq!(square(3), 9);
+    assert_eq!(square(3), 9);
+    assert_eq!(CALLS.get(), 1);
+}
+
+#[smart_cache::test]
+fn second_test_starts_from_an_empty_store() {
+    assert_eq!(square(3), 9);
+    assert_eq!(square(3), 9);
+    assert_eq!(CALLS.get(), 1);
+}
+
+#[test]
+fn each_isolated_store_is_fresh() {
+    {
+        let _isolated = smart_cache::testing::isolated();
+        square(4);
+        square(4);
+        assert_eq!(smart_cache::clear_function("square").unwrap(), 1);
+        square(4);
+    }
+    assert_eq!(CALLS.get(), 2);
+
+    let _isolated = smart_cache::testing::isolated();
+    square(4);
+    assert_eq!(CALLS.get(), 3);
+}
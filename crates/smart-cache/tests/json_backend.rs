@@ -0,0 +1,87 @@
+#![cfg(feature = "json")]
+
+use std::{
+    fs, process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use smart_cache::{backend::JsonBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Summary {
+    words: usize,
+    longest: String,
+}
+
+#[cached(with = "json")]
+fn summarize(text: String) -> Summary {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    Summary {
+        words: text.split_whitespace().count(),
+        longest: text
+            .split_whitespace()
+            .max_by_key(|word| word.len())
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+#[cached]
+fn shout(text: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    text.to_uppercase()
+}
+
+#[test]
+fn entries_are_stored_as_readable_json() {
+    let root = std::env::temp_dir().join(format!("smart-cache-json-{}", process::id()));
+    smart_cache::Config::default()
+        .store_name("json")
+        .backend("json", JsonBackend::open(&root).unwrap())
+        .install()
+        .unwrap();
+
+    let text = "the quick brown fox".to_string();
+    assert_eq!(summarize(text.clone()).words, 4);
+    assert_eq!(summarize(text.clone()).words, 4);
+    assert_eq!(shout("hi".to_string()), "HI");
+    assert_eq!(shout("hi".to_string()), "HI");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    let dir = fs::read_dir(&root)
+        .unwrap()
+        .map(|dir| dir.unwrap().path())
+        .find(|dir| {
+            dir.file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("summarize-")
+        })
+        .unwrap();
+    let file = fs::read_dir(dir).unwrap().next().unwrap().unwrap().path();
+    let contents = fs::read_to_string(&file).unwrap();
+    let document: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    assert_eq!(document["function"], "summarize");
+    assert_eq!(document["key"]["text"], "the quick brown fox");
+    assert_eq!(document["value"]["longest"], "brown");
+
+    // Editing the file changes what is served.
+    fs::write(&file, contents.replace("\"brown\"", "\"quick\"")).unwrap();
+    assert_eq!(summarize(text).longest, "quick");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    // Values of other codecs are kept as hex, and still served.
+    let functions = smart_cache::functions().unwrap();
+    assert!(functions
+        .iter()
+        .any(|f| f.name == "shout" && f.entries == 1));
+    assert_eq!(smart_cache::clear_function("shout").unwrap(), 1);
+    assert_eq!(shout("hi".to_string()), "HI");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    fs::remove_dir_all(root).unwrap();
+}
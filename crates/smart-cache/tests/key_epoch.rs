@@ -0,0 +1,39 @@
+use std::{
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{cached, Epoch, ExportFilter};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn render(page: u32) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("page {page}")
+}
+
+#[test]
+fn keys_carry_the_git_commit() {
+    smart_cache::Config::default()
+        .store_name("key-epoch")
+        .backend("key-epoch", smart_cache::backend::MemoryBackend::new())
+        .key_epoch(Epoch::GitCommit)
+        .install()
+        .unwrap();
+
+    assert_eq!(render(1), "page 1");
+    assert_eq!(render(1), "page 1");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let head = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .unwrap();
+    let commit = String::from_utf8(head.stdout).unwrap().trim().to_string();
+    let filter = ExportFilter::new().key(move |key| key.ends_with(commit.as_bytes()));
+
+    let path = std::env::temp_dir().join(format!("smart-cache-epoch-{}.archive", process::id()));
+    assert_eq!(smart_cache::export_filtered(&path, &filter).unwrap(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
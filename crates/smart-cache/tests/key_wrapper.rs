@@ -0,0 +1,56 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+/// Stands in for a type of another crate, which implements none of the cache's traits.
+mod external {
+    pub struct Document {
+        pub id: u64,
+        pub revision: u32,
+        pub body: String,
+    }
+}
+
+use external::Document;
+
+smart_cache::key_wrapper!(
+    /// A document, keyed by its id and revision.
+    DocumentKey(Document) => |doc| (doc.id, doc.revision)
+);
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn word_count(#[cache_key] doc: &DocumentKey) -> usize {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    doc.body.split_whitespace().count()
+}
+
+#[test]
+fn foreign_types_are_keyed_through_their_wrapper() {
+    smart_cache::Config::default()
+        .store_name("key-wrapper")
+        .backend("key-wrapper", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    let doc = |revision, body: &str| {
+        DocumentKey::from(Document {
+            id: 7,
+            revision,
+            body: body.to_string(),
+        })
+    };
+
+    assert_eq!(word_count(&doc(1, "one two three")), 3);
+    // Only the id and revision identify a document.
+    assert_eq!(word_count(&doc(1, "changed without a new revision")), 3);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(word_count(&doc(2, "one two")), 2);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    assert_eq!(doc(3, "x").into_inner().revision, 3);
+}
@@ -0,0 +1,81 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use smart_cache::backend::MemcachedBackend;
+use smart_cache_macro::cached;
+
+/// A single-connection memcached stand-in supporting the commands the backend uses.
+fn serve(stream: TcpStream) {
+    let mut items: HashMap<String, (String, Vec<u8>)> = HashMap::new();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap() > 0 {
+        let fields: Vec<String> = line.split_whitespace().map(String::from).collect();
+        line.clear();
+        let reply = match fields[0].as_str() {
+            "get" => match items.get(&fields[1]) {
+                Some((flags, data)) => {
+                    let mut reply =
+                        format!("VALUE {} {flags} {}\r\n", fields[1], data.len()).into_bytes();
+                    reply.extend_from_slice(data);
+                    reply.extend_from_slice(b"\r\nEND\r\n");
+                    reply
+                }
+                None => b"END\r\n".to_vec(),
+            },
+            "set" => {
+                let mut data = vec![0; fields[4].parse::<usize>().unwrap() + 2];
+                reader.read_exact(&mut data).unwrap();
+                data.truncate(data.len() - 2);
+                items.insert(fields[1].clone(), (fields[2].clone(), data));
+                b"STORED\r\n".to_vec()
+            }
+            "delete" => match items.remove(&fields[1]) {
+                Some(_) => b"DELETED\r\n".to_vec(),
+                None => b"NOT_FOUND\r\n".to_vec(),
+            },
+            "incr" => match items.get_mut(&fields[1]) {
+                Some((_, data)) => {
+                    let value = String::from_utf8_lossy(data).parse::<u64>().unwrap() + 1;
+                    *data = value.to_string().into_bytes();
+                    format!("{value}\r\n").into_bytes()
+                }
+                None => b"NOT_FOUND\r\n".to_vec(),
+            },
+            command => panic!("unexpected command {command}"),
+        };
+        writer.write_all(&reply).unwrap();
+    }
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn banner(width: usize) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    "=".repeat(width)
+}
+
+#[test]
+fn large_entries_are_chunked() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve(listener.accept().unwrap().0));
+
+    let backend = MemcachedBackend::new([address]).unwrap().chunk_size(64);
+    smart_cache::Config::default()
+        .store_name("memcached")
+        .backend("memcached", backend)
+        .install()
+        .unwrap();
+
+    assert_eq!(banner(500).len(), 500);
+    assert_eq!(banner(500).len(), 500);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+}
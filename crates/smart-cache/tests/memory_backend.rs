@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use smart_cache::{
+    cached,
+    testing::{self, MemoryBackend},
+};
+
+#[cached]
+fn greet(name: String) -> String {
+    format!("hello {name}")
+}
+
+#[test]
+fn lookups_and_writes_are_recorded() {
+    let backend = Arc::new(MemoryBackend::new());
+    let _isolated = testing::isolated_with(Arc::clone(&backend));
+
+    greet("ada".to_string());
+    assert_eq!(backend.not_found("greet"), 1);
+    assert_eq!(backend.written("greet"), 1);
+
+    greet("ada".to_string());
+    assert_eq!(backend.found("greet"), 1);
+    assert_eq!(backend.written("greet"), 1);
+
+    greet("grace".to_string());
+    let lookups = backend.lookups();
+    assert_eq!(lookups.len(), 3);
+    assert_eq!(
+        lookups
+            .iter()
+            .map(|lookup| lookup.found)
+            .collect::<Vec<_>>(),
+        [false, true, false]
+    );
+    assert_eq!(lookups[0].key, lookups[1].key);
+    let writes = backend.writes();
+    assert_eq!(writes.len(), 2);
+    assert!(writes.iter().all(|write| write.function == "greet"));
+    assert_eq!(writes[0].key, lookups[0].key);
+
+    backend.reset();
+    assert!(backend.lookups().is_empty());
+    greet("grace".to_string());
+    assert_eq!(backend.found("greet"), 1);
+    assert_eq!(backend.written("greet"), 0);
+}
@@ -0,0 +1,41 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn lookup(id: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    id * 7
+}
+
+#[test]
+fn rarely_hit_functions_stop_writing() {
+    smart_cache::Config::default()
+        .store_name("min-hit-rate")
+        .backend("min-hit-rate", Arc::new(MemoryBackend::new()))
+        .min_hit_rate(0.5, 20)
+        .install()
+        .unwrap();
+
+    for id in 0..20 {
+        assert_eq!(lookup(id), id * 7);
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 20);
+
+    // New values are no longer written...
+    assert_eq!(lookup(100), 700);
+    assert_eq!(lookup(100), 700);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 22);
+    // ...but those already stored are still read.
+    assert_eq!(lookup(3), 21);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 22);
+
+    let stats = smart_cache::stats().unwrap();
+    let stats = stats.iter().find(|stats| stats.name == "lookup").unwrap();
+    assert!(stats.rarely_hit());
+}
@@ -0,0 +1,58 @@
+use std::{
+    env, fs, process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{backend::FsBackend, cached};
+
+/// Set in the child process writing to the parent's store.
+const WRITER: &str = "SMART_CACHE_MISS_FILTER_STORE";
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+fn install(root: &str) {
+    smart_cache::Config::default()
+        .store_name("files")
+        .backend("files", FsBackend::open(root).unwrap())
+        .miss_filter(1000)
+        .install()
+        .unwrap();
+}
+
+#[test]
+fn entries_written_by_other_processes_are_found() {
+    let root = env::temp_dir().join(format!("smart-cache-miss-filter-{}", process::id()));
+    let root = root.to_str().unwrap();
+    install(root);
+    assert_eq!(square(1), 1);
+
+    // Another process computes a value after this one opened the store.
+    let status = process::Command::new(env::current_exe().unwrap())
+        .args(["--exact", "writer", "--ignored"])
+        .env(WRITER, root)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    assert_eq!(square(2), 4);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+/// The other process of [`entries_written_by_other_processes_are_found`].
+#[test]
+#[ignore = "run by entries_written_by_other_processes_are_found"]
+fn writer() {
+    let root = env::var(WRITER).unwrap();
+    install(&root);
+    assert_eq!(square(2), 4);
+    smart_cache::flush();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,54 @@
+use std::{fs, process, sync::Arc};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached]
+fn report(id: u32) -> String {
+    format!("report {id}")
+}
+
+#[test]
+fn mutations_are_logged_and_rotated() {
+    let dir = std::env::temp_dir().join(format!("smart-cache-mutation-log-{}", process::id()));
+    let path = dir.join("mutations.log");
+    smart_cache::Config::default()
+        .store_name("mutation-log")
+        .backend("mutation-log", Arc::new(MemoryBackend::new()))
+        .mutation_log(&path)
+        .mutation_log_size(1024)
+        .install()
+        .unwrap();
+
+    for id in 0..8 {
+        report(id);
+    }
+    report(0);
+    assert_eq!(smart_cache::clear_function("report").unwrap(), 1);
+
+    let mut lines = Vec::new();
+    let mut logs: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    // Rotated logs are named after the time they were rotated at, so they sort in order.
+    logs.sort();
+    logs.rotate_left(1);
+    assert!(logs.len() > 1);
+    for log in &logs {
+        lines.extend(fs::read_to_string(log).unwrap().lines().map(str::to_string));
+    }
+
+    assert_eq!(lines.len(), 9);
+    for line in &lines[..8] {
+        let fields: Vec<_> = line.split('\t').collect();
+        assert_eq!(fields.len(), 7, "{line}");
+        assert_eq!(fields[1], "set");
+        assert_eq!(fields[2], "report");
+        assert_eq!(fields[4].len(), 64);
+        assert!(fields[5].parse::<usize>().unwrap() > 0);
+    }
+    let fields: Vec<_> = lines[8].split('\t').collect();
+    assert_eq!(&fields[1..3], ["delete", "report"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
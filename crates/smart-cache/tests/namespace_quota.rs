@@ -0,0 +1,61 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, cached, Quota};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn model(id: u32) -> Vec<u8> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    vec![u8::try_from(id).unwrap(); 256]
+}
+
+fn calls() -> usize {
+    CALLS.load(Ordering::SeqCst)
+}
+
+#[test]
+fn full_namespaces_evict_only_their_own_entries() {
+    smart_cache::Config::default()
+        .store_name("namespace-quota")
+        .backend("namespace-quota", Arc::new(MemoryBackend::new()))
+        .namespace_quota("experiments", Quota::new().max_entries(3))
+        .namespace_quota("staging", Quota::new().max_bytes(1024))
+        .install()
+        .unwrap();
+
+    for id in 0..5 {
+        smart_cache::scoped("production", || model(id));
+    }
+    for id in 0..5 {
+        smart_cache::scoped("experiments", || model(id));
+    }
+    assert_eq!(calls(), 10);
+
+    // The experiments namespace kept its three newest entries.
+    smart_cache::scoped("experiments", || model(4));
+    smart_cache::scoped("experiments", || model(2));
+    assert_eq!(calls(), 10);
+    smart_cache::scoped("experiments", || model(0));
+    assert_eq!(calls(), 11);
+
+    // Production, without a quota, lost nothing to make room.
+    for id in 0..5 {
+        smart_cache::scoped("production", || model(id));
+    }
+    assert_eq!(calls(), 11);
+
+    // A byte quota counts the entries as stored, headers included, so a few 256-byte values
+    // fit in 1 KiB but not four.
+    for id in 0..4 {
+        smart_cache::scoped("staging", || model(id));
+    }
+    assert_eq!(calls(), 15);
+    smart_cache::scoped("staging", || model(3));
+    assert_eq!(calls(), 15);
+    smart_cache::scoped("staging", || model(0));
+    assert_eq!(calls(), 16);
+}
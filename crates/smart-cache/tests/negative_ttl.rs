@@ -0,0 +1,57 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(ttl = "1h", negative_ttl = "1s")]
+fn user(id: u32) -> Option<String> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    id.is_multiple_of(2).then(|| format!("user {id}"))
+}
+
+static FETCHES: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(negative_ttl = "1s")]
+fn fetch(url: &str) -> Result<String, String> {
+    FETCHES.fetch_add(1, Ordering::SeqCst);
+    url.strip_prefix("ok:")
+        .map(ToString::to_string)
+        .ok_or_else(|| format!("{url} is unreachable"))
+}
+
+#[test]
+fn negative_results_expire_sooner() {
+    smart_cache::Config::default()
+        .store_name("negative-ttl")
+        .backend("negative-ttl", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(user(2).as_deref(), Some("user 2"));
+    assert_eq!(user(3), None);
+    assert_eq!(user(2).as_deref(), Some("user 2"));
+    assert_eq!(user(3), None);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    assert_eq!(fetch("ok:page").unwrap(), "page");
+    assert!(fetch("down").is_err());
+    assert!(fetch("down").is_err());
+    assert_eq!(FETCHES.load(Ordering::SeqCst), 2);
+
+    thread::sleep(Duration::from_millis(1100));
+    // Negative results were looked up again; the others are still cached.
+    assert_eq!(user(2).as_deref(), Some("user 2"));
+    assert_eq!(user(3), None);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(fetch("ok:page").unwrap(), "page");
+    assert!(fetch("down").is_err());
+    assert_eq!(FETCHES.load(Ordering::SeqCst), 3);
+}
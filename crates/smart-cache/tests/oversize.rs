@@ -0,0 +1,136 @@
+use std::{
+    fs, process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, RedbBackend, WriteEntry},
+    cached, Function, Oversize,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Repetitive enough to compress well, varied enough to exercise long and overlapping matches.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| u8::try_from((i / 7) % 13 + (i % 3) * 17).unwrap())
+        .collect()
+}
+
+#[cached]
+fn skipped(len: usize) -> Vec<u8> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    vec![1; len]
+}
+
+#[cached(max_size = 16384, oversize = "compress")]
+fn compressed(len: usize) -> Vec<u8> {
+    pattern(len)
+}
+
+#[cached(max_size = 1024, oversize = "compress")]
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state.to_le_bytes()[0]
+        })
+        .collect()
+}
+
+#[cached(max_size = 1024, oversize = "error")]
+fn refused(len: usize) -> Vec<u8> {
+    vec![2; len]
+}
+
+#[cached(max_size = 1024, oversize = "spill")]
+fn spilled(len: usize) -> Vec<u8> {
+    vec![3; len]
+}
+
+fn stored(memory: &MemoryBackend, name: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let Some(function) = smart_cache::functions()
+        .unwrap()
+        .into_iter()
+        .find(|function| function.name == name)
+    else {
+        return Vec::new();
+    };
+    let mut entries = Vec::new();
+    memory
+        .for_each_function_entry(&function.hash, &mut |key, entry| {
+            entries.push((key.to_vec(), entry.to_vec()));
+        })
+        .unwrap();
+    entries
+}
+
+#[test]
+fn oversize_values_follow_their_policy() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("oversize")
+        .backend("oversize", Arc::clone(&memory))
+        .max_value_size(64 * 1024)
+        .oversize(Oversize::Skip)
+        .install()
+        .unwrap();
+
+    // The global limit applies to functions without one of their own.
+    skipped(1024);
+    skipped(1024);
+    skipped(128 * 1024);
+    skipped(128 * 1024);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(stored(&memory, "skipped").len(), 1);
+
+    assert_eq!(compressed(256 * 1024), pattern(256 * 1024));
+    let entries = stored(&memory, "compressed");
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].1.len() < 16384);
+    assert_eq!(compressed(256 * 1024), pattern(256 * 1024));
+    // Values that don't compress under the limit are skipped.
+    noise(4096);
+    assert!(stored(&memory, "noise").is_empty());
+
+    refused(512);
+    refused(4096);
+    assert_eq!(stored(&memory, "refused").len(), 1);
+
+    // Spilling is up to the store; the in-memory one keeps the value, a redb one moves it out.
+    spilled(4096);
+    let entries = stored(&memory, "spilled");
+    assert_eq!(entries.len(), 1);
+
+    let root = std::env::temp_dir().join(format!("smart-cache-oversize-{}", process::id()));
+    let hash = smart_cache::functions()
+        .unwrap()
+        .into_iter()
+        .find(|function| function.name == "spilled")
+        .unwrap()
+        .hash;
+    let function = Function::new("spilled", hash);
+    let backend = RedbBackend::open(&root.join("oversize.redb"), 1).unwrap();
+    let (key, entry) = &entries[0];
+    backend
+        .insert_batch(&[WriteEntry {
+            function: &function,
+            key,
+            entry,
+        }])
+        .unwrap();
+    assert_eq!(
+        fs::read_dir(root.join("oversize.values")).unwrap().count(),
+        1
+    );
+    assert_eq!(&*backend.get(&function, key).unwrap().unwrap(), &entry[..]);
+
+    drop(backend);
+    fs::remove_dir_all(&root).unwrap();
+}
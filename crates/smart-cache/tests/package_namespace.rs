@@ -0,0 +1,44 @@
+use std::{
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{cached, ExportFilter};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn label(id: u32) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("item-{id}")
+}
+
+#[cached(share_across_crates)]
+fn shared_label(id: u32) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("item-{id}")
+}
+
+#[test]
+fn keys_carry_the_crate_name_and_version() {
+    smart_cache::Config::default()
+        .store_name("package-namespace")
+        .backend(
+            "package-namespace",
+            smart_cache::backend::MemoryBackend::new(),
+        )
+        .install()
+        .unwrap();
+
+    assert_eq!(label(1), label(1));
+    assert_eq!(shared_label(1), shared_label(1));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    let package = concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION")).as_bytes();
+    let filter = ExportFilter::new()
+        .key(move |key| key.windows(package.len()).any(|window| window == package));
+    let path = std::env::temp_dir().join(format!("smart-cache-package-{}.archive", process::id()));
+    // Only `label`'s entry; `shared_label` opted out.
+    assert_eq!(smart_cache::export_filtered(&path, &filter).unwrap(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
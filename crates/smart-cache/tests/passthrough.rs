@@ -0,0 +1,38 @@
+use std::{
+    fs, process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn unavailable_stores_compute_every_call() {
+    // A store "inside" a regular file, which no process can create.
+    let file = std::env::temp_dir().join(format!("smart-cache-passthrough-{}", process::id()));
+    fs::write(&file, "").unwrap();
+    let path = file.join("store.redb");
+    smart_cache::Config::default()
+        .store_name("store")
+        .database("store", &path)
+        .install()
+        .unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(square(12), 144);
+    }
+    smart_cache::flush();
+    // Nothing was served from or written to a store.
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+    assert!(smart_cache::functions().unwrap().is_empty());
+    assert!(!path.exists());
+
+    fs::remove_file(file).unwrap();
+}
@@ -0,0 +1,55 @@
+use std::{
+    fs, process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use smart_cache::{backend::RedbBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn locked_stores_fall_back_to_reusable_private_files() {
+    let dir = std::env::temp_dir().join(format!("smart-cache-fallback-{}", process::id()));
+    let path = dir.join("store.redb");
+    smart_cache::Config::default()
+        .store_name("store")
+        .database("store", &path)
+        .lock_retries(1)
+        .lock_backoff(Duration::from_millis(1))
+        .per_process_fallback(true)
+        .install()
+        .unwrap();
+
+    // Another handle holds the shared file, as another process would.
+    let shared = RedbBackend::open(&path, 1).unwrap();
+    assert_eq!(square(12), 144);
+    assert_eq!(square(12), 144);
+    smart_cache::flush();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    assert!(dir.join("store-private1.redb").exists());
+
+    // A second contender takes the next free file, and once it's gone, the next one reuses it.
+    let second = RedbBackend::open(&path, 1).unwrap();
+    drop(second);
+    let third = RedbBackend::open(&path, 1).unwrap();
+    drop((third, shared));
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".redb"))
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        ["store-private1.redb", "store-private2.redb", "store.redb"]
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,35 @@
+use std::{
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{cached, ExportFilter};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(per_target)]
+fn separator(parts: Vec<String>) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    parts.join(std::path::MAIN_SEPARATOR_STR)
+}
+
+#[test]
+fn keys_carry_the_target_triple() {
+    smart_cache::Config::default()
+        .store_name("per-target")
+        .backend("per-target", smart_cache::backend::MemoryBackend::new())
+        .install()
+        .unwrap();
+
+    let parts = vec!["a".to_string(), "b".to_string()];
+    let joined = separator(parts.clone());
+    assert_eq!(separator(parts), joined);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let target = smart_cache::TARGET.as_bytes();
+    let filter = ExportFilter::new()
+        .key(move |key| key.windows(target.len()).any(|window| window == target));
+    let path = std::env::temp_dir().join(format!("smart-cache-target-{}.archive", process::id()));
+    assert_eq!(smart_cache::export_filtered(&path, &filter).unwrap(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,80 @@
+#![cfg(feature = "serde")]
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use smart_cache::{
+    backend::MemoryBackend,
+    cached,
+    codec::{self, Format},
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Reading {
+    Missing,
+    Celsius(i16),
+    Labelled { name: String, value: Option<u32> },
+}
+
+#[cached(with = "postcard")]
+fn read(sensor: u32) -> Vec<Reading> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    vec![
+        Reading::Missing,
+        Reading::Celsius(-(sensor as i16)),
+        Reading::Labelled {
+            name: format!("sensor-{sensor}"),
+            value: Some(sensor * 1000),
+        },
+    ]
+}
+
+#[cached(with = "serde")]
+fn scale(value: i64) -> (char, i64) {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    ('é', value * -3)
+}
+
+#[test]
+fn postcard_values_are_cached_in_postcards_format() {
+    smart_cache::Config::default()
+        .store_name("postcard-codec")
+        .backend("postcard-codec", Arc::new(MemoryBackend::new()))
+        .serde_format(Format::Postcard)
+        .install()
+        .unwrap();
+
+    let computed = read(300);
+    assert_eq!(read(300), computed);
+    assert_eq!(scale(1 << 40), scale(1 << 40));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    // The bytes postcard writes: varint integers and lengths, zigzagged signed integers, and
+    // chars as UTF-8.
+    assert_eq!(
+        codec::to_bytes_in(Format::Postcard, &computed),
+        [
+            3, // items
+            0, // Missing
+            1, 0xd7, 0x04, // Celsius(-300)
+            2, 10, b's', b'e', b'n', b's', b'o', b'r', b'-', b'3', b'0', b'0', // Labelled
+            1, 0xe0, 0xa7, 0x12, // Some(300_000)
+        ]
+    );
+    // `with = "serde"` follows the global format.
+    assert_eq!(codec::to_bytes(&('é', -1_i64)), [2, 0xc3, 0xa9, 1]);
+    assert_eq!(
+        codec::from_bytes_in::<u16>(Format::Postcard, &[0xff, 0xff, 0x03]),
+        Some(u16::MAX)
+    );
+    // Varints longer than their type are rejected.
+    assert_eq!(
+        codec::from_bytes_in::<u16>(Format::Postcard, &[0xff, 0xff, 0x04]),
+        None
+    );
+}
@@ -0,0 +1,72 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(prefetch)]
+fn page(query: &str, number: u32) -> Vec<String> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(50));
+    (0..3)
+        .map(|i| format!("{query} #{}", number * 3 + i))
+        .collect()
+}
+
+fn calls() -> usize {
+    CALLS.load(Ordering::SeqCst)
+}
+
+fn wait_for_calls(expected: usize) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while calls() < expected {
+        assert!(Instant::now() < deadline, "prefetch never ran");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn prefetched_values_are_hits() {
+    smart_cache::Config::default()
+        .store_name("prefetch")
+        .backend("prefetch", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(page("rust", 0)[0], "rust #0");
+    let started = Instant::now();
+    page_prefetch("rust", 1);
+    assert!(started.elapsed() < Duration::from_millis(50));
+    wait_for_calls(2);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(page("rust", 1)[0], "rust #3");
+    assert_eq!(calls(), 2);
+
+    // Prefetching a cached value computes nothing.
+    page_prefetch("rust", 1);
+    page_prefetch("rust", 2);
+    wait_for_calls(3);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(calls(), 3);
+
+    // Prefetches made in a namespace are stored under it.
+    smart_cache::scoped("tenant", || page_prefetch("rust", 0));
+    wait_for_calls(4);
+    thread::sleep(Duration::from_millis(100));
+    smart_cache::scoped("tenant", || page("rust", 0));
+    assert_eq!(calls(), 4);
+
+    // A burst of prefetches beyond the queue's capacity is dropped rather than waited for.
+    let started = Instant::now();
+    for number in 100..3000 {
+        page_prefetch("burst", number);
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,53 @@
+use std::{
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{cached, ExportFilter, Function};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn integrate(steps: u32) -> f64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    (0..steps).map(|step| f64::from(step).sqrt()).sum()
+}
+
+/// A function of a crate built with the other profile.
+static ELSEWHERE: Function =
+    Function::new("elsewhere", [7; 32]).debug_assertions(!cfg!(debug_assertions));
+
+#[test]
+fn keys_carry_the_build_profile() {
+    smart_cache::Config::default()
+        .store_name("profile")
+        .backend("profile", smart_cache::backend::MemoryBackend::new())
+        .partition_by_profile(true)
+        .install()
+        .unwrap();
+
+    let first = integrate(100);
+    assert_eq!(integrate(100).to_bits(), first.to_bits());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let profile: &[u8] = if cfg!(debug_assertions) {
+        b"debug"
+    } else {
+        b"release"
+    };
+    let filter = ExportFilter::new().key(move |key| key.ends_with(profile));
+    let path = std::env::temp_dir().join(format!("smart-cache-profile-{}.archive", process::id()));
+    assert_eq!(smart_cache::export_filtered(&path, &filter).unwrap(), 1);
+    std::fs::remove_file(&path).unwrap();
+
+    // Keys follow the profile of the crate defining the function rather than smart-cache's.
+    smart_cache::insert_archived(&ELSEWHERE, b"key", &1_u32).unwrap();
+    let other: &[u8] = if cfg!(debug_assertions) {
+        b"release"
+    } else {
+        b"debug"
+    };
+    let filter = ExportFilter::new().key(move |key| key.ends_with(other));
+    assert_eq!(smart_cache::export_filtered(&path, &filter).unwrap(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
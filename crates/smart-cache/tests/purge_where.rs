@@ -0,0 +1,40 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn recommendations(user_id: String, limit: u32) -> Vec<String> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    (0..limit).map(|rank| format!("{user_id}-{rank}")).collect()
+}
+
+#[test]
+fn entries_are_purged_by_their_arguments() {
+    smart_cache::Config::default()
+        .store_name("purge-where")
+        .backend("purge-where", Arc::new(MemoryBackend::new()))
+        .inspectable_keys(true)
+        .install()
+        .unwrap();
+
+    recommendations("alice".to_string(), 3);
+    recommendations("alice".to_string(), 5);
+    recommendations("bob".to_string(), 3);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    let removed = smart_cache::purge_where(|entry| {
+        entry.function == "recommendations" && entry.debug_key.contains("\"alice\"")
+    })
+    .unwrap();
+    assert_eq!(removed, 2);
+
+    // Alice's values are computed again; Bob's are still cached.
+    recommendations("alice".to_string(), 3);
+    recommendations("bob".to_string(), 3);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 4);
+}
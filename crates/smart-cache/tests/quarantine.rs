@@ -0,0 +1,70 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(db = "torn")]
+fn label(id: u32) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("item-{id}")
+}
+
+#[cached(db = "overwritten")]
+fn other_label(id: u32) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("other-{id}")
+}
+
+/// The files next to `path` it was moved to.
+fn quarantined(path: &Path) -> Vec<PathBuf> {
+    let name = path.file_name().unwrap().to_string_lossy();
+    fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|file| {
+            let file = file.file_name().unwrap().to_string_lossy();
+            file.starts_with(&format!("{name}.corrupt-"))
+        })
+        .collect()
+}
+
+#[test]
+fn corrupted_databases_are_moved_aside() {
+    let dir = std::env::temp_dir().join(format!("smart-cache-quarantine-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // A database whose pages were torn apart after redb's header, and a file that was
+    // overwritten with something else entirely.
+    let torn = dir.join("torn.redb");
+    let mut torn_bytes = b"redb\x1A\x0A\xA9\x0D\x0A".to_vec();
+    torn_bytes.resize(64 * 1024, 0xA5);
+    fs::write(&torn, &torn_bytes).unwrap();
+    let overwritten = dir.join("overwritten.redb");
+    fs::write(&overwritten, "not a database").unwrap();
+
+    smart_cache::Config::default()
+        .database("torn", &torn)
+        .database("overwritten", &overwritten)
+        .install()
+        .unwrap();
+
+    for _ in 0..2 {
+        assert_eq!(label(1), "item-1");
+        assert_eq!(other_label(1), "other-1");
+    }
+    // Both are served from the fresh databases created in their place.
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    for (path, bytes) in [(&torn, &torn_bytes[..]), (&overwritten, b"not a database")] {
+        let quarantined = quarantined(path);
+        assert_eq!(quarantined.len(), 1, "{}", path.display());
+        assert_eq!(fs::read(&quarantined[0]).unwrap(), bytes);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
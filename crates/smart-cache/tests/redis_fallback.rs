@@ -0,0 +1,76 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use smart_cache::{
+    backend::{CacheBackend, FsBackend, MemoryBackend, RedisBackend, WriteEntry},
+    Function,
+};
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn double(x: u32) -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * 2
+}
+
+#[test]
+fn unreachable_redis_falls_back_to_local_store() {
+    let root = std::env::temp_dir().join(format!("smart-cache-redis-{}", process::id()));
+    // Nothing listens on port 1, so every Redis operation fails fast.
+    let backend = RedisBackend::new("redis://127.0.0.1:1")
+        .unwrap()
+        .with_fallback(FsBackend::open(&root).unwrap());
+    smart_cache::Config::default()
+        .store_name("redis")
+        .backend("redis", backend)
+        .install()
+        .unwrap();
+
+    assert_eq!(double(4), 8);
+    assert_eq!(double(4), 8);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn redis_errors_are_not_masked_by_the_fallback() {
+    // A server answering every command with an error.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut buffer = [0; 1024];
+            let error = b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+            while stream.read(&mut buffer).is_ok_and(|read| read > 0)
+                && stream.write_all(error).is_ok()
+            {}
+        }
+    });
+
+    let function = Function::new("f", [0; 32]);
+    let fallback = MemoryBackend::new();
+    fallback
+        .insert_batch(&[WriteEntry {
+            function: &function,
+            key: b"key",
+            entry: b"local",
+        }])
+        .unwrap();
+    let backend = RedisBackend::new(&format!("redis://{address}"))
+        .unwrap()
+        .with_fallback(fallback);
+
+    let Err(error) = backend.get(&function, b"key") else {
+        panic!("the fallback answered");
+    };
+    assert!(format!("{error:#}").contains("WRONGTYPE"), "{error:#}");
+}
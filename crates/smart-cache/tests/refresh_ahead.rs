@@ -0,0 +1,44 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static VERSION: AtomicU64 = AtomicU64::new(0);
+
+#[cached(ttl = "4s", refresh_ahead)]
+fn quote(symbol: &str) -> String {
+    format!("{symbol} v{}", VERSION.fetch_add(1, Ordering::SeqCst))
+}
+
+#[test]
+fn hot_keys_are_recomputed_before_they_expire() {
+    smart_cache::Config::default()
+        .store_name("refresh-ahead")
+        .backend("refresh-ahead", Arc::new(MemoryBackend::new()))
+        .refresh_ahead(0.5)
+        .install()
+        .unwrap();
+
+    assert_eq!(quote("ACME"), "ACME v0");
+    assert_eq!(quote("ACME"), "ACME v0");
+    assert_eq!(VERSION.load(Ordering::SeqCst), 1);
+
+    thread::sleep(Duration::from_millis(2100));
+    // Past half the TTL, a hit returns the current value and starts computing the next one.
+    assert_eq!(quote("ACME"), "ACME v0");
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while quote("ACME") == "ACME v0" {
+        assert!(Instant::now() < deadline, "value was never refreshed");
+        thread::sleep(Duration::from_millis(10));
+    }
+    // The refreshed value replaced the old one without a miss in between.
+    assert_eq!(quote("ACME"), "ACME v1");
+    assert_eq!(VERSION.load(Ordering::SeqCst), 2);
+}
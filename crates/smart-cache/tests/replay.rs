@@ -0,0 +1,39 @@
+use std::{
+    panic,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{cached, Mode};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn lookup_price(item: u32) -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    item * 3
+}
+
+#[test]
+fn replay_serves_only_recorded_calls() {
+    smart_cache::Config::default()
+        .store_name("replay")
+        .backend("replay", smart_cache::backend::MemoryBackend::new())
+        .install()
+        .unwrap();
+
+    smart_cache::mode(Mode::Record);
+    assert_eq!(lookup_price(1), 3);
+    assert_eq!(lookup_price(1), 3);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    smart_cache::mode(Mode::Replay);
+    assert_eq!(lookup_price(1), 3);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    assert!(panic::catch_unwind(|| lookup_price(2)).is_err());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    smart_cache::mode(Mode::Normal);
+    assert_eq!(lookup_price(2), 6);
+    assert_eq!(lookup_price(2), 6);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+}
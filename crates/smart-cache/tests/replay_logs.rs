@@ -0,0 +1,117 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn import(batch: u32) -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    tracing::warn!(rows = 3, "skipped malformed rows in batch {batch}");
+    batch * 100
+}
+
+#[cached(ttl = "1s", stale_for = "1h")]
+fn rates(currency: &str) -> usize {
+    let version = CALLS.fetch_add(1, Ordering::SeqCst);
+    tracing::warn!("fetched {currency} rates, version {version}");
+    version
+}
+
+/// Collects the events it sees as `(target, text)`.
+#[derive(Clone, Default)]
+struct Collector(Arc<Mutex<Vec<(String, String)>>>);
+
+struct Text(String);
+
+impl Visit for Text {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0 += &format!(" {}={value:?}", field.name());
+    }
+}
+
+impl Subscriber for Collector {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= &tracing::Level::WARN
+    }
+
+    fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut text = Text(String::new());
+        event.record(&mut text);
+        self.0
+            .lock()
+            .unwrap()
+            .push((event.metadata().target().to_string(), text.0));
+    }
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+#[test]
+fn hits_log_what_the_computation_logged() {
+    smart_cache::Config::default()
+        .store_name("replay-logs")
+        .backend("replay-logs", Arc::new(MemoryBackend::new()))
+        .replay_logs(true)
+        .install()
+        .unwrap();
+
+    let collector = Collector::default();
+    let _subscriber = tracing::subscriber::set_default(collector.clone());
+
+    assert_eq!(import(7), 700);
+    assert_eq!(import(7), 700);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let events = collector.0.lock().unwrap().clone();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].0, "replay_logs");
+    assert!(events[0].1.contains("skipped malformed rows in batch 7"));
+    assert_eq!(events[1].0, "smart_cache::replay");
+    assert!(events[1].1.contains("replayed=true"));
+    assert!(events[1].1.contains("original_target=\"replay_logs\""));
+    assert!(events[1]
+        .1
+        .contains("skipped malformed rows in batch 7 rows=3"));
+
+    // A value recomputed in the background keeps what it logged too.
+    let version = rates("EUR");
+    thread::sleep(Duration::from_millis(1500));
+    assert_eq!(rates("EUR"), version);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while rates("EUR") == version {
+        assert!(
+            Instant::now() < deadline,
+            "stale value was never recomputed"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    let replayed = format!("fetched EUR rates, version {}", version + 1);
+    assert!(collector
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(target, text)| target == "smart_cache::replay" && text.contains(&replayed)));
+}
@@ -0,0 +1,84 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(retry = 3, backoff = "exponential", retry_delay = "1ms")]
+fn flaky(name: &str) -> Result<String, String> {
+    if ATTEMPTS.fetch_add(1, Ordering::SeqCst) < 2 {
+        return Err("connection reset".to_string());
+    }
+    Ok(name.to_uppercase())
+}
+
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(retry = 2, retry_delay = "1ms")]
+fn broken(id: u32) -> Option<u32> {
+    FAILURES.fetch_add(1, Ordering::SeqCst);
+    (id == 0).then_some(id)
+}
+
+static ASYNC_ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(retry = 1, backoff = "linear", retry_delay = "10ms")]
+async fn flaky_async(x: u64) -> Result<u64, String> {
+    if ASYNC_ATTEMPTS.fetch_add(1, Ordering::SeqCst) == 0 {
+        return Err("timed out".to_string());
+    }
+    Ok(x + 1)
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn failures_are_retried_and_only_successes_cached() {
+    smart_cache::Config::default()
+        .store_name("retry")
+        .backend("retry", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(flaky("abc").unwrap(), "ABC");
+    assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 3);
+    assert_eq!(flaky("abc").unwrap(), "ABC");
+    assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 3);
+
+    // Out of retries, the failure is returned but not cached.
+    assert_eq!(broken(7), None);
+    assert_eq!(FAILURES.load(Ordering::SeqCst), 3);
+    assert_eq!(broken(7), None);
+    assert_eq!(FAILURES.load(Ordering::SeqCst), 6);
+
+    assert_eq!(block_on(flaky_async(1)).unwrap(), 2);
+    assert_eq!(block_on(flaky_async(1)).unwrap(), 2);
+    assert_eq!(ASYNC_ATTEMPTS.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "rocksdb")]
+
+use std::{
+    fs, process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use smart_cache::{
+    backend::{CacheBackend, RocksDbBackend, RocksDbCompaction, RocksDbOptions, WriteEntry},
+    cached, Function,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn entries_are_stored_in_rocksdb() {
+    let root = std::env::temp_dir().join(format!("smart-cache-rocksdb-{}", process::id()));
+    let options = RocksDbOptions::new()
+        .block_cache(8 << 20)
+        .write_buffer(4 << 20)
+        .compaction(RocksDbCompaction::Universal);
+    let backend = Arc::new(RocksDbBackend::open(root.join("store"), options).unwrap());
+    smart_cache::Config::default()
+        .store_name("rocksdb")
+        .backend("rocksdb", Arc::clone(&backend))
+        .install()
+        .unwrap();
+
+    assert_eq!(square(3), 9);
+    assert_eq!(square(3), 9);
+    assert_eq!(square(4), 16);
+    smart_cache::flush();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    let functions = backend.functions().unwrap();
+    assert_eq!(functions.len(), 1);
+    assert_eq!(
+        (functions[0].name.as_str(), functions[0].entries),
+        ("square", 2)
+    );
+    let hash = functions[0].hash;
+    let mut values = 0;
+    backend
+        .for_each_function_entry(&hash, &mut |_, _| values += 1)
+        .unwrap();
+    assert_eq!(values, 2);
+    let stats = smart_cache::stats().unwrap();
+    let square_stats = stats.iter().find(|stats| stats.name == "square").unwrap();
+    assert_eq!((square_stats.calls, square_stats.hits), (3, 1));
+
+    // Entries removed directly are recomputed, and the function is no longer listed.
+    backend.clear_function(&hash).unwrap();
+    assert!(backend.functions().unwrap().is_empty());
+    assert_eq!(square(3), 9);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    // Entries inside a scope are purged with it, leaving the rest.
+    smart_cache::scoped("tenant", || square(5));
+    assert_eq!(smart_cache::purge_scope("tenant").unwrap(), 1);
+    assert_eq!(backend.functions().unwrap()[0].entries, 1);
+
+    // Entries outlive the database being closed, and clearing one function leaves its
+    // neighbours in key order alone.
+    let path = root.join("reopened");
+    let first = Function::new("first", [1; 32]);
+    let last = Function::new("last", [u8::MAX; 32]);
+    let next = Function::new("next", [2; 32]);
+    let written = RocksDbBackend::open(&path, RocksDbOptions::new()).unwrap();
+    let batch: Vec<_> = [&first, &last, &next]
+        .into_iter()
+        .map(|function| WriteEntry {
+            function,
+            key: b"key",
+            entry: function.name().as_bytes(),
+        })
+        .collect();
+    written.insert_batch(&batch).unwrap();
+    drop(written);
+    let reopened = RocksDbBackend::open(&path, RocksDbOptions::new()).unwrap();
+    assert_eq!(&*reopened.get(&last, b"key").unwrap().unwrap(), b"last");
+    reopened.clear_function(first.hash()).unwrap();
+    reopened.clear_function(last.hash()).unwrap();
+    let mut names: Vec<_> = reopened
+        .functions()
+        .unwrap()
+        .into_iter()
+        .map(|function| function.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, ["next"]);
+    assert!(reopened.get(&first, b"key").unwrap().is_none());
+
+    drop(reopened);
+    fs::remove_dir_all(root).unwrap();
+}
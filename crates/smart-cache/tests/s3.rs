@@ -0,0 +1,342 @@
+#![cfg(feature = "s3")]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use smart_cache::{
+    backend::{CacheBackend, S3Backend, WriteEntry},
+    Function,
+};
+use smart_cache_macro::cached;
+
+type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+fn decode(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut chars = value.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex: String = [chars.next().unwrap(), chars.next().unwrap()]
+                .iter()
+                .map(|&b| char::from(b))
+                .collect();
+            bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).unwrap()
+}
+
+fn list(objects: &BTreeMap<String, Vec<u8>>, query: &str) -> Vec<u8> {
+    let params: BTreeMap<_, _> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name, decode(value)))
+        .collect();
+    let prefix = params.get("prefix").cloned().unwrap_or_default();
+    let delimited = params.contains_key("delimiter");
+
+    let mut xml = String::from("<ListBucketResult><IsTruncated>false</IsTruncated>");
+    let mut common = BTreeSet::new();
+    for key in objects.keys().filter(|key| key.starts_with(&prefix)) {
+        match key[prefix.len()..].find('/') {
+            Some(slash) if delimited => {
+                common.insert(key[..prefix.len() + slash + 1].to_string());
+            }
+            _ => xml.push_str(&format!("<Contents><Key>{key}</Key></Contents>")),
+        }
+    }
+    for prefix in common {
+        xml.push_str(&format!(
+            "<CommonPrefixes><Prefix>{prefix}</Prefix></CommonPrefixes>"
+        ));
+    }
+    xml.push_str("</ListBucketResult>");
+    xml.into_bytes()
+}
+
+/// How many objects (rather than listings) have been downloaded.
+static OBJECT_GETS: AtomicUsize = AtomicUsize::new(0);
+
+/// Serves one request against an in-memory bucket named `bucket`.
+fn handle(stream: impl Read + Write, objects: &Objects) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    if line.is_empty() {
+        // The client hung up, e.g. after a failed TLS handshake.
+        return;
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target) = (
+        parts.next().unwrap().to_string(),
+        parts.next().unwrap().to_string(),
+    );
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let key = decode(path.trim_start_matches("/bucket").trim_start_matches('/'));
+    let mut objects = objects.lock().unwrap();
+    let (status, body) = match method.as_str() {
+        "GET" if key.is_empty() => (200, list(&objects, query)),
+        "GET" => match objects.get(&key).inspect(|_| {
+            OBJECT_GETS.fetch_add(1, Ordering::SeqCst);
+        }) {
+            Some(object) => (200, object.clone()),
+            None => (404, Vec::new()),
+        },
+        "PUT" => {
+            objects.insert(key, body);
+            (200, Vec::new())
+        }
+        "DELETE" => {
+            objects.remove(&key);
+            (204, Vec::new())
+        }
+        method => panic!("unexpected method {method}"),
+    };
+
+    let mut writer = reader.into_inner();
+    let head = format!(
+        "HTTP/1.1 {status} X\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    );
+    writer.write_all(head.as_bytes()).unwrap();
+    writer.write_all(&body).unwrap();
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn results_round_trip_through_the_bucket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let objects = Objects::default();
+    let served = Arc::clone(&objects);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            handle(stream.unwrap(), &served);
+        }
+    });
+
+    let backend = S3Backend::new(&endpoint, "bucket")
+        .unwrap()
+        .prefix(format!("test-{}", process::id()))
+        .credentials("access", "secret");
+    smart_cache::Config::default()
+        .store_name("s3")
+        .backend("s3", backend)
+        .install()
+        .unwrap();
+
+    assert_eq!(square(12), 144);
+    assert_eq!(square(12), 144);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    // The entry and the function's name.
+    assert_eq!(objects.lock().unwrap().len(), 2);
+
+    let functions = smart_cache::functions().unwrap();
+    assert!(functions
+        .iter()
+        .any(|f| f.name == "square" && f.entries == 1));
+
+    assert_eq!(smart_cache::clear_function("square").unwrap(), 1);
+    assert!(objects.lock().unwrap().is_empty());
+}
+
+/// Serves an in-memory bucket on `ip`, wrapping each connection (e.g. in TLS) with `wrap`.
+fn serve(
+    ip: &str,
+    wrap: impl Fn(TcpStream) -> Box<dyn ReadWrite> + Send + 'static,
+) -> (String, Objects) {
+    let listener = TcpListener::bind((ip, 0)).unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let objects = Objects::default();
+    let served = Arc::clone(&objects);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            handle(wrap(stream.unwrap()), &served);
+        }
+    });
+    (address, objects)
+}
+
+trait ReadWrite: Read + Write {}
+
+impl<T: Read + Write> ReadWrite for T {}
+
+#[test]
+fn purging_a_prefix_lists_instead_of_downloading() {
+    let (address, _objects) = serve("127.0.0.1", |stream| Box::new(stream));
+    let backend = S3Backend::new(&format!("http://{address}"), "bucket").unwrap();
+    let function = Function::new("f", [1; 32]);
+    let long_key = [b'x'; 300];
+    let keys: [&[u8]; 4] = [b"tenant-a/1", b"tenant-a/2", b"tenant-b/1", &long_key];
+    for key in keys {
+        backend
+            .insert_batch(&[WriteEntry {
+                function: &function,
+                key,
+                entry: b"entry",
+            }])
+            .unwrap();
+    }
+
+    let gets = OBJECT_GETS.load(Ordering::SeqCst);
+    assert_eq!(backend.remove_prefix(b"tenant-a/").unwrap(), 2);
+    assert_eq!(OBJECT_GETS.load(Ordering::SeqCst), gets);
+    assert!(backend.get(&function, b"tenant-a/1").unwrap().is_none());
+    assert!(backend.get(&function, b"tenant-b/1").unwrap().is_some());
+
+    // Prefixes longer than an object name spells out are confirmed against the stored key.
+    assert_eq!(backend.remove_prefix(&[b'x'; 250]).unwrap(), 1);
+    assert_eq!(backend.remove_prefix(&[b'x'; 301]).unwrap(), 0);
+}
+
+#[test]
+fn talks_to_https_endpoints() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cache-fixtures/tls");
+    // Trust the fixture's CA, which issued the server a certificate naming `localhost` and
+    // `127.0.0.1`.
+    std::env::set_var("SSL_CERT_FILE", fixtures.join("ca.pem"));
+    let server = Arc::new(tls::Server::new(
+        &fixtures.join("cert.pem"),
+        &fixtures.join("key.pem"),
+    ));
+    let accept = |server: Arc<tls::Server>| {
+        move |stream| Box::new(server.accept(stream)) as Box<dyn ReadWrite>
+    };
+    let (address, objects) = serve("127.0.0.1", accept(Arc::clone(&server)));
+    let port = address.rsplit_once(':').unwrap().1;
+
+    let function = Function::new("f", [2; 32]);
+    for host in ["localhost", "127.0.0.1"] {
+        let backend = S3Backend::new(&format!("https://{host}:{port}"), "bucket")
+            .unwrap()
+            .credentials("access", "secret");
+        backend
+            .insert_batch(&[WriteEntry {
+                function: &function,
+                key: host.as_bytes(),
+                entry: b"entry",
+            }])
+            .unwrap();
+        assert!(backend.get(&function, host.as_bytes()).unwrap().is_some());
+    }
+    assert_eq!(objects.lock().unwrap().len(), 3);
+
+    // A certificate that does not name the host is refused.
+    let (address, _) = serve("127.0.0.2", accept(server));
+    let backend = S3Backend::new(&format!("https://{address}"), "bucket").unwrap();
+    let error = backend.get(&function, b"key").err().unwrap();
+    assert!(format!("{error:?}").contains("certificate"), "{error:?}");
+}
+
+/// The server side of TLS, through rustls like the client.
+mod tls {
+    use std::{
+        io::{self, Read, Write},
+        net::TcpStream,
+        path::Path,
+        sync::Arc,
+    };
+
+    use rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        ServerConfig, ServerConnection, StreamOwned,
+    };
+
+    pub struct Server(Arc<ServerConfig>);
+
+    impl Server {
+        pub fn new(cert: &Path, key: &Path) -> Self {
+            let chain = CertificateDer::pem_file_iter(cert)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            let key = PrivateKeyDer::from_pem_file(key).unwrap();
+            let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(chain, key)
+                .unwrap();
+            Self(Arc::new(config))
+        }
+
+        /// Completes the handshake on `stream`; a failed one yields a session that reads nothing.
+        pub fn accept(&self, mut stream: TcpStream) -> Session {
+            let mut session = ServerConnection::new(Arc::clone(&self.0)).unwrap();
+            let mut accepted = true;
+            while accepted && session.is_handshaking() {
+                accepted = session.complete_io(&mut stream).is_ok();
+            }
+            Session {
+                stream: StreamOwned::new(session, stream),
+                accepted,
+            }
+        }
+    }
+
+    pub struct Session {
+        stream: StreamOwned<ServerConnection, TcpStream>,
+        accepted: bool,
+    }
+
+    impl Read for Session {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.accepted {
+                return Ok(0);
+            }
+            Ok(self.stream.read(buf).unwrap_or_default())
+        }
+    }
+
+    impl Write for Session {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.stream.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.stream.flush()
+        }
+    }
+
+    impl Drop for Session {
+        fn drop(&mut self) {
+            self.stream.conn.send_close_notify();
+            let _ = self.stream.flush();
+        }
+    }
+}
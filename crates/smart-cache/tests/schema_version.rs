@@ -0,0 +1,42 @@
+use std::process;
+
+use redb::{Database, TableDefinition};
+use smart_cache::backend::RedbBackend;
+
+const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+
+fn recorded_version(path: &std::path::Path) -> Option<u64> {
+    let db = Database::open(path).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let meta = read_txn.open_table(META).unwrap();
+    meta.get("schema_version")
+        .unwrap()
+        .map(|version| version.value())
+}
+
+#[test]
+fn stores_record_and_check_their_layout_version() {
+    let root = std::env::temp_dir().join(format!("smart-cache-schema-{}", process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+
+    let current = root.join("current.redb");
+    drop(RedbBackend::open(&current, 1).unwrap());
+    let version = recorded_version(&current).unwrap();
+    assert!(version >= 1);
+
+    let newer = root.join("newer.redb");
+    {
+        let db = Database::create(&newer).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        write_txn
+            .open_table(META)
+            .unwrap()
+            .insert("schema_version", version + 1)
+            .unwrap();
+        write_txn.commit().unwrap();
+    }
+    assert!(RedbBackend::open(&newer, 1).is_err());
+    assert_eq!(recorded_version(&newer), Some(version + 1));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn tenant_greeting(name: String) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    format!("hello {name}")
+}
+
+static RAW_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn checksum(bytes: [u8; 31]) -> u32 {
+    RAW_CALLS.fetch_add(1, Ordering::SeqCst);
+    bytes.iter().map(|&byte| u32::from(byte)).sum()
+}
+
+#[test]
+fn scopes_partition_and_purge_entries() {
+    // The first call opens this crate's store, so earlier runs' entries can be purged.
+    tenant_greeting("warm-up".to_string());
+    smart_cache::purge_scope("tenant-a").unwrap();
+    smart_cache::purge_scope("tenant-b").unwrap();
+    let calls = CALLS.load(Ordering::SeqCst);
+
+    let a = smart_cache::scoped("tenant-a", || tenant_greeting("ada".to_string()));
+    let b = smart_cache::scoped("tenant-b", || tenant_greeting("ada".to_string()));
+    assert_eq!(a, b);
+    assert_eq!(CALLS.load(Ordering::SeqCst), calls + 2);
+
+    smart_cache::scoped("tenant-a", || tenant_greeting("ada".to_string()));
+    assert_eq!(CALLS.load(Ordering::SeqCst), calls + 2);
+
+    assert_eq!(smart_cache::purge_scope("tenant-a").unwrap(), 1);
+    smart_cache::scoped("tenant-a", || tenant_greeting("ada".to_string()));
+    smart_cache::scoped("tenant-b", || tenant_greeting("ada".to_string()));
+    assert_eq!(CALLS.load(Ordering::SeqCst), calls + 3);
+}
+
+#[test]
+fn purging_a_scope_keeps_unscoped_entries_that_look_like_it() {
+    // Arrays encode as their bytes, so these arguments spell out the segment `tenant-c` adds.
+    let mut bytes = [0; 31];
+    bytes[..19].copy_from_slice(b"\0smart-cache scope\0");
+    bytes[19..23].copy_from_slice(&8_u32.to_le_bytes());
+    bytes[23..].copy_from_slice(b"tenant-c");
+
+    checksum(bytes);
+    let calls = RAW_CALLS.load(Ordering::SeqCst);
+    smart_cache::purge_scope("tenant-c").unwrap();
+    checksum(bytes);
+    assert_eq!(RAW_CALLS.load(Ordering::SeqCst), calls);
+}
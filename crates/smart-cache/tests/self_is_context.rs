@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+/// Reuses one buffer across calls; the count depends on `text` alone.
+#[derive(Default)]
+struct Tokenizer {
+    buffer: Vec<String>,
+    computed: usize,
+}
+
+impl Tokenizer {
+    #[cached(self_is_context)]
+    fn count_tokens(&mut self, text: String) -> usize {
+        self.computed += 1;
+        self.buffer.clear();
+        self.buffer
+            .extend(text.split_whitespace().map(str::to_owned));
+        self.buffer.len()
+    }
+
+    #[cached(self_is_context, retry = 1)]
+    fn longest(&self, text: &str) -> Option<String> {
+        text.split_whitespace()
+            .max_by_key(|token| token.len())
+            .map(str::to_owned)
+    }
+}
+
+#[test]
+fn receivers_are_left_out_of_the_key() {
+    smart_cache::Config::default()
+        .store_name("self-is-context")
+        .backend("self-is-context", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    let mut first = Tokenizer::default();
+    assert_eq!(first.count_tokens("a b c".to_string()), 3);
+    assert_eq!(first.buffer, ["a", "b", "c"]);
+    assert_eq!(first.count_tokens("a b c".to_string()), 3);
+    assert_eq!(first.computed, 1);
+
+    // Another instance, in another state, is served the same entry.
+    let mut second = Tokenizer {
+        buffer: vec!["left over".to_string()],
+        computed: 0,
+    };
+    assert_eq!(second.count_tokens("a b c".to_string()), 3);
+    assert_eq!(second.computed, 0);
+    assert_eq!(second.count_tokens("d e".to_string()), 2);
+    assert_eq!(second.computed, 1);
+
+    assert_eq!(first.longest("a bbb cc").as_deref(), Some("bbb"));
+    assert_eq!(second.longest("a bbb cc").as_deref(), Some("bbb"));
+}
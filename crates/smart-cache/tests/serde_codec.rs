@@ -0,0 +1,80 @@
+#![cfg(feature = "serde")]
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Implements serde's traits but not rkyv's.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Query {
+    table: String,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Report {
+    Empty,
+    Rows(Vec<(String, f64)>),
+    Summary {
+        totals: BTreeMap<String, i64>,
+        note: Option<String>,
+    },
+}
+
+#[cached(with = "serde")]
+fn run(query: Query) -> Report {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    match query.limit {
+        Some(0) => Report::Empty,
+        Some(limit) => Report::Rows(
+            (0..limit)
+                .map(|i| (format!("{}-{i}", query.table), f64::from(i) / 2.0))
+                .collect(),
+        ),
+        None => Report::Summary {
+            totals: BTreeMap::from([(query.table, 3), ("all".to_string(), -1)]),
+            note: None,
+        },
+    }
+}
+
+#[test]
+fn serde_values_are_cached_and_decoded() {
+    smart_cache::Config::default()
+        .store_name("serde-codec")
+        .backend("serde-codec", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    let queries = [
+        Query {
+            table: "users".to_string(),
+            limit: Some(0),
+        },
+        Query {
+            table: "users".to_string(),
+            limit: Some(3),
+        },
+        Query {
+            table: "orders".to_string(),
+            limit: None,
+        },
+    ];
+    let computed: Vec<_> = queries.iter().cloned().map(run).collect();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    let hits: Vec<_> = queries.iter().cloned().map(run).collect();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(hits, computed);
+    assert_eq!(hits[0], Report::Empty);
+    assert!(matches!(&hits[1], Report::Rows(rows) if rows.len() == 3));
+}
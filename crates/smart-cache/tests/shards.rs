@@ -0,0 +1,58 @@
+use std::{fs, process};
+
+use smart_cache::{
+    backend::{CacheBackend, RedbBackend, WriteEntry},
+    Function,
+};
+
+/// A function whose hash routes it to shard `shard` of three.
+fn function(name: &'static str, shard: u8) -> Function {
+    let mut hash = [0; 32];
+    hash[0] = shard;
+    Function::new(name, hash)
+}
+
+#[test]
+fn functions_are_routed_to_their_shard_and_survive_reopening() {
+    let dir = std::env::temp_dir().join(format!("smart-cache-shards-{}", process::id()));
+    let path = dir.join("store.redb");
+    let functions = [function("a", 0), function("b", 1), function("c", 2)];
+
+    let backend = RedbBackend::open(&path, 3).unwrap();
+    let entries: Vec<_> = functions
+        .iter()
+        .map(|function| WriteEntry {
+            function,
+            key: b"key",
+            entry: function.name().as_bytes(),
+        })
+        .collect();
+    backend.insert_batch(&entries).unwrap();
+    drop(backend);
+
+    // Each file holds the one function routed to it.
+    let files = [
+        path.clone(),
+        dir.join("store-shard1.redb"),
+        dir.join("store-shard2.redb"),
+    ];
+    for (file, function) in files.iter().zip(&functions) {
+        let shard = RedbBackend::open(file, 1).unwrap();
+        let names: Vec<_> = shard
+            .functions()
+            .unwrap()
+            .into_iter()
+            .map(|function| function.name)
+            .collect();
+        assert_eq!(names, [function.name()]);
+    }
+
+    // Reopened as a whole, the store serves them all.
+    let backend = RedbBackend::open(&path, 3).unwrap();
+    for function in &functions {
+        let entry = backend.get(function, b"key").unwrap().unwrap();
+        assert_eq!(&*entry, function.name().as_bytes());
+    }
+    drop(backend);
+    fs::remove_dir_all(dir).unwrap();
+}
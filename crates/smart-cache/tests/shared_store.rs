@@ -0,0 +1,40 @@
+use std::{env, fs, process};
+
+use smart_cache::cached;
+
+#[cached]
+fn double(n: u64) -> u64 {
+    n * 2
+}
+
+#[cached(db = "triples")]
+fn triple(n: u64) -> u64 {
+    n * 3
+}
+
+#[test]
+fn shared_stores_replace_the_crates_own() {
+    smart_cache::Config::default()
+        .shared_store(true)
+        .install()
+        .unwrap();
+
+    assert_eq!(double(2), 4);
+    assert_eq!(triple(2), 6);
+    smart_cache::flush();
+
+    // Tests cache in a temporary directory of their own; see `test_dir.rs`.
+    let dir = env::temp_dir().join(format!("smart-cache-tests-{}", process::id()));
+    let own = concat!(
+        env!("CARGO_PKG_NAME"),
+        "-",
+        env!("CARGO_PKG_VERSION"),
+        ".redb"
+    );
+    assert!(dir.join("cache.redb").exists());
+    assert!(!dir.join(own).exists());
+    // Databases named on the function still take precedence.
+    assert!(dir.join("triples.redb").exists());
+
+    let _ = fs::remove_dir_all(dir);
+}
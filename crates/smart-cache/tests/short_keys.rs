@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend},
+    cached,
+};
+
+#[cached(share_across_crates)]
+fn double(x: u64) -> u64 {
+    x * 2
+}
+
+#[cached(share_across_crates)]
+fn triple(x: u64) -> u64 {
+    x * 3
+}
+
+#[test]
+fn keys_hold_only_the_arguments() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("short-keys")
+        .backend("short-keys", Arc::clone(&memory))
+        .thread_memo(16)
+        .install()
+        .unwrap();
+
+    // Both functions store the same key; neither is served the other's value.
+    for _ in 0..2 {
+        assert_eq!(double(7), 14);
+        assert_eq!(triple(7), 21);
+    }
+
+    for function in smart_cache::functions().unwrap() {
+        let mut keys = Vec::new();
+        memory
+            .for_each_function_entry(&function.hash, &mut |key, _| keys.push(key.to_vec()))
+            .unwrap();
+        // An archived `&u64`, shorter than the function hash alone.
+        assert_eq!(keys.len(), 1, "{}", function.name);
+        assert!(keys[0].len() < 32, "{}: {:?}", function.name, keys[0]);
+    }
+}
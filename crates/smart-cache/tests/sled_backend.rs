@@ -0,0 +1,95 @@
+#![cfg(feature = "sled")]
+
+use std::{
+    fs, process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use smart_cache::{
+    backend::{CacheBackend, SledBackend, WriteEntry},
+    cached, Function,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn entries_are_stored_in_sled() {
+    let root = std::env::temp_dir().join(format!("smart-cache-sled-{}", process::id()));
+    let backend = Arc::new(SledBackend::open(root.join("store")).unwrap());
+    smart_cache::Config::default()
+        .store_name("sled")
+        .backend("sled", Arc::clone(&backend))
+        .install()
+        .unwrap();
+
+    assert_eq!(square(3), 9);
+    assert_eq!(square(3), 9);
+    assert_eq!(square(4), 16);
+    smart_cache::flush();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    let functions = backend.functions().unwrap();
+    assert_eq!(functions.len(), 1);
+    assert_eq!(
+        (functions[0].name.as_str(), functions[0].entries),
+        ("square", 2)
+    );
+    let hash = functions[0].hash;
+    let mut values = 0;
+    backend
+        .for_each_function_entry(&hash, &mut |_, _| values += 1)
+        .unwrap();
+    assert_eq!(values, 2);
+    let stats = smart_cache::stats().unwrap();
+    let square_stats = stats.iter().find(|stats| stats.name == "square").unwrap();
+    assert_eq!((square_stats.calls, square_stats.hits), (3, 1));
+
+    // Entries removed directly are recomputed, and the function is no longer listed.
+    backend.clear_function(&hash).unwrap();
+    assert!(backend.functions().unwrap().is_empty());
+    assert_eq!(square(3), 9);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    // Entries inside a scope are purged with it, leaving the rest.
+    smart_cache::scoped("tenant", || square(5));
+    assert_eq!(smart_cache::purge_scope("tenant").unwrap(), 1);
+    assert_eq!(backend.functions().unwrap()[0].entries, 1);
+
+    // Functions keep their IDs once the database is closed, so their entries are still found.
+    let path = root.join("reopened");
+    let first = Function::new("first", [1; 32]);
+    let second = Function::new("second", [2; 32]);
+    let written = SledBackend::open(&path).unwrap();
+    let batch: Vec<_> = [&first, &second]
+        .into_iter()
+        .map(|function| WriteEntry {
+            function,
+            key: b"key",
+            entry: function.name().as_bytes(),
+        })
+        .collect();
+    written.insert_batch(&batch).unwrap();
+    drop(written);
+    let reopened = SledBackend::open(&path).unwrap();
+    assert_eq!(&*reopened.get(&second, b"key").unwrap().unwrap(), b"second");
+    reopened.clear_function(first.hash()).unwrap();
+    let names: Vec<_> = reopened
+        .functions()
+        .unwrap()
+        .into_iter()
+        .map(|function| function.name)
+        .collect();
+    assert_eq!(names, ["second"]);
+
+    drop((backend, reopened));
+    fs::remove_dir_all(root).unwrap();
+}
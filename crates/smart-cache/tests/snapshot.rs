@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smart_cache::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn simulate(step: u32) -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    step + 1
+}
+
+#[test]
+fn restore_rolls_back_to_the_snapshot() {
+    smart_cache::Config::default()
+        .store_name("snapshot")
+        .backend("snapshot", smart_cache::backend::MemoryBackend::new())
+        .install()
+        .unwrap();
+
+    assert_eq!(simulate(1), 2);
+    let warm = smart_cache::snapshot().unwrap();
+
+    assert_eq!(simulate(2), 3);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    smart_cache::restore(warm).unwrap();
+    assert_eq!(simulate(1), 2);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(simulate(2), 3);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    smart_cache::delete_snapshot(warm).unwrap();
+    assert!(smart_cache::restore(warm).is_err());
+}
@@ -0,0 +1,116 @@
+use std::{fs, path::Path, process, sync::Arc};
+
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, RedbBackend, WriteEntry},
+    cached, Function,
+};
+
+#[cached]
+fn render(id: u64) -> Vec<u8> {
+    let _ = id;
+    vec![3; 64 * 1024]
+}
+
+#[cached]
+fn label(id: u64) -> Vec<u8> {
+    let _ = id;
+    vec![1; 16]
+}
+
+fn spilled_files(dir: &Path) -> usize {
+    fs::read_dir(dir).map_or(0, Iterator::count)
+}
+
+#[test]
+fn large_values_are_spilled_to_files() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("spill")
+        .backend("spill", Arc::clone(&memory))
+        .install()
+        .unwrap();
+
+    render(1);
+    render(2);
+    label(1);
+    let functions = smart_cache::functions().unwrap();
+    let hash_of = |name: &str| {
+        functions
+            .iter()
+            .find(|function| function.name == name)
+            .unwrap()
+            .hash
+    };
+    let hash = hash_of("render");
+    let mut small = Vec::new();
+    memory
+        .for_each_function_entry(&hash_of("label"), &mut |_, entry| small = entry.to_vec())
+        .unwrap();
+    let mut entries = Vec::new();
+    memory
+        .for_each_function_entry(&hash, &mut |key, entry| {
+            entries.push((key.to_vec(), entry.to_vec()));
+        })
+        .unwrap();
+
+    let root = std::env::temp_dir().join(format!("smart-cache-spill-{}", process::id()));
+    let path = root.join("spill.redb");
+    let values = root.join("spill.values");
+    let function = Function::new("render", hash);
+    let backend = RedbBackend::open(&path, 1).unwrap().spill(1024);
+    let batch: Vec<_> = entries
+        .iter()
+        .map(|(key, entry)| WriteEntry {
+            function: &function,
+            key,
+            entry,
+        })
+        .collect();
+    backend.insert_batch(&batch).unwrap();
+
+    // Both entries hold the same value, which is written once.
+    assert_eq!(spilled_files(&values), 1);
+    for (key, entry) in &entries {
+        assert_eq!(&*backend.get(&function, key).unwrap().unwrap(), &entry[..]);
+    }
+
+    backend.remove(&function, &entries[0].0).unwrap();
+    assert_eq!(spilled_files(&values), 1);
+    backend.clear_function(&hash).unwrap();
+    assert_eq!(spilled_files(&values), 0);
+
+    // A value whose last entry is replaced stays if the same transaction references it again.
+    let (first, large) = (&entries[0].0, &entries[0].1);
+    let second = &entries[1].0;
+    backend
+        .insert_batch(&[WriteEntry {
+            function: &function,
+            key: first,
+            entry: large,
+        }])
+        .unwrap();
+    backend
+        .insert_batch(&[
+            WriteEntry {
+                function: &function,
+                key: first,
+                entry: &small,
+            },
+            WriteEntry {
+                function: &function,
+                key: second,
+                entry: large,
+            },
+        ])
+        .unwrap();
+    assert_eq!(spilled_files(&values), 1);
+    assert_eq!(
+        &*backend.get(&function, second).unwrap().unwrap(),
+        &large[..]
+    );
+    backend.clear_function(&hash).unwrap();
+    assert_eq!(spilled_files(&values), 0);
+
+    drop(backend);
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "sqlite")]
+
+use std::{
+    fs, process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use smart_cache::{
+    backend::{CacheBackend, SqliteBackend},
+    cached,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn entries_are_stored_in_sqlite() {
+    let dir = std::env::temp_dir().join(format!("smart-cache-sqlite-{}", process::id()));
+    let path = dir.join("cache.sqlite");
+    smart_cache::Config::default()
+        .store_name("sqlite")
+        .backend("sqlite", SqliteBackend::open(&path).unwrap())
+        .install()
+        .unwrap();
+
+    assert_eq!(square(3), 9);
+    assert_eq!(square(3), 9);
+    assert_eq!(square(4), 16);
+    smart_cache::flush();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    // Another connection, as another process would open, sees the entries and statistics.
+    let other = SqliteBackend::open(&path).unwrap();
+    let functions = other.functions().unwrap();
+    assert_eq!(functions.len(), 1);
+    assert_eq!(
+        (functions[0].name.as_str(), functions[0].entries),
+        ("square", 2)
+    );
+    let hash = functions[0].hash;
+    let mut values = 0;
+    other
+        .for_each_function_entry(&hash, &mut |_, _| values += 1)
+        .unwrap();
+    assert_eq!(values, 2);
+    let stats = smart_cache::stats().unwrap();
+    let square_stats = stats.iter().find(|stats| stats.name == "square").unwrap();
+    assert_eq!((square_stats.calls, square_stats.hits), (3, 1));
+
+    // Entries removed elsewhere are recomputed.
+    other.clear_function(&hash).unwrap();
+    assert!(other.functions().unwrap().is_empty());
+    assert_eq!(square(3), 9);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+    drop(other);
+    fs::remove_dir_all(dir).unwrap();
+}
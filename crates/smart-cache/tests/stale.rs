@@ -0,0 +1,45 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static VERSION: AtomicU64 = AtomicU64::new(0);
+
+#[cached(ttl = "1s", stale_for = "1h")]
+fn latest(name: &str) -> String {
+    format!("{name} v{}", VERSION.fetch_add(1, Ordering::SeqCst))
+}
+
+#[test]
+fn stale_values_are_served_while_recomputed() {
+    smart_cache::Config::default()
+        .store_name("stale")
+        .backend("stale", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(latest("feed"), "feed v0");
+    assert_eq!(latest("feed"), "feed v0");
+    assert_eq!(VERSION.load(Ordering::SeqCst), 1);
+
+    thread::sleep(Duration::from_millis(2100));
+    // Past the TTL, the old value is returned while a new one is computed.
+    assert_eq!(latest("feed"), "feed v0");
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while latest("feed") == "feed v0" {
+        assert!(
+            Instant::now() < deadline,
+            "stale value was never recomputed"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(latest("feed"), "feed v1");
+    assert_eq!(VERSION.load(Ordering::SeqCst), 2);
+}
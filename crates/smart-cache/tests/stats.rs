@@ -0,0 +1,18 @@
+use smart_cache_macro::cached;
+
+#[cached]
+fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[test]
+fn stats_count_hits() {
+    assert_eq!(add(2, 3), 5);
+    assert_eq!(add(2, 3), 5);
+    smart_cache::flush();
+
+    let stats = smart_cache::stats().unwrap();
+    let add = stats.iter().find(|s| s.name == "add").unwrap();
+    assert!(add.hits >= 1);
+    assert!(add.calls >= 2);
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached]
+fn double(n: u64) -> u64 {
+    n * 2
+}
+
+#[test]
+fn stores_are_described_and_checked() {
+    smart_cache::Config::default()
+        .store_name("status")
+        .backend("status", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    double(1);
+    double(2);
+
+    let status = smart_cache::status();
+    assert!(status.healthy());
+    assert!(status.entry_format > 0);
+    assert_eq!(status.stores.len(), 1);
+    let store = &status.stores[0];
+    assert_eq!(store.name, "status");
+    assert_eq!(store.backend, "memory");
+    assert_eq!(store.entries, Some(2));
+    assert!(store.readable && store.writable);
+    assert_eq!(store.error, None);
+
+    // The writability probe leaves nothing behind.
+    let functions = smart_cache::functions().unwrap();
+    assert_eq!(functions.len(), 1);
+    assert_eq!(functions[0].name, "double");
+
+    let _isolated = smart_cache::testing::isolated();
+    double(3);
+
+    let status = smart_cache::status();
+    let store = &status.stores[0];
+    assert_eq!(store.backend, "redb");
+    assert!(store.location.as_deref().unwrap().ends_with(".redb"));
+    assert!(store.size.unwrap() > 0);
+    assert!(store.layout_version.is_some());
+    assert_eq!(store.entries, Some(1));
+    assert!(store.healthy());
+}
@@ -0,0 +1,56 @@
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, Function};
+
+static RENDER: Function = Function::new("render", [7; 32]);
+
+#[test]
+fn streamed_values_round_trip_in_chunks() {
+    smart_cache::Config::default()
+        .store_name("stream")
+        .backend("stream", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert!(smart_cache::stream_reader(&RENDER, b"frame")
+        .unwrap()
+        .is_none());
+
+    // Spans three chunks, the last one partial.
+    let value: Vec<u8> = (0..10 * 1024 * 1024)
+        .map(|i: u32| (i % 251) as u8)
+        .collect();
+    let mut writer = smart_cache::stream_writer(&RENDER, b"frame");
+    for piece in value.chunks(100_000) {
+        writer.write_all(piece).unwrap();
+    }
+    assert!(
+        smart_cache::stream_reader(&RENDER, b"frame")
+            .unwrap()
+            .is_none(),
+        "an unfinished value is not visible"
+    );
+    writer.finish().unwrap();
+
+    let mut reader = smart_cache::stream_reader(&RENDER, b"frame")
+        .unwrap()
+        .unwrap();
+    assert_eq!(reader.len(), value.len() as u64);
+    let mut read = Vec::new();
+    reader.read_to_end(&mut read).unwrap();
+    assert!(read == value);
+
+    let mut writer = smart_cache::stream_writer(&RENDER, b"empty");
+    writer.write_all(&[]).unwrap();
+    writer.finish().unwrap();
+    let mut reader = smart_cache::stream_reader(&RENDER, b"empty")
+        .unwrap()
+        .unwrap();
+    assert!(reader.is_empty());
+    let mut read = Vec::new();
+    reader.read_to_end(&mut read).unwrap();
+    assert!(read.is_empty());
+}
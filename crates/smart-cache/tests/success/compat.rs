@@ -0,0 +1,16 @@
+use smart_cache::compat::cached;
+
+#[cached(time = 3600, unbound, sync_writes = false)]
+fn label(id: u32) -> String {
+    format!("label-{id}")
+}
+
+#[cached(result = true)]
+fn parse(input: String) -> Result<u32, String> {
+    input.parse().map_err(|_| format!("not a number: {input}"))
+}
+
+fn main() {
+    assert_eq!(label(3), "label-3");
+    assert_eq!(parse("12".to_string()), Ok(12));
+}
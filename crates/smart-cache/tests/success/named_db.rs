@@ -0,0 +1,11 @@
+use smart_cache_macro::cached;
+
+#[cached(db = "trybuild-metadata")]
+fn lookup(id: u32) -> String {
+    format!("item-{id}")
+}
+
+fn main() {
+    assert_eq!(lookup(7), "item-7");
+    assert_eq!(lookup(7), "item-7");
+}
@@ -0,0 +1,17 @@
+use smart_cache_macro::cached;
+
+// Not `Clone`: without `stale_for` or `refresh_ahead`, nothing is recomputed in the background,
+// so the arguments are never copied.
+#[derive(Debug, rkyv::Archive, rkyv::Serialize)]
+struct Query {
+    term: String,
+}
+
+#[cached(ttl = "1m")]
+fn search(query: Query) -> usize {
+    query.term.len()
+}
+
+fn main() {
+    assert_eq!(search(Query { term: "rust".to_string() }), 4);
+}
@@ -0,0 +1,33 @@
+#![cfg(target_os = "linux")]
+
+use std::{env, fs, process};
+
+use smart_cache::cached;
+
+#[cached]
+fn double(n: u64) -> u64 {
+    n * 2
+}
+
+#[test]
+fn tests_cache_in_a_temporary_directory() {
+    // The user's cache directory follows XDG_CACHE_HOME on Linux.
+    let home = env::temp_dir().join(format!("smart-cache-home-{}", process::id()));
+    env::set_var("XDG_CACHE_HOME", &home);
+
+    assert_eq!(double(2), 4);
+    assert_eq!(double(2), 4);
+    smart_cache::flush();
+
+    let dir = env::temp_dir().join(format!("smart-cache-tests-{}", process::id()));
+    let store = dir.join(concat!(
+        env!("CARGO_PKG_NAME"),
+        "-",
+        env!("CARGO_PKG_VERSION"),
+        ".redb"
+    ));
+    assert!(store.exists(), "{} is missing", store.display());
+    assert!(!home.join("smart-cache").exists());
+
+    let _ = fs::remove_dir_all(dir);
+}
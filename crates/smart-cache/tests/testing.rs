@@ -0,0 +1,45 @@
+use std::{
+    panic,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use smart_cache::{backend::MemoryBackend, cached, testing::assert_deterministic};
+
+static CALLS: AtomicU64 = AtomicU64::new(0);
+
+#[cached]
+fn pure(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * 2
+}
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[cached]
+fn impure(x: u64) -> u64 {
+    x + COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[test]
+fn determinism_is_checked_without_the_cache() {
+    smart_cache::Config::default()
+        .store_name("testing")
+        .backend("testing", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_deterministic(|| pure(21));
+    // Every run computed the value, and none of them stored it.
+    assert_eq!(CALLS.load(Ordering::SeqCst), 8);
+    assert_eq!(pure(21), 42);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 9);
+
+    let failed = panic::catch_unwind(|| assert_deterministic(|| impure(1)));
+    assert!(failed.is_err());
+    // Caching is back on afterwards.
+    assert_eq!(pure(21), 42);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 9);
+}
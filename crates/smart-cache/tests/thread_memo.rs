@@ -0,0 +1,99 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use eyre::Result;
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, StoredEntry, WriteEntry},
+    cached, Function, FunctionHash, FunctionInfo,
+};
+
+/// A memory backend counting the reads that reach it.
+#[derive(Default)]
+struct Counting {
+    inner: MemoryBackend,
+    gets: AtomicUsize,
+}
+
+impl CacheBackend for Counting {
+    fn get(&self, function: &Function, key: &[u8]) -> Result<Option<StoredEntry>> {
+        self.gets.fetch_add(1, Ordering::SeqCst);
+        self.inner.get(function, key)
+    }
+
+    fn insert_batch(&self, entries: &[WriteEntry<'_>]) -> Result<()> {
+        self.inner.insert_batch(entries)
+    }
+
+    fn remove(&self, function: &Function, key: &[u8]) -> Result<()> {
+        self.inner.remove(function, key)
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        self.inner.remove_prefix(prefix)
+    }
+
+    fn functions(&self) -> Result<Vec<FunctionInfo>> {
+        self.inner.functions()
+    }
+
+    fn clear_function(&self, function: &FunctionHash) -> Result<()> {
+        self.inner.clear_function(function)
+    }
+
+    fn for_each_key(&self, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.inner.for_each_key(f)
+    }
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn repeated_calls_skip_the_store() {
+    let backend = Arc::new(Counting::default());
+    smart_cache::Config::default()
+        .store_name("counting")
+        .backend("counting", Arc::clone(&backend) as Arc<dyn CacheBackend>)
+        .thread_memo(2)
+        .install()
+        .unwrap();
+    let gets = || backend.gets.load(Ordering::SeqCst);
+
+    // One read finds nothing; the computed value is memoized and serves every later call.
+    for _ in 0..100 {
+        assert_eq!(square(1), 1);
+    }
+    assert_eq!((CALLS.load(Ordering::SeqCst), gets()), (1, 1));
+
+    // Other threads have memos of their own, so their first call reads the store.
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            assert_eq!(square(1), 1);
+            assert_eq!(square(1), 1);
+        });
+    });
+    assert_eq!((CALLS.load(Ordering::SeqCst), gets()), (1, 2));
+
+    // Past its capacity, the least recently used value is evicted and read again.
+    assert_eq!(square(2), 4);
+    assert_eq!(square(3), 9);
+    assert_eq!(square(1), 1);
+    assert_eq!((CALLS.load(Ordering::SeqCst), gets()), (3, 5));
+    assert_eq!(square(1), 1);
+    assert_eq!(gets(), 5);
+
+    // Clearing the function drops memoized values along with stored ones.
+    smart_cache::clear_function("square").unwrap();
+    assert_eq!(square(1), 1);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 4);
+}
@@ -0,0 +1,64 @@
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use smart_cache::{
+    backend::{CacheBackend, FsBackend, MemoryBackend, TieredBackend, WriteEntry, WritePolicy},
+    Function,
+};
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn tiers_are_written_and_promoted() {
+    let root = std::env::temp_dir().join(format!("smart-cache-tiered-{}", process::id()));
+    let memory = Arc::new(MemoryBackend::new());
+    let disk = Arc::new(FsBackend::open(&root).unwrap());
+    smart_cache::Config::default()
+        .store_name("tiered")
+        .backend(
+            "tiered",
+            TieredBackend::new()
+                .tier(Arc::clone(&memory), WritePolicy::WriteThrough)
+                .tier(Arc::clone(&disk), WritePolicy::WriteBack),
+        )
+        .install()
+        .unwrap();
+
+    assert_eq!(square(4), 16);
+    assert_eq!(square(4), 16);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    // The write-back tier catches up on flush.
+    smart_cache::flush();
+    assert_eq!(memory.functions().unwrap()[0].entries, 1);
+    assert_eq!(disk.functions().unwrap()[0].entries, 1);
+
+    // An entry only the slower tier has is copied up on the first hit.
+    let tiered = TieredBackend::new()
+        .tier(Arc::clone(&memory), WritePolicy::WriteThrough)
+        .tier(Arc::clone(&disk), WritePolicy::WriteThrough);
+    let function = Function::new("lower", [7; 32]);
+    disk.insert_batch(&[WriteEntry {
+        function: &function,
+        key: b"key",
+        entry: b"entry",
+    }])
+    .unwrap();
+    assert!(memory.get(&function, b"key").unwrap().is_none());
+    assert_eq!(&*tiered.get(&function, b"key").unwrap().unwrap(), b"entry");
+    assert_eq!(&*memory.get(&function, b"key").unwrap().unwrap(), b"entry");
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,54 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static VERSION: AtomicU64 = AtomicU64::new(0);
+static DELAY_MS: AtomicU64 = AtomicU64::new(0);
+
+#[cached(ttl = "1s", timeout = "100ms", on_timeout = "stale")]
+fn forecast(city: &str) -> String {
+    thread::sleep(Duration::from_millis(DELAY_MS.load(Ordering::SeqCst)));
+    format!("{city} v{}", VERSION.fetch_add(1, Ordering::SeqCst))
+}
+
+#[test]
+fn slow_recomputations_serve_the_expired_value() {
+    smart_cache::Config::default()
+        .store_name("timeout")
+        .backend("timeout", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(forecast("Oslo"), "Oslo v0");
+    thread::sleep(Duration::from_millis(2100));
+
+    // Fast recomputations return the new value as usual.
+    assert_eq!(forecast("Oslo"), "Oslo v1");
+    assert_eq!(forecast("Oslo"), "Oslo v1");
+    thread::sleep(Duration::from_millis(2100));
+
+    // Slow ones return the expired value once the timeout passes, and store the new one later.
+    DELAY_MS.store(1000, Ordering::SeqCst);
+    let started = Instant::now();
+    assert_eq!(forecast("Oslo"), "Oslo v1");
+    assert!(started.elapsed() < Duration::from_millis(900));
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while forecast("Oslo") == "Oslo v1" {
+        assert!(Instant::now() < deadline, "value was never recomputed");
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(forecast("Oslo"), "Oslo v2");
+
+    // Without an expired value to fall back on, a miss waits for the computation.
+    let started = Instant::now();
+    assert_eq!(forecast("Bergen"), "Bergen v3");
+    assert!(started.elapsed() >= Duration::from_millis(1000));
+}
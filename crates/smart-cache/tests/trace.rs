@@ -0,0 +1,47 @@
+use std::{process, sync::Arc, thread, time::Duration};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached]
+fn render(page: u32) -> String {
+    thread::sleep(Duration::from_millis(20));
+    format!("page {page}")
+}
+
+#[test]
+fn calls_are_written_as_a_chrome_trace() {
+    smart_cache::Config::default()
+        .store_name("trace")
+        .backend("trace", Arc::new(MemoryBackend::new()))
+        .trace_calls(true)
+        .install()
+        .unwrap();
+
+    assert_eq!(render(1), "page 1");
+    assert_eq!(render(1), "page 1");
+    assert_eq!(render(2), "page 2");
+
+    let path = std::env::temp_dir().join(format!("smart-cache-trace-{}.json", process::id()));
+    smart_cache::write_trace(&path).unwrap();
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(trace.starts_with("{\"traceEvents\":["));
+    let events: Vec<_> = trace
+        .lines()
+        .filter(|line| line.contains("\"ph\":\"X\""))
+        .collect();
+    assert_eq!(events.len(), 3);
+    assert!(events
+        .iter()
+        .all(|event| event.contains("\"name\":\"render\"")));
+    assert!(events[0].contains("\"outcome\":\"miss\""));
+    assert!(events[0].contains("\"compute_us\":"));
+    assert!(events[1].contains("\"outcome\":\"hit\""));
+    assert!(events[2].contains("\"outcome\":\"miss\""));
+
+    // The same key hashes the same, and different keys differently.
+    let key = |event: &str| event.split("\"key\":\"").nth(1).unwrap()[..16].to_string();
+    assert_eq!(key(events[0]), key(events[1]));
+    assert_ne!(key(events[0]), key(events[2]));
+}
@@ -0,0 +1,41 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// A response carrying how long it stays valid, like an API's `max-age`.
+#[cached(ttl = "1h", ttl_fn = "|response| Duration::from_secs(response.1)")]
+fn quote(symbol: String) -> (String, u64) {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    let valid_for = if symbol == "volatile" { 1 } else { 3600 };
+    (format!("{symbol}: 42"), valid_for)
+}
+
+#[test]
+fn each_entry_expires_after_the_ttl_of_its_value() {
+    smart_cache::Config::default()
+        .store_name("ttl-fn")
+        .backend("ttl-fn", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    quote("volatile".to_string());
+    quote("steady".to_string());
+    quote("volatile".to_string());
+    quote("steady".to_string());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    thread::sleep(Duration::from_millis(1100));
+    // Only the value that said it was valid for a second was computed again.
+    quote("volatile".to_string());
+    quote("steady".to_string());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+}
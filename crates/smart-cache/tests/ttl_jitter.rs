@@ -0,0 +1,45 @@
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached(ttl = "1h")]
+fn warm(id: u64) -> u64 {
+    id
+}
+
+#[test]
+fn expiries_are_spread_out() {
+    smart_cache::Config::default()
+        .store_name("ttl-jitter")
+        .backend("ttl-jitter", Arc::new(MemoryBackend::new()))
+        .ttl_jitter(0.5)
+        .install()
+        .unwrap();
+
+    for id in 0..100 {
+        warm(id);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let entries = smart_cache::entries("warm").unwrap();
+    assert_eq!(entries.len(), 100);
+    let mut expiries = HashSet::new();
+    for entry in entries {
+        let expires_at = entry
+            .expires_at
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert!((now + 1800 - 2..=now + 3600).contains(&expires_at));
+        expiries.insert(expires_at);
+    }
+    assert!(expiries.len() > 50);
+}
@@ -0,0 +1,92 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use sha2::{Digest, Sha256};
+use smart_cache::{
+    backend::{CacheBackend, MemoryBackend, WriteEntry},
+    cached, Function,
+};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, PartialEq)]
+struct Profile {
+    id: u64,
+    name: String,
+}
+
+#[cached]
+fn profile(id: u64) -> Profile {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    Profile {
+        id,
+        name: format!("user-{id}"),
+    }
+}
+
+/// Rewrites the type layout recorded in `entry` as `layout`, keeping the envelope valid, as if
+/// the value had been written by a build whose `Profile` had other fields.
+fn with_layout(entry: &[u8], layout: u128) -> Vec<u8> {
+    let mut entry = entry.to_vec();
+    entry[32 + 32..32 + 48].copy_from_slice(&layout.to_le_bytes());
+    let checksum: [u8; 32] = Sha256::new()
+        .chain_update(b"smart-cache entry v6")
+        .chain_update(&entry[32..])
+        .finalize()
+        .into();
+    entry[..32].copy_from_slice(&checksum);
+    entry
+}
+
+#[test]
+fn entries_of_another_type_layout_are_misses() {
+    let memory = Arc::new(MemoryBackend::new());
+    smart_cache::Config::default()
+        .store_name("type-layout")
+        .backend("type-layout", Arc::clone(&memory))
+        .install()
+        .unwrap();
+
+    assert_ne!(
+        smart_cache::type_layout::<Profile, rkyv::Archived<Profile>>(),
+        smart_cache::type_layout::<(u64, String), rkyv::Archived<(u64, String)>>()
+    );
+
+    assert_eq!(profile(1).name, "user-1");
+    assert_eq!(profile(1).name, "user-1");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let function = smart_cache::functions().unwrap().remove(0);
+    let descriptor = Function::new("profile", function.hash);
+    let rewrite = |layout: u128| {
+        let mut stored = Vec::new();
+        memory
+            .for_each_function_entry(&function.hash, &mut |key, entry| {
+                stored.push((key.to_vec(), with_layout(entry, layout)));
+            })
+            .unwrap();
+        let writes: Vec<_> = stored
+            .iter()
+            .map(|(key, entry)| WriteEntry {
+                function: &descriptor,
+                key,
+                entry,
+            })
+            .collect();
+        memory.insert_batch(&writes).unwrap();
+    };
+
+    // Written without a layout: still served.
+    rewrite(0);
+    assert_eq!(profile(1).name, "user-1");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    // Written with another layout: recomputed, and replaced.
+    rewrite(42);
+    assert_eq!(profile(1).name, "user-1");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(profile(1).name, "user-1");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+#[cached]
+fn small(id: u64) -> u64 {
+    id
+}
+
+#[cached]
+fn large(id: u64) -> Vec<u8> {
+    vec![0; 1024 + usize::try_from(id).unwrap()]
+}
+
+#[test]
+fn usage_lists_functions_largest_first() {
+    smart_cache::Config::default()
+        .store_name("usage")
+        .backend("usage", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    small(1);
+    small(2);
+    small(3);
+    large(1);
+
+    let usage = smart_cache::usage().unwrap();
+    let names: Vec<_> = usage.iter().map(|usage| usage.name.as_str()).collect();
+    assert_eq!(names, ["large", "small"]);
+    assert_eq!(usage[0].entries, 1);
+    assert!(usage[0].bytes > 1024);
+    assert_eq!(usage[1].entries, 3);
+    assert!(usage.iter().all(|usage| usage.last_written.is_some()));
+}
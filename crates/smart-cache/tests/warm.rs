@@ -0,0 +1,67 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(warm)]
+fn price(sku: &str, quantity: u32) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    u64::from(quantity) * sku.len() as u64
+}
+
+#[cached(warm)]
+fn square(n: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    n * n
+}
+
+struct Catalog {
+    lookups: usize,
+}
+
+impl Catalog {
+    #[cached(self_is_context, warm)]
+    fn describe(&mut self, sku: &str) -> String {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        self.lookups += 1;
+        format!("{sku} (lookup {})", self.lookups)
+    }
+}
+
+fn calls() -> usize {
+    CALLS.load(Ordering::SeqCst)
+}
+
+#[test]
+fn warming_stores_values_before_they_are_needed() {
+    smart_cache::Config::default()
+        .store_name("warm")
+        .backend("warm", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    price_warm([("apple", 3), ("pear", 2)]);
+    assert_eq!(calls(), 2);
+    assert_eq!(price("apple", 3), 15);
+    assert_eq!(price("pear", 2), 8);
+    assert_eq!(calls(), 2);
+
+    // Warming again only computes the arguments not yet cached.
+    price_warm([("apple", 3), ("plum", 1)]);
+    assert_eq!(calls(), 3);
+
+    square_warm(1..=4);
+    assert_eq!(calls(), 7);
+    assert_eq!(square(4), 16);
+    assert_eq!(calls(), 7);
+
+    let mut catalog = Catalog { lookups: 0 };
+    catalog.describe_warm(["apple", "pear"]);
+    assert_eq!(catalog.lookups, 2);
+    assert_eq!(catalog.describe("pear"), "pear (lookup 2)");
+    assert_eq!(calls(), 9);
+}
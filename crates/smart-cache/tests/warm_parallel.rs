@@ -0,0 +1,62 @@
+#![cfg(feature = "rayon")]
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, ThreadId},
+};
+
+use smart_cache::{backend::MemoryBackend, cached};
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+static THREADS: Mutex<Option<HashSet<ThreadId>>> = Mutex::new(None);
+
+#[cached]
+fn tile(zoom: u8, x: u32, y: u32) -> String {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    THREADS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashSet::new)
+        .insert(thread::current().id());
+    format!("{zoom}/{x}/{y}")
+}
+
+fn calls() -> usize {
+    CALLS.load(Ordering::SeqCst)
+}
+
+#[test]
+fn values_are_warmed_in_parallel() {
+    smart_cache::Config::default()
+        .store_name("warm-parallel")
+        .backend("warm-parallel", Arc::new(MemoryBackend::new()))
+        .install()
+        .unwrap();
+
+    assert_eq!(tile(3, 0, 0), "3/0/0");
+
+    // More tiles than a worker writes at a time, so some are committed before the pool ends.
+    let tiles: Vec<_> = (0..40)
+        .flat_map(|x| (0..20).map(move |y| (3, x, y)))
+        .collect();
+    smart_cache::warm_parallel(4, tiles.clone(), |(zoom, x, y)| {
+        tile(zoom, x, y);
+    });
+    assert_eq!(calls(), 800);
+    assert!(THREADS.lock().unwrap().as_ref().unwrap().len() > 1);
+
+    for (zoom, x, y) in tiles {
+        assert_eq!(tile(zoom, x, y), format!("{zoom}/{x}/{y}"));
+    }
+    assert_eq!(calls(), 800);
+
+    // Values held back for the next batch are found by later calls.
+    smart_cache::warm_parallel(1, [(4, 1, 1); 10], |(zoom, x, y)| {
+        tile(zoom, x, y);
+    });
+    assert_eq!(calls(), 801);
+}
@@ -0,0 +1,41 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use smart_cache::{backend::MemoryBackend, cached, SizeAlert, Watchdog};
+
+static ALERTS: Mutex<Vec<SizeAlert>> = Mutex::new(Vec::new());
+
+#[cached]
+fn payload(n: u64) -> Vec<u8> {
+    vec![0; usize::try_from(n).unwrap()]
+}
+
+#[test]
+fn crossing_a_threshold_alerts_once() {
+    smart_cache::Config::default()
+        .store_name("watchdog")
+        .backend("watchdog", Arc::new(MemoryBackend::new()))
+        .watchdog(
+            Watchdog::new()
+                .threshold(1)
+                .threshold(u64::MAX)
+                .interval(Duration::ZERO)
+                .on_alert(|alert| ALERTS.lock().unwrap().push(*alert)),
+        )
+        .install()
+        .unwrap();
+
+    // Stores that keep no local files aren't measured.
+    payload(1000);
+    assert!(ALERTS.lock().unwrap().is_empty());
+
+    let _isolated = smart_cache::testing::isolated();
+    payload(1000);
+    payload(2000);
+    let alerts = ALERTS.lock().unwrap();
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].threshold, 1);
+    assert!(alerts[0].size > 1000);
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smart_cache_macro::cached;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached]
+fn square(x: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn write_behind_serves_pending_values() {
+    smart_cache::Config::default()
+        .write_behind(16)
+        .install()
+        .unwrap();
+
+    // The second call is served from the queue, before the write lands.
+    assert_eq!(square(12), 144);
+    assert_eq!(square(12), 144);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    // Once flushed, the value is in the store and read back from it.
+    smart_cache::flush();
+    assert_eq!(smart_cache::entries("square").unwrap().len(), 1);
+    assert_eq!(square(12), 144);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+}